@@ -1,8 +1,9 @@
 use clap::*;
 use raster_tools::{utils::*, *};
 
+use rasters::align::SampleMode;
 use rasters::histogram::Config as HistConfig;
-use std::{convert::TryInto, path::PathBuf};
+use std::path::PathBuf;
 /// Program arguments
 pub struct Args {
     /// First input
@@ -11,18 +12,57 @@ pub struct Args {
     pub input_b: PathBuf,
     /// Operand order
     pub negate: bool,
-    /// Histogram config
-    pub hist: Option<(HistConfig, PathBuf)>,
+    /// Write absolute difference to output
+    pub abs: bool,
+    /// Histogram binning
+    pub hist: Option<(HistBinning, PathBuf)>,
+    /// Bins of the values of the first input, for the 2D
+    /// histogram of the values and the difference
+    pub hist2d: Option<HistConfig>,
     /// Polygon to restrict compute to
     pub polygon: Option<geo::MultiPolygon<f64>>,
+    /// CRS of the polygon, if not that of the first input
+    pub polygon_srs: Option<PolygonSrs>,
     /// Output filename
     pub output: Option<OutputArgs>,
     /// Output type
     pub output_type: OutputType,
+    /// Output of the second input resampled to the grid of
+    /// the first
+    pub resampled: Option<OutputArgs>,
     /// Chunk size to read input raster
     pub chunk_size: usize,
     /// Adjust
     pub adjust: f64,
+    /// Resampling of second input
+    pub resample: SampleMode,
+    /// Bands to compare
+    pub bands: BandSelection,
+    /// Mask raster to restrict compute to
+    pub mask: Option<PathBuf>,
+    /// No-data value overriding that of the inputs
+    pub nodata: Option<f64>,
+}
+
+/// Bins of the histogram.
+pub enum HistBinning {
+    /// Bins over the range given by `--min` and `--max`.
+    Fixed(HistConfig),
+    /// The number of bins, over a range adapted to the
+    /// values (see `AdaptiveHistogram`).
+    Adaptive(usize),
+}
+
+/// Number of bins of the histogram, if neither `--bins` nor
+/// the range is given.
+const ADAPTIVE_BINS: usize = 256;
+
+/// Bands of the two inputs to compare.
+pub enum BandSelection {
+    /// A band of the first input, and one of the second.
+    Single(isize, isize),
+    /// Each band present in both the inputs.
+    All,
 }
 
 pub enum OutputType {
@@ -52,40 +92,106 @@ pub fn parse_cmd_line() -> Args {
         )
         .arg(
             opt!("hist")
-                .help("Generate histogram (requires min, max, bins|step)")
-                .requires_all(&["min", "max", "binning"]),
+                .help("Generate histogram (range adapted to the values, unless min and max are given); use .json for a plain listing of bins"),
         )
         .arg(
             opt!("min")
                 .allow_hyphen_values(true)
-                .requires("hist")
+                .requires_all(&["hist", "max", "binning"])
                 .help("Min value to consider"),
         )
         .arg(
             opt!("max")
                 .allow_hyphen_values(true)
-                .requires("hist")
+                .requires_all(&["hist", "min"])
                 .help("Max value to consider"),
         )
-        .arg(opt!("bins").help("Number of bins (overrides step size)"))
-        .arg(opt!("step").help("Bin size for histogram"))
+        .arg(opt!("bins").help(
+            "Number of bins (overrides step size; default: 256 if the range is adapted)",
+        ))
+        .arg(
+            opt!("step")
+                .requires("min")
+                .help("Bin size for histogram"),
+        )
+        .arg(
+            opt!("hist scale")
+                .requires_all(&["min", "bins"])
+                .conflicts_with("step")
+                .help("Spacing of the bins: linear (default), log (requires 0 < min), or symlog (magnitudes from min to max of either sign, bins of each sign)"),
+        )
+        .arg(
+            opt!("hist2d")
+                .takes_value(false)
+                .requires_all(&["min", "value min", "value max", "value bins"])
+                .help("Also generate the 2D histogram of the values of the first input and the difference (written next to the histogram)"),
+        )
+        .arg(
+            opt!("value min")
+                .allow_hyphen_values(true)
+                .requires("hist2d")
+                .help("Min value of the first input in the 2D histogram"),
+        )
+        .arg(
+            opt!("value max")
+                .allow_hyphen_values(true)
+                .requires("hist2d")
+                .help("Max value of the first input in the 2D histogram"),
+        )
+        .arg(
+            opt!("value bins")
+                .requires("hist2d")
+                .help("Number of bins of the values of the first input in the 2D histogram"),
+        )
         .group(
             ArgGroup::with_name("binning")
                 .args(&["bins", "step"])
                 .requires("hist"),
         )
-        .arg(opt!("polygon").help("Region to restrict to (Polygon or MultiPolygon WKT)"))
+        .arg(
+            opt!("polygon")
+                .conflicts_with("polygon file")
+                .help("Region to restrict to (Polygon or MultiPolygon WKT)"),
+        )
+        .arg(opt!("polygon file").help("Region to restrict to (vector dataset)"))
+        .arg(opt!("polygon srs").help(
+            "EPSG code of the CRS of the polygon (default: CRS of the polygon file, or input_a)",
+        ))
+        .arg(opt!("mask").help("Mask raster to restrict to (non-zero pixels of band 1)"))
         .arg(
             opt!("output type")
                 .help("Output type: discretized or the default, value")
                 .requires("output"),
         )
         .arg(opt!("output").help("Output path (raster dataset)"))
+        .arg(
+            opt!("abs")
+                .help("Write the absolute difference to the output (stats are unchanged)")
+                .takes_value(false)
+                .requires("output"),
+        )
         .arg(
             opt!("driver")
                 .requires("output")
                 .help("Output driver (default: GTIFF)"),
         )
+        .arg(
+            opt!("cog")
+                .takes_value(false)
+                .requires("output")
+                .conflicts_with("driver")
+                .help("Write the output as a Cloud-Optimized GeoTIFF (with overviews)"),
+        )
+        .arg(
+            opt!("resampled")
+                .conflicts_with("all bands")
+                .help("Write the second input resampled to the grid of the first (raster dataset)"),
+        )
+        .arg(
+            opt!("overwrite")
+                .takes_value(false)
+                .help("Replace the outputs if they exist"),
+        )
         .arg(
             opt!("chunk size")
                 .short("c")
@@ -96,6 +202,16 @@ pub fn parse_cmd_line() -> Args {
                 .allow_hyphen_values(true)
                 .help("Adjust difference by value (float)"),
         )
+        .arg(opt!("resample").help("Resample second input: bilinear or the default, nearest"))
+        .arg(opt!("band a").help("Band of first input (default: 1)"))
+        .arg(opt!("band b").help("Band of second input (default: 1)"))
+        .arg(
+            opt!("all bands")
+                .help("Compute stats of each band present in both inputs")
+                .takes_value(false)
+                .conflicts_with_all(&["band a", "band b", "hist", "output"]),
+        )
+        .arg(nodata_arg())
         .get_matches();
 
     let input_a = value_t!(matches, "input_a", PathBuf).unwrap_or_else(|e| e.exit());
@@ -103,11 +219,38 @@ pub fn parse_cmd_line() -> Args {
 
     let hist_file = value_t!(matches, "hist", PathBuf).ok();
     let hist = if let Some(hist_file) = hist_file {
-        let hist = {
+        let hist = if !matches.is_present("min") {
+            let bins = value_t!(matches, "bins", usize).unwrap_or_else(|_| ADAPTIVE_BINS);
+            if bins == 0 || bins % 2 != 0 {
+                Error::with_description(
+                    &format!("bins must be even without min and max: got {}", bins),
+                    InvalidValue,
+                )
+                .exit()
+            }
+            HistBinning::Adaptive(bins)
+        } else {
             let min = value_t!(matches, "min", f64).unwrap_or_else(|e| e.exit());
             let max = value_t!(matches, "max", f64).unwrap_or_else(|e| e.exit());
             let bins = value_t!(matches, "bins", usize).ok();
-            if let Some(bins) = bins {
+            let scale =
+                value_t!(matches, "hist scale", String).unwrap_or_else(|_| String::from("linear"));
+            if (scale == "log" || scale == "symlog") && !(0. < min && min < max && bins != Some(0))
+            {
+                Error::with_description(
+                    &format!("{} scale requires 0 < min < max, and non-zero bins", scale),
+                    InvalidValue,
+                )
+                .exit()
+            }
+            HistBinning::Fixed(if scale == "log" {
+                HistConfig::log_spaced(min, max, bins.expect("bins of log scale"))
+            } else if scale == "symlog" {
+                HistConfig::signed_log_spaced(min, max, bins.expect("bins of symlog scale"))
+            } else if scale != "linear" {
+                Error::with_description(&format!("invalid hist scale: {}", scale), InvalidValue)
+                    .exit()
+            } else if let Some(bins) = bins {
                 HistConfig::from_min_max_bins(min, max, bins)
             } else {
                 HistConfig::from_min_max_step(
@@ -115,21 +258,52 @@ pub fn parse_cmd_line() -> Args {
                     max,
                     value_t!(matches, "step", f64).unwrap_or_else(|e| e.exit()),
                 )
-            }
+            })
         };
         Some((hist, hist_file))
     } else {
         None
     };
 
+    let hist2d = if matches.is_present("hist2d") {
+        let min = value_t!(matches, "value min", f64).unwrap_or_else(|e| e.exit());
+        let max = value_t!(matches, "value max", f64).unwrap_or_else(|e| e.exit());
+        let bins = value_t!(matches, "value bins", usize).unwrap_or_else(|e| e.exit());
+        if min >= max {
+            Error::with_description(
+                &format!("value min must be below value max: {} >= {}", min, max),
+                InvalidValue,
+            )
+            .exit()
+        }
+        Some(HistConfig::from_min_max_bins(min, max, bins))
+    } else {
+        None
+    };
+
     let negate = matches.is_present("negate");
+    let abs = matches.is_present("abs");
+    let overwrite = matches.is_present("overwrite");
     let output = if matches.is_present("output") {
         let o = value_t!(matches, "output", PathBuf).unwrap_or_else(|e| e.exit());
         let driver = value_t!(matches, "driver", String).unwrap_or_else(|_| String::from("GTIFF"));
-        Some(OutputArgs { path: o, driver })
+        Some(OutputArgs {
+            path: o,
+            driver,
+            overwrite,
+            cog: matches.is_present("cog"),
+        })
     } else {
         None
     };
+    let resampled = value_t!(matches, "resampled", PathBuf)
+        .ok()
+        .map(|path| OutputArgs {
+            path,
+            driver: String::from("GTIFF"),
+            overwrite,
+            cog: false,
+        });
 
     let output_type = {
         let output_type =
@@ -148,9 +322,9 @@ pub fn parse_cmd_line() -> Args {
     };
 
     if let OutputType::Discretized = output_type {
-        if hist.is_none() {
+        if !matches!(hist, Some((HistBinning::Fixed(_), _))) {
             Error::with_description(
-                "`discretized' output requires generating histogram (`--hist')",
+                "`discretized' output requires generating histogram (`--hist') of a given range (`--min', `--max')",
                 InvalidValue,
             )
             .exit()
@@ -158,31 +332,75 @@ pub fn parse_cmd_line() -> Args {
     }
 
     let chunk_size = value_t!(matches, "chunk size", usize).unwrap_or_else(|_| 0x10000);
-    let polygon = value_t!(matches, "polygon", String).ok().map(|wkt| {
-        let geom = gdal::vector::Geometry::from_wkt(&wkt)
-            .unwrap_or_else(|_| Error::with_description("cannot parse WKT", InvalidValue).exit())
-            .try_into()
-            .unwrap_or_else(|_| {
-                Error::with_description("cannot parse as geometry", InvalidValue).exit()
-            });
-        use geo::Geometry::{MultiPolygon, Polygon};
-        match geom {
-            Polygon(p) => p.into(),
-            MultiPolygon(p) => p,
-            _ => Error::with_description("WKT is not a (multi)-polygon", InvalidValue).exit(),
-        }
+    let polygon_srs = value_t!(matches, "polygon srs", u32).ok().map(|code| {
+        PolygonSrs::from_epsg(code).unwrap_or_else(|e| {
+            Error::with_description(
+                &format!("invalid polygon SRS {}: {}", code, e),
+                InvalidValue,
+            )
+            .exit()
+        })
     });
+    let (polygon, polygon_srs) = if let Some(wkt) = value_t!(matches, "polygon", String).ok() {
+        let poly = multipoly_from_wkt(&wkt).unwrap_or_else(|e| {
+            Error::with_description(&format!("cannot parse input WKT: {}", e), InvalidValue).exit()
+        });
+        (Some(poly), polygon_srs)
+    } else if let Some(path) = value_t!(matches, "polygon file", PathBuf).ok() {
+        let (poly, srs) = read_region(&path).unwrap_or_else(|e| {
+            Error::with_description(
+                &format!("reading polygons in {}: {}", path.display(), e),
+                InvalidValue,
+            )
+            .exit()
+        });
+        (Some(poly), polygon_srs.or(srs))
+    } else {
+        (None, None)
+    };
     let adjust = value_t!(matches, "adjust", f64).unwrap_or_default();
+    let resample = {
+        let resample =
+            value_t!(matches, "resample", String).unwrap_or_else(|_| String::from("nearest"));
+        if resample == "nearest" {
+            SampleMode::Nearest
+        } else if resample == "bilinear" {
+            SampleMode::Bilinear
+        } else {
+            Error::with_description(&format!("invalid resample: {}", resample), InvalidValue).exit()
+        }
+    };
+
+    let bands = if matches.is_present("all bands") {
+        BandSelection::All
+    } else {
+        let band_a = value_t!(matches, "band a", isize).unwrap_or_else(|_| 1);
+        let band_b = value_t!(matches, "band b", isize).unwrap_or_else(|_| 1);
+        if band_a < 1 || band_b < 1 {
+            Error::with_description("band indices start at 1", InvalidValue).exit()
+        }
+        BandSelection::Single(band_a, band_b)
+    };
+
+    let mask = value_t!(matches, "mask", PathBuf).ok();
 
     Args {
         input_a,
         input_b,
         hist,
+        hist2d,
         negate,
+        abs,
         polygon,
+        polygon_srs,
         chunk_size,
         output,
         output_type,
+        resampled,
         adjust,
+        resample,
+        bands,
+        mask,
+        nodata: nodata_from_matches(&matches),
     }
 }
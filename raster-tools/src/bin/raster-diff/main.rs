@@ -1,20 +1,23 @@
-use anyhow::{anyhow, Context};
+use anyhow::{anyhow, bail, Context};
 use nalgebra::Point2;
 use ndarray::*;
-use rayon::prelude::*;
 use std::sync::mpsc::*;
+use std::sync::Mutex;
 
 use args::*;
 use raster_tools::{utils::*, *};
 use rasters::prelude::*;
 
 mod args;
-mod diff;
 mod outputs;
 
 // Main function
 raster_tools::sync_main!(run());
 
+/// Tolerance (in pixels) to compare the grids of the inputs
+/// (see `grids_aligned`).
+const GRID_TOLERANCE: f64 = 1e-6;
+
 fn run() -> Result<()> {
     // Parse command line
     let args = args::parse_cmd_line();
@@ -22,21 +25,65 @@ fn run() -> Result<()> {
     // Read input raster
     let ds = read_dataset(&args.input_a)?;
     let transform_1 = transform_from_dataset(&ds);
-    let no_val_1 = ds.rasterband(1)?.no_data_value().unwrap_or(f64::NAN);
 
     let ds_2 = read_dataset(&args.input_b)?;
     let transform_2 = transform_from_dataset(&ds_2);
-    let no_val_2 = ds_2.rasterband(1)?.no_data_value().unwrap_or(f64::NAN);
+
+    // Pairs of bands to compare
+    let bands = match args.bands {
+        BandSelection::Single(band_1, band_2) => {
+            for (ds, band, input) in [(&ds, band_1, "input_a"), (&ds_2, band_2, "input_b")] {
+                if band > ds.raster_count() {
+                    bail!(
+                        "{}: band {} out of range: dataset has {} band(s)",
+                        input,
+                        band,
+                        ds.raster_count()
+                    );
+                }
+            }
+            vec![(band_1, band_2)]
+        }
+        BandSelection::All => {
+            let (count_1, count_2) = (ds.raster_count(), ds_2.raster_count());
+            if count_1 != count_2 {
+                eprintln!(
+                    "Warning: inputs have {} and {} bands; comparing the first {}",
+                    count_1,
+                    count_2,
+                    count_1.min(count_2)
+                );
+            }
+            (1..=count_1.min(count_2)).map(|b| (b, b)).collect()
+        }
+    };
 
     // Compute transform: raster 1 -> 2 (in pixels)
     let transform = transform_between(&ds, &ds_2)?;
 
+    // Report how the grids relate; nearest sampling is
+    // lossless only if the pixel centers coincide
+    let grids = grids_aligned(&transform_1, &transform_2, GRID_TOLERANCE);
+    eprintln!("Grid relation: {:?}", grids);
+    if grids.has_subpixel_shift(GRID_TOLERANCE) {
+        eprintln!("Warning: the grids are shifted by a fraction of a pixel");
+    }
+
+    // Reproject polygon to the CRS of raster 1
+    let polygon = match (&args.polygon, &args.polygon_srs) {
+        (Some(poly), Some(srs)) => match srs.transform_to(&ds)? {
+            Some(transform) => Some(reproject_polygon(poly, &transform)?),
+            None => Some(poly.clone()),
+        },
+        (polygon, _) => polygon.clone(),
+    };
+
     // Compute extent on raster 1 pixels
     let extent = {
         let inv = transform_1
             .try_inverse()
             .ok_or_else(|| anyhow!("input_a: couldn't invert transform"))?;
-        args.polygon.as_ref().map(|poly| {
+        polygon.as_ref().map(|poly| {
             use geo::algorithm::map_coords::MapCoords;
             poly.map_coords(|coord| {
                 let pt = inv.transform_point(&Point2::from_slice(&[coord.x, coord.y]));
@@ -45,14 +92,7 @@ fn run() -> Result<()> {
         })
     };
 
-    #[derive(Clone)]
-    enum OutputSender {
-        ValueSender(Sender<Chunk<f64>>),
-        DiscSender(Sender<Chunk<i32>>),
-    }
-    use OutputSender::*;
-
-    let (sender, writer) = if let Some(out) = &args.output {
+    let (sender, output_writer) = if let Some(out) = &args.output {
         match args.output_type {
             OutputType::Value => {
                 let out_ds = create_output_raster::<f64>(&out, &ds, 1, Some(f64::NAN))?;
@@ -61,49 +101,217 @@ fn run() -> Result<()> {
                 (Some(ValueSender(s)), Some(writer))
             }
             OutputType::Discretized => {
+                // The bins of an adaptive histogram are only
+                // known once every chunk is processed
+                let cfg = match &args.hist {
+                    Some((HistBinning::Fixed(cfg), _)) => cfg.clone(),
+                    _ => bail!("discretized output requires a histogram of a given range"),
+                };
                 let out_ds = create_output_raster::<i32>(&out, &ds, 1, Some(-128.))?;
                 let (s, r) = channel();
                 let writer = std::thread::spawn(|| writer::<i32>(r, out_ds));
-                (Some(DiscSender(s)), Some(writer))
+                (Some(DiscSender(s, cfg)), Some(writer))
             }
         }
     } else {
         (None, None)
     };
+    let (resampled, resampled_writer) = if let Some(out) = &args.resampled {
+        let out_ds = create_output_raster::<f64>(&out, &ds, 1, Some(f64::NAN))?;
+        let (s, r) = channel();
+        (
+            Some(s),
+            Some(std::thread::spawn(|| writer::<f64>(r, out_ds))),
+        )
+    } else {
+        (None, None)
+    };
 
-    // Calculate processing chunks
-    let chunks_cfg = ChunkConfig::for_dataset(&ds, Some(1..2))?.with_min_data_size(args.chunk_size);
+    // Calculate processing chunks. If both the rasters are
+    // on the same grid, align the chunks to the blocks of
+    // both the inputs.
+    let chunks_cfg = ChunkConfig::for_dataset(&ds, Some(bands.iter().map(|b| b.0)))?;
+    let same_grid = ds.raster_size() == ds_2.raster_size() && grids == GridRelation::Identical;
+    let chunks_cfg = if same_grid {
+        chunks_cfg.add_dataset(&ds_2, Some(bands.iter().map(|b| b.1)))?
+    } else {
+        chunks_cfg
+    }
+    .with_min_data_size(args.chunk_size)
+    .with_valid_window(valid_window(&ds, &ds_2, &transform, extent.as_ref())?);
 
-    let diff_proc = diff::processor(extent, transform, ds_2.raster_size(), no_val_1, no_val_2);
-    let chunk_proc = chunks_cfg.into_par_iter().map_init(
-        || {
-            let ds_a = read_dataset(&args.input_a).expect("reader A initialization failed");
-            let ds_b = read_dataset(&args.input_b).expect("reader B initialization failed");
-            (DatasetReader(ds_a, 1), DatasetReader(ds_b, 1))
-        },
-        |(rd_1, rd_2), win_1| diff_proc.read_window(&*rd_1, &*rd_2, win_1),
-    );
-    let tracker = Tracker::new("chunks", chunk_proc.len());
-
-    macro_rules! accumulate {
-        ($init:expr, $proc:expr,) => {{
-            chunk_proc
-                .try_fold_with(($init(), sender), |out, res| {
-                    let ((off_1, data_1), (off_2, data_2)) = res?;
-                    let (mut out, sender) = out;
-
-                    // If we need to output, allocate array
-                    let (mut data, mut data_disc) = if let Some(s) = &sender {
-                        match s {
-                            ValueSender(_) => {
-                                (Some(Array2::from_elem(data_1.dim(), f64::NAN)), None)
-                            }
-                            DiscSender(_) => (None, Some(Array2::from_elem(data_1.dim(), -128))),
-                        }
-                    } else {
-                        (None, None)
-                    };
+    let mut diff_proc =
+        DiffProcessor::new(transform, (args.nodata, args.nodata), extent).with_mode(args.resample);
+    if let Some(mask) = &args.mask {
+        let ds_m = read_dataset(mask)?;
+        let transform_m = transform_between(&ds, &ds_m).context("mask")?;
+        diff_proc = diff_proc.with_mask(transform_m, ds_m.raster_size());
+    }
+    let inputs = Inputs {
+        args: &args,
+        bands: &bands,
+        diff_proc: &diff_proc,
+        chunks_cfg: &chunks_cfg,
+        sender: Mutex::new(sender),
+        resampled: Mutex::new(resampled),
+    };
 
+    if let Some((binning, path)) = &args.hist {
+        // Only a single pair of bands with a histogram
+        let is_json = path.extension().map_or(false, |ext| ext == "json");
+        let hist = match (binning, &args.hist2d) {
+            (HistBinning::Fixed(cfg), Some(value_cfg)) => {
+                // The histogram of the difference is the
+                // marginal of the 2D histogram
+                let cfg_2d = Config2d::new(value_cfg.clone(), cfg.clone());
+                let hist_2d = inputs
+                    .accumulate(|| Histogram2d::new(&cfg_2d), |val_1, _, diff| (val_1, diff))?
+                    .remove(0);
+                let path_2d = hist2d_path(path);
+                if is_json {
+                    write_json(&path_2d, &Histogram2dOutput::from(&hist_2d))?;
+                } else {
+                    rasters::histogram::write_2d(&hist_2d, &path_2d)?;
+                }
+                let mut hist = Histogram::new(cfg);
+                hist += hist_2d.marginal_y();
+                hist
+            }
+            (HistBinning::Fixed(cfg), None) => inputs
+                .accumulate(|| Histogram::new(cfg), |_, _, diff| diff)?
+                .remove(0),
+            (HistBinning::Adaptive(bins), _) => inputs
+                .accumulate(|| AdaptiveHistogram::new(*bins), |_, _, diff| diff)?
+                .remove(0)
+                .into_histogram(),
+        };
+        if is_json {
+            write_json(&path, &HistogramOutput::from(&hist))?;
+        } else {
+            rasters::histogram::write(&hist, path)?;
+        }
+    } else {
+        let stats = inputs.accumulate(Default::default, |val_1, val_2, _| (val_1, val_2))?;
+        let mut outputs = stats
+            .into_iter()
+            .zip(&bands)
+            .map(|(stats, &(band_1, band_2))| outputs::BandDiffOutput {
+                band_1,
+                band_2,
+                output: outputs::RasterDiffOutput {
+                    pix_area_1: transform_1.determinant().abs(),
+                    pix_area_2: transform_2.determinant().abs(),
+                    correlation: stats.correlation(),
+                    stats,
+                },
+            })
+            .collect::<Vec<_>>();
+        if let BandSelection::All = args.bands {
+            print_json(&outputs)?;
+        } else {
+            print_json(&outputs.remove(0).output)?;
+        }
+    }
+
+    for writer in output_writer.into_iter().chain(resampled_writer) {
+        writer.join().expect("writer thread panicked")?.finish()?;
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+enum OutputSender {
+    ValueSender(Sender<Chunk<f64>>),
+    /// Discretized to the bins of the histogram config
+    DiscSender(Sender<Chunk<i32>>, rasters::histogram::Config),
+}
+use OutputSender::*;
+
+/// Inputs required to compute the differences of each
+/// chunk.
+struct Inputs<'a> {
+    args: &'a Args,
+    bands: &'a [(isize, isize)],
+    diff_proc: &'a DiffProcessor,
+    chunks_cfg: &'a ChunkConfig,
+    /// Sender to the writer of the output (if any), cloned
+    /// by each thread
+    sender: Mutex<Option<OutputSender>>,
+    /// Sender to the writer of the resampled second input
+    /// (if any), cloned by each thread
+    resampled: Mutex<Option<Sender<Chunk<f64>>>>,
+}
+
+impl<'a> Inputs<'a> {
+    /// Accumulate `proc` of the values of each pair of bands
+    /// (and their difference) over the chunks, sending the
+    /// differences to the writer of the output (if any), and
+    /// the resampled chunks of the second input to its
+    /// writer (if any).
+    /// Consumes the inputs, so that the writers finish once
+    /// all the chunks are sent.
+    fn accumulate<S, T, I, P>(self, init: I, proc: P) -> Result<Vec<S>>
+    where
+        S: Send + std::ops::AddAssign<T> + std::ops::AddAssign,
+        I: Fn() -> S + Sync + Send,
+        P: Fn(f64, f64, f64) -> T + Sync + Send,
+    {
+        let Inputs {
+            args,
+            bands,
+            diff_proc,
+            chunks_cfg,
+            ref sender,
+            ref resampled,
+        } = self;
+
+        process_chunks(
+            chunks_cfg,
+            || {
+                let ds_a = read_dataset(&args.input_a).context("reader A initialization failed")?;
+                let ds_b = read_dataset(&args.input_b).context("reader B initialization failed")?;
+                let rd_m = args
+                    .mask
+                    .as_ref()
+                    .map(|mask| {
+                        read_dataset(mask)
+                            .and_then(|ds| DatasetReader::new(ds, 1))
+                            .context("mask reader initialization failed")
+                    })
+                    .transpose()?;
+                let sender = sender.lock().expect("sender lock poisoned").clone();
+                let resampled = resampled.lock().expect("sender lock poisoned").clone();
+                Ok((ds_a, ds_b, rd_m, sender, resampled))
+            },
+            |(ds_a, ds_b, rd_m, sender, resampled), out: &mut Vec<S>, win_1| {
+                // A pair of aligned chunks for each pair of
+                // bands compared, in order
+                let chunks = bands
+                    .iter()
+                    .map(|&(band_1, band_2)| {
+                        diff_proc.read_window(
+                            ds_a.rasterband(band_1)?,
+                            ds_b.rasterband(band_2)?,
+                            win_1.raster_window(),
+                        )
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let mask = match rd_m {
+                    Some(rd_m) => diff_proc.read_mask_window(&*rd_m, win_1.raster_window())?,
+                    None => None,
+                };
+                let (off_1, dim_1) = (chunks[0].off_a, chunks[0].data_a.dim());
+
+                // If we need to output, allocate array
+                let (mut data, mut data_disc) = match &*sender {
+                    Some(ValueSender(_)) => (Some(Array2::from_elem(dim_1, f64::NAN)), None),
+                    Some(DiscSender(_, cfg)) => {
+                        (None, Some((Array2::from_elem(dim_1, -128), &*cfg)))
+                    }
+                    None => (None, None),
+                };
+
+                for (acc, chunk) in out.iter_mut().zip(&chunks) {
                     diff_proc.process(
                         &mut |(i, j), val_1, val_2| {
                             let mut diff = val_2 - val_1 + args.adjust;
@@ -112,70 +320,104 @@ fn run() -> Result<()> {
                             }
 
                             if let Some(d) = &mut data {
-                                d[(i, j)] = diff;
-                            } else if let Some(d) = &mut data_disc {
-                                if let Some((cfg, _)) = &args.hist {
-                                    use HistBin::*;
-                                    let bins = cfg.len();
-                                    d[(i, j)] = match cfg.bin_for(diff) {
-                                        Min => -1,
-                                        Bin(i) => i as i32,
-                                        Max => bins as i32,
-                                    }
+                                d[(i, j)] = if args.abs { diff.abs() } else { diff };
+                            } else if let Some((d, cfg)) = &mut data_disc {
+                                use HistBin::*;
+                                let bins = cfg.len();
+                                d[(i, j)] = match cfg.bin_for(diff) {
+                                    Min => -1,
+                                    Bin(i) => i as i32,
+                                    Max => bins as i32,
                                 }
                             }
-                            out += $proc(val_1, val_2, diff);
+                            *acc += proc(val_1, val_2, diff);
                         },
-                        &data_1,
-                        off_1,
-                        &data_2,
-                        off_2,
-                    );
+                        chunk,
+                        mask.as_ref(),
+                    )?;
+                }
 
-                    if let Some(s) = &sender {
-                        match s {
-                            ValueSender(s) => {
-                                s.send(((off_1.1, data.unwrap())))
-                                    .with_context(|| anyhow!("send to writer"))?;
-                            }
-                            DiscSender(s) => {
-                                s.send(((off_1.1, data_disc.unwrap())))
-                                    .with_context(|| anyhow!("send to writer"))?;
-                            }
-                        };
-                    }
-                    tracker.increment();
-                    Ok::<_, Error>((out, sender))
-                })
-                .map(|res| res.map(|(acc, _)| acc))
-                .try_reduce($init, |mut acc_1, acc_2| {
-                    acc_1 += acc_2;
-                    Ok(acc_1)
-                })
-        }};
+                match &*sender {
+                    Some(ValueSender(s)) => s
+                        .send((off_1.1, data.unwrap()))
+                        .with_context(|| anyhow!("send to writer"))?,
+                    Some(DiscSender(s, _)) => s
+                        .send((off_1.1, data_disc.unwrap().0))
+                        .with_context(|| anyhow!("send to writer"))?,
+                    None => {}
+                }
+
+                // Only a single pair of bands with the
+                // resampled output
+                if let Some(s) = resampled {
+                    let chunk = &chunks[0];
+                    let data = resample_chunk(
+                        &chunk.data_b,
+                        chunk.off_b,
+                        win_1.raster_window(),
+                        diff_proc.transform(),
+                        args.resample,
+                        f64::NAN,
+                    );
+                    s.send((off_1.1, data))
+                        .with_context(|| anyhow!("send to writer"))?;
+                }
+                Ok(())
+            },
+            // One accumulator per pair of bands
+            || bands.iter().map(|_| init()).collect(),
+            |mut acc_1, acc_2| {
+                for (acc_1, acc_2) in acc_1.iter_mut().zip(acc_2) {
+                    *acc_1 += acc_2;
+                }
+                Ok(acc_1)
+            },
+        )
     }
+}
 
-    if let Some((cfg, path)) = &args.hist {
-        let hist = accumulate!(|| Histogram::new(cfg), |_, _, diff| diff,)?;
-        write_bin(&path, &hist)?;
-    } else {
-        let stats = accumulate!(Default::default, |val_1, val_2, _| (val_1, val_2),)?;
-        print_json(&outputs::RasterDiffOutput {
-            pix_area_1: transform_1.determinant().abs(),
-            pix_area_2: transform_2.determinant().abs(),
-            stats,
-        })?;
+/// Path of the 2D histogram written next to the histogram
+/// at `path`: `hist.json` -> `hist.2d.json`.
+fn hist2d_path(path: &std::path::Path) -> std::path::PathBuf {
+    match path.extension() {
+        Some(ext) => path.with_extension(format!("2d.{}", ext.to_string_lossy())),
+        None => path.with_extension("2d"),
     }
+}
 
-    if let Some(writer) = writer {
-        writer.join().expect("writer thread panicked")?;
+/// Window of the first raster that may contain valid
+/// differences: the region overlapping the second raster,
+/// restricted to the extent (if any).
+fn valid_window(
+    ds: &gdal::Dataset,
+    ds_2: &gdal::Dataset,
+    transform: &PixelTransform,
+    extent: Option<&geo::MultiPolygon<f64>>,
+) -> Result<RasterWindow> {
+    let inv = transform
+        .try_inverse()
+        .ok_or_else(|| anyhow!("couldn't invert transform between inputs"))?;
+    let ((x, y), (w, h)) = transform_window(((0, 0), ds_2.raster_size()), inv, ds.raster_size());
+    let mut bounds = Bounds::new(
+        (x as f64, y as f64),
+        (x as f64 + w as f64, y as f64 + h as f64),
+    );
+
+    if let Some(extent) = extent {
+        use geo::algorithm::bounding_rect::BoundingRect;
+        bounds = extent
+            .bounding_rect()
+            .and_then(|rect| bounds.intersect(&rect))
+            .unwrap_or_else(|| Bounds::new((0., 0.), (0., 0.)));
     }
-    Ok(())
+    Ok(bounds.window_from_bounds(ds.raster_size()))
 }
 
 use gdal::raster::GdalType;
-use gdal::Dataset;
-fn writer<T: GdalType + Copy>(receiver: Receiver<Chunk<T>>, ds: Dataset) -> Result<()> {
+fn writer<T: GdalType + Copy>(
+    receiver: Receiver<Chunk<T>>,
+    ds: OutputRaster,
+) -> Result<OutputRaster> {
     let mut band = ds.rasterband(1)?;
     for (y, data) in receiver {
         use gdal::raster::Buffer;
@@ -186,5 +428,5 @@ fn writer<T: GdalType + Copy>(receiver: Receiver<Chunk<T>>, ds: Dataset) -> Resu
             &Buffer::new((xsize, ysize), data.into_raw_vec()),
         )?;
     }
-    Ok(())
+    Ok(ds)
 }
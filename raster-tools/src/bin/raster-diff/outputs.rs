@@ -6,9 +6,22 @@ use std::ops::AddAssign;
 pub struct RasterDiffOutput {
     pub pix_area_1: f64,
     pub pix_area_2: f64,
+    /// Pearson's correlation of the inputs (see
+    /// [`RasterDiffStats::correlation`]).
+    pub correlation: f64,
     pub stats: RasterDiffStats,
 }
 
+/// Output for a pair of bands, when comparing multiple
+/// bands.
+#[derive(Debug, Serialize, Clone)]
+pub struct BandDiffOutput {
+    pub band_1: isize,
+    pub band_2: isize,
+    #[serde(flatten)]
+    pub output: RasterDiffOutput,
+}
+
 #[derive(Serialize, Clone, Default, Debug)]
 pub struct RasterDiffStats {
     count: usize,
@@ -16,12 +29,14 @@ pub struct RasterDiffStats {
     second: PixelStats,
     diff: PixelStats,
     abs_diff: PixelStats,
+    sum_xy: f64,
 }
 impl AddAssign<(f64, f64)> for RasterDiffStats {
     fn add_assign(&mut self, other: (f64, f64)) {
         self.count += 1;
         self.first += other.0;
         self.second += other.1;
+        self.sum_xy += other.0 * other.1;
         let diff = other.1 - other.0;
         self.diff += diff;
         self.abs_diff += diff.abs();
@@ -35,5 +50,55 @@ impl AddAssign for RasterDiffStats {
         self.second += &other.second;
         self.diff += &other.diff;
         self.abs_diff += &other.abs_diff;
+        self.sum_xy += other.sum_xy;
+    }
+}
+
+impl RasterDiffStats {
+    /// Pearson's correlation coefficient of the values of
+    /// the first and second inputs. NaN if there are no
+    /// values, or either input is constant.
+    pub fn correlation(&self) -> f64 {
+        let count = self.first.count();
+        let covariance = self.sum_xy / count - self.first.mean() * self.second.mean();
+        let deviations = self.first.std_deviation() * self.second.std_deviation();
+        if count > 0. && deviations > 0. {
+            (covariance / deviations).clamp(-1., 1.)
+        } else {
+            f64::NAN
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correlation() {
+        let pairs = [(1., 2.), (2., 4.5), (3., 5.5), (4., 8.)];
+        let mut stats = RasterDiffStats::default();
+        let mut halves = (RasterDiffStats::default(), RasterDiffStats::default());
+        for (i, &pair) in pairs.iter().enumerate() {
+            stats += pair;
+            if i < 2 {
+                halves.0 += pair;
+            } else {
+                halves.1 += pair;
+            }
+        }
+
+        // cov = 2.375, var_x = 1.25, var_y = 4.625
+        let expected = 2.375 / (1.25f64 * 4.625).sqrt();
+        assert!((stats.correlation() - expected).abs() < 1e-12);
+        halves.0 += halves.1;
+        assert!((halves.0.correlation() - expected).abs() < 1e-12);
+
+        // Undefined if empty, or either input is constant
+        assert!(RasterDiffStats::default().correlation().is_nan());
+        let mut stats = RasterDiffStats::default();
+        stats += (1., 2.);
+        stats += (1., 3.);
+        assert!(stats.correlation().is_nan());
     }
 }
@@ -1,13 +1,61 @@
-use super::triangulation::Triangulation;
+use super::triangulation::{in_hull, Triangulation};
 use super::Chunk;
 use nalgebra::{Matrix3, Point2};
+use rasters::prelude::{Bounds, BoundsExt};
 
+/// Interpolation method.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Method {
+    /// C1 natural neighbor (Sibson's) interpolation, with
+    /// the given smoothness parameter.
+    Sibson(f64),
+    /// C1 natural neighbor (Farin's) interpolation: slow.
+    Farin,
+    /// Barycentric interpolation on the containing triangle:
+    /// very fast.
+    Barycentric,
+    /// C0 natural neighbor interpolation.
+    NaturalNeighbor,
+}
+
+impl Method {
+    /// Whether the method uses the gradients at the source
+    /// points.
+    pub fn needs_gradients(&self) -> bool {
+        matches!(self, Method::Sibson(_) | Method::Farin)
+    }
+
+    /// Interpolate at the point. Returns `None` if the method
+    /// can't interpolate there (eg. outside the convex hull).
+    fn interpolate(&self, triangulation: &Triangulation, pt: &[f64; 2]) -> Option<f64> {
+        match *self {
+            Method::Sibson(sibson) => triangulation.nn_interpolation_c1_sibson(
+                pt,
+                sibson,
+                |v| v.height,
+                |_, v| v.gradient,
+            ),
+            Method::Farin => {
+                triangulation.nn_interpolation_c1_farin(pt, |v| v.height, |_, v| v.gradient)
+            }
+            Method::Barycentric => triangulation.barycentric_interpolation(pt, |v| v.height),
+            Method::NaturalNeighbor => triangulation.nn_interpolation(pt, |v| v.height),
+        }
+    }
+}
+
+/// Fill the NaN values of the chunk whose pixel centers are
+/// within `region` (in pixel coordinates). If `hull_only` is
+/// set, only those inside the convex hull of the
+/// triangulation are filled. The values the method can't
+/// interpolate are left as is.
 pub fn fill_chunk(
     chunk: &mut Chunk<f64>,
-    no_val: f64,
     transform: Matrix3<f64>,
     triangulation: &Triangulation,
-    sibson: f64,
+    method: Method,
+    region: &Bounds,
+    hull_only: bool,
 ) -> usize {
     let mut count = 0;
     let (ht, wid) = chunk.1.dim();
@@ -16,31 +64,64 @@ pub fn fill_chunk(
     for y in 0..ht {
         for x in 0..wid {
             let val = data[(y, x)];
-            if (val == f64::NAN) || (val == no_val) {
+            let in_region =
+                region.contains_point(x as f64 + 0.5, (y as isize + start_y) as f64 + 0.5);
+            if val.is_nan() && in_region {
                 let pt = {
                     use nalgebra::Vector3;
                     let pt = Vector3::new(x as f64 + 0.5, (y as isize + start_y) as f64 + 0.5, 1.);
                     let pt = transform * pt;
                     Point2::new(pt.x, pt.y)
                 };
-                // NN c1 sibson
-                let val = triangulation
-                    .nn_interpolation_c1_sibson(&[pt.x, pt.y], sibson, |v| v.height, |_, v| v.gradient)
-                    .unwrap();
-
-                // Farin: slow
-                // let val = triangulation.nn_interpolation_c1_farin(
-                //     &pt, |v| v.height, |_, v| v.gradient,
-                // ).unwrap();
-
-                // Barycentric: very fast
-                // let val = triangulation.barycentric_interpolation(
-                //     &pt, |v| v.height).unwrap();
-
-                data[(y, x)] = val;
-                count += 1;
+                if hull_only && !in_hull(triangulation, [pt.x, pt.y]) {
+                    continue;
+                }
+                if let Some(val) = method.interpolate(triangulation, &[pt.x, pt.y]) {
+                    data[(y, x)] = val;
+                    count += 1;
+                }
             }
         }
     }
     count
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::triangulation::{get_triangulation, PointWithHeight};
+    use ndarray::array;
+    use rasters::prelude::{MemoryReader, NodataToNan};
+
+    #[test]
+    fn test_fill_nan_and_no_data() -> rasters::Result<()> {
+        let no_val = -9999.;
+        let arr = array![[1., 1., no_val], [1., f64::NAN, 1.], [1., 1., 1.]];
+
+        // No-data values are read as NaN (as in `run`)
+        let reader = NodataToNan::new(MemoryReader(arr, Some(no_val)))?;
+        let mut chunk = (0, reader.read_as_array((0, 0), (3, 3))?);
+
+        let pts = vec![
+            PointWithHeight::new(-1., -1., 1.),
+            PointWithHeight::new(5., -1., 1.),
+            PointWithHeight::new(5., 5., 1.),
+            PointWithHeight::new(-1., 5., 1.),
+        ];
+        let triangulation = get_triangulation(pts, true);
+        let count = fill_chunk(
+            &mut chunk,
+            Matrix3::identity(),
+            &triangulation,
+            Method::Sibson(0.5),
+            &Bounds::new((0., 0.), (3., 3.)),
+            false,
+        );
+
+        assert_eq!(count, 2);
+        for &val in chunk.1.iter() {
+            assert!((val - 1.).abs() < 1e-9, "unexpected value: {}", val);
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{arg, args_parser, opt};
-use gdal::Dataset;
+use anyhow::format_err;
 use rayon::prelude::*;
 use std::sync::mpsc::Receiver;
 
@@ -22,55 +22,84 @@ fn run() -> Result<()> {
     // Read input raster
     let ds = read_dataset(&args.input)?;
     let transform = transform_from_dataset(&ds);
-    let band = ds.rasterband(1)?;
-    let no_val = band.no_data_value().unwrap_or(f64::NAN);
 
     // Create output dataset
     let out_ds = create_output_raster::<f64>(&args.output, &ds, 1, Some(f64::NAN))?;
 
-    // Calculate processing chunks
-    let chunks_cfg = ChunkConfig::for_dataset(&ds, Some(1..2))?.with_min_data_size(args.chunk_size);
-    let chunks = chunks_cfg.into_par_iter();
+    // Restrict interpolation to the bounding box (on
+    // raster pixels) of the source points, plus the margin
+    let to_pixel = GeoTransform::from_dataset(&ds)
+        .inverse()
+        .map_err(|_| format_err!("input: couldn't invert geo transform"))?;
+    let region = triangulation::pixel_bounds(&triangles, &to_pixel)
+        .ok_or_else(|| format_err!("no source points"))?
+        .buffer(args.margin);
+
+    // Calculate processing chunks. The chunks outside the
+    // region are copied as is.
+    let chunks_cfg = ChunkConfig::for_dataset(&ds, Some(1..2))?
+        .with_min_data_size(args.chunk_size)
+        .with_valid_window(region.window_from_bounds(ds.raster_size()));
+    let chunks = chunks_cfg.par_iter();
     let tracker = Tracker::new("chunks", chunks.len());
 
     // Create channel for writer to receive chunks
     let (s, r) = std::sync::mpsc::channel();
     let writer = { std::thread::spawn(|| writer(r, out_ds, tracker)) };
 
-    // For safe reading in different threads.
-    // Use map_init to initialize data per thread
     let total_filled = chunks
         .map_init(
             || {
-                let ds = read_dataset(&args.input).expect("reader initialization failed");
-                DatasetReader(ds, 1)
+                // No-data values are read as NaN
+                read_dataset(&args.input)
+                    .and_then(|ds| DatasetReader::new(ds, 1))
+                    .map(|reader| NodataOverride::new(reader, args.nodata))
+                    .and_then(NodataToNan::new)
             },
-            |reader, chunk| {
-                let data = reader.read_chunk(chunk)?;
-                Ok::<_, Error>((chunk.1, data))
+            |reader, win| {
+                let reader = reader.as_ref().map_err(|e| format_err!("{:#}", e))?;
+                Ok::<_, Error>((win, reader.read_chunk(win)?))
             },
         )
         .map_with(s, |s, data| {
-            let (y, data) = data?;
+            let (win, data) = data?;
             // Process chunk
-            let mut chunk = (y as isize, data);
-            let count =
-                interpolation::fill_chunk(&mut chunk, no_val, transform, &triangles, args.sibson);
-
-            s.send(chunk)?;
+            let mut chunk = (win.y_offset(), data);
+            let skipped = win.is_skipped();
+            let count = if skipped {
+                0
+            } else {
+                interpolation::fill_chunk(
+                    &mut chunk,
+                    transform,
+                    &triangles,
+                    args.method,
+                    &region,
+                    args.hull_only,
+                )
+            };
+
+            s.send((chunk, skipped))?;
             Ok::<_, Error>(count)
         })
         .try_reduce(|| 0, |a, b| Ok(a + b));
 
-    // Join spawned threads
-    writer.join().expect("writer thread panicked")?;
+    // Join spawned threads; the output is only kept if all
+    // the chunks were processed
+    let out_ds = writer.join().expect("writer thread panicked")?;
+    let total_filled = total_filled?;
+    out_ds.finish()?;
 
-    eprintln!("Filled {} values", total_filled?);
+    eprintln!("Filled {} values", total_filled);
     Ok(())
 }
 
-fn writer(receiver: Receiver<Chunk<f64>>, out_ds: Dataset, progress: Tracker) -> Result<()> {
-    for (y, data) in receiver {
+fn writer(
+    receiver: Receiver<(Chunk<f64>, bool)>,
+    out_ds: OutputRaster,
+    progress: Tracker,
+) -> Result<OutputRaster> {
+    for ((y, data), skipped) in receiver {
         use gdal::raster::Buffer;
         let (ysize, xsize) = data.dim();
         out_ds.rasterband(1)?.write(
@@ -78,9 +107,13 @@ fn writer(receiver: Receiver<Chunk<f64>>, out_ds: Dataset, progress: Tracker) ->
             (xsize, ysize),
             &Buffer::new((xsize, ysize), data.into_raw_vec()),
         )?;
-        progress.increment();
+        if skipped {
+            progress.skip();
+        } else {
+            progress.increment();
+        }
     }
-    Ok(())
+    Ok(out_ds)
 }
 
 /// Program arguments
@@ -95,8 +128,16 @@ pub struct Args {
     pub prop_name: String,
     /// Chunk size to read input raster
     pub chunk_size: usize,
-    /// Sibson smoothness parameter
-    pub sibson: f64,
+    /// Interpolation method
+    pub method: interpolation::Method,
+    /// Margin (in pixels) around the source points to
+    /// interpolate in
+    pub margin: f64,
+    /// Only interpolate inside the convex hull of the source
+    /// points
+    pub hull_only: bool,
+    /// No-data value overriding that of the input
+    pub nodata: Option<f64>,
 }
 
 use clap::value_t;
@@ -125,18 +166,36 @@ fn parse_cmd_line() -> Args {
                 .short("d")
                 .help("Output driver (default: GTIFF)"),
         )
+        .arg(
+            opt!("overwrite")
+                .takes_value(false)
+                .help("Replace the output if it exists"),
+        )
         .arg(
             opt!("property")
                 .short("p")
                 .required(true)
                 .help("Name of property containing z value"),
         )
+        .arg(
+            opt!("method")
+                .help("Interpolation method: farin, barycentric, nn or the default, sibson"),
+        )
         .arg(opt!("sibson").help("Sibson smoothness parameter (default: 0.5)"))
+        .arg(opt!("margin").help(
+            "Fill within this margin (in pixels) of the bounding box of the source points (default: 0)",
+        ))
+        .arg(
+            opt!("hull only")
+                .takes_value(false)
+                .help("Only fill inside the convex hull of the source points"),
+        )
         .arg(
             opt!("chunk size")
                 .short("c")
                 .help("Read chunk size (default: 64k pixels)"),
         )
+        .arg(nodata_arg())
         .get_matches();
 
     let input = value_t!(matches, "input", PathBuf).unwrap_or_else(|e| e.exit());
@@ -144,10 +203,33 @@ fn parse_cmd_line() -> Args {
     let output = value_t!(matches, "output", PathBuf).unwrap_or_else(|e| e.exit());
     let driver = value_t!(matches, "driver", String).unwrap_or_else(|_| String::from("GTIFF"));
     let chunk_size = value_t!(matches, "chunk size", usize).unwrap_or_else(|_| 0x10000);
-    let sibson = value_t!(matches, "sibson", f64).unwrap_or_else(|_| 0.5);
+    let method = {
+        use interpolation::Method::*;
+        let method = value_t!(matches, "method", String).unwrap_or_else(|_| String::from("sibson"));
+        if method != "sibson" && matches.is_present("sibson") {
+            eprintln!("Warning: --sibson is ignored by the {} method", method);
+        }
+        match method.as_str() {
+            "sibson" => Sibson(value_t!(matches, "sibson", f64).unwrap_or_else(|_| 0.5)),
+            "farin" => Farin,
+            "barycentric" => Barycentric,
+            "nn" => NaturalNeighbor,
+            _ => {
+                use clap::{Error, ErrorKind::InvalidValue};
+                Error::with_description(&format!("invalid method: {}", method), InvalidValue).exit()
+            }
+        }
+    };
+    let margin = if matches.is_present("margin") {
+        value_t!(matches, "margin", f64).unwrap_or_else(|e| e.exit())
+    } else {
+        0.
+    };
     let output = OutputArgs {
         path: output,
         driver,
+        overwrite: matches.is_present("overwrite"),
+        cog: false,
     };
     let prop_name = value_t!(matches, "property", String).unwrap_or_else(|e| e.exit());
 
@@ -157,6 +239,9 @@ fn parse_cmd_line() -> Args {
         source,
         prop_name,
         chunk_size,
-        sibson,
+        method,
+        margin,
+        hull_only: matches.is_present("hull only"),
+        nodata: nodata_from_matches(&matches),
     }
 }
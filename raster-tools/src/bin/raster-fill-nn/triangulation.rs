@@ -1,6 +1,7 @@
 use anyhow::{anyhow, bail};
 use raster_tools::{utils::*, *};
 use gdal::vector::LayerAccess;
+use rasters::prelude::{Bounds, BoundsExt, GeoTransform};
 
 #[derive(Clone)]
 pub struct PointWithHeight {
@@ -34,7 +35,7 @@ pub fn get_triangles(args: &super::Args) -> Result<Triangles> {
     let start = Instant::now();
     let ds = read_dataset(&args.source)?;
     let pts = get_points(ds, &args.prop_name)?;
-    let triangles = get_triangulation(pts.clone());
+    let triangles = get_triangulation(pts.clone(), args.method.needs_gradients());
     if triangles.num_triangles() < 1 {
         bail!("triangulation failed");
     }
@@ -49,15 +50,46 @@ pub fn get_triangles(args: &super::Args) -> Result<Triangles> {
 
 pub type Triangulation =
     FloatDelaunayTriangulation<PointWithHeight, DelaunayTreeLocate<[f64; 2]>>;
-pub fn get_triangulation<I: IntoIterator<Item = PointWithHeight>>(pts: I) -> Triangulation {
+
+/// Triangulate the points. The gradients at the points are
+/// only estimated if `gradients` is set (see
+/// [`Method::needs_gradients`]).
+///
+/// [`Method::needs_gradients`]: super::interpolation::Method::needs_gradients
+pub fn get_triangulation<I: IntoIterator<Item = PointWithHeight>>(
+    pts: I,
+    gradients: bool,
+) -> Triangulation {
     let mut tr = FloatDelaunayTriangulation::with_tree_locate();
     for p in pts {
         tr.insert(p);
     }
-    tr.estimate_gradients(&(|v| v.height), &(|v, g| v.gradient = g));
+    if gradients {
+        tr.estimate_gradients(&(|v| v.height), &(|v, g| v.gradient = g));
+    }
     return tr;
 }
 
+/// Bounding box of the points in the pixel coordinates of
+/// a raster (`to_pixel` maps world to pixel coordinates).
+/// Returns `None` if there are no points.
+pub fn pixel_bounds(tr: &Triangulation, to_pixel: &GeoTransform) -> Option<Bounds> {
+    Bounds::from_points(
+        tr.vertices()
+            .map(|v| to_pixel.apply(v.point[0], v.point[1])),
+    )
+}
+
+/// Whether the point (in world coordinates) is inside the
+/// convex hull of the triangulation (including its edges).
+pub fn in_hull(tr: &Triangulation, pt: [f64; 2]) -> bool {
+    !matches!(
+        tr.locate(&pt),
+        PositionInTriangulation::OutsideConvexHull(_)
+            | PositionInTriangulation::NoTriangulationPresent
+    )
+}
+
 pub fn get_points(ds: gdal::Dataset, prop_name: &str) -> Result<Vec<PointWithHeight>> {
     let mut layer = ds.layer(0)?;
     let mut out = vec![];
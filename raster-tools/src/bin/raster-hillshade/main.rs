@@ -0,0 +1,166 @@
+use crate::{arg, args_parser, opt};
+use anyhow::bail;
+use gdal::raster::Buffer;
+
+use raster_tools::{utils::*, *};
+use rasters::focal::hillshade;
+use rasters::prelude::*;
+
+/// The output value of the pixels without a shade
+const NO_DATA: u8 = 0;
+
+// Main function
+raster_tools::sync_main!(run());
+
+fn run() -> Result<()> {
+    // Parse command line args
+    let args = parse_cmd_line();
+
+    // Read input raster; the pixel size is read from the
+    // geo. transform, which must be north aligned
+    let ds = read_dataset(&args.input)?;
+    let transform = GeoTransform::from_dataset(&ds);
+    if !transform.is_axis_aligned(1e-5) {
+        bail!("transform is not north aligned");
+    }
+    let cell_size = (transform.matrix()[(0, 0)], transform.matrix()[(1, 1)]);
+
+    // Create output dataset
+    let out_ds = create_output_raster::<u8>(&args.output, &ds, 1, Some(NO_DATA as f64))?;
+    let out_band = out_ds.rasterband(1)?;
+
+    // The 3x3 window of each pixel is read by padding the
+    // chunks by a row
+    let chunks_cfg = ChunkConfig::for_dataset(&ds, Some(1..2))?
+        .with_min_data_size(args.chunk_size)
+        .with_padding(1);
+    let reader = NodataOverride::new(
+        DatasetReader::new(read_dataset(&args.input)?, 1)?,
+        args.nodata,
+    );
+
+    let tracker = Tracker::new("chunks", chunks_cfg.iter().len());
+    focal_apply(
+        &reader,
+        &chunks_cfg,
+        1,
+        hillshade(cell_size, args.azimuth, args.altitude, args.z_factor),
+        |(x, y), data| {
+            // The shade in [0, 1] is scaled to 1..=255, as 0
+            // is the no-data value
+            let data = data.mapv(|shade| {
+                if shade.is_nan() {
+                    NO_DATA
+                } else {
+                    (1. + 254. * shade).round() as u8
+                }
+            });
+            let (ysize, xsize) = data.dim();
+            out_band.write(
+                (x as isize, y as isize),
+                (xsize, ysize),
+                &Buffer::new((xsize, ysize), data.into_raw_vec()),
+            )?;
+            tracker.increment();
+            Ok(())
+        },
+    )?;
+
+    out_ds.finish()?;
+    Ok(())
+}
+
+/// Program arguments
+pub struct Args {
+    /// Input filename
+    pub input: InputArgs,
+    /// Output filename
+    pub output: OutputArgs,
+    /// Chunk size to read input raster
+    pub chunk_size: usize,
+    /// Azimuth of the sun (degrees clockwise from north)
+    pub azimuth: f64,
+    /// Altitude of the sun (degrees above the horizon)
+    pub altitude: f64,
+    /// Scale of the values of the input
+    pub z_factor: f64,
+    /// No-data value overriding that of the input
+    pub nodata: Option<f64>,
+}
+
+use clap::value_t;
+use std::path::PathBuf;
+fn parse_cmd_line() -> Args {
+    let matches = args_parser!("raster-hillshade")
+        .about("Computes the shaded relief of a DEM using Horn's method.")
+        .arg(
+            arg!("input")
+                .required(true)
+                .help("Input path (raster dataset)"),
+        )
+        .arg(
+            arg!("output")
+                .required(true)
+                .help("Output path (raster dataset)"),
+        )
+        .arg(
+            opt!("driver")
+                .short("d")
+                .help("Output driver (default: GTIFF)"),
+        )
+        .arg(
+            opt!("overwrite")
+                .takes_value(false)
+                .help("Replace the output if it exists"),
+        )
+        .arg(
+            opt!("cog")
+                .takes_value(false)
+                .conflicts_with("driver")
+                .help("Write the output as a Cloud-Optimized GeoTIFF (with overviews)"),
+        )
+        .arg(
+            opt!("chunk size")
+                .short("c")
+                .help("Read chunk size (default: 64k pixels)"),
+        )
+        .arg(
+            opt!("azimuth")
+                .help("Azimuth of the sun in degrees clockwise from north (default: 315)"),
+        )
+        .arg(
+            opt!("altitude").help("Altitude of the sun in degrees above the horizon (default: 45)"),
+        )
+        .arg(
+            opt!("z factor").help(
+                "Scale of the values, eg. to convert them to the units of the CRS (default: 1)",
+            ),
+        )
+        .arg(nodata_arg())
+        .get_matches();
+
+    let input = value_t!(matches, "input", PathBuf).unwrap_or_else(|e| e.exit());
+    let output = value_t!(matches, "output", PathBuf).unwrap_or_else(|e| e.exit());
+    let driver = value_t!(matches, "driver", String).unwrap_or_else(|_| String::from("GTIFF"));
+    let chunk_size = value_t!(matches, "chunk size", usize).unwrap_or_else(|_| 0x10000);
+    let azimuth = value_t!(matches, "azimuth", f64).unwrap_or_else(|_| 315.);
+    let altitude = value_t!(matches, "altitude", f64).unwrap_or_else(|_| 45.);
+    let z_factor = value_t!(matches, "z factor", f64).unwrap_or_else(|_| 1.);
+
+    let output = OutputArgs {
+        path: output,
+        driver,
+        overwrite: matches.is_present("overwrite"),
+        cog: matches.is_present("cog"),
+    };
+
+    Args {
+        input,
+        output,
+        chunk_size,
+        azimuth,
+        altitude,
+        z_factor,
+        nodata: nodata_from_matches(&matches),
+    }
+}
@@ -0,0 +1,98 @@
+/// # Raster-Hist-Dump
+/// Print a histogram stored by `raster-diff --hist` as JSON
+/// or CSV.
+use crate::{arg, args_parser, opt};
+use std::io::Write;
+use std::path::PathBuf;
+
+use raster_tools::{utils::*, *};
+use rasters::histogram::{self, Histogram};
+
+// Main function
+raster_tools::sync_main!(run());
+
+fn run() -> Result<()> {
+    // Parse command line args
+    let args = parse_cmd_line();
+
+    let hist = histogram::read(&args.input)?;
+    if args.csv {
+        let stdout = std::io::stdout();
+        write_csv(std::io::BufWriter::new(stdout.lock()), &hist)
+    } else {
+        print_json(&HistogramOutput::from(&hist))
+    }
+}
+
+/// Write the bins of `hist` as CSV, with a line for the
+/// underflow (without a lower edge) and the overflow
+/// (without an upper edge) before and after the bins.
+fn write_csv<W: Write>(mut w: W, hist: &Histogram<'_>) -> Result<()> {
+    let cfg = hist.config();
+    writeln!(w, "lower,upper,count")?;
+    writeln!(w, ",{},{}", cfg.min(), hist.underflow())?;
+    for (lower, upper, count) in hist.to_bins() {
+        writeln!(w, "{},{},{}", lower, upper, count)?;
+    }
+    writeln!(w, "{},,{}", cfg.max(), hist.overflow())?;
+    Ok(w.flush()?)
+}
+
+/// Program arguments
+pub struct Args {
+    /// Histogram filename
+    pub input: PathBuf,
+    /// Print as CSV instead of JSON
+    pub csv: bool,
+}
+
+use clap::value_t;
+fn parse_cmd_line() -> Args {
+    let matches = args_parser!("raster-hist-dump")
+        .about("Prints a histogram stored by raster-diff.")
+        .arg(
+            arg!("input")
+                .required(true)
+                .help("Histogram path (written by raster-diff --hist)"),
+        )
+        .arg(opt!("format").help("Output format: csv or the default, json"))
+        .get_matches();
+
+    let input = value_t!(matches, "input", PathBuf).unwrap_or_else(|e| e.exit());
+    let csv = {
+        let format = value_t!(matches, "format", String).unwrap_or_else(|_| String::from("json"));
+        if format == "json" {
+            false
+        } else if format == "csv" {
+            true
+        } else {
+            use clap::{Error, ErrorKind::InvalidValue};
+            Error::with_description(&format!("invalid format: {}", format), InvalidValue).exit()
+        }
+    };
+
+    Args { input, csv }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rasters::histogram::Config;
+
+    #[test]
+    fn test_write_csv() -> Result<()> {
+        let cfg = Config::from_min_max_step(0., 1., 0.5);
+        let mut hist = Histogram::new(&cfg);
+        for &val in &[-1., 0.25, 0.5, 0.75, 2.] {
+            hist += val;
+        }
+
+        let mut out = vec![];
+        write_csv(&mut out, &hist)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "lower,upper,count\n,0,1\n0,0.5,1\n0.5,1,2\n1,,1\n"
+        );
+        Ok(())
+    }
+}
@@ -1,25 +1,51 @@
 use super::Chunk;
-use ndarray::Array2;
+use ndarray::{Array2, Array3};
 
-pub type MultiBandChunk<T> = (isize, Vec<Array2<T>>);
+pub type MultiBandChunk<T> = (isize, Array3<T>);
 
-pub fn mask_chunk(input_chunk: &MultiBandChunk<f64>, no_val: f64) -> Chunk<u8> {
-    let (ht, wid) = input_chunk.1[0].dim();
-    let mut mask = Array2::<u8>::zeros((ht, wid));
-    let band_count = input_chunk.1.len();
-
-    let is_data = |x, y| {
-        // For RGB without mask, no data if _all_ bands have no_val
-        if band_count == 3 {
-            let r_band = &input_chunk.1[0];
-            let g_band = &input_chunk.1[1];
-            let b_band = &input_chunk.1[2];
+/// Policy to decide if a pixel has data, from its values in
+/// the bands of the input. A band value is valid if it is
+/// neither NaN, nor the no-data value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaskPolicy {
+    /// Data if any of the bands is valid (eg. RGB without
+    /// an alpha band).
+    AnyBandData,
+    /// Data only if all the bands are valid.
+    AllBandsData,
+    /// Data if the last band is valid.
+    LastBandData,
+    /// Data if the given (1-based) band is non-zero (eg. the
+    /// alpha band of RGBA). The no-data value is not used.
+    AlphaBand(isize),
+}
 
-            !(r_band[(y, x)] == no_val && g_band[(y, x)] == no_val && b_band[(y, x)] == no_val)
+impl MaskPolicy {
+    /// The default policy for a raster with `band_count`
+    /// bands: use the alpha band of RGBA rasters, and
+    /// consider any valid band as data otherwise.
+    pub fn for_band_count(band_count: isize) -> Self {
+        if band_count == 4 {
+            MaskPolicy::AlphaBand(4)
         } else {
-            let val = input_chunk.1[band_count - 1][(y, x)];
+            MaskPolicy::AnyBandData
+        }
+    }
+}
+
+pub fn mask_chunk(input_chunk: &MultiBandChunk<f64>, no_val: f64, policy: MaskPolicy) -> Chunk<u8> {
+    let data = &input_chunk.1;
+    let (band_count, ht, wid) = data.dim();
+    let mut mask = Array2::<u8>::zeros((ht, wid));
 
-            !val.is_nan() && val != no_val
+    let is_valid = |val: f64| !val.is_nan() && val != no_val;
+    let is_data = |x, y| match policy {
+        MaskPolicy::AnyBandData => (0..band_count).any(|b| is_valid(data[(b, y, x)])),
+        MaskPolicy::AllBandsData => (0..band_count).all(|b| is_valid(data[(b, y, x)])),
+        MaskPolicy::LastBandData => is_valid(data[(band_count - 1, y, x)]),
+        MaskPolicy::AlphaBand(band) => {
+            let val = data[(band as usize - 1, y, x)];
+            !val.is_nan() && val != 0.
         }
     };
 
@@ -3,14 +3,17 @@
 /// Expected functionality:
 /// - [ ] Ability to create a mask of valid pixels and non-valid pixels
 use crate::{arg, args_parser, opt};
-use gdal::Dataset;
-use rayon::prelude::*;
 use std::sync::mpsc::Receiver;
+use std::sync::Mutex;
 
 use raster_tools::{utils::*, *};
-use rasters::prelude::{Error, Result, *};
+use rasters::prelude::{Result, *};
 
 mod clipping;
+use clipping::MaskPolicy;
+
+/// Overviews are built until the raster fits in this size
+const OVERVIEW_MIN_SIZE: usize = 256;
 
 // Main function
 raster_tools::sync_main!(run());
@@ -21,62 +24,76 @@ fn run() -> Result<()> {
 
     // Read input raster
     let ds = read_dataset(&args.input)?;
-    let no_val = ds.rasterband(1)?.no_data_value().unwrap_or(0.0);
+    let no_val = args
+        .nodata
+        .or(ds.rasterband(1)?.no_data_value())
+        .unwrap_or(0.0);
     let band_count = ds.raster_count();
+    let policy = args
+        .policy
+        .unwrap_or_else(|| MaskPolicy::for_band_count(band_count));
+    if let MaskPolicy::AlphaBand(band) = policy {
+        if band < 1 || band > band_count {
+            anyhow::bail!(
+                "alpha band {} out of range: input has {} band(s)",
+                band,
+                band_count
+            );
+        }
+    }
+    eprintln!("Mask policy: {:?}", policy);
 
     // Create output dataset
-    let out_ds = create_output_raster::<u8>(&args.output, &ds, 1, None)?;
+    let out_ds =
+        create_output_raster::<u8>(&args.output, &ds, 1, None)?.with_overview_resampling("NEAREST");
     out_ds.rasterband(1)?.set_no_data_value(Some(0.0))?;
 
     // Configure chunking
     let chunks_cfg = ChunkConfig::for_dataset(&ds, Some(1..2))?.with_min_data_size(args.chunk_size);
-    let chunks = chunks_cfg.into_par_iter();
-    let tracker = Tracker::new("chunks", chunks.len());
 
     // Create channel for writer to receive chunks
     let (s, r) = std::sync::mpsc::channel();
-    let writer = { std::thread::spawn(|| writer(r, out_ds, tracker)) };
-
-    // Use map_init to initialize data per thread
-    let total_chunks = chunks
-        .into_par_iter()
-        .map_init(
-            || {
-                let mut readers = Vec::with_capacity(band_count as usize);
-                for i in 1..(band_count + 1) {
-                    let dataset = read_dataset(&args.input).expect("reader initialization failed");
-                    readers.push(DatasetReader(dataset, i));
-                }
-
-                readers
-            },
-            |readers, chunk| {
-                let mut data_vector = Vec::with_capacity(readers.len());
-                for reader in readers {
-                    let data = reader.read_chunk(chunk)?;
-                    data_vector.push(data)
-                }
-
-                Ok::<_, Error>((chunk.1, data_vector))
-            },
-        )
-        .map_with(s, |s, data| {
-            let (y, data_vector) = data?;
-            let chunk = (y as isize, data_vector);
-            let mask: Chunk<u8> = clipping::mask_chunk(&chunk, no_val);
+    let writer = { std::thread::spawn(|| writer(r, out_ds)) };
+
+    // Each thread reads the chunks with its own reader, and
+    // sends the masks to the writer
+    let sender = Mutex::new(s);
+    let total_chunks = process_chunks(
+        &chunks_cfg,
+        || {
+            let dataset = read_dataset(&args.input)?;
+            let s = sender.lock().expect("sender lock poisoned").clone();
+            Ok((MultiBandReader::all_bands(dataset), s))
+        },
+        |(reader, s), count: &mut usize, chunk| {
+            let chunk = (chunk.y_offset(), reader.read_chunk::<f64>(chunk)?);
+            let mask: Chunk<u8> = clipping::mask_chunk(&chunk, no_val, policy);
             s.send(mask)?;
-            Ok::<_, Error>(1)
-        })
-        .try_reduce(|| 0, |a, b| Ok(a + b));
-
-    // Join spawned threads
-    writer.join().expect("writer thread panicked")?;
-
-    eprintln!("Wrote {} chunks", total_chunks?);
+            *count += 1;
+            Ok(())
+        },
+        || 0,
+        |a, b| Ok(a + b),
+    );
+    drop(sender);
+
+    // Join spawned threads; the output is only kept if all
+    // the chunks were processed
+    let out_ds = writer.join().expect("writer thread panicked")?;
+    let total_chunks = total_chunks?;
+    out_ds.finish()?;
+
+    eprintln!("Wrote {} chunks", total_chunks);
+
+    // Build overviews on the (now closed) output
+    if args.overviews {
+        let levels = build_overviews(&args.output.path, "NEAREST", OVERVIEW_MIN_SIZE)?;
+        eprintln!("Built {} overview levels", levels);
+    }
     Ok(())
 }
 
-fn writer(receiver: Receiver<Chunk<u8>>, out_ds: Dataset, progress: Tracker) -> Result<()> {
+fn writer(receiver: Receiver<Chunk<u8>>, out_ds: OutputRaster) -> Result<OutputRaster> {
     for (y, data) in receiver {
         use gdal::raster::Buffer;
         let (ysize, xsize) = data.dim();
@@ -85,9 +102,8 @@ fn writer(receiver: Receiver<Chunk<u8>>, out_ds: Dataset, progress: Tracker) ->
             (xsize, ysize),
             &Buffer::new((xsize, ysize), data.into_raw_vec()),
         )?;
-        progress.increment();
     }
-    Ok(())
+    Ok(out_ds)
 }
 
 /// Program arguments
@@ -98,6 +114,13 @@ pub struct Args {
     pub output: OutputArgs,
     /// Chunk size to read input raster
     pub chunk_size: usize,
+    /// Build internal overviews of the output
+    pub overviews: bool,
+    /// Policy to decide which pixels have data (default
+    /// depends on the band count)
+    pub policy: Option<MaskPolicy>,
+    /// No-data value overriding that of the input
+    pub nodata: Option<f64>,
 }
 
 use clap::value_t;
@@ -120,11 +143,32 @@ fn parse_cmd_line() -> Args {
                 .short("d")
                 .help("Output driver (default: GTIFF)"),
         )
+        .arg(
+            opt!("overwrite")
+                .takes_value(false)
+                .help("Replace the output if it exists"),
+        )
         .arg(
             opt!("chunk size")
                 .short("c")
                 .help("Read chunk size (default: 64k pixels)"),
         )
+        .arg(opt!("policy").help(
+            "Which pixels have data: any (band valid), all (bands valid), last (band valid), \
+             or alpha:<band> (band non-zero). Default: alpha:4 for 4 bands, else any",
+        ))
+        .arg(
+            opt!("overviews")
+                .takes_value(false)
+                .help("Build internal overviews (power-of-two levels) of the output"),
+        )
+        .arg(
+            opt!("cog")
+                .takes_value(false)
+                .conflicts_with_all(&["driver", "overviews"])
+                .help("Write the output as a Cloud-Optimized GeoTIFF (with overviews)"),
+        )
+        .arg(nodata_arg())
         .get_matches();
 
     let input = value_t!(matches, "input", PathBuf).unwrap_or_else(|e| e.exit());
@@ -132,14 +176,37 @@ fn parse_cmd_line() -> Args {
     let driver = value_t!(matches, "driver", String).unwrap_or_else(|_| String::from("GTIFF"));
     let chunk_size = value_t!(matches, "chunk size", usize).unwrap_or_else(|_| 0x10000);
 
+    let overviews = matches.is_present("overviews");
+    let policy = value_t!(matches, "policy", String).ok().map(|policy| {
+        use clap::{Error, ErrorKind::InvalidValue};
+        let invalid =
+            || Error::with_description(&format!("invalid policy: {}", policy), InvalidValue);
+        if policy == "any" {
+            MaskPolicy::AnyBandData
+        } else if policy == "all" {
+            MaskPolicy::AllBandsData
+        } else if policy == "last" {
+            MaskPolicy::LastBandData
+        } else if let Some(band) = policy.strip_prefix("alpha:") {
+            MaskPolicy::AlphaBand(band.parse().unwrap_or_else(|_| invalid().exit()))
+        } else {
+            invalid().exit()
+        }
+    });
+
     let output = OutputArgs {
         path: output,
         driver,
+        overwrite: matches.is_present("overwrite"),
+        cog: matches.is_present("cog"),
     };
 
     Args {
         input,
         output,
         chunk_size,
+        overviews,
+        policy,
+        nodata: nodata_from_matches(&matches),
     }
 }
@@ -0,0 +1,170 @@
+use crate::{arg, args_parser, opt};
+use anyhow::bail;
+use gdal::raster::Buffer;
+
+use raster_tools::{utils::*, *};
+use rasters::focal::{aspect, slope, SlopeUnits};
+use rasters::prelude::*;
+
+// Main function
+raster_tools::sync_main!(run());
+
+fn run() -> Result<()> {
+    // Parse command line args
+    let args = parse_cmd_line();
+
+    // Read input raster; the pixel size is read from the
+    // geo. transform, which must be north aligned
+    let ds = read_dataset(&args.input)?;
+    let transform = GeoTransform::from_dataset(&ds);
+    if !transform.is_axis_aligned(1e-5) {
+        bail!("transform is not north aligned");
+    }
+    let cell_size = (transform.matrix()[(0, 0)], transform.matrix()[(1, 1)]);
+
+    // Create output dataset: the slope, and the aspect (if
+    // requested) in a second band
+    let bands = if args.aspect { 2 } else { 1 };
+    let out_ds = create_output_raster::<f64>(&args.output, &ds, bands, Some(f64::NAN))?;
+
+    // The 3x3 window of each pixel is read by padding the
+    // chunks by a row
+    let chunks_cfg = ChunkConfig::for_dataset(&ds, Some(1..2))?
+        .with_min_data_size(args.chunk_size)
+        .with_padding(1);
+    let reader = NodataOverride::new(
+        DatasetReader::new(read_dataset(&args.input)?, 1)?,
+        args.nodata,
+    );
+
+    let write = |band: isize, tracker: &Tracker| {
+        let out_band = out_ds.rasterband(band)?;
+        let write = move |(x, y): (usize, usize), data: ndarray::Array2<f64>| -> Result<()> {
+            let (ysize, xsize) = data.dim();
+            out_band.write(
+                (x as isize, y as isize),
+                (xsize, ysize),
+                &Buffer::new((xsize, ysize), data.into_raw_vec()),
+            )?;
+            tracker.increment();
+            Ok(())
+        };
+        Ok::<_, Error>(write)
+    };
+
+    let tracker = Tracker::new("chunks", chunks_cfg.iter().len());
+    focal_apply(
+        &reader,
+        &chunks_cfg,
+        1,
+        slope(cell_size, args.units),
+        write(1, &tracker)?,
+    )?;
+    if args.aspect {
+        let tracker = Tracker::new("chunks (aspect)", chunks_cfg.iter().len());
+        focal_apply(
+            &reader,
+            &chunks_cfg,
+            1,
+            aspect(cell_size),
+            write(2, &tracker)?,
+        )?;
+    }
+
+    out_ds.finish()?;
+    Ok(())
+}
+
+/// Program arguments
+pub struct Args {
+    /// Input filename
+    pub input: InputArgs,
+    /// Output filename
+    pub output: OutputArgs,
+    /// Chunk size to read input raster
+    pub chunk_size: usize,
+    /// Units of the slope
+    pub units: SlopeUnits,
+    /// Write the aspect in a second band
+    pub aspect: bool,
+    /// No-data value overriding that of the input
+    pub nodata: Option<f64>,
+}
+
+use clap::value_t;
+use std::path::PathBuf;
+fn parse_cmd_line() -> Args {
+    let matches = args_parser!("raster-slope")
+        .about("Computes the slope (and aspect) of a DEM using Horn's method.")
+        .arg(
+            arg!("input")
+                .required(true)
+                .help("Input path (raster dataset)"),
+        )
+        .arg(
+            arg!("output")
+                .required(true)
+                .help("Output path (raster dataset)"),
+        )
+        .arg(
+            opt!("driver")
+                .short("d")
+                .help("Output driver (default: GTIFF)"),
+        )
+        .arg(
+            opt!("overwrite")
+                .takes_value(false)
+                .help("Replace the output if it exists"),
+        )
+        .arg(
+            opt!("cog")
+                .takes_value(false)
+                .conflicts_with("driver")
+                .help("Write the output as a Cloud-Optimized GeoTIFF (with overviews)"),
+        )
+        .arg(
+            opt!("chunk size")
+                .short("c")
+                .help("Read chunk size (default: 64k pixels)"),
+        )
+        .arg(opt!("units").help("Units of the slope: percent or the default, degrees"))
+        .arg(
+            opt!("aspect")
+                .takes_value(false)
+                .help("Write the aspect (degrees clockwise from north) in a second band"),
+        )
+        .arg(nodata_arg())
+        .get_matches();
+
+    let input = value_t!(matches, "input", PathBuf).unwrap_or_else(|e| e.exit());
+    let output = value_t!(matches, "output", PathBuf).unwrap_or_else(|e| e.exit());
+    let driver = value_t!(matches, "driver", String).unwrap_or_else(|_| String::from("GTIFF"));
+    let chunk_size = value_t!(matches, "chunk size", usize).unwrap_or_else(|_| 0x10000);
+    let units = {
+        let units = value_t!(matches, "units", String).unwrap_or_else(|_| String::from("degrees"));
+        if units == "degrees" {
+            SlopeUnits::Degrees
+        } else if units == "percent" {
+            SlopeUnits::Percent
+        } else {
+            use clap::{Error, ErrorKind::InvalidValue};
+            Error::with_description(&format!("invalid units: {}", units), InvalidValue).exit()
+        }
+    };
+
+    let output = OutputArgs {
+        path: output,
+        driver,
+        overwrite: matches.is_present("overwrite"),
+        cog: matches.is_present("cog"),
+    };
+
+    Args {
+        input,
+        output,
+        chunk_size,
+        units,
+        aspect: matches.is_present("aspect"),
+        nodata: nodata_from_matches(&matches),
+    }
+}
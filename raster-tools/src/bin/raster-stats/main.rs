@@ -1,11 +1,11 @@
-use rayon::prelude::*;
-
 use clap::*;
 
 use anyhow::{anyhow, bail};
-use raster_tools::{utils::*, Result, Tracker, *};
+use raster_tools::{utils::*, Result, *};
 use rasters::prelude::*;
-use gdal::vector::LayerAccess;
+
+mod outputs;
+use outputs::{AreaStats, OutputFormat};
 
 // Main function
 raster_tools::sync_main!(run());
@@ -16,91 +16,246 @@ fn run() -> Result<()> {
 
     // Read input raster
     let ds = &read_dataset(&args.input)?;
-    let transform = transform_from_dataset(&ds);
-    let no_val = ds.rasterband(1)?.no_data_value().unwrap_or(f64::NAN);
+
+    // Validate selected bands
+    let bands = args.bands.clone().unwrap_or_else(|| vec![1]);
+    let band_count = ds.raster_count();
+    for &band in &bands {
+        if band < 1 || band > band_count {
+            bail!(
+                "band {} out of range: input has {} band(s)",
+                band,
+                band_count
+            );
+        }
+    }
 
     use anyhow::*;
-    use nalgebra::*;
+
+    // Reproject polygons to the CRS of the raster
+    let mut polygons = args.polygons.clone();
+    if let Some(srs) = &args.polygon_srs {
+        if let Some(transform) = srs.transform_to(ds)? {
+            for poly in polygons.iter_mut().flatten() {
+                *poly = reproject_polygon(poly, &transform)?;
+            }
+        }
+    }
 
     // Project polygons on raster pixels
     let polygons: Vec<Option<MultiPolygon>> = {
-        let inv = transform
-            .try_inverse()
-            .ok_or_else(|| anyhow!("input: couldn't invert geo transform"))?;
-        args.polygons
+        let to_pixel = GeoTransform::from_dataset(ds)
+            .inverse()
+            .map_err(|_| anyhow!("input: couldn't invert geo transform"))?;
+        polygons
             .iter()
             .map(|poly| {
                 use geo::algorithm::map_coords::MapCoords;
                 poly.as_ref().map(|poly| {
-                    poly.map_coords(|coord| {
-                        let pt = inv.transform_point(&Point2::from_slice(&[coord.x, coord.y]));
-                        let p: Coord = (pt.x, pt.y).into();
-                        p
-                    })
+                    poly.map_coords(|coord| -> Coord { to_pixel.apply(coord.x, coord.y).into() })
                 })
             })
             .collect()
     };
 
-    // Calculate processing chunks
-    let chunks_cfg = ChunkConfig::for_dataset(&ds, Some(1..2))?.with_min_data_size(args.chunk_size);
-    let chunks = chunks_cfg.into_par_iter();
-    let tracker = Tracker::new("chunks", chunks.len());
+    // Calculate processing chunks. If every stat is
+    // restricted to a polygon, only the columns of their
+    // bounding box are read, and chunks outside all of
+    // them are skipped.
+    let mut chunks_cfg = ChunkConfig::for_dataset(&ds, Some(bands.iter().cloned()))?;
+    let mut empty = false;
+    if let Some(bounds) = polygons_bounds(&polygons) {
+        let window = bounds.window_from_bounds(ds.raster_size());
+        let ((x, _), (width, height)) = window;
+        empty = width == 0 || height == 0;
+        chunks_cfg = chunks_cfg
+            .with_x_start(x as usize)
+            .with_x_end(x as usize + width)
+            .with_valid_window(window);
+    }
+    // No pixel to read if the polygons are outside the
+    // raster: the stats are empty. If a row of blocks
+    // doesn't fit in the memory budget (eg. a wide tiled
+    // raster), read a row of blocks per chunk anyway.
+    let chunks_cfg = if empty {
+        None
+    } else {
+        let budget = chunks_cfg.clone().with_memory_budget(
+            args.memory,
+            bands.len(),
+            std::mem::size_of::<f64>(),
+        );
+        Some(budget.unwrap_or_else(|e| {
+            eprintln!("Warning: {:#}; reading a row of blocks per chunk", e);
+            chunks_cfg
+        }))
+    };
+    let ctx = Inputs {
+        args: &args,
+        bands: &bands,
+        polygons: &polygons,
+    };
+    if args.categorical {
+        let stats = ctx.compute(chunks_cfg.as_ref(), |stats: &mut CategoryStats, val, _| {
+            stats.add_value(val)
+        })?;
+        ctx.print(&stats, outputs::write_category_csv)?;
+    } else if args.area {
+        let areas = RowAreaTable::from_dataset(ds)?;
+        let stats = ctx.compute(chunks_cfg.as_ref(), |stats: &mut AreaStats, _, row| {
+            stats.add_pixel(areas.area(row));
+            Ok(())
+        })?;
+        ctx.print(&stats, outputs::write_area_csv)?;
+    } else {
+        let stats = ctx.compute(chunks_cfg.as_ref(), |stats: &mut PixelStats, val, _| {
+            *stats += val;
+            Ok(())
+        })?;
+        ctx.print(&stats, outputs::write_csv)?;
+    }
+    Ok(())
+}
+
+/// Inputs required to compute the stats of each chunk.
+struct Inputs<'a> {
+    args: &'a Args,
+    bands: &'a [isize],
+    polygons: &'a [Option<MultiPolygon>],
+}
 
-    let init = || vec![PixelStats::default(); polygons.len()];
+/// Stats of each selected band, and each polygon.
+type BandStats<S> = Vec<Vec<S>>;
+
+impl<'a> Inputs<'a> {
+    /// Accumulate the stats of the valid pixels in the
+    /// chunks, using `add` to add a value (along with the row
+    /// of its pixel) to a stat. Without chunks (`None`), the
+    /// stats are empty.
+    fn compute<S, F>(&self, chunks_cfg: Option<&ChunkConfig>, add: F) -> Result<BandStats<S>>
+    where
+        S: Default + Clone + Send + for<'b> std::ops::AddAssign<&'b S>,
+        F: Fn(&mut S, f64, usize) -> Result<()> + Sync,
+    {
+        let Inputs {
+            args,
+            bands,
+            polygons,
+        } = *self;
+
+        let init = || vec![vec![S::default(); polygons.len()]; bands.len()];
+        let chunks_cfg = match chunks_cfg {
+            Some(chunks_cfg) => chunks_cfg,
+            None => return Ok(init()),
+        };
+
+        // The chunks are read into buffers (one per band)
+        // sized to the largest chunk, re-used across the
+        // chunks of each thread.
+        let max_len = chunks_cfg
+            .iter()
+            .map(|win| win.width() * win.height())
+            .max()
+            .unwrap_or(0);
 
-    let stats = chunks
-        .map_init(
+        process_chunks(
+            chunks_cfg,
             || {
-                DatasetReader(
-                    read_dataset(&args.input).expect("reader initialization failed"),
-                    1,
-                )
+                let rd = read_dataset(&args.input).and_then(|ds| DatasetReader::new(ds, 1))?;
+                let bufs = vec![(vec![0.; max_len], vec![false; max_len]); bands.len()];
+                Ok((rd, bufs))
             },
-            |rd, chunk| (rd.read_chunk::<f64>(chunk), chunk.1),
-        )
-        .try_fold(init, |mut stats, (data, y)| {
-            let arr = data?;
-            let (rows, cols) = arr.dim();
-            for i in 0..rows {
-                for j in 0..cols {
-                    let val = arr[(i, j)];
-                    if val == no_val || val.is_nan() {
-                        continue;
-                    }
+            |(rd, bufs), stats: &mut BandStats<S>, chunk| {
+                let ((x, y), size) = chunk.raster_window();
+                let mut arrs = Vec::with_capacity(bands.len());
+                for (&band, (data, valid)) in bands.iter().zip(bufs.iter_mut()) {
+                    let shape = (size.1, size.0);
+                    let len = size.0 * size.1;
+                    let mut data = ArrayViewMut2::from_shape(shape, &mut data[..len])?;
+                    let mut valid = ArrayViewMut2::from_shape(shape, &mut valid[..len])?;
+                    let band = NodataOverride::new(rd.dataset().rasterband(band)?, args.nodata);
+                    band.read_into_array_masked(data.view_mut(), valid.view_mut(), (x, y), size)?;
+                    arrs.push((data, valid));
+                }
+
+                // Pixels of the chunk inside each polygon
+                let masks = polygons
+                    .iter()
+                    .map(|poly| {
+                        poly.as_ref()
+                            .map(|poly| rasterize_polygon(poly, ((x, y), size)))
+                            .transpose()
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                for i in 0..size.1 {
+                    for j in 0..size.0 {
+                        if !arrs.iter().any(|(_, valid)| valid[(i, j)]) {
+                            continue;
+                        }
 
-                    use geo::algorithm::contains::Contains;
-                    use geo::Point;
-                    let pt = Point::new(j as f64 + 0.5, y as f64 + i as f64 + 0.5);
-                    for (k, poly) in polygons.iter().enumerate() {
-                        if let Some(poly) = &poly {
-                            if !poly.contains(&pt) {
-                                continue;
+                        for (k, mask) in masks.iter().enumerate() {
+                            if let Some(mask) = mask {
+                                if !mask[(i, j)] {
+                                    continue;
+                                }
+                            }
+                            for ((arr, valid), band_stats) in arrs.iter().zip(stats.iter_mut()) {
+                                if valid[(i, j)] {
+                                    add(&mut band_stats[k], arr[(i, j)], y as usize + i)?;
+                                }
                             }
                         }
-                        stats[k] += val;
                     }
                 }
-            }
-            tracker.increment();
-            Ok(stats)
-        })
-        .try_reduce(init, |mut acc_1, acc_2| {
-            for (i, acc) in acc_1.iter_mut().enumerate() {
-                *acc += &acc_2[i];
-            }
-            Ok(acc_1)
-        })?;
+                Ok(())
+            },
+            init,
+            |mut acc_1, acc_2| {
+                for (band_acc_1, band_acc_2) in acc_1.iter_mut().zip(&acc_2) {
+                    for (acc, other) in band_acc_1.iter_mut().zip(band_acc_2) {
+                        *acc += other;
+                    }
+                }
+                Ok(acc_1)
+            },
+        )
+    }
 
-    print_json(&stats)?;
-    Ok(())
+    /// Print the stats in the requested format. With a band
+    /// selection, the JSON output is a map from the band
+    /// index to the stats of the band.
+    fn print<S, W>(&self, stats: &BandStats<S>, write_csv: W) -> Result<()>
+    where
+        S: serde::Serialize,
+        W: FnOnce(
+            std::io::BufWriter<std::io::Stdout>,
+            &[Option<u64>],
+            &[isize],
+            &[Vec<S>],
+        ) -> Result<()>,
+    {
+        match self.args.format {
+            OutputFormat::Json => match &self.args.bands {
+                Some(bands) => print_json(
+                    &bands
+                        .iter()
+                        .zip(stats)
+                        .collect::<std::collections::BTreeMap<_, _>>(),
+                ),
+                None => print_json(&stats[0]),
+            },
+            OutputFormat::Csv => {
+                let writer = std::io::BufWriter::new(std::io::stdout());
+                write_csv(writer, &self.args.fids, self.bands, stats)
+            }
+        }
+    }
 }
 
-use std::{
-    convert::TryInto,
-    path::{Path, PathBuf},
-};
 use geo::{Coord, MultiPolygon};
+use ndarray::ArrayViewMut2;
+use std::path::PathBuf;
 
 /// Program arguments
 pub struct Args {
@@ -108,29 +263,38 @@ pub struct Args {
     input: PathBuf,
     /// Polygon to restrict compute to
     polygons: Vec<Option<geo::MultiPolygon<f64>>>,
-    /// Chunk size to read input raster
-    chunk_size: usize,
-}
-
-fn read_polygons(path: &Path) -> Result<Vec<Option<geo::MultiPolygon<f64>>>> {
-    let ds = read_dataset(path)?;
-    let mut layer = ds.layer(0)?;
-    layer
-        .features()
-        .map(|feature| -> Result<_> {
-            Some(multipoly_from_wkt(&feature.geometry().wkt()?)).transpose()
-        })
-        .collect()
+    /// FIDs of the polygons (if read from a layer)
+    fids: Vec<Option<u64>>,
+    /// CRS of the polygons, if not that of the raster
+    polygon_srs: Option<PolygonSrs>,
+    /// Bands to compute stats of (default: band 1)
+    bands: Option<Vec<isize>>,
+    /// Memory budget (bytes) for each chunk
+    memory: usize,
+    /// Output format
+    format: OutputFormat,
+    /// Compute class counts instead of moments
+    categorical: bool,
+    /// Compute the area of the valid pixels instead of
+    /// moments
+    area: bool,
+    /// No-data value overriding that of the input
+    nodata: Option<f64>,
 }
 
-fn multipoly_from_wkt(wkt: &str) -> Result<geo::MultiPolygon<f64>> {
-    let geom = gdal::vector::Geometry::from_wkt(wkt)?.try_into()?;
-    use geo::Geometry::{MultiPolygon, Polygon};
-    Ok(match geom {
-        Polygon(p) => p.into(),
-        MultiPolygon(p) => p,
-        _ => bail!("polygon WKT is not a (multi)-polygon"),
-    })
+/// Union of the bounding boxes of the polygons. Returns
+/// `None` if any of the polygons is unrestricted.
+fn polygons_bounds(polygons: &[Option<MultiPolygon>]) -> Option<Bounds> {
+    use geo::algorithm::bounding_rect::BoundingRect;
+    let mut union: Option<Bounds> = None;
+    for poly in polygons {
+        let rect = match poly.as_ref()?.bounding_rect() {
+            Some(rect) => rect,
+            None => continue,
+        };
+        union = Some(union.map_or(rect, |acc| acc.union(&rect)));
+    }
+    Some(union.unwrap_or_else(|| Bounds::new((0., 0.), (0., 0.))))
 }
 
 fn parse_cmd_line() -> Args {
@@ -149,35 +313,95 @@ fn parse_cmd_line() -> Args {
                 .help("Region to restrict to (Polygon or MultiPolygon WKT)"),
         )
         .arg(opt!("polygons file").help("Path to polygons (vector dataset)"))
+        .arg(opt!("polygon srs").help(
+            "EPSG code of the CRS of the polygons (default: CRS of the polygons file, or the raster)",
+        ))
+        .arg(opt!("bands").help("Comma-separated list of bands (default: 1)"))
+        .arg(opt!("format").help("Output format: csv or the default, json"))
+        .arg(
+            opt!("categorical")
+                .takes_value(false)
+                .help("Compute class counts of an integer valued raster"),
+        )
+        .arg(
+            opt!("area")
+                .takes_value(false)
+                .conflicts_with("categorical")
+                .help("Compute the area (in square meters) of the valid pixels"),
+        )
         .arg(
-            opt!("chunk size")
-                .short("c")
-                .help("Read chunk size (default: 64k pixels)"),
+            opt!("memory")
+                .short("m")
+                .help("Memory budget per read chunk (default: 64M bytes; min: a row of blocks)"),
         )
+        .arg(nodata_arg())
         .get_matches();
 
     let input = value_t!(matches, "input", PathBuf).unwrap_or_else(|e| e.exit());
-    let chunk_size = value_t!(matches, "chunk size", usize).unwrap_or_else(|_| 0x10000);
+    let memory = value_t!(matches, "memory", usize).unwrap_or_else(|_| 0x4000000);
+
+    let bands = value_t!(matches, "bands", String).ok().map(|bands| {
+        bands
+            .split(',')
+            .map(|band| band.trim().parse::<isize>())
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap_or_else(|e| {
+                Error::with_description(&format!("invalid bands {}: {}", bands, e), InvalidValue)
+                    .exit()
+            })
+    });
+
+    let format = {
+        let format = value_t!(matches, "format", String).unwrap_or_else(|_| String::from("json"));
+        if format == "json" {
+            OutputFormat::Json
+        } else if format == "csv" {
+            OutputFormat::Csv
+        } else {
+            Error::with_description(&format!("invalid format: {}", format), InvalidValue).exit()
+        }
+    };
+
+    let polygon_srs = value_t!(matches, "polygon srs", u32).ok().map(|code| {
+        PolygonSrs::from_epsg(code).unwrap_or_else(|e| {
+            Error::with_description(
+                &format!("invalid polygon SRS {}: {}", code, e),
+                InvalidValue,
+            )
+            .exit()
+        })
+    });
 
-    let polygons = if let Some(wkt) = value_t!(matches, "polygon", String).ok() {
-        vec![Some(multipoly_from_wkt(&wkt).unwrap_or_else(|e| {
+    let (fids, polygons, polygon_srs) = if let Some(wkt) = value_t!(matches, "polygon", String).ok()
+    {
+        let poly = multipoly_from_wkt(&wkt).unwrap_or_else(|e| {
             Error::with_description(&format!("cannot parse input WKT: {}", e), InvalidValue).exit()
-        }))]
+        });
+        (vec![None], vec![Some(poly)], polygon_srs)
     } else if let Some(path) = value_t!(matches, "polygons file", PathBuf).ok() {
-        read_polygons(&path).unwrap_or_else(|e| {
+        let (polygons, srs) = read_polygons(&path).unwrap_or_else(|e| {
             Error::with_description(
                 &format!("reading polygons in {}: {}", path.display(), e),
                 InvalidValue,
             )
             .exit()
-        })
+        });
+        let (fids, polygons) = polygons.into_iter().unzip();
+        (fids, polygons, polygon_srs.or(srs))
     } else {
-        vec![None]
+        (vec![None], vec![None], None)
     };
 
     Args {
         input,
-        chunk_size,
+        bands,
+        memory,
         polygons,
+        fids,
+        polygon_srs,
+        format,
+        categorical: matches.is_present("categorical"),
+        area: matches.is_present("area"),
+        nodata: nodata_from_matches(&matches),
     }
 }
@@ -1,39 +1,178 @@
-use rasters::stats::PixelStats;
+use rasters::stats::{CategoryStats, PixelStats};
 use serde_derive::Serialize;
-use std::ops::AddAssign;
+use std::io::Write;
 
-#[derive(Serialize, Clone)]
-pub struct RasterDiffOutput {
-    pub pix_area_1: f64,
-    pub pix_area_2: f64,
-    pub stats: RasterDiffStats,
+use raster_tools::Result;
+
+/// Format to print the stats in.
+pub enum OutputFormat {
+    Json,
+    Csv,
 }
 
-#[derive(Serialize, Clone, Default)]
-pub struct RasterDiffStats {
-    count: usize,
-    first: PixelStats,
-    second: PixelStats,
-    diff: PixelStats,
-    abs_diff: PixelStats,
+/// Write the stats as CSV, with a header and one line per
+/// polygon and band. The first column is the FID of the
+/// polygon in the input layer (empty if not read from a
+/// layer). The stats of polygons that cover no pixels are
+/// left empty.
+pub fn write_csv<W: Write>(
+    mut w: W,
+    fids: &[Option<u64>],
+    bands: &[isize],
+    stats: &[Vec<PixelStats>],
+) -> Result<()> {
+    writeln!(w, "fid,band,count,min,max,mean,std")?;
+    for (band, band_stats) in bands.iter().zip(stats) {
+        for (fid, stats) in fids.iter().zip(band_stats) {
+            write_csv_line(&mut w, *fid, *band, stats)?;
+        }
+    }
+    Ok(w.flush()?)
 }
-impl AddAssign<(f64, f64)> for RasterDiffStats {
-    fn add_assign(&mut self, other: (f64, f64)) {
+
+fn write_csv_line<W: Write>(
+    w: &mut W,
+    fid: Option<u64>,
+    band: isize,
+    stats: &PixelStats,
+) -> Result<()> {
+    if let Some(fid) = fid {
+        write!(w, "{}", fid)?;
+    }
+    write!(w, ",{},{}", band, stats.count())?;
+    if stats.count() > 0. {
+        writeln!(
+            w,
+            ",{},{},{},{}",
+            stats.min(),
+            stats.max(),
+            stats.mean(),
+            stats.std_deviation()
+        )?;
+    } else {
+        writeln!(w, ",,,,")?;
+    }
+    Ok(())
+}
+
+/// Write the class counts as CSV, in the same layout as
+/// [`write_csv`]. The mode of polygons that cover no pixels
+/// is left empty.
+pub fn write_category_csv<W: Write>(
+    mut w: W,
+    fids: &[Option<u64>],
+    bands: &[isize],
+    stats: &[Vec<CategoryStats>],
+) -> Result<()> {
+    writeln!(w, "fid,band,count,distinct,mode")?;
+    for (band, band_stats) in bands.iter().zip(stats) {
+        for (fid, stats) in fids.iter().zip(band_stats) {
+            if let Some(fid) = fid {
+                write!(w, "{}", fid)?;
+            }
+            write!(w, ",{},{},{},", band, stats.count(), stats.distinct())?;
+            if let Some(mode) = stats.mode() {
+                write!(w, "{}", mode)?;
+            }
+            writeln!(w)?;
+        }
+    }
+    Ok(w.flush()?)
+}
+
+/// Number of valid pixels, and their area (in square meters;
+/// see `RowAreaTable`).
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct AreaStats {
+    count: u64,
+    area: f64,
+}
+
+impl AreaStats {
+    /// Count a pixel of the given area.
+    pub fn add_pixel(&mut self, area: f64) {
         self.count += 1;
-        self.first += other.0;
-        self.second += other.1;
-        let diff = other.1 - other.0;
-        self.diff += diff;
-        self.abs_diff += diff.abs();
+        self.area += area;
     }
 }
 
-impl AddAssign for RasterDiffStats {
-    fn add_assign(&mut self, other: RasterDiffStats) {
+impl std::ops::AddAssign<&AreaStats> for AreaStats {
+    fn add_assign(&mut self, other: &AreaStats) {
         self.count += other.count;
-        self.first += other.first;
-        self.second += other.second;
-        self.diff += other.diff;
-        self.abs_diff += other.abs_diff;
+        self.area += other.area;
+    }
+}
+
+/// Write the areas as CSV, in the same layout as
+/// [`write_csv`].
+pub fn write_area_csv<W: Write>(
+    mut w: W,
+    fids: &[Option<u64>],
+    bands: &[isize],
+    stats: &[Vec<AreaStats>],
+) -> Result<()> {
+    writeln!(w, "fid,band,count,area")?;
+    for (band, band_stats) in bands.iter().zip(stats) {
+        for (fid, stats) in fids.iter().zip(band_stats) {
+            if let Some(fid) = fid {
+                write!(w, "{}", fid)?;
+            }
+            writeln!(w, ",{},{},{}", band, stats.count, stats.area)?;
+        }
+    }
+    Ok(w.flush()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_csv() -> Result<()> {
+        let mut stats = vec![PixelStats::default(); 2];
+        stats[0] += 1.;
+        stats[0] += 3.;
+
+        let mut out = vec![];
+        write_csv(&mut out, &[Some(7), None], &[2], &[stats])?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "fid,band,count,min,max,mean,std\n7,2,2,1,3,2,1\n,2,0,,,,\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_category_csv() -> Result<()> {
+        let mut stats = vec![CategoryStats::default(); 2];
+        stats[0] += 4;
+        stats[0] += 4;
+        stats[0] += 1;
+
+        let mut out = vec![];
+        write_category_csv(&mut out, &[Some(3), Some(5)], &[1], &[stats])?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "fid,band,count,distinct,mode\n3,1,3,2,4\n5,1,0,0,\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_area_csv() -> Result<()> {
+        let mut stats = vec![AreaStats::default(); 2];
+        stats[0].add_pixel(2.5);
+        stats[0].add_pixel(1.5);
+        let mut other = AreaStats::default();
+        other.add_pixel(0.5);
+        stats[0] += &other;
+
+        let mut out = vec![];
+        write_area_csv(&mut out, &[Some(3), None], &[1], &[stats])?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "fid,band,count,area\n3,1,3,4.5\n,1,0,0\n"
+        );
+        Ok(())
     }
 }
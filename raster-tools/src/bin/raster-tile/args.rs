@@ -1,5 +1,7 @@
+use crate::tiling::dem::{ColorRamp, Resample, TileFormat};
 use crate::{arg, args_parser, opt};
 use clap::value_t;
+use raster_tools::utils::{nodata_arg, nodata_from_matches};
 use std::path::PathBuf;
 
 /// Program arguments
@@ -14,10 +16,22 @@ pub struct Args {
     pub output: PathBuf,
     /// Tile size for output,
     pub tile_size: usize,
+    /// Resampling to scale down tiles
+    pub resample: Resample,
+    /// Warp input to a north-aligned web mercator grid
+    pub warp: bool,
+    /// Format of the tiles written
+    pub tile_format: TileFormat,
+    /// Only report the extent of the pyramid
+    pub dry_run: bool,
+    /// URL of the output directory, for the TileJSON
+    pub tile_url: String,
+    /// No-data value overriding that of the input
+    pub nodata: Option<f64>,
 }
 
 pub fn parse_cmd_line() -> Args {
-    use clap::ErrorKind::InvalidValue;
+    use clap::ErrorKind::{ArgumentConflict, InvalidValue};
     use clap::*;
     let matches = args_parser!("raster-tile")
         .about("Create EPSG 3857 tiles.")
@@ -39,6 +53,29 @@ pub fn parse_cmd_line() -> Args {
                 .help("Read chunk size (default: 64k pixels)"),
         )
         .arg(opt!("tile size").help("Read tile size (default: 256 pixels)"))
+        .arg(
+            opt!("resample")
+                .help("Resampling to scale down tiles: bilinear, cubic, mode (categorical) or the default, average"),
+        )
+        .arg(opt!("tile format").help("Tile format: png or the default, bin"))
+        .arg(
+            opt!("colormap")
+                .help("Color ramp for png tiles (file with one 'r g b' color per line)"),
+        )
+        .arg(
+            opt!("warp")
+                .takes_value(false)
+                .help("Warp input to a north-aligned grid (eg. for rotated rasters)"),
+        )
+        .arg(opt!("tile url").help(
+            "URL of the output directory, to list the tiles in tilejson.json (default: relative)",
+        ))
+        .arg(
+            opt!("dry run")
+                .takes_value(false)
+                .help("Print the zoom levels and tiles (as JSON) without writing them"),
+        )
+        .arg(nodata_arg())
         .get_matches();
 
     let input = value_t!(matches, "input", PathBuf).unwrap_or_else(|e| e.exit());
@@ -56,11 +93,60 @@ pub fn parse_cmd_line() -> Args {
         .exit();
     }
 
+    let resample = {
+        let resample =
+            value_t!(matches, "resample", String).unwrap_or_else(|_| String::from("average"));
+        if resample == "average" {
+            Resample::Average
+        } else if resample == "bilinear" {
+            Resample::Bilinear
+        } else if resample == "cubic" {
+            Resample::Cubic
+        } else if resample == "mode" {
+            Resample::Mode
+        } else {
+            Error::with_description(&format!("invalid resample: {}", resample), InvalidValue).exit()
+        }
+    };
+
+    let tile_format = {
+        let format =
+            value_t!(matches, "tile format", String).unwrap_or_else(|_| String::from("bin"));
+        let colormap = value_t!(matches, "colormap", PathBuf).ok();
+        if format == "bin" && colormap.is_none() {
+            TileFormat::Bin
+        } else if format == "png" {
+            TileFormat::Png(colormap.map(|path| {
+                ColorRamp::from_file(&path).unwrap_or_else(|e| {
+                    Error::with_description(&format!("colormap: {:#}", e), InvalidValue).exit()
+                })
+            }))
+        } else if format == "bin" {
+            Error::with_description("colormap requires png tiles", ArgumentConflict).exit()
+        } else {
+            Error::with_description(&format!("invalid tile format: {}", format), InvalidValue)
+                .exit()
+        }
+    };
+
+    // The tiles are listed relative to the URL
+    let tile_url = match value_t!(matches, "tile url", String) {
+        Ok(url) if !url.is_empty() && !url.ends_with('/') => format!("{}/", url),
+        Ok(url) => url,
+        Err(_) => String::new(),
+    };
+
     Args {
         input,
         min_zoom,
         max_zoom,
         output,
         tile_size,
+        resample,
+        warp: matches.is_present("warp"),
+        tile_format,
+        dry_run: matches.is_present("dry run"),
+        tile_url,
+        nodata: nodata_from_matches(&matches),
     }
 }
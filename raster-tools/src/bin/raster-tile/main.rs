@@ -8,7 +8,14 @@ fn run() -> Result<()> {
     // Parse command line
     let args = parse_cmd_line();
 
-    let ds = read_dataset(&args.input)?;
+    // Optionally warp the input, and tile the warped grid
+    let input = if args.warp {
+        tiling::warp::warp_to_web_mercator(&args.input)?
+    } else {
+        args.input.clone()
+    };
+
+    let ds = read_dataset(&input)?;
     let cfg = tiling::Config::for_raster(&ds, args.tile_size)?;
 
     let min_zoom = args.min_zoom.unwrap_or_else(|| cfg.min_zoom());
@@ -17,16 +24,86 @@ fn run() -> Result<()> {
     let max_zoom = args.max_zoom.unwrap_or_else(|| cfg.max_zoom());
     eprintln!("max zoom: {}", max_zoom);
 
-    let index = construct_base(max_zoom, min_zoom, &args, &cfg)?;
+    if args.dry_run {
+        return print_json(&DryRunReport::new(min_zoom, max_zoom, &args, &cfg));
+    }
+
+    // Categorical rasters are tiled as (integer) classes
+    let mut index = if args.resample == Resample::Mode {
+        construct_base::<i32>(max_zoom, min_zoom, &input, &args, &cfg)?
+    } else {
+        construct_base::<f64>(max_zoom, min_zoom, &input, &args, &cfg)?
+    };
+    index.set_raster(GeoTransform::from_dataset(&ds), cfg.wm_bounds());
     write_json(&args.output.join("index.json"), &index)?;
 
+    let tilejson = cfg.tilejson(
+        min_zoom,
+        max_zoom,
+        &args.tile_url,
+        args.tile_format.extension(),
+    );
+    write_json(&args.output.join("tilejson.json"), &tilejson)?;
+
     Ok(())
 }
 
 use args::Args;
+use std::path::Path;
 use tiling::dem::*;
 use tiling::Config;
-fn construct_base(zoom: usize, min_zoom: usize, args: &Args, cfg: &Config) -> Result<Index> {
+
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+
+/// Extent of the pyramid to be written (see `--dry-run`).
+#[derive(Serialize)]
+struct DryRunReport {
+    min_zoom: usize,
+    max_zoom: usize,
+    /// Tiles at each zoom level
+    zooms: BTreeMap<usize, ZoomReport>,
+    total_tiles: usize,
+    /// Estimated size of the tiles (see
+    /// `TileFormat::estimated_tile_bytes`)
+    estimated_bytes: usize,
+}
+
+#[derive(Serialize)]
+struct ZoomReport {
+    /// Tile indices `[left, top, right, bottom]` (right and
+    /// bottom exclusive)
+    tile_bounds: [usize; 4],
+    tiles: usize,
+}
+
+impl DryRunReport {
+    fn new(min_zoom: usize, max_zoom: usize, args: &Args, cfg: &Config) -> Self {
+        let zooms: BTreeMap<_, _> = (min_zoom..=max_zoom)
+            .map(|zoom| {
+                let [left, top, right, bot] = cfg.tile_index_bounds(zoom);
+                let tiles = (right - left) * (bot - top);
+                let tile_bounds = [left, top, right, bot];
+                (zoom, ZoomReport { tile_bounds, tiles })
+            })
+            .collect();
+        let total_tiles = zooms.values().map(|zoom| zoom.tiles).sum();
+        DryRunReport {
+            min_zoom,
+            max_zoom,
+            zooms,
+            total_tiles,
+            estimated_bytes: total_tiles * args.tile_format.estimated_tile_bytes(args.tile_size),
+        }
+    }
+}
+fn construct_base<T: TileValue>(
+    zoom: usize,
+    min_zoom: usize,
+    input: &Path,
+    args: &Args,
+    cfg: &Config,
+) -> Result<Index> {
     let [left, top, right, bot] = cfg.tile_index_bounds(zoom);
     eprintln!("Constructing base of pyramid @ z={}...", zoom);
     // eprintln!("    lt tile coords: {},{}", left, top);
@@ -37,24 +114,24 @@ fn construct_base(zoom: usize, min_zoom: usize, args: &Args, cfg: &Config) -> Re
     use rayon::prelude::*;
     use tiling::dem::*;
 
-    let write_update_idx = |map: &mut Index, ts: &TileSet| -> Result<()> {
-        let idx = ts.write(&args.output)?;
+    let write_update_idx = |map: &mut Index, ts: &TileSet<T>| -> Result<()> {
+        let idx = ts.write(&args.output, &args.tile_format)?;
         map.update_index(ts.zoom(), idx);
         Ok(())
     };
 
-    let reducer = |acc: &mut (Vec<TileSet>, _), data| -> Result<_> {
+    let reducer = |acc: &mut (Vec<TileSet<T>>, _), data| -> Result<_> {
         let sets = &mut acc.0;
         let map = &mut acc.1;
 
         // let (mut sets, mut map) = acc;
 
-        let mut ts: TileSet = data?;
+        let mut ts: TileSet<T> = data?;
         write_update_idx(map, &ts)?;
 
         while let Some(top) = sets.pop() {
             if ts.can_scale_down_with_top() && ts.zoom() == top.zoom() && ts.zoom() > min_zoom {
-                ts.scale_down_with_top(Some(top));
+                ts.scale_down_with_top(Some(top), args.resample);
                 write_update_idx(map, &ts)?;
             } else {
                 sets.push(top);
@@ -65,8 +142,8 @@ fn construct_base(zoom: usize, min_zoom: usize, args: &Args, cfg: &Config) -> Re
         Ok(())
     };
 
-    let ds = read_dataset(&args.input).expect("input dataset");
-    let no_val = ds.rasterband(1)?.no_data_value();
+    let ds = read_dataset(input).expect("input dataset");
+    let no_val = args.nodata.or(ds.rasterband(1)?.no_data_value());
     let size = ds.raster_size();
 
     let chunks = (top..bot).into_par_iter();
@@ -76,8 +153,9 @@ fn construct_base(zoom: usize, min_zoom: usize, args: &Args, cfg: &Config) -> Re
         .into_par_iter()
         .map_init(
             || {
-                let ds = read_dataset(&args.input).expect("input dataset");
-                DatasetReader(ds, 1)
+                read_dataset(input)
+                    .and_then(|ds| DatasetReader::new(ds, 1))
+                    .expect("input dataset")
             },
             |reader, y| -> Result<_> {
                 let pix_bounds = proc.get_pix_bounds(y, &cfg);
@@ -95,12 +173,20 @@ fn construct_base(zoom: usize, min_zoom: usize, args: &Args, cfg: &Config) -> Re
                     let pix = &mut tiles[tx][(tpy, tpx)];
                     let val = data[(py, px)];
                     if !val.is_nan() && (no_val.is_none() || val != no_val.unwrap()) {
-                        if pix.1.is_nan() {
-                            pix.1 = mu;
+                        if args.resample == Resample::Mode {
+                            // The class of the input pixel
+                            // overlapping the most
+                            if pix.1.is_nan() || mu > pix.1 {
+                                *pix = (val, mu);
+                            }
                         } else {
-                            pix.1 += mu;
+                            if pix.1.is_nan() {
+                                pix.1 = mu;
+                            } else {
+                                pix.1 += mu;
+                            }
+                            pix.0 += mu * val;
                         }
-                        pix.0 += mu * data[(py, px)];
                     }
                 });
 
@@ -111,7 +197,7 @@ fn construct_base(zoom: usize, min_zoom: usize, args: &Args, cfg: &Config) -> Re
                     tiles
                         .into_iter()
                         .zip(left..right)
-                        .map(|(tile, x)| Tile::from_aggregate(tile, (x, y))),
+                        .map(|(tile, x)| Tile::from_aggregate(tile, (x, y), args.resample)),
                 );
 
                 Ok(tileset)
@@ -135,10 +221,10 @@ fn construct_base(zoom: usize, min_zoom: usize, args: &Args, cfg: &Config) -> Re
     let (tss, mut idx) = out;
 
     // Final left-to-right scan
-    let mut sets: Vec<TileSet> = vec![];
+    let mut sets: Vec<TileSet<T>> = vec![];
     for mut ts in tss {
         while ts.can_scale_down_with_top() && ts.zoom() > min_zoom {
-            ts.scale_down_with_top(sets.pop());
+            ts.scale_down_with_top(sets.pop(), args.resample);
             write_update_idx(&mut idx, &ts)?;
         }
         sets.push(ts);
@@ -148,10 +234,10 @@ fn construct_base(zoom: usize, min_zoom: usize, args: &Args, cfg: &Config) -> Re
     while let Some(mut ts) = sets.pop() {
         while ts.zoom() > min_zoom {
             if ts.can_scale_down_with_top() {
-                ts.scale_down_with_top(sets.pop());
+                ts.scale_down_with_top(sets.pop(), args.resample);
                 write_update_idx(&mut idx, &ts)?;
             } else {
-                ts.scale_down_as_top();
+                ts.scale_down_as_top(args.resample);
                 write_update_idx(&mut idx, &ts)?;
             }
         }
@@ -1,15 +1,57 @@
+use gdal::raster::GdalType;
 use ndarray::Array2;
 use rasters::Result;
 
-pub struct TileSet {
-    tiles: Vec<Tile>,
+/// Element type of the tiles. The filters combine the values
+/// as `f64`; no-data is `NaN` for floats, and the minimum
+/// value for integers.
+pub trait TileValue: GdalType + Copy + PartialEq + Send + Sync {
+    fn no_data() -> Self;
+    fn is_no_data(self) -> bool;
+    fn to_f64(self) -> f64;
+    /// Convert from `f64` (rounded for integers).
+    fn from_f64(val: f64) -> Self;
+}
+
+impl TileValue for f64 {
+    fn no_data() -> Self {
+        f64::NAN
+    }
+    fn is_no_data(self) -> bool {
+        self.is_nan()
+    }
+    fn to_f64(self) -> f64 {
+        self
+    }
+    fn from_f64(val: f64) -> Self {
+        val
+    }
+}
+
+impl TileValue for i32 {
+    fn no_data() -> Self {
+        i32::MIN
+    }
+    fn is_no_data(self) -> bool {
+        self == i32::MIN
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+    fn from_f64(val: f64) -> Self {
+        val.round() as i32
+    }
+}
+
+pub struct TileSet<T = f64> {
+    tiles: Vec<Tile<T>>,
     xrange: Dims,
     y: usize,
     zoom: usize,
 }
 
-impl TileSet {
-    pub fn new<I: IntoIterator<Item = Tile>>(
+impl<T: TileValue> TileSet<T> {
+    pub fn new<I: IntoIterator<Item = Tile<T>>>(
         zoom: usize,
         xrange: Dims,
         y: usize,
@@ -36,7 +78,7 @@ impl TileSet {
         self.y % 2 == 1
     }
 
-    pub fn scale_down_as_top(&mut self) {
+    pub fn scale_down_as_top(&mut self, resample: Resample) {
         assert!(!self.can_scale_down_with_top());
         let (left, right) = self.xrange;
         // eprintln!("Scaling down as top:");
@@ -48,10 +90,10 @@ impl TileSet {
         for (x, tile) in (left..right).zip(tiles) {
             if x % 2 == 1 {
                 let corners = [None, None, prev.take(), Some(tile)];
-                self.tiles.push(Tile::scale_4_to_1(corners));
+                self.tiles.push(Tile::scale_4_to_1(corners, resample));
             } else if x == right - 1 {
                 let corners = [None, None, Some(tile), None];
-                self.tiles.push(Tile::scale_4_to_1(corners));
+                self.tiles.push(Tile::scale_4_to_1(corners, resample));
             } else {
                 prev = Some(tile);
             }
@@ -61,7 +103,7 @@ impl TileSet {
         self.zoom -= 1;
     }
 
-    pub fn scale_down_with_top(&mut self, other: Option<Self>) {
+    pub fn scale_down_with_top(&mut self, other: Option<Self>, resample: Resample) {
         assert!(self.can_scale_down_with_top());
 
         let (left, right) = self.xrange;
@@ -88,10 +130,10 @@ impl TileSet {
         for (x, (tile, otile)) in (left..right).zip(pairs) {
             if x % 2 == 1 {
                 let corners = [prev.take(), Some(tile), oprev.take(), otile];
-                self.tiles.push(Tile::scale_4_to_1(corners));
+                self.tiles.push(Tile::scale_4_to_1(corners, resample));
             } else if x == right - 1 {
                 let corners = [Some(tile), None, otile, None];
-                self.tiles.push(Tile::scale_4_to_1(corners));
+                self.tiles.push(Tile::scale_4_to_1(corners, resample));
             } else {
                 prev = Some(tile);
                 oprev = otile;
@@ -103,7 +145,7 @@ impl TileSet {
         self.zoom -= 1;
     }
 
-    pub fn write(&self, base_path: &Path) -> Result<YIndex> {
+    pub fn write(&self, base_path: &Path, format: &TileFormat) -> Result<YIndex> {
         let base_path = base_path
             .join(&format!("{}", self.zoom))
             .join(&format!("{}", self.y));
@@ -115,8 +157,8 @@ impl TileSet {
             .par_iter()
             .map(|tile| -> Result<_> {
                 let (x, _) = tile.coords();
-                let path = base_path.join(&format!("{}.bin", x));
-                let cfg = tile.write(&path)?;
+                let path = base_path.join(&format!("{}.{}", x, format.extension()));
+                let cfg = tile.write(&path, format)?;
                 Ok((x, cfg))
             })
             .try_fold(
@@ -138,27 +180,129 @@ impl TileSet {
     }
 }
 
+/// Resampling used to combine the pixels of the child tiles
+/// when scaling down a zoom level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resample {
+    /// Mean of the 2x2 child pixels (box filter).
+    Average,
+    /// Tent filter over the 4x4 child pixels.
+    Bilinear,
+    /// Cubic (Keys, `a = -0.5`) filter over the 8x8 child
+    /// pixels. Pixels whose filter needs tiles beyond the
+    /// 2x2 child tiles (i.e. at their edges), or no-data
+    /// pixels, fall back to bilinear.
+    Cubic,
+    /// Most frequent of the 2x2 child pixels (the first in
+    /// row-major order on ties), for categorical rasters.
+    Mode,
+}
+
+impl Default for Resample {
+    fn default() -> Self {
+        Resample::Average
+    }
+}
+
+/// 1D filter weights, for a factor of 2 downsampling, of
+/// the child pixels centered around an output pixel.
+const AVERAGE_WEIGHTS: [f64; 2] = [0.5, 0.5];
+const BILINEAR_WEIGHTS: [f64; 4] = [0.125, 0.375, 0.375, 0.125];
+const CUBIC_WEIGHTS: [f64; 8] = [
+    -0.01171875,
+    -0.03515625,
+    0.11328125,
+    0.43359375,
+    0.43359375,
+    0.11328125,
+    -0.03515625,
+    -0.01171875,
+];
+
+/// Apply the separable filter with 1D `weights` centered
+/// at the output pixel `(r, c)`, sampling the child pixels
+/// from `sample`. If `partial`, no-data samples are ignored
+/// and the weights re-normalized; otherwise, any no-data
+/// sample yields no-data.
+fn filter<F: Fn(isize, isize) -> f64>(
+    sample: &F,
+    (r, c): (usize, usize),
+    weights: &[f64],
+    partial: bool,
+) -> f64 {
+    let offset = weights.len() as isize / 2 - 1;
+    let (sr, sc) = (2 * r as isize - offset, 2 * c as isize - offset);
+
+    let mut val = 0.;
+    let mut total = 0.;
+    for (i, wr) in weights.iter().enumerate() {
+        for (j, wc) in weights.iter().enumerate() {
+            let v = sample(sr + i as isize, sc + j as isize);
+            if v.is_nan() {
+                if partial {
+                    continue;
+                }
+                return f64::NAN;
+            }
+            val += wr * wc * v;
+            total += wr * wc;
+        }
+    }
+    if total > 0. {
+        val / total
+    } else {
+        f64::NAN
+    }
+}
+
+/// Most frequent of the valid `vals`, or `None` if there
+/// are none. Ties go to the value seen first.
+fn mode<T: TileValue>(vals: &[T]) -> Option<T> {
+    let mut best = None;
+    let mut best_count = 0;
+    for (i, &val) in vals.iter().enumerate() {
+        if val.is_no_data() || vals[..i].contains(&val) {
+            continue;
+        }
+        let count = vals[i..].iter().filter(|&&v| v == val).count();
+        if count > best_count {
+            best = Some(val);
+            best_count = count;
+        }
+    }
+    best
+}
+
 #[derive(Debug)]
-pub struct Tile {
-    data: Array2<f64>,
+pub struct Tile<T = f64> {
+    data: Array2<T>,
     data_range: (f64, f64),
     coords: Dims,
 }
 
 use std::path::Path;
-impl Tile {
-    pub fn from_aggregate(data: Array2<(f64, f64)>, coords: Dims) -> Self {
+impl<T: TileValue> Tile<T> {
+    /// Construct a tile from the `(value, weight)` of each
+    /// pixel aggregated from the input, with a `NaN` weight
+    /// for no-data. The value is the sum weighted by the
+    /// overlap of the input pixels, or for
+    /// [`Resample::Mode`], that of the input pixel
+    /// overlapping the most.
+    pub fn from_aggregate(data: Array2<(f64, f64)>, coords: Dims, resample: Resample) -> Self {
         let mut min = f64::INFINITY;
         let mut max = f64::NEG_INFINITY;
-        let data = data.map(|(val, count)| {
-            let count = *count;
+        let data = data.map(|&(val, count)| {
             if count.is_nan() {
-                count
+                T::no_data()
             } else {
                 assert!(!val.is_nan());
-                let x = val / count;
-                max = max.max(x);
-                min = min.min(x);
+                let x = if resample == Resample::Mode {
+                    T::from_f64(val)
+                } else {
+                    T::from_f64(val / count)
+                };
+                max = max.max(x.to_f64());
+                min = min.min(x.to_f64());
                 x
             }
         });
@@ -173,28 +317,14 @@ impl Tile {
         self.coords
     }
 
-    pub fn scale_4_to_1(corners: [Option<Self>; 4]) -> Self {
-        let mut min = f64::INFINITY;
-        let mut max = f64::NEG_INFINITY;
-        let mut checked_average = |vals: [f64; 4]| -> f64 {
-            let mut val = 0.;
-            let mut count = 0;
-            for v in vals.iter() {
-                if !v.is_nan() {
-                    val += v;
-                    count += 1;
-                }
-            }
-            if count > 0 {
-                let val = val / count as f64;
-                min = min.min(val);
-                max = max.max(val);
-                val
-            } else {
-                f64::NAN
-            }
-        };
-
+    /// Combine (up to) 4 tiles of the next zoom level into
+    /// a tile of half the resolution. The `corners` are the
+    /// top-left, top-right, bottom-left and bottom-right
+    /// tiles, and missing tiles are treated as no-data. The
+    /// `resample` decides how the child pixels are combined
+    /// (see [`Resample`]). A pixel is no-data if all the 2x2
+    /// child pixels it covers are no-data.
+    pub fn scale_4_to_1(corners: [Option<Self>; 4], resample: Resample) -> Self {
         let (rows, cols, coords) = {
             let some = corners
                 .iter()
@@ -210,35 +340,70 @@ impl Tile {
         assert!(rows % 2 == 0);
         assert!(cols % 2 == 0);
 
-        let mut data = Array2::from_elem((rows, cols), f64::NAN);
+        // Value of a pixel of the 2x2 mosaic of the corners
+        let value = |sr: isize, sc: isize| -> T {
+            if sr < 0 || sc < 0 || sr >= 2 * rows as isize || sc >= 2 * cols as isize {
+                return T::no_data();
+            }
+            let (sr, sc) = (sr as usize, sc as usize);
+            let sidx = 2 * (sr / rows) + sc / cols;
+            corners[sidx]
+                .as_ref()
+                .map(|tile| tile.data[(sr % rows, sc % cols)])
+                .unwrap_or_else(T::no_data)
+        };
+        let sample = |sr: isize, sc: isize| -> f64 {
+            let val = value(sr, sc);
+            if val.is_no_data() {
+                f64::NAN
+            } else {
+                val.to_f64()
+            }
+        };
+
+        let mut min = f64::INFINITY;
+        let mut max = f64::NEG_INFINITY;
+        let mut data = Array2::from_elem((rows, cols), T::no_data());
 
         for r in 0..rows {
             for c in 0..cols {
-                let sr = 2 * r;
-                let sc = 2 * c;
-
-                let mut sidx = 0;
-                if sr >= rows {
-                    sidx += 2;
-                }
-                if sc >= cols {
-                    sidx += 1;
+                let val = if resample == Resample::Mode {
+                    let (sr, sc) = (2 * r as isize, 2 * c as isize);
+                    let vals = [
+                        value(sr, sc),
+                        value(sr, sc + 1),
+                        value(sr + 1, sc),
+                        value(sr + 1, sc + 1),
+                    ];
+                    mode(&vals).unwrap_or_else(T::no_data)
+                } else {
+                    let average = filter(&sample, (r, c), &AVERAGE_WEIGHTS, true);
+                    let val = if average.is_nan() {
+                        average
+                    } else {
+                        match resample {
+                            Resample::Bilinear => filter(&sample, (r, c), &BILINEAR_WEIGHTS, true),
+                            Resample::Cubic => {
+                                let val = filter(&sample, (r, c), &CUBIC_WEIGHTS, false);
+                                if val.is_nan() {
+                                    filter(&sample, (r, c), &BILINEAR_WEIGHTS, true)
+                                } else {
+                                    val
+                                }
+                            }
+                            _ => average,
+                        }
+                    };
+                    if val.is_nan() {
+                        T::no_data()
+                    } else {
+                        T::from_f64(val)
+                    }
+                };
+                if !val.is_no_data() {
+                    min = min.min(val.to_f64());
+                    max = max.max(val.to_f64());
                 }
-
-                let sr = sr % rows;
-                let sc = sc % cols;
-
-                let val = corners[sidx]
-                    .as_ref()
-                    .map(|tile| {
-                        checked_average([
-                            tile.data[(sr, sc)],
-                            tile.data[(sr + 1, sc)],
-                            tile.data[(sr, sc + 1)],
-                            tile.data[(sr + 1, sc + 1)],
-                        ])
-                    })
-                    .unwrap_or(f64::NAN);
                 data[(r, c)] = val;
             }
         }
@@ -250,7 +415,19 @@ impl Tile {
         }
     }
 
-    pub fn write(&self, path: &Path) -> Result<TileStats> {
+    /// Write the tile in the given format, and return its
+    /// stats for the index.
+    pub fn write(&self, path: &Path, format: &TileFormat) -> Result<TileStats> {
+        match format {
+            TileFormat::Bin => self.write_bin(path),
+            TileFormat::Png(ramp) => self.write_png(path, ramp.as_ref()),
+        }
+    }
+
+    /// Write the tile as 16-bit values (most significant
+    /// byte first) discretizing the data range, with `0`
+    /// for no-data.
+    fn write_bin(&self, path: &Path) -> Result<TileStats> {
         let file = std::fs::File::create(&path)?;
         let mut buf = std::io::BufWriter::with_capacity(0x100000, file);
 
@@ -262,11 +439,11 @@ impl Tile {
         let coeff = bins as f64 / (max - min);
 
         use std::io::Write;
-        self.data.iter().try_for_each(|val| -> Result<()> {
-            let mut val = *val;
-            if val.is_nan() {
+        self.data.iter().try_for_each(|&val| -> Result<()> {
+            if val.is_no_data() {
                 buf.write(&[0, 0])?;
             } else {
+                let mut val = val.to_f64();
                 if val < min {
                     val = min;
                 } else if val > max {
@@ -296,6 +473,143 @@ impl Tile {
             err,
         })
     }
+
+    /// Write the tile as a PNG image, discretizing the data
+    /// range to 256 levels. The levels are written as gray
+    /// values, or mapped to colors by `ramp`. No-data pixels
+    /// are transparent.
+    fn write_png(&self, path: &Path, ramp: Option<&ColorRamp>) -> Result<TileStats> {
+        let bins = (1 << 8) - 1;
+        let (min, max) = self.data_range;
+        let coeff = if max > min {
+            bins as f64 / (max - min)
+        } else {
+            0.
+        };
+
+        let (rows, cols) = self.data.dim();
+        let mut img = image::RgbaImage::new(cols as u32, rows as u32);
+        let mut err: f64 = 0.;
+        for ((r, c), &val) in self.data.indexed_iter() {
+            if val.is_no_data() {
+                continue;
+            }
+            let val = val.to_f64().clamp(min, max);
+            let disc = ((val - min) * coeff).round() as u8;
+            if coeff > 0. {
+                let rec = min + disc as f64 / coeff;
+                err = err.max((val - rec).abs());
+            }
+
+            let [red, green, blue] = match ramp {
+                Some(ramp) => ramp.color(disc as f64 / bins as f64),
+                None => [disc, disc, disc],
+            };
+            img.put_pixel(c as u32, r as u32, image::Rgba([red, green, blue, 255]));
+        }
+        img.save_with_format(path, image::ImageFormat::Png)?;
+
+        Ok(TileStats {
+            min,
+            max,
+            bins,
+            err,
+        })
+    }
+}
+
+/// Format of the tiles written.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TileFormat {
+    /// Custom 16-bit binary format (see `Tile::write_bin`).
+    Bin,
+    /// PNG image, optionally color mapped.
+    Png(Option<ColorRamp>),
+}
+
+impl Default for TileFormat {
+    fn default() -> Self {
+        TileFormat::Bin
+    }
+}
+
+impl TileFormat {
+    /// File extension of the tiles.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TileFormat::Bin => "bin",
+            TileFormat::Png(_) => "png",
+        }
+    }
+
+    /// Estimated size (in bytes) of a tile of `tile_size`
+    /// pixels square. PNG tiles are compressed, so this is
+    /// an upper bound for them.
+    pub fn estimated_tile_bytes(&self, tile_size: usize) -> usize {
+        let bytes_per_pixel = match self {
+            TileFormat::Bin => 2,
+            TileFormat::Png(_) => 4,
+        };
+        tile_size * tile_size * bytes_per_pixel
+    }
+}
+
+/// A color ramp of evenly spaced colors, interpolated
+/// linearly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorRamp {
+    colors: Vec<[u8; 3]>,
+}
+
+impl ColorRamp {
+    /// Construct a ramp from at least two colors.
+    pub fn new(colors: Vec<[u8; 3]>) -> Result<Self> {
+        if colors.len() < 2 {
+            anyhow::bail!("color ramp needs at least 2 colors");
+        }
+        Ok(ColorRamp { colors })
+    }
+
+    /// Read a ramp from a text file with one color per
+    /// line, as space separated `r g b` values. Blank lines
+    /// and lines starting with `#` are ignored.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        use anyhow::Context;
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading color ramp {}", path.display()))?;
+        let colors = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| -> Result<[u8; 3]> {
+                let vals = line
+                    .split_whitespace()
+                    .map(str::parse)
+                    .collect::<std::result::Result<Vec<u8>, _>>()
+                    .with_context(|| format!("invalid color: {}", line))?;
+                match vals[..] {
+                    [r, g, b] => Ok([r, g, b]),
+                    _ => anyhow::bail!("invalid color: {}", line),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        ColorRamp::new(colors)
+    }
+
+    /// Color at position `t` (clamped to `[0, 1]`) of the
+    /// ramp.
+    pub fn color(&self, t: f64) -> [u8; 3] {
+        let pos = t.clamp(0., 1.) * (self.colors.len() - 1) as f64;
+        let idx = (pos.floor() as usize).min(self.colors.len() - 2);
+        let frac = pos - idx as f64;
+
+        let (from, to) = (self.colors[idx], self.colors[idx + 1]);
+        let mut color = [0; 3];
+        for ((c, &from), &to) in color.iter_mut().zip(&from).zip(&to) {
+            *c = (from as f64 + (to as f64 - from as f64) * frac).round() as u8;
+        }
+        color
+    }
 }
 
 use serde_derive::Serialize;
@@ -310,7 +624,8 @@ pub struct TileStats {
 
 use std::collections::HashMap;
 
-use super::Dims;
+use super::{Bounds, Dims};
+use rasters::geometry::GeoTransform;
 #[derive(Serialize)]
 pub struct YIndex {
     y: usize,
@@ -336,10 +651,23 @@ impl YIndex {
 
 #[derive(Serialize, Default)]
 pub struct Index {
+    /// Geo. transform of the (possibly warped) input
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transform: Option<GeoTransform>,
+    /// Bounds of the input in web mercator coordinates
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bounds: Option<Bounds>,
     #[serde(flatten)]
     index: HashMap<usize, HashMap<usize, YIndex>>,
 }
 impl Index {
+    /// Record the geo. transform and the (web mercator)
+    /// bounds of the input.
+    pub fn set_raster(&mut self, transform: GeoTransform, bounds: Bounds) {
+        self.transform = Some(transform);
+        self.bounds = Some(bounds);
+    }
+
     pub fn update_index(&mut self, zoom: usize, idx: YIndex) {
         let y = idx.y;
 
@@ -365,3 +693,142 @@ impl AddAssign for Index {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scale down a 2x2 mosaic of `size` x `size` tiles,
+    /// where the value of each pixel is `f` of its column.
+    fn scale_ramp<F: Fn(f64) -> f64>(size: usize, f: F, resample: Resample) -> Array2<f64> {
+        let tile = |x: usize, y: usize| {
+            let data = Array2::from_shape_fn((size, size), |(_, c)| f((x * size + c) as f64));
+            Some(Tile {
+                data,
+                data_range: (0., 0.),
+                coords: (x, y),
+            })
+        };
+        let corners = [tile(0, 0), tile(1, 0), tile(0, 1), tile(1, 1)];
+        Tile::scale_4_to_1(corners, resample).data
+    }
+
+    #[test]
+    fn test_resample_ramp() {
+        let size = 8;
+        let linear = |x: f64| x;
+        let quadratic = |x: f64| x * x;
+
+        let average = scale_ramp(size, linear, Resample::Average);
+        let bilinear = scale_ramp(size, linear, Resample::Bilinear);
+        let cubic = scale_ramp(size, linear, Resample::Cubic);
+        for ((r, c), &val) in average.indexed_iter() {
+            assert_eq!(val, 2. * c as f64 + 0.5);
+
+            // The symmetric filters preserve a linear ramp
+            // away from the edges of the mosaic
+            if c > 0 && c < size - 1 {
+                assert!((bilinear[(r, c)] - val).abs() < 1e-9);
+                assert!((cubic[(r, c)] - val).abs() < 1e-9);
+            }
+        }
+
+        // On a quadratic ramp, the bilinear filter differs
+        // from the box average by the curvature term
+        let average = scale_ramp(size, quadratic, Resample::Average);
+        let bilinear = scale_ramp(size, quadratic, Resample::Bilinear);
+        for c in 1..size - 1 {
+            let x = c as f64;
+            assert_eq!(average[(3, c)], 4. * x * x + 2. * x + 0.5);
+            assert!((bilinear[(3, c)] - average[(3, c)] - 0.5).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_resample_mode() {
+        // A 4x4 class raster, tiled as 2x2 tiles
+        const NO_DATA: i32 = i32::MIN;
+        let classes = [
+            [1, 1, 2, 3],
+            [1, 2, 3, 3],
+            [4, 5, NO_DATA, NO_DATA],
+            [5, 4, NO_DATA, 7],
+        ];
+        let tile = |x: usize, y: usize| {
+            let data = Array2::from_shape_fn((2, 2), |(r, c)| classes[2 * y + r][2 * x + c]);
+            Some(Tile {
+                data,
+                data_range: (0., 0.),
+                coords: (x, y),
+            })
+        };
+        let corners = || [tile(0, 0), tile(1, 0), tile(0, 1), tile(1, 1)];
+
+        // The ties go to the first class, and no-data pixels
+        // are ignored
+        let tile = Tile::scale_4_to_1(corners(), Resample::Mode);
+        assert_eq!(tile.data.into_raw_vec(), vec![1, 3, 4, 7]);
+        assert_eq!(tile.data_range, (1., 7.));
+
+        // Averaging mixes the classes
+        let tile = Tile::scale_4_to_1(corners(), Resample::Average);
+        assert_eq!(tile.data[(1, 0)], 5);
+    }
+
+    #[test]
+    fn test_color_ramp() -> Result<()> {
+        let ramp = ColorRamp::new(vec![[0, 0, 255], [0, 255, 0], [255, 0, 0]])?;
+        assert_eq!(ramp.color(0.), [0, 0, 255]);
+        assert_eq!(ramp.color(0.25), [0, 128, 128]);
+        assert_eq!(ramp.color(0.5), [0, 255, 0]);
+        assert_eq!(ramp.color(1.), [255, 0, 0]);
+        assert_eq!(ramp.color(2.), [255, 0, 0]);
+        assert!(ColorRamp::new(vec![[0, 0, 0]]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_png() -> Result<()> {
+        let tmp_dir = tempdir::TempDir::new("raster_tile_test")?;
+        let path = tmp_dir.path().join("tile.png");
+
+        let mut data = Array2::from_shape_fn((4, 4), |(r, c)| (r * 4 + c) as f64);
+        data[(0, 1)] = f64::NAN;
+        let tile = Tile {
+            data,
+            data_range: (0., 15.),
+            coords: (0, 0),
+        };
+        let stats = tile.write(&path, &TileFormat::Png(None))?;
+        assert_eq!(stats.bins, 255);
+
+        let img = image::open(&path)?.to_rgba8();
+        assert_eq!(img.dimensions(), (4, 4));
+        assert_eq!(img.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(img.get_pixel(1, 0).0[3], 0);
+        assert_eq!(img.get_pixel(3, 3).0, [255, 255, 255, 255]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resample_no_data() {
+        for &resample in &[Resample::Average, Resample::Bilinear, Resample::Cubic] {
+            let top_left = Tile {
+                data: Array2::from_elem((4, 4), 1.),
+                data_range: (1., 1.),
+                coords: (0, 0),
+            };
+            let tile = Tile::scale_4_to_1([Some(top_left), None, None, None], resample);
+
+            // Only the top-left quadrant has data, and the
+            // filters do not spread it to the other pixels
+            for ((r, c), &val) in tile.data.indexed_iter() {
+                if r < 2 && c < 2 {
+                    assert_eq!(val, 1.);
+                } else {
+                    assert!(val.is_nan());
+                }
+            }
+        }
+    }
+}
@@ -3,6 +3,7 @@ use base::RowProc;
 use gdal::Dataset;
 use nalgebra::{Matrix3, Point2};
 use rasters::{geometry, Result};
+use serde_derive::Serialize;
 
 use self::web_mercator::wm_transform_for_raster;
 
@@ -18,6 +19,11 @@ pub struct Config {
 impl Config {
     pub fn for_raster(ds: &Dataset, tile_size: usize) -> Result<Self> {
         fn wm_bounds_for_raster(ds: &Dataset) -> Result<[f64; 4]> {
+            // The reprojection to web mercator may also rotate
+            // the raster; checked below.
+            if !geometry::GeoTransform::from_dataset(ds).is_axis_aligned(1e-5) {
+                bail!("transform is not north aligned (use --warp)");
+            }
             let pix_to_wm = wm_transform_for_raster(ds)?;
 
             let (left, top) = pix_to_wm(0., 0.)?;
@@ -32,7 +38,7 @@ impl Config {
                 || (lb.0 - left).abs() / left > 1e-5
                 || (lb.1 - bot).abs() / bot > 1e-5
             {
-                bail!("transform is not north aligned");
+                bail!("transform is not north aligned (use --warp)");
             }
 
             Ok([left, top, right, bot])
@@ -98,12 +104,56 @@ impl Config {
         [left, top, right + 1, bot + 1]
     }
 
+    /// The bounds of the raster in web mercator coordinates.
+    pub fn wm_bounds(&self) -> Bounds {
+        self.wm_bounds
+    }
+
+    /// The [TileJSON](https://github.com/mapbox/tilejson-spec)
+    /// metadata of the tiles between the zoom levels. The
+    /// tiles are at `{z}/{y}/{x}.<extension>` relative to
+    /// `base_url`, with `y` increasing northwards (the "tms"
+    /// scheme).
+    pub fn tilejson(
+        &self,
+        min_zoom: usize,
+        max_zoom: usize,
+        base_url: &str,
+        extension: &str,
+    ) -> TileJson {
+        use web_mercator::wm_to_wgs84;
+        let (west, south) = wm_to_wgs84(self.wm_bounds.min().x_y());
+        let (east, north) = wm_to_wgs84(self.wm_bounds.max().x_y());
+        TileJson {
+            tilejson: "3.0.0",
+            tiles: vec![format!("{}{{z}}/{{y}}/{{x}}.{}", base_url, extension)],
+            scheme: "tms",
+            minzoom: min_zoom,
+            maxzoom: max_zoom,
+            bounds: [west, south, east, north],
+        }
+    }
+
     pub fn base_proc(&self, zoom: usize) -> RowProc {
         let [left, _, right, _] = self.tile_index_bounds(zoom);
         RowProc::new(zoom, self.tile_size, (left, right))
     }
 }
 
+/// TileJSON (3.0) metadata of the tiles (see
+/// [`Config::tilejson`]).
+#[derive(Serialize)]
+pub struct TileJson {
+    tilejson: &'static str,
+    tiles: Vec<String>,
+    scheme: &'static str,
+    minzoom: usize,
+    maxzoom: usize,
+    /// `[west, south, east, north]` in degrees (WGS84)
+    bounds: [f64; 4],
+}
+
 pub mod base;
 pub mod dem;
+pub mod warp;
 pub mod web_mercator;
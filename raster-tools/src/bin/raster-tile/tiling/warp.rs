@@ -0,0 +1,59 @@
+//! Warp rasters to a north-aligned grid in web mercator.
+
+use super::web_mercator::WEB_MERCATOR_EPSG;
+use anyhow::{bail, Context};
+use gdal::{spatial_ref::SpatialRef, Dataset, DriverManager};
+use rasters::Result;
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+
+/// Maximum error (in pixels) of the approximate transform
+/// used by the warper; same as the default of `gdalwarp`.
+const MAX_ERROR: f64 = 0.125;
+
+/// Warp the raster at `path` to a north-aligned grid in web
+/// mercator CRS. The extent and resolution of the grid are
+/// computed by GDAL to approximately preserve the
+/// resolution of the input. Returns the path of a (lazily)
+/// warped VRT in GDAL's in-memory filesystem, which may be
+/// opened (eg. once per thread) for the duration of the
+/// process.
+///
+/// The regions of the grid outside the input are set to
+/// the no-data value of the input; thus, the input should
+/// have a no-data value.
+pub fn warp_to_web_mercator(path: &Path) -> Result<PathBuf> {
+    // The VRT refers to the input by its path
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("reading dataset {}", path.display()))?;
+    let ds = Dataset::open(&path).with_context(|| format!("reading dataset {}", path.display()))?;
+
+    let wm_wkt = SpatialRef::from_epsg(WEB_MERCATOR_EPSG)
+        .and_then(|srs| srs.to_wkt())
+        .with_context(|| "couldn't load wm transform")?;
+    let wm_wkt = CString::new(wm_wkt)?;
+
+    let warped = unsafe {
+        let c_dataset = gdal_sys::GDALAutoCreateWarpedVRT(
+            ds.c_dataset(),
+            std::ptr::null(),
+            wm_wkt.as_ptr(),
+            gdal_sys::GDALResampleAlg::GRA_Bilinear,
+            MAX_ERROR,
+            std::ptr::null(),
+        );
+        if c_dataset.is_null() {
+            bail!("couldn't warp {} to web mercator", path.display());
+        }
+        Dataset::from_c_dataset(c_dataset)
+    };
+
+    let vrt_path = PathBuf::from(format!("/vsimem/raster-tile-{}.vrt", std::process::id()));
+    let driver = DriverManager::get_driver_by_name("VRT")?;
+    warped
+        .create_copy(&driver, &vrt_path, &[])
+        .with_context(|| format!("writing warped dataset {}", vrt_path.display()))?;
+
+    Ok(vrt_path)
+}
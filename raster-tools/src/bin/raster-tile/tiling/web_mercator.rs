@@ -24,7 +24,7 @@ pub fn wm_transform_for_raster(ds: &Dataset) -> Result<impl Fn(f64, f64) -> Resu
     let pix_transform = transform_from_dataset(&ds);
 
     if pix_transform[(0, 1)].abs() > 1e-5 || pix_transform[(1, 0)].abs() > 1e-5 {
-        bail!("transform is not north aligned");
+        bail!("transform is not north aligned (use --warp)");
     }
     if (pix_transform[(1, 1)].abs() - pix_transform[(0, 0)].abs()).abs() > 1e-2 {
         bail!("pixels are not square");
@@ -42,6 +42,18 @@ pub fn wm_transform_for_raster(ds: &Dataset) -> Result<impl Fn(f64, f64) -> Resu
 
 const MAX_COORD: f64 = 20037508.;
 
+/// Radius of the sphere of the web mercator projection.
+const EARTH_RADIUS: f64 = 6378137.;
+
+/// Convert web mercator coordinates to WGS84 longitude and
+/// latitude (in degrees).
+pub fn wm_to_wgs84(pt: (f64, f64)) -> (f64, f64) {
+    use std::f64::consts::FRAC_PI_2;
+    let lon = pt.0 / EARTH_RADIUS;
+    let lat = 2. * (pt.1 / EARTH_RADIUS).exp().atan() - FRAC_PI_2;
+    (lon.to_degrees(), lat.to_degrees())
+}
+
 /// Compute the width (and height) of a tile in web mercator
 /// CRS at a given zoom level.
 pub fn tile_size(zoom: usize) -> f64 {
@@ -100,3 +112,29 @@ pub fn tile_index(zoom: usize, pt: (f64, f64)) -> (usize, usize) {
     let pt = tile_index_transform(zoom).transform_point(&Point2::new(pt.0, pt.1));
     (pt.x.floor() as usize, pt.y.floor() as usize)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wm_to_wgs84() {
+        let close =
+            |a: (f64, f64), b: (f64, f64)| (a.0 - b.0).abs() < 1e-5 && (a.1 - b.1).abs() < 1e-5;
+        assert!(close(wm_to_wgs84((0., 0.)), (0., 0.)));
+        assert!(close(wm_to_wgs84((MAX_COORD, 0.)), (180., 0.)));
+        // The web mercator square extends to about 85.05N/S
+        assert!(close(
+            wm_to_wgs84((-MAX_COORD, MAX_COORD)),
+            (-180., 85.05113)
+        ));
+        assert!(close(
+            wm_to_wgs84((MAX_COORD, -MAX_COORD)),
+            (180., -85.05113)
+        ));
+        assert!(close(
+            wm_to_wgs84((75_f64.to_radians() * EARTH_RADIUS, 8399737.8898)),
+            (75., 60.)
+        ));
+    }
+}
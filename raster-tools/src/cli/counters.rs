@@ -1,5 +1,7 @@
+use super::progress::Flag;
 use std::fmt;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
 
 #[derive(Debug, Default)]
 pub struct Counter {
@@ -34,6 +36,8 @@ pub struct DetailCounter {
     pub processed: Counter,
     pub skipped: Counter,
     name: &'static str,
+    start: Instant,
+    rate: Flag,
 }
 impl DetailCounter {
     pub fn new(name: &'static str) -> Self {
@@ -42,17 +46,64 @@ impl DetailCounter {
             processed: Default::default(),
             skipped: Default::default(),
             name,
+            start: Instant::now(),
+            rate: Default::default(),
         }
     }
+
+    /// Also display the rate of processing (per second), and
+    /// the estimated time remaining, measured from the
+    /// construction of the counter. Not recommended for
+    /// short-running tasks, where the estimates are noisy.
+    pub fn with_rate(self) -> Self {
+        self.set_rate(true);
+        self
+    }
+
+    /// Toggle the display of the rate and estimated time
+    /// remaining (see [`with_rate`]).
+    ///
+    /// [`with_rate`]: DetailCounter::with_rate
+    pub fn set_rate(&self, rate: bool) {
+        self.rate.store(rate);
+    }
 }
 impl fmt::Display for DetailCounter {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}: completed {}", self.name, self.processed.load())?;
+        let processed = self.processed.load();
+        let total = self.total.load();
+        write!(f, "{}: completed {}", self.name, processed)?;
         let skipped = self.skipped.load();
         if skipped > 0 {
             write!(f, " (skipped {})", skipped)?;
         }
-        write!(f, " of {}.", self.total.load())
+        write!(f, " of {}.", total)?;
+
+        let elapsed = self.start.elapsed().as_secs_f64();
+        if self.rate.load() && processed > 0 && elapsed > 0. {
+            let rate = processed as f64 / elapsed;
+            write!(f, " {:.1} {}/s", rate, self.name)?;
+            if total > processed {
+                let remaining = (total - processed) as f64 / rate;
+                write!(f, ", ETA {}", FmtDuration(remaining as u64))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Display a duration in seconds as eg. `1h02m03s`.
+struct FmtDuration(u64);
+impl fmt::Display for FmtDuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (h, m, s) = (self.0 / 3600, self.0 / 60 % 60, self.0 % 60);
+        if h > 0 {
+            write!(f, "{}h{:02}m{:02}s", h, m, s)
+        } else if m > 0 {
+            write!(f, "{}m{:02}s", m, s)
+        } else {
+            write!(f, "{}s", s)
+        }
     }
 }
 
@@ -1,4 +1,8 @@
 use crate::cli::*;
+use anyhow::format_err;
+use rasters::chunking::{ChunkConfig, ChunkWindow};
+use rasters::Result;
+use rayon::prelude::*;
 use std::sync::Arc;
 use std::thread::JoinHandle;
 
@@ -21,9 +25,24 @@ impl Tracker {
             handle: Some(handle),
         }
     }
+
+    /// Also display the rate of processing, and the
+    /// estimated time remaining (see
+    /// [`DetailCounter::with_rate`]).
+    pub fn with_rate(self) -> Self {
+        self.progress.value.set_rate(true);
+        self
+    }
+
     pub fn increment(&self) {
         self.progress.value.processed.fetch_add(1);
     }
+
+    /// Count an item that was skipped without processing.
+    pub fn skip(&self) {
+        self.progress.value.skipped.fetch_add(1);
+        self.increment();
+    }
 }
 impl Drop for Tracker {
     fn drop(&mut self) {
@@ -33,3 +52,50 @@ impl Drop for Tracker {
         }
     }
 }
+
+/// Process the chunks of `cfg` in parallel, folding them
+/// into an accumulator, and reduce the accumulators of the
+/// threads. The progress is displayed as the chunks are
+/// processed.
+///
+/// Each thread initializes its state (eg. the readers of the
+/// inputs) with `reader_init`, and an accumulator with
+/// `init`. Each chunk is processed by `per_chunk` with the
+/// state and the accumulator of its thread; skipped chunks
+/// (see `ChunkWindow::is_skipped`) are not processed. The
+/// accumulators are reduced by `combine`.
+pub fn process_chunks<S, A, R, P, I, C>(
+    cfg: &ChunkConfig,
+    reader_init: R,
+    per_chunk: P,
+    init: I,
+    combine: C,
+) -> Result<A>
+where
+    S: Send,
+    A: Send,
+    R: Fn() -> Result<S> + Sync + Send,
+    P: Fn(&mut S, &mut A, ChunkWindow<'_>) -> Result<()> + Sync + Send,
+    I: Fn() -> A + Sync + Send,
+    C: Fn(A, A) -> Result<A> + Sync + Send,
+{
+    let chunks = cfg.into_par_iter();
+    let tracker = Tracker::new("chunks", chunks.len());
+    chunks
+        .try_fold(
+            || (reader_init(), init()),
+            |(mut state, mut acc), chunk| -> Result<_> {
+                if chunk.is_skipped() {
+                    tracker.skip();
+                    return Ok((state, acc));
+                }
+                // Report reader init. failures on the chunks
+                let reader = state.as_mut().map_err(|e| format_err!("{:#}", e))?;
+                per_chunk(reader, &mut acc, chunk)?;
+                tracker.increment();
+                Ok((state, acc))
+            },
+        )
+        .map(|res| res.map(|(_, acc)| acc))
+        .try_reduce(&init, combine)
+}
@@ -2,22 +2,226 @@
 
 use gdal::{DatasetOptions, DriverManager};
 use gdal::GdalOpenFlags;
+use rasters::reader::RetryPolicy;
 use rasters::Result;
 use std::fs::File;
 use std::path::Path;
 use std::path::PathBuf;
+use std::time::Duration;
 
 pub type InputArgs = PathBuf;
 pub struct OutputArgs {
     pub path: PathBuf,
     pub driver: String,
+    /// Replace the output if it exists
+    pub overwrite: bool,
+    /// Write a Cloud-Optimized GeoTIFF (`driver` is ignored)
+    pub cog: bool,
 }
 
-use anyhow::Context;
+/// The `--nodata` option of the tools reading rasters: a
+/// value (or `nan`) overriding the no-data value declared
+/// by the input, eg. when it uses a sentinel value without
+/// declaring it.
+pub fn nodata_arg<'a, 'b>() -> clap::Arg<'a, 'b> {
+    clap::Arg::with_name("nodata")
+        .long("nodata")
+        .value_name("NODATA")
+        .allow_hyphen_values(true)
+        .help("No-data value of the input (a number or nan), overriding that of the dataset")
+}
+
+/// The value of the `--nodata` option (see [`nodata_arg`]),
+/// if given. Exits with an error if it is invalid.
+pub fn nodata_from_matches(matches: &clap::ArgMatches<'_>) -> Option<f64> {
+    let value = matches.value_of("nodata")?;
+    let no_val = parse_nodata(value).unwrap_or_else(|e| {
+        use clap::{Error, ErrorKind::InvalidValue};
+        Error::with_description(&format!("{:#}", e), InvalidValue).exit()
+    });
+    Some(no_val)
+}
+
+/// Parse a no-data value: a number, or `nan` (in any case).
+pub fn parse_nodata(value: &str) -> Result<f64> {
+    if value.eq_ignore_ascii_case("nan") {
+        return Ok(f64::NAN);
+    }
+    Ok(value
+        .parse()
+        .with_context(|| format!("invalid no-data value: {}", value))?)
+}
+
+use anyhow::{bail, Context};
 use gdal::Dataset;
 
+/// Open the raster dataset at `path`. Remote paths (see
+/// [`is_remote`]) are opened with the default
+/// [`RetryPolicy`], to survive transient failures, which
+/// are logged to stderr.
 pub fn read_dataset(path: &Path) -> Result<Dataset> {
-    Ok(Dataset::open(&path).with_context(|| format!("reading dataset {}", path.display()))?)
+    if is_remote(path) {
+        let policy = RetryPolicy::default().with_on_retry(warn_retry);
+        read_dataset_with_retry(path, &policy)
+    } else {
+        Ok(Dataset::open(&path).with_context(|| format!("reading dataset {}", path.display()))?)
+    }
+}
+
+/// Open the raster dataset at `path`, retrying failures as
+/// per `policy`.
+pub fn read_dataset_with_retry(path: &Path, policy: &RetryPolicy) -> Result<Dataset> {
+    policy.run(&format!("reading dataset {}", path.display()), || {
+        Ok(Dataset::open(&path)?)
+    })
+}
+
+/// A [`rasters::reader::RetryHook`] logging the failure to
+/// stderr.
+pub fn warn_retry(what: &str, attempt: usize, err: &rasters::Error, delay: Duration) {
+    eprintln!(
+        "Warning: {} failed (attempt {}): {:#}; retrying in {:?}",
+        what, attempt, err, delay
+    );
+}
+
+/// Whether `path` refers to a network resource via one of
+/// GDAL's virtual file systems (eg. `/vsicurl/`, `/vsis3/`).
+pub fn is_remote(path: &Path) -> bool {
+    const REMOTE_PREFIXES: &[&str] = &[
+        "/vsicurl",
+        "/vsis3",
+        "/vsigs",
+        "/vsiaz",
+        "/vsiadls",
+        "/vsioss",
+        "/vsiswift",
+        "/vsiwebhdfs",
+    ];
+    let path = path.to_string_lossy();
+    REMOTE_PREFIXES.iter().any(|prefix| {
+        path.strip_prefix(prefix).map_or(false, |rest| {
+            rest.starts_with('/') || rest.starts_with("_streaming/")
+        })
+    })
+}
+
+use gdal::spatial_ref::{CoordTransform, SpatialRef};
+use gdal::vector::{Geometry, LayerAccess, ToGdal};
+use geo::MultiPolygon;
+use std::convert::TryInto;
+
+/// Polygons read from a vector dataset, along with their
+/// FIDs.
+pub type Polygons = Vec<(Option<u64>, Option<MultiPolygon<f64>>)>;
+
+/// Read the polygons of the first layer of the vector
+/// dataset at `path`, along with the CRS of the layer (if
+/// any).
+pub fn read_polygons(path: &Path) -> Result<(Polygons, Option<PolygonSrs>)> {
+    let ds = read_dataset(path)?;
+    let mut layer = ds.layer(0)?;
+    let srs = layer
+        .spatial_ref()
+        .ok()
+        .map(|srs| PolygonSrs::from_srs(&srs, false))
+        .transpose()?;
+    let polygons = layer
+        .features()
+        .map(|feature| -> Result<_> {
+            let poly = Some(multipoly_from_wkt(&feature.geometry().wkt()?)).transpose()?;
+            Ok((feature.fid(), poly))
+        })
+        .collect::<Result<_>>()?;
+    Ok((polygons, srs))
+}
+
+/// Read the polygons of the first layer of the vector
+/// dataset at `path` as a single region: a point is in the
+/// region if it is in any of the features. Returns an error
+/// if the layer has no polygons.
+pub fn read_region(path: &Path) -> Result<(MultiPolygon<f64>, Option<PolygonSrs>)> {
+    let (polygons, srs) = read_polygons(path)?;
+    let polys: Vec<_> = polygons
+        .into_iter()
+        .filter_map(|(_, poly)| poly)
+        .flat_map(|poly| poly.0)
+        .collect();
+    if polys.is_empty() {
+        bail!("no polygons in {}", path.display());
+    }
+    Ok((MultiPolygon(polys), srs))
+}
+
+/// Parse a Polygon or MultiPolygon WKT.
+pub fn multipoly_from_wkt(wkt: &str) -> Result<MultiPolygon<f64>> {
+    multipoly_from_geometry(Geometry::from_wkt(wkt)?)
+}
+
+fn multipoly_from_geometry(geom: Geometry) -> Result<MultiPolygon<f64>> {
+    use geo::Geometry::{MultiPolygon, Polygon};
+    Ok(match geom.try_into()? {
+        Polygon(p) => p.into(),
+        MultiPolygon(p) => p,
+        _ => bail!("polygon WKT is not a (multi)-polygon"),
+    })
+}
+
+/// The CRS of input polygons, to reproject them to the CRS
+/// of a raster. Stored as WKT, as GDAL's `SpatialRef` is not
+/// `Sync`.
+#[derive(Clone, Debug)]
+pub struct PolygonSrs {
+    pub wkt: String,
+    /// Whether the CRS was given by the user (eg. via
+    /// `--polygon-srs`), rather than read from a dataset.
+    pub explicit: bool,
+}
+
+impl PolygonSrs {
+    /// The CRS with the given EPSG code, given by the user.
+    pub fn from_epsg(code: u32) -> Result<Self> {
+        PolygonSrs::from_srs(&SpatialRef::from_epsg(code)?, true)
+    }
+
+    fn from_srs(srs: &SpatialRef, explicit: bool) -> Result<Self> {
+        Ok(PolygonSrs {
+            wkt: srs.to_wkt()?,
+            explicit,
+        })
+    }
+
+    /// The transform from this CRS to that of the raster
+    /// `ds`. If the raster has no projection, returns `None`
+    /// (the polygons are then used as is), or an error if
+    /// the CRS was given by the user.
+    pub fn transform_to(&self, ds: &Dataset) -> Result<Option<CoordTransform>> {
+        if ds.projection().is_empty() {
+            if self.explicit {
+                bail!("cannot reproject polygons: raster has no projection");
+            }
+            return Ok(None);
+        }
+        let src = gis_axis_order(SpatialRef::from_wkt(&self.wkt)?);
+        let dst = gis_axis_order(ds.spatial_ref()?);
+        Ok(Some(CoordTransform::new(&src, &dst)?))
+    }
+}
+
+/// Use the (easting, northing) axis order for `srs`, eg.
+/// longitude first for EPSG:4326, as in GeoJSON.
+fn gis_axis_order(srs: SpatialRef) -> SpatialRef {
+    srs.set_axis_mapping_strategy(gdal_sys::OSRAxisMappingStrategy::OAMS_TRADITIONAL_GIS_ORDER);
+    srs
+}
+
+/// Reproject the vertices of `poly` via `transform` (see
+/// [`PolygonSrs::transform_to`]).
+pub fn reproject_polygon(
+    poly: &MultiPolygon<f64>,
+    transform: &CoordTransform,
+) -> Result<MultiPolygon<f64>> {
+    multipoly_from_geometry(poly.to_gdal()?.transform(transform)?)
 }
 
 pub fn edit_dataset(path: &Path) -> Result<Dataset> {
@@ -32,19 +236,47 @@ pub fn edit_dataset(path: &Path) -> Result<Dataset> {
 }
 
 use gdal::raster::GdalType;
+/// Create the output raster of `arg`, with the size,
+/// geo. transform and projection of `ds`. Returns an error
+/// if the output exists, unless `arg.overwrite` is set.
+///
+/// The raster is written to a temporary file alongside the
+/// output, which replaces the output when the returned
+/// [`OutputRaster`] is finished. Thus, a failure leaves any
+/// existing output intact.
+///
+/// If `arg.cog` is set, the temporary file is a GeoTIFF,
+/// which is translated to a Cloud-Optimized GeoTIFF (with
+/// overviews) when finished, as GDAL's COG driver can't
+/// write a dataset chunk by chunk.
 pub fn create_output_raster<T: GdalType>(
     arg: &OutputArgs,
     ds: &Dataset,
     num_bands: isize,
     no_val: Option<f64>,
-) -> Result<Dataset> {
-    let mut out_ds = {
-        let driver = DriverManager::get_driver_by_name(&arg.driver)?;
+) -> Result<OutputRaster> {
+    if !arg.overwrite && arg.path.exists() {
+        bail!("output {} exists (use --overwrite)", arg.path.display());
+    }
+    let temp_path = partial_path(&arg.path);
+    if arg.cog && temp_path == arg.path {
+        bail!("cannot write a COG to {}", arg.path.display());
+    }
+    let dataset = {
+        let driver = if arg.cog { "GTIFF" } else { &arg.driver };
+        let driver = DriverManager::get_driver_by_name(driver)?;
         let (width, height) = ds.raster_size();
         driver
-            .create_with_band_type::<T, _>(&arg.path, width as isize, height as isize, num_bands)
+            .create_with_band_type::<T, _>(&temp_path, width as isize, height as isize, num_bands)
             .with_context(|| format!("creating dataset {}", arg.path.display()))?
     };
+    let mut out_ds = OutputRaster {
+        dataset: Some(dataset),
+        temp_path,
+        path: arg.path.clone(),
+        cog: arg.cog,
+        overview_resampling: "AVERAGE",
+    };
     if let Some(no_val) = no_val {
         for i in 1..=num_bands {
             out_ds.rasterband(i)?.set_no_data_value(Some(no_val))?;
@@ -57,6 +289,136 @@ pub fn create_output_raster<T: GdalType>(
     Ok(out_ds)
 }
 
+/// Path of the temporary file an output is written to,
+/// before it is renamed to `path`. GDAL's virtual file
+/// systems (eg. `/vsimem/`) are written to directly.
+fn partial_path(path: &Path) -> PathBuf {
+    match path.file_name() {
+        Some(name) if !path.to_string_lossy().starts_with("/vsi") => {
+            let mut temp_name = std::ffi::OsString::from(".");
+            temp_name.push(name);
+            temp_name.push(".partial");
+            path.with_file_name(temp_name)
+        }
+        _ => path.to_owned(),
+    }
+}
+
+/// An output raster being written (see
+/// [`create_output_raster`]). Derefs to the `Dataset`.
+/// Call [`OutputRaster::finish`] once the output is
+/// complete; if dropped before that, the partial output is
+/// removed.
+pub struct OutputRaster {
+    dataset: Option<Dataset>,
+    temp_path: PathBuf,
+    path: PathBuf,
+    cog: bool,
+    overview_resampling: &'static str,
+}
+
+impl OutputRaster {
+    /// Set the resampling of the overviews of a COG output
+    /// (default: `AVERAGE`; see `GDALBuildOverviews`).
+    pub fn with_overview_resampling(mut self, resampling: &'static str) -> Self {
+        self.overview_resampling = resampling;
+        self
+    }
+
+    /// Close the dataset, and move it to the output path.
+    pub fn finish(mut self) -> Result<()> {
+        drop(self.dataset.take());
+        if self.cog {
+            let cog_path = self.temp_path.with_extension("cog.partial");
+            let written = write_cog(&self.temp_path, &cog_path, self.overview_resampling);
+            let _ = std::fs::remove_file(&self.temp_path);
+            self.temp_path = cog_path;
+            written.with_context(|| format!("writing COG {}", self.path.display()))?;
+        }
+        if self.temp_path != self.path {
+            persist(&self.temp_path, &self.path)
+                .with_context(|| format!("writing dataset {}", self.path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::ops::Deref for OutputRaster {
+    type Target = Dataset;
+    fn deref(&self) -> &Dataset {
+        self.dataset.as_ref().expect("output raster is closed")
+    }
+}
+
+impl std::ops::DerefMut for OutputRaster {
+    fn deref_mut(&mut self) -> &mut Dataset {
+        self.dataset.as_mut().expect("output raster is closed")
+    }
+}
+
+impl Drop for OutputRaster {
+    fn drop(&mut self) {
+        // Discard the partial output, if not finished (or if
+        // finishing failed)
+        drop(self.dataset.take());
+        if self.temp_path != self.path {
+            let _ = std::fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// Block size (and the size of the smallest overview) of
+/// the COG outputs; the default of GDAL's COG driver.
+const COG_BLOCK_SIZE: usize = 512;
+
+/// Translate the (closed) GeoTIFF at `src` into a
+/// Cloud-Optimized GeoTIFF at `dst`. The overviews are built
+/// on `src` first, and copied over by the COG driver.
+fn write_cog(src: &Path, dst: &Path, resampling: &str) -> Result<()> {
+    build_overviews(src, resampling, COG_BLOCK_SIZE)?;
+    let driver = DriverManager::get_driver_by_name("COG")?;
+    read_dataset(src)?.create_copy(&driver, dst, &[])?;
+    Ok(())
+}
+
+/// Move the file `from` to `to`, replacing it. Falls back to
+/// a copy if they are on different file systems.
+fn persist(from: &Path, to: &Path) -> Result<()> {
+    if std::fs::rename(from, to).is_err() {
+        std::fs::copy(from, to)?;
+        std::fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Power-of-two decimation factors for the overviews of a
+/// `width` x `height` raster: halve the resolution until
+/// the largest dimension fits within `min_size` pixels.
+pub fn overview_levels(width: usize, height: usize, min_size: usize) -> Vec<i32> {
+    let size = width.max(height);
+    let mut levels = vec![];
+    let mut factor = 2;
+    while size > min_size * factor / 2 {
+        levels.push(factor as i32);
+        factor *= 2;
+    }
+    levels
+}
+
+/// Build internal overviews of the (closed) raster at
+/// `path`, re-opening it for update. Returns the number of
+/// overview levels built.
+pub fn build_overviews(path: &Path, resampling: &str, min_size: usize) -> Result<usize> {
+    let mut ds = edit_dataset(path)?;
+    let (width, height) = ds.raster_size();
+    let levels = overview_levels(width, height, min_size);
+    if !levels.is_empty() {
+        ds.build_overviews(resampling, &levels, &[])
+            .with_context(|| format!("building overviews of {}", path.display()))?;
+    }
+    Ok(levels.len())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -113,6 +475,131 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_output_raster() -> Result<()> {
+        let driver = DriverManager::get_driver_by_name("MEM")?;
+        let ds = driver.create_with_band_type::<u8, _>("", 4, 3, 1)?;
+
+        let tmp_dir = TempDir::new("rasters_test").unwrap();
+        let mut arg = OutputArgs {
+            path: tmp_dir.path().join("out.tif"),
+            driver: String::from("GTIFF"),
+            overwrite: false,
+            cog: false,
+        };
+        std::fs::write(&arg.path, b"prior output")?;
+
+        // Existing outputs are kept, unless overwriting
+        assert!(create_output_raster::<f64>(&arg, &ds, 1, None).is_err());
+        arg.overwrite = true;
+
+        // A failed write (dropped before finishing) leaves
+        // the prior output intact
+        {
+            let out_ds = create_output_raster::<f64>(&arg, &ds, 1, None)?;
+            assert!(partial_path(&arg.path).exists());
+            drop(out_ds);
+        }
+        assert_eq!(std::fs::read(&arg.path)?, b"prior output");
+        assert!(!partial_path(&arg.path).exists());
+
+        // A finished write replaces it
+        let out_ds = create_output_raster::<f64>(&arg, &ds, 1, Some(-1.))?;
+        out_ds.finish()?;
+        assert!(!partial_path(&arg.path).exists());
+        let ds = read_dataset(&arg.path)?;
+        assert_eq!(ds.raster_size(), (4, 3));
+        assert_eq!(ds.rasterband(1)?.no_data_value(), Some(-1.));
+
+        // A COG output has overviews
+        let driver = DriverManager::get_driver_by_name("MEM")?;
+        let ds = driver.create_with_band_type::<u8, _>("", 1100, 700, 1)?;
+        arg.cog = true;
+        create_output_raster::<u8>(&arg, &ds, 1, None)?.finish()?;
+        let ds = read_dataset(&arg.path)?;
+        assert_eq!(ds.driver().short_name(), "GTiff");
+        assert_eq!(ds.rasterband(1)?.overview_count()?, 2);
+        assert_eq!(std::fs::read_dir(tmp_dir.path())?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_remote() {
+        assert!(is_remote(Path::new("/vsicurl/https://example.com/a.tif")));
+        assert!(is_remote(Path::new("/vsis3/bucket/a.tif")));
+        assert!(is_remote(Path::new("/vsis3_streaming/bucket/a.tif")));
+        assert!(!is_remote(Path::new("/vsimem/a.tif")));
+        assert!(!is_remote(Path::new("/vsis3data/a.tif")));
+        assert!(!is_remote(Path::new("data/a.tif")));
+    }
+
+    #[test]
+    fn test_partial_path() {
+        assert_eq!(
+            partial_path(Path::new("data/out.tif")),
+            Path::new("data/.out.tif.partial")
+        );
+        assert_eq!(
+            partial_path(Path::new("/vsimem/out.tif")),
+            Path::new("/vsimem/out.tif")
+        );
+        assert_eq!(
+            partial_path(Path::new("/vsis3/bucket/out.tif")),
+            Path::new("/vsis3/bucket/out.tif")
+        );
+    }
+
+    #[test]
+    fn test_overviews() -> Result<()> {
+        assert_eq!(overview_levels(256, 100, 256), Vec::<i32>::new());
+        assert_eq!(overview_levels(257, 100, 256), vec![2]);
+        assert_eq!(overview_levels(100, 1100, 256), vec![2, 4, 8]);
+
+        let driver = DriverManager::get_driver_by_name("GTIFF")?;
+        let tmp_dir = TempDir::new("rasters_test").unwrap();
+        let path = tmp_dir.path().join("overviews.tif");
+        {
+            driver.create_with_band_type::<u8, _>(&path, 600, 300, 1)?;
+        }
+
+        assert_eq!(build_overviews(&path, "NEAREST", 128)?, 3);
+        let ds = read_dataset(&path)?;
+        assert_eq!(ds.rasterband(1)?.overview_count()?, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn chunk_config_round_trip() -> Result<()> {
+        use rasters::chunking::ChunkConfig;
+        let tmp_dir = TempDir::new("rasters_test").unwrap();
+        let path = tmp_dir.path().join("chunks.bin");
+
+        let cfg = ChunkConfig::with_dims(1000, 1000)
+            .add_block_size(7)
+            .with_tile_dims(100, 30)
+            .with_padding(2)
+            .with_x_padding(3)
+            .with_start(11)
+            .with_end(900);
+        write_bin(&path, &cfg)?;
+
+        let read: ChunkConfig = read_bin(&path)?;
+        assert_eq!(read, cfg);
+        assert_eq!(read.windows(), cfg.windows());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_nodata() -> Result<()> {
+        assert_eq!(parse_nodata("-9999")?, -9999.);
+        assert_eq!(parse_nodata("0.5")?, 0.5);
+        assert!(parse_nodata("nan")?.is_nan());
+        assert!(parse_nodata("NaN")?.is_nan());
+        assert!(parse_nodata("none").is_err());
+        Ok(())
+    }
 }
 
 pub fn write_bin<T: serde::Serialize>(path: &Path, data: &T) -> Result<()> {
@@ -128,6 +615,79 @@ pub fn read_bin<T: for<'a> serde::Deserialize<'a>>(path: &Path) -> Result<T> {
     Ok(serde_cbor::from_slice(file.as_ref())?)
 }
 
+use rasters::histogram::Histogram;
+/// A histogram (eg. of the difference computed by
+/// `raster-diff`), in a form that is easy to plot.
+#[derive(Debug, serde_derive::Serialize, Clone)]
+pub struct HistogramOutput {
+    pub count: usize,
+    pub underflow: usize,
+    pub overflow: usize,
+    pub bins: Vec<HistogramBin>,
+}
+
+#[derive(Debug, serde_derive::Serialize, Clone)]
+pub struct HistogramBin {
+    pub lower: f64,
+    pub upper: f64,
+    pub count: usize,
+}
+
+impl<'a> From<&Histogram<'a>> for HistogramOutput {
+    fn from(hist: &Histogram<'a>) -> Self {
+        HistogramOutput {
+            count: hist.count(),
+            underflow: hist.underflow(),
+            overflow: hist.overflow(),
+            bins: hist
+                .to_bins()
+                .into_iter()
+                .map(|(lower, upper, count)| HistogramBin {
+                    lower,
+                    upper,
+                    count,
+                })
+                .collect(),
+        }
+    }
+}
+
+use rasters::histogram::{Config, Histogram2d};
+/// A 2D histogram (eg. of the values of the first input and
+/// the difference computed by `raster-diff`), in a form
+/// that is easy to plot.
+#[derive(Debug, serde_derive::Serialize, Clone)]
+pub struct Histogram2dOutput {
+    pub count: usize,
+    /// Edges of the bins of the `x` values
+    pub x_edges: Vec<f64>,
+    /// Edges of the bins of the `y` values
+    pub y_edges: Vec<f64>,
+    /// Counts by the bin of `x` (rows), and of `y`
+    /// (columns); the first (resp. last) row and column are
+    /// the underflow (resp. overflow).
+    pub counts: Vec<Vec<usize>>,
+}
+
+/// The edges of the bins of `cfg`.
+fn bin_edges(cfg: &Config) -> Vec<f64> {
+    (0..cfg.len())
+        .map(|bin| cfg.bin_edges(bin).0)
+        .chain(std::iter::once(cfg.max()))
+        .collect()
+}
+
+impl<'a> From<&Histogram2d<'a>> for Histogram2dOutput {
+    fn from(hist: &Histogram2d<'a>) -> Self {
+        Histogram2dOutput {
+            count: hist.count(),
+            x_edges: bin_edges(hist.config().x()),
+            y_edges: bin_edges(hist.config().y()),
+            counts: hist.rows().map(|row| row.to_vec()).collect(),
+        }
+    }
+}
+
 use serde::Serialize;
 pub fn write_json<T: Serialize>(path: &Path, json: &T) -> Result<()> {
     let file = File::create(path)?;
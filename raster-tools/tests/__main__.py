@@ -2,3 +2,6 @@ from .test_stats import *
 from .test_diff import *
 from .test_mask import *
 from .test_tile import *
+from .test_slope import *
+from .test_hillshade import *
+from .test_hist_dump import *
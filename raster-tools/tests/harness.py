@@ -45,11 +45,11 @@ def create_random_raster(path, width, height, bands=1, dist=None):
     data = dist(size=(height, width, bands))
     return create_raster(path, data)
 
-def run_cargo(bin_name, *args, build=None):
+def run_cargo(bin_name, *args, build=None, chunk_args=('-c', '1')):
     cargs = ['cargo', 'run', '--quiet', '--manifest-path', MANIFEST_PATH]
     if build: cargs.append( f'--{build}' )
     cargs += ['--bin', bin_name, '--']
-    cargs += ['-c', str(1)]
+    cargs += chunk_args
     cargs += args
     output = check_output(cargs)
     if output:
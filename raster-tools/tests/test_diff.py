@@ -1,9 +1,12 @@
-from .harness import create_random_raster, run_cargo, create_raster, assert_is_close
+from .harness import create_random_raster, run_cargo, create_raster, assert_is_close, read_raster
 from tempfile import TemporaryDirectory
+from subprocess import CalledProcessError
 
 from pathlib import Path
+from osgeo import gdal, osr
 import numpy as N
 from math import sqrt
+import json
 
 with TemporaryDirectory(prefix="test-raster-diff-") as base_path:
     base_path = Path(base_path)
@@ -16,11 +19,118 @@ with TemporaryDirectory(prefix="test-raster-diff-") as base_path:
     data2 = N.random.normal(size=(32, 64, 1))
     create_raster(raster2_path, data2).SetGeoTransform([-128., 4., 0., -64., 0., 4.])
 
-    stats = run_cargo('raster-diff', str(raster1_path), str(raster2_path))['stats']['diff']
-    diff = data2[8:-8, 16:-16, 0].repeat(4, axis=0).repeat(4, axis=1) - data1[:,:,0]
+    output = run_cargo('raster-diff', str(raster1_path), str(raster2_path))
+    stats = output['stats']['diff']
+    sampled = data2[8:-8, 16:-16, 0].repeat(4, axis=0).repeat(4, axis=1)
+    diff = sampled - data1[:,:,0]
 
     assert_is_close(stats['max'], N.max(diff), desc='max')
     assert_is_close(stats['min'], N.min(diff), desc='min')
     assert_is_close(stats['sum'], N.sum(diff), desc='sum')
+    correlation = N.corrcoef(data1[:,:,0].ravel(), sampled.ravel())[0, 1]
+    assert abs(output['correlation'] - correlation) < 1e-6, 'correlation'
+
+    # Second input resampled to the grid of the first
+    resampled_path = base_path / "resampled.tif"
+    run_cargo('raster-diff', '--resampled', str(resampled_path),
+              str(raster1_path), str(raster2_path))
+    resampled = read_raster(resampled_path)[0]
+    expected = data2[8:-8, 16:-16, 0].repeat(4, axis=0).repeat(4, axis=1)
+    assert N.allclose(resampled, expected), "resampled output"
+
+    # Multi-band inputs: the second input has an extra band
+    raster3_path = base_path / "raster3.tif"
+    data3 = N.random.normal(size=(64, 128, 2))
+    create_raster(raster3_path, data3)
+
+    raster4_path = base_path / "raster4.tif"
+    data4 = N.random.normal(size=(64, 128, 3))
+    create_raster(raster4_path, data4)
+
+    outputs = run_cargo('raster-diff', '--all-bands', str(raster3_path), str(raster4_path))
+    assert len(outputs) == 2, f"expected 2 bands: {outputs}"
+    for b, output in enumerate(outputs):
+        assert output['band_1'] == b + 1 and output['band_2'] == b + 1
+        diff = data4[:, :, b] - data3[:, :, b]
+        assert_is_close(output['stats']['diff']['sum'], N.sum(diff), desc=f'band {b + 1} sum')
+
+    stats = run_cargo('raster-diff', '--band-a', '2', '--band-b', '3',
+                      str(raster3_path), str(raster4_path))['stats']['diff']
+    assert_is_close(stats['sum'], N.sum(data4[:, :, 2] - data3[:, :, 1]), desc='band 2-3 sum')
+
+    # Mask at half the resolution of the first input
+    mask_path = base_path / "mask.tif"
+    mask = (N.random.uniform(size=(32, 64, 1)) > 0.5).astype(N.uint8)
+    create_raster(mask_path, mask).SetGeoTransform([-64., 2., 0., -32., 0., 2.])
+
+    stats = run_cargo('raster-diff', '--mask', str(mask_path),
+                      str(raster1_path), str(raster2_path))['stats']
+    valid = mask[:, :, 0].repeat(2, axis=0).repeat(2, axis=1) != 0
+    diff = data2[8:-8, 16:-16, 0].repeat(4, axis=0).repeat(4, axis=1) - data1[:,:,0]
+    assert stats['count'] == N.sum(valid), f"count: {stats['count']} == {N.sum(valid)}"
+    assert_is_close(stats['diff']['sum'], N.sum(diff[valid]), desc='masked sum')
+
+    # Absolute difference output, with signed stats
+    out_path = base_path / "abs.tif"
+    stats = run_cargo('raster-diff', '--negate', '--abs', '--output', str(out_path),
+                      str(raster3_path), str(raster4_path))['stats']['diff']
+    assert_is_close(stats['sum'], N.sum(data4[:, :, 0] - data3[:, :, 0]), desc='signed sum')
+    out = read_raster(out_path)[0]
+    assert N.allclose(out, N.abs(data4[:, :, 0] - data3[:, :, 0])), "abs output"
+
+    # Region from a vector file: union of its features
+    rects = [(4, 4, 20, 12), (16, 8, 40, 30)]
+    def ring(x0, y0, x1, y1):
+        return [[x0, y0], [x1, y0], [x1, y1], [x0, y1], [x0, y0]]
+    polygon_path = base_path / "region.geojson"
+    polygon_path.write_text(json.dumps({
+        "type": "FeatureCollection",
+        "features": [
+            {"type": "Feature", "properties": {},
+             "geometry": {"type": "Polygon", "coordinates": [ring(*r)]}}
+            for r in rects
+        ],
+    }))
+    wkt = 'MULTIPOLYGON(' + ', '.join(
+        '((' + ', '.join(f'{x} {y}' for x, y in ring(*r)) + '))' for r in rects
+    ) + ')'
+
+    from_file = run_cargo('raster-diff', '--polygon-file', str(polygon_path),
+                          str(raster3_path), str(raster4_path))['stats']
+    from_wkt = run_cargo('raster-diff', '--polygon', wkt,
+                         str(raster3_path), str(raster4_path))['stats']
+    assert 0 < from_file['count'] < 64 * 128, f"count: {from_file['count']}"
+    assert from_file['count'] == from_wkt['count'], f"count: {from_file['count']} == {from_wkt['count']}"
+    assert_is_close(from_file['diff']['sum'], from_wkt['diff']['sum'], desc='region sum')
+
+    # Discretized output: the bin of each difference, which
+    # requires a histogram of a given range
+    disc_path = base_path / "disc.tif"
+    run_cargo('raster-diff', '--output', str(disc_path), '--output-type', 'discretized',
+              '--hist', str(base_path / "disc.json"), '--min', '-2', '--max', '2', '--bins', '4',
+              str(raster3_path), str(raster4_path))
+    disc = read_raster(disc_path)[0]
+    expected = N.clip(N.floor(data4[:, :, 0] - data3[:, :, 0] + 2), -1, 4)
+    assert N.mean(disc == expected) > 0.99, "discretized output"
+    try:
+        run_cargo('raster-diff', '--output', str(disc_path), '--overwrite',
+                  '--output-type', 'discretized', '--hist', str(base_path / "disc.json"),
+                  str(raster3_path), str(raster4_path))
+        assert False, "discretized output of an adaptive histogram is not refused"
+    except CalledProcessError:
+        pass
+
+    # Inputs in different CRS are refused
+    for path, epsg in [(raster3_path, 32643), (raster4_path, 4326)]:
+        srs = osr.SpatialReference()
+        srs.ImportFromEPSG(epsg)
+        ds = gdal.Open(str(path), gdal.GA_Update)
+        ds.SetProjection(srs.ExportToWkt())
+        del ds
+    try:
+        run_cargo('raster-diff', str(raster3_path), str(raster4_path))
+        assert False, "inputs in different CRS are not refused"
+    except CalledProcessError:
+        pass
 
 print("Test raster-diff succeeded")
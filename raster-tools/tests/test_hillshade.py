@@ -0,0 +1,33 @@
+from .harness import run_cargo, create_raster, read_raster
+from tempfile import TemporaryDirectory
+
+from pathlib import Path
+import numpy as N
+from math import radians, sin
+
+with TemporaryDirectory(prefix="test-raster-hillshade-") as base_path:
+    base_path = Path(base_path)
+
+    raster_path = base_path / "raster.tif"
+    out_path = base_path / "shade.tif"
+
+    # Flat DEM (10 unit pixels, north-up) with a no-data
+    # pixel
+    data = N.full((20, 30, 1), 100.)
+    data[10, 10, 0] = -9999.
+    ds = create_raster(raster_path, data)
+    ds.SetGeoTransform((0., 10., 0., 0., 0., -10.))
+    ds.GetRasterBand(1).SetNoDataValue(-9999.)
+    ds = None
+
+    run_cargo('raster-hillshade', str(raster_path), str(out_path), '--altitude', '30')
+
+    shade = read_raster(out_path)[0]
+    # A flat surface is lit by the cosine of the incidence
+    # angle (90 - altitude), scaled to 1..255
+    expected = round(1 + 254 * sin(radians(30)))
+    valid = N.ones(shade.shape, dtype=bool)
+    valid[0, :] = valid[-1, :] = valid[:, 0] = valid[:, -1] = False
+    valid[9:12, 9:12] = False
+    assert N.all(shade[valid] == expected), f"shade is {expected}"
+    assert N.all(shade[~valid] == 0), "edges and no-data are 0"
@@ -0,0 +1,59 @@
+from .harness import run_cargo, create_raster
+from tempfile import TemporaryDirectory
+
+from pathlib import Path
+import numpy as N
+import json
+
+with TemporaryDirectory(prefix="test-raster-hist-dump-") as base_path:
+    base_path = Path(base_path)
+
+    raster1_path = base_path / "raster1.tif"
+    create_raster(raster1_path, N.random.normal(size=(32, 32, 1)))
+    raster2_path = base_path / "raster2.tif"
+    create_raster(raster2_path, N.random.normal(size=(32, 32, 1)))
+
+    # The stored histogram dumps to the JSON listing
+    hist_args = ('--min', '-2', '--max', '2', '--bins', '8')
+    bin_path = base_path / "hist.bin"
+    json_path = base_path / "hist.json"
+    for path in (bin_path, json_path):
+        run_cargo('raster-diff', str(raster1_path), str(raster2_path), '--hist', str(path), *hist_args)
+
+    dumped = run_cargo('raster-hist-dump', str(bin_path), chunk_args=())
+    with open(json_path) as f:
+        assert dumped == json.load(f), "dumped histogram matches the JSON output"
+    assert dumped['count'] == 32 * 32, "histogram count"
+
+    # Without a range, the histogram adapts to the values
+    adaptive_path = base_path / "adaptive.json"
+    run_cargo('raster-diff', str(raster1_path), str(raster2_path), '--hist', str(adaptive_path), '--bins', '16')
+    with open(adaptive_path) as f:
+        adaptive = json.load(f)
+    assert adaptive['count'] == 32 * 32, "adaptive histogram count"
+    assert adaptive['underflow'] == 0 and adaptive['overflow'] == 0, "adaptive histogram range"
+    assert len(adaptive['bins']) == 16, "adaptive histogram bins"
+
+    # Log-spaced bins of either sign
+    symlog_path = base_path / "symlog.json"
+    run_cargo('raster-diff', str(raster1_path), str(raster2_path), '--hist', str(symlog_path),
+              '--min', '0.01', '--max', '100', '--bins', '4', '--hist-scale', 'symlog')
+    with open(symlog_path) as f:
+        symlog = json.load(f)
+    assert len(symlog['bins']) == 9, "symlog histogram bins"
+    assert abs(symlog['bins'][5]['upper'] - 0.1) < 1e-9, "symlog histogram edges"
+    assert symlog['count'] == 32 * 32, "symlog histogram count"
+
+    # 2D histogram of the values of the first input and the
+    # difference, whose marginal is the histogram
+    joint_path = base_path / "joint.json"
+    run_cargo('raster-diff', str(raster1_path), str(raster2_path), '--hist', str(joint_path), *hist_args,
+              '--hist2d', '--value-min', '-2', '--value-max', '2', '--value-bins', '4')
+    with open(base_path / "joint.2d.json") as f:
+        joint = json.load(f)
+    with open(joint_path) as f:
+        assert json.load(f) == dumped, "histogram along with the 2D histogram"
+    counts = N.array(joint['counts'])
+    assert counts.shape == (6, 10), f"2D histogram shape: {counts.shape}"
+    assert counts.sum() == joint['count'] == 32 * 32, "2D histogram count"
+    assert list(counts.sum(axis=0)[1:-1]) == [b['count'] for b in dumped['bins']], "2D histogram marginal"
@@ -1,7 +1,9 @@
 from .harness import create_random_raster, run_cargo, create_raster, assert_is_close, read_raster
 from tempfile import TemporaryDirectory
+from subprocess import CalledProcessError
 
 from pathlib import Path
+from osgeo import gdal
 import numpy as N
 from math import sqrt
 
@@ -22,4 +24,61 @@ with TemporaryDirectory(prefix="test-raster-mask-") as base_path:
     odata = odata.astype(N.bool).astype(N.uint8)
     assert N.all((cdata + odata) == 1), f"mask is correct"
 
+with TemporaryDirectory(prefix="test-raster-mask-") as base_path:
+    base_path = Path(base_path)
+
+    raster_path = base_path / "raster.tif"
+    out_path = base_path / "mask.tif"
+    data = N.random.randint(low=2, size=(700, 1100, 1))
+    create_raster(raster_path, data)
+
+    run_cargo('raster-mask', str(raster_path), str(out_path), '--overviews', chunk_args=())
+
+    # 1100 px wide: levels 2, 4 and 8 (down to <= 256 px)
+    ds = gdal.Open(str(out_path))
+    assert ds.GetRasterBand(1).GetOverviewCount() == 3, "overview levels"
+
+    # A COG has overviews down to a single 512 px block
+    cog_path = base_path / "mask-cog.tif"
+    run_cargo('raster-mask', str(raster_path), str(cog_path), '--cog', chunk_args=())
+    info = gdal.Info(str(cog_path), format='json')
+    assert info['metadata']['IMAGE_STRUCTURE']['LAYOUT'] == 'COG', "COG layout"
+    assert info['bands'][0]['block'] == [512, 512], "COG tiling"
+    assert len(info['bands'][0]['overviews']) == 2, "COG overview levels"
+    assert N.all(read_raster(cog_path)[0] == read_raster(out_path)[0]), "COG mask is correct"
+
+    odata = read_raster(str(out_path))[0]
+    cdata = (data[:, :, 0] != 0).astype(N.uint8)
+    assert N.all(odata.astype(N.bool).astype(N.uint8) == cdata), f"mask is correct"
+
+with TemporaryDirectory(prefix="test-raster-mask-") as base_path:
+    base_path = Path(base_path)
+
+    raster_path = base_path / "raster.tif"
+    data = N.random.randint(low=2, size=(64, 64, 4))
+    create_raster(raster_path, data)
+
+    # RGBA uses the alpha band by default
+    out_path = base_path / "mask-alpha.tif"
+    run_cargo('raster-mask', str(raster_path), str(out_path))
+    odata = read_raster(str(out_path))[0].astype(N.bool)
+    assert N.all(odata == (data[:, :, 3] != 0)), f"alpha mask is correct"
+
+    # Data only where all the bands are valid
+    out_path = base_path / "mask-all.tif"
+    run_cargo('raster-mask', str(raster_path), str(out_path), '--policy', 'all')
+    odata = read_raster(str(out_path))[0].astype(N.bool)
+    assert N.all(odata == N.all(data != 0, axis=2)), f"all-bands mask is correct"
+
+    # Existing outputs are only replaced with --overwrite
+    try:
+        run_cargo('raster-mask', str(raster_path), str(out_path))
+        assert False, "existing output is not replaced"
+    except CalledProcessError:
+        pass
+    run_cargo('raster-mask', str(raster_path), str(out_path), '--overwrite')
+    odata = read_raster(str(out_path))[0].astype(N.bool)
+    assert N.all(odata == (data[:, :, 3] != 0)), f"overwritten mask is correct"
+    assert list(base_path.glob(".*.partial")) == [], "no partial outputs remain"
+
 print("Test raster-mask succeeded")
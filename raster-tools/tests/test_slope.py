@@ -0,0 +1,28 @@
+from .harness import run_cargo, create_raster, read_raster
+from tempfile import TemporaryDirectory
+
+from pathlib import Path
+import numpy as N
+from math import atan, degrees
+
+with TemporaryDirectory(prefix="test-raster-slope-") as base_path:
+    base_path = Path(base_path)
+
+    raster_path = base_path / "raster.tif"
+    out_path = base_path / "slope.tif"
+
+    # Plane rising by 3 per unit to the east, and by 4 per
+    # unit to the south (10 unit pixels, north-up)
+    i, j = N.mgrid[0:20, 0:30]
+    data = (30. * j - 40. * i).reshape((20, 30, 1))
+    ds = create_raster(raster_path, data)
+    ds.SetGeoTransform((0., 10., 0., 0., 0., -10.))
+    ds = None
+
+    run_cargo('raster-slope', str(raster_path), str(out_path), '--units', 'percent', '--aspect')
+
+    slope, aspect = read_raster(out_path)
+    assert N.all(N.isnan(slope[0, :])) and N.all(N.isnan(slope[:, -1])), "edges are no-data"
+    assert N.allclose(slope[1:-1, 1:-1], 500.), "slope is 500%"
+    expected = 180. + degrees(atan(0.75))
+    assert N.allclose(aspect[1:-1, 1:-1], expected), f"aspect is {expected}"
@@ -4,6 +4,9 @@ from tempfile import TemporaryDirectory
 from pathlib import Path
 import numpy as N
 from math import sqrt
+from subprocess import CalledProcessError
+from osgeo import osr
+import json
 
 with TemporaryDirectory(prefix="test-raster-stats-") as base_path:
     base_path = Path(base_path)
@@ -13,11 +16,130 @@ with TemporaryDirectory(prefix="test-raster-stats-") as base_path:
     data = N.random.normal(size=(64, 64, 1))
     create_raster(raster_path, data)
 
-    stats = run_cargo('raster-stats', str(raster_path))[0]
+    # Budget a single row (64 f64 pixels) per chunk
+    stats = run_cargo('raster-stats', str(raster_path), chunk_args=('-m', str(64 * 8)))[0]
 
     assert_is_close(stats['max'], N.max(data), desc='max')
     assert_is_close(stats['min'], N.min(data), desc='min')
     assert_is_close(stats['sum'], N.sum(data), desc='sum')
-    assert_is_close(sqrt(stats['sum_2'] / stats['count']), N.std(data), desc='std')
+    assert_is_close(stats['mean'], N.mean(data), desc='mean')
+    assert_is_close(sqrt(stats['m2'] / stats['count']), N.std(data), desc='std')
+
+    # A budget below a row of blocks reads a row per chunk
+    stats = run_cargo('raster-stats', str(raster_path), chunk_args=('-m', '8'))[0]
+    assert_is_close(stats['sum'], N.sum(data), desc='small budget sum')
+
+    # Sparse raster: only a few rows are valid. Chunks
+    # outside the polygon are skipped without reading.
+    sparse_path = base_path / "sparse.tif"
+    sparse = N.full((64, 64, 1), N.nan)
+    sparse[40:48] = data[40:48]
+    create_raster(sparse_path, sparse)
+
+    stats = run_cargo('raster-stats', str(sparse_path),
+                      '--polygon', 'POLYGON((0 40, 64 40, 64 48, 0 48, 0 40))',
+                      chunk_args=('-m', str(64 * 8)))[0]
+    valid = data[40:48]
+
+    assert stats['count'] == valid.size, f"count: {stats['count']} == {valid.size}"
+    assert_is_close(stats['max'], N.max(valid), desc='sparse max')
+    assert_is_close(stats['min'], N.min(valid), desc='sparse min')
+    assert_is_close(stats['sum'], N.sum(valid), desc='sparse sum')
+
+    # Multi-band raster: stats of selected bands
+    multi_path = base_path / "multi.tif"
+    multi = N.random.normal(size=(64, 64, 3))
+    create_raster(multi_path, multi)
+
+    stats = run_cargo('raster-stats', str(multi_path), '--bands', '1,3',
+                      chunk_args=('-m', str(64 * 8 * 2)))
+    assert sorted(stats.keys()) == ['1', '3'], f"bands: {list(stats.keys())}"
+    for band in (1, 3):
+        assert_is_close(stats[str(band)][0]['sum'], N.sum(multi[:, :, band - 1]), desc=f'band {band} sum')
+        assert_is_close(stats[str(band)][0]['max'], N.max(multi[:, :, band - 1]), desc=f'band {band} max')
+
+    # Polygons in WGS84, against a raster in UTM
+    utm = osr.SpatialReference()
+    utm.ImportFromEPSG(32643)
+    wgs84 = osr.SpatialReference()
+    wgs84.ImportFromEPSG(4326)
+    wgs84.SetAxisMappingStrategy(osr.OAMS_TRADITIONAL_GIS_ORDER)
+    to_wgs84 = osr.CoordinateTransformation(utm, wgs84)
+
+    utm_path = base_path / "utm.tif"
+    (x_0, y_0) = (500000., 2000000.)
+    ds = create_raster(utm_path, data)
+    ds.SetGeoTransform([x_0, 10., 0., y_0, 0., -10.])
+    ds.SetProjection(utm.ExportToWkt())
+    ds = None
+
+    # Edges between pixel centers: the count is robust to
+    # rounding errors of the reprojection
+    ring = [(x_0 + 10 * x, y_0 - 10 * y) for (x, y) in
+            [(4.25, 8.25), (40.25, 8.25), (40.25, 30.25), (4.25, 30.25), (4.25, 8.25)]]
+    ring_wgs84 = [to_wgs84.TransformPoint(x, y)[:2] for (x, y) in ring]
+    def wkt(ring):
+        return 'POLYGON((' + ', '.join(f'{x!r} {y!r}' for (x, y) in ring) + '))'
+
+    polygons_path = base_path / "polygons.geojson"
+    polygons_path.write_text(json.dumps({
+        "type": "FeatureCollection",
+        "features": [{"type": "Feature", "properties": {},
+                      "geometry": {"type": "Polygon", "coordinates": [ring_wgs84]}}],
+    }))
+
+    expected = data[8:30, 4:40]
+    from_file = run_cargo('raster-stats', str(utm_path), '--polygons-file', str(polygons_path),
+                          chunk_args=('-m', str(64 * 8)))[0]
+    from_wkt = run_cargo('raster-stats', str(utm_path), '--polygon', wkt(ring_wgs84),
+                         '--polygon-srs', '4326', chunk_args=('-m', str(64 * 8)))[0]
+    for stats in (from_file, from_wkt):
+        assert stats['count'] == expected.size, f"count: {stats['count']} == {expected.size}"
+        assert_is_close(stats['sum'], N.sum(expected), desc='reprojected sum')
+
+    # Reprojection requires the raster to have a projection
+    try:
+        run_cargo('raster-stats', str(raster_path), '--polygon', wkt(ring_wgs84),
+                  '--polygon-srs', '4326', chunk_args=())
+        assert False, "expected failure without a raster projection"
+    except CalledProcessError:
+        pass
+
+    # Area of the valid pixels: 10m x 10m pixels in UTM
+    areas = run_cargo('raster-stats', str(utm_path), '--area', chunk_args=('-m', str(64 * 8)))[0]
+    assert areas['count'] == data.size, f"count: {areas['count']} == {data.size}"
+    assert_is_close(areas['area'], data.size * 100., desc='UTM area')
+
+    # In WGS84, the area of a pixel varies with the latitude;
+    # the rows centered at 60N are about half as large as
+    # at the equator
+    geo_path = base_path / "wgs84.tif"
+    ds = create_raster(geo_path, sparse)
+    ds.SetGeoTransform([75., 0.01, 0., 60.4, 0., -0.01])
+    ds.SetProjection(wgs84.ExportToWkt())
+    ds = None
+    north = run_cargo('raster-stats', str(geo_path), '--area', chunk_args=())[0]
+
+    ds = create_raster(geo_path, sparse)
+    ds.SetGeoTransform([75., 0.01, 0., 0.4, 0., -0.01])
+    ds.SetProjection(wgs84.ExportToWkt())
+    ds = None
+    equator = run_cargo('raster-stats', str(geo_path), '--area', chunk_args=())[0]
+
+    assert north['count'] == equator['count'] == valid.size
+    assert_is_close(north['area'] / equator['area'], 0.5, desc='area ratio')
+
+    # A sentinel value the raster does not declare is
+    # excluded with --nodata
+    sentinel_path = base_path / "sentinel.tif"
+    sentinel = data.copy()
+    sentinel[:8] = -9999
+    create_raster(sentinel_path, sentinel)
+
+    stats = run_cargo('raster-stats', str(sentinel_path), '--nodata', '-9999',
+                      chunk_args=('-m', str(64 * 8)))[0]
+    assert stats['count'] == 56 * 64, f"count: {stats['count']}"
+    assert_is_close(stats['sum'], N.sum(data[8:]), desc='sentinel sum')
+    assert_is_close(stats['min'], N.min(data[8:]), desc='sentinel min')
 
 print("Test raster-stats succeeded")
@@ -3,9 +3,12 @@ from tempfile import TemporaryDirectory
 
 from pathlib import Path
 import numpy as N
-from math import sqrt
+from math import sqrt, cos, radians
 
 import json
+
+ref_projection = 'PROJCS["WGS 84 / UTM zone 43N",GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AUTHORITY["EPSG","4326"]],PROJECTION["Transverse_Mercator"],PARAMETER["latitude_of_origin",0],PARAMETER["central_meridian",75],PARAMETER["scale_factor",0.9996],PARAMETER["false_easting",500000],PARAMETER["false_northing",0],UNIT["metre",1,AUTHORITY["EPSG","9001"]],AXIS["Easting",EAST],AXIS["Northing",NORTH],AUTHORITY["EPSG","32643"]]'
+
 def load_json(path):
     return json.load(open(path))
 
@@ -40,14 +43,89 @@ with TemporaryDirectory(prefix="test-raster-tile-") as base_path:
     data = N.random.normal(size=(64, 64, 1))
     raster = create_raster(raster_path, data)
     raster.SetGeoTransform( (363737.54688808107, 0.08917409880025007, 0.0, 2059515.3774022115, 0.0, -0.08917409880023142) )
-    raster.SetProjection('PROJCS["WGS 84 / UTM zone 43N",GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AUTHORITY["EPSG","4326"]],PROJECTION["Transverse_Mercator"],PARAMETER["latitude_of_origin",0],PARAMETER["central_meridian",75],PARAMETER["scale_factor",0.9996],PARAMETER["false_easting",500000],PARAMETER["false_northing",0],UNIT["metre",1,AUTHORITY["EPSG","9001"]],AXIS["Easting",EAST],AXIS["Northing",NORTH],AUTHORITY["EPSG","32643"]]')
+    raster.SetProjection(ref_projection)
     raster = None
 
     out_path = base_path / "tiles"
     run_cargo('raster-tile', str(raster_path), str(out_path))
 
     tile_idx = load_json(out_path / "index.json")
+
+    # The index records the transform (row-major) and the web
+    # mercator bounds of the input
+    transform = tile_idx.pop('transform')
+    assert transform == [0.08917409880025007, 0.0, 363737.54688808107,
+                         0.0, -0.08917409880023142, 2059515.3774022115,
+                         0.0, 0.0, 1.0], transform
+    bounds = tile_idx.pop('bounds')
+    assert bounds['min']['x'] < bounds['max']['x'] and bounds['min']['y'] < bounds['max']['y']
+    assert_is_close(bounds['max']['x'] - bounds['min']['x'], 64 * 0.0891740988 / cos(radians(18.62)))
+
     ref_idx = load_json(Path(__file__).parent / "fixtures" / "tile-test-fab1-index.json")
     compare_index(tile_idx, ref_idx)
 
+    # TileJSON: the required fields (and the types of the
+    # optional ones) of the TileJSON 3.0.0 schema
+    tilejson = load_json(out_path / "tilejson.json")
+    assert tilejson['tilejson'] == '3.0.0'
+    assert tilejson['tiles'] == ['{z}/{y}/{x}.bin']
+    assert tilejson['scheme'] == 'tms'
+    assert tilejson['minzoom'] == min(int(z) for z in ref_idx.keys())
+    assert tilejson['maxzoom'] == max(int(z) for z in ref_idx.keys())
+    assert 0 <= tilejson['minzoom'] <= tilejson['maxzoom'] <= 30
+    (west, south, east, north) = tilejson['bounds']
+    assert -180 <= west < east <= 180 and -85.06 <= south < north <= 85.06
+    # The raster is near (73.71E, 18.62N)
+    assert abs(west - 73.71) < 0.01 and abs(north - 18.62) < 0.01, tilejson['bounds']
+    for z in tile_idx:
+        for y in tile_idx[z]:
+            for x in tile_idx[z][y]['index']:
+                tile = tilejson['tiles'][0].format(z=z, y=y, x=x)
+                assert (out_path / tile).exists(), tile
+
+    # The dry run reports the zoom levels of the index, and
+    # writes nothing
+    dry_path = base_path / "dry-run"
+    report = run_cargo('raster-tile', str(raster_path), str(dry_path), '--dry-run')
+    assert not dry_path.exists(), "dry run writes no tiles"
+    assert sorted(report['zooms'].keys(), key=int) == sorted(ref_idx.keys(), key=int)
+    assert report['min_zoom'] == min(int(z) for z in ref_idx.keys())
+    assert report['max_zoom'] == max(int(z) for z in ref_idx.keys())
+    assert report['total_tiles'] == sum(z['tiles'] for z in report['zooms'].values())
+    assert report['estimated_bytes'] == report['total_tiles'] * 256 * 256 * 2
+
+with TemporaryDirectory(prefix="test-raster-tile-") as base_path:
+    from math import cos, sin, radians
+    from subprocess import CalledProcessError
+
+    base_path = Path(base_path)
+
+    # Rotated by 5 degrees, with a no-data value for the
+    # regions outside the raster after warping
+    res, angle = 0.08917409880025007, radians(5)
+    raster_path = base_path / "rotated.tif"
+    data = N.random.normal(size=(64, 64, 1))
+    raster = create_raster(raster_path, data)
+    raster.SetGeoTransform( (363737.54688808107, res * cos(angle), res * sin(angle), 2059515.3774022115, res * sin(angle), -res * cos(angle)) )
+    raster.SetProjection(ref_projection)
+    raster.GetRasterBand(1).SetNoDataValue(-9999.)
+    raster = None
+
+    out_path = base_path / "tiles"
+    try:
+        run_cargo('raster-tile', str(raster_path), str(out_path))
+        assert False, "rotated raster requires --warp"
+    except CalledProcessError:
+        pass
+
+    run_cargo('raster-tile', str(raster_path), str(out_path), '--warp')
+    tile_idx = load_json(out_path / "index.json")
+
+    # The zoom levels are derived from the warped grid, which
+    # has about the same resolution
+    ref_idx = load_json(Path(__file__).parent / "fixtures" / "tile-test-fab1-index.json")
+    max_zoom = max(int(z) for z in tile_idx.keys() if z.isdigit())
+    ref_max_zoom = max(int(z) for z in ref_idx.keys())
+    assert abs(max_zoom - ref_max_zoom) <= 1, f"max zoom {max_zoom} ~ {ref_max_zoom}"
+
 print("Test raster-tile succeeded")
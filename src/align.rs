@@ -10,18 +10,43 @@
 //!
 //! - Extend the above functionality efficiently to work
 //! with chunks of `A`.
+//!
+//! - Optionally, resample `B` at the center of the pixel
+//! `(i, j)` in `A` (see [`sampler`]).
+//!
+//! - Read the aligned chunks of `A` and `B` (see
+//! [`AlignedPairReader`]).
+//!
+//! - Resample a chunk of `B` onto the grid of `A` (see
+//! [`resample_chunk`]).
+//!
+//! - Compute a grid covering a number of rasters (see
+//! [`common_grid`]).
 
-use geo::Rect;
 use nalgebra::{Point2, Vector2, Vector3};
+use ndarray::Array2;
 
-use crate::prelude::{BoundsExt, PixelTransform, RasterDims, RasterWindow};
+use crate::prelude::{
+    intersect_windows, is_axis_aligned, translate_transform, Bounds, BoundsExt, PixelTransform,
+    RasterDims, RasterOffset, RasterWindow, Result,
+};
 #[cfg(feature = "gdal")]
-use crate::prelude::transform_from_dataset;
+use crate::prelude::{transform_from_dataset, ChunkReader, GeoTransform, NoData};
 
 /// Transforms a `RasterWindow` from one raster to another,
 /// possibly truncating to ensure the output is valid for
-/// the target raster. The rasters are expected to be
-/// axis-aligned.
+/// the target raster. If the transform is not axis-aligned
+/// (eg. rotated), the output is the smallest window
+/// containing the transformed window, which also contains
+/// pixels outside it.
+///
+/// The window is rounded outwards, so it contains the
+/// pixels partially covered by the transformed window; use
+/// it to read the target raster (eg. the chunk of the
+/// second raster in `raster-diff`). To write the target
+/// raster, where the pixels partially covered by adjacent
+/// windows must only be written once, use
+/// [`transform_window_inner`] instead.
 ///
 /// # Arguments
 ///
@@ -31,30 +56,338 @@ use crate::prelude::transform_from_dataset;
 ///
 /// Returns the `RasterWindow` in the target raster.
 pub fn transform_window(win: RasterWindow, t: PixelTransform, dim: RasterDims) -> RasterWindow {
+    let bounds = transformed_bounds(win, &t);
+
+    // Smallest window (of whole pixels) containing the
+    // transformed region, clamped to the target raster
+    let (min_x, min_y) = (bounds.min().x.floor(), bounds.min().y.floor());
+    let (max_x, max_y) = (bounds.max().x.ceil(), bounds.max().y.ceil());
+    let win = (
+        (min_x as isize, min_y as isize),
+        ((max_x - min_x) as usize, (max_y - min_y) as usize),
+    );
+    intersect_windows(win, ((0, 0), dim)).unwrap_or(((0, 0), (0, 0)))
+}
+
+/// Transforms a `RasterWindow` from one raster to another,
+/// as [`transform_window`], but rounding inwards: the
+/// output contains only the pixels fully covered by the
+/// transformed window, and is empty (`((0, 0), (0, 0))`)
+/// if there are none. The edges within `1e-6` pixels of a
+/// pixel boundary are taken to be on it.
+///
+/// Thus, the inner windows of adjacent windows (eg. the
+/// chunks of the source raster) do not overlap, and no
+/// pixel is written twice when they are used to write the
+/// target raster; the pixels partially covered by each of
+/// them are in neither. If the transform is not
+/// axis-aligned, the output is the largest window inside
+/// the bounding box of the transformed window, which may
+/// contain pixels outside it.
+pub fn transform_window_inner(
+    win: RasterWindow,
+    t: PixelTransform,
+    dim: RasterDims,
+) -> RasterWindow {
+    const EPS: f64 = 1e-6;
+    let bounds = transformed_bounds(win, &t);
+
+    // Largest window (of whole pixels) contained in the
+    // transformed region, clamped to the target raster
+    let (min_x, min_y) = ((bounds.min().x - EPS).ceil(), (bounds.min().y - EPS).ceil());
+    let (max_x, max_y) = (
+        (bounds.max().x + EPS).floor(),
+        (bounds.max().y + EPS).floor(),
+    );
+    if max_x <= min_x || max_y <= min_y {
+        return ((0, 0), (0, 0));
+    }
+    let win = (
+        (min_x as isize, min_y as isize),
+        ((max_x - min_x) as usize, (max_y - min_y) as usize),
+    );
+    intersect_windows(win, ((0, 0), dim)).unwrap_or(((0, 0), (0, 0)))
+}
+
+/// Bounds of the image of a window under a transform, in
+/// the pixel coordinates of the target raster.
+fn transformed_bounds(win: RasterWindow, t: &PixelTransform) -> Bounds {
     let offset = win.0;
     let size = win.1;
 
-    let t_lt = t.transform_point(&Point2::new(offset.0 as f64, offset.1 as f64));
-    let t_rb = t.transform_point(&Point2::new(
-        offset.0 as f64 + size.0 as f64,
-        offset.1 as f64 + size.1 as f64,
-    ));
+    let (left, top) = (offset.0 as f64, offset.1 as f64);
+    let (right, bot) = (left + size.0 as f64, top + size.1 as f64);
+    let corners = [(left, top), (right, top), (left, bot), (right, bot)];
+    Bounds::from_points(corners.iter().map(|&(x, y)| {
+        let pt = t.transform_point(&Point2::new(x, y));
+        (pt.x, pt.y)
+    }))
+    .expect("window has corners")
+}
+
+/// Relation between the pixel grids of two rasters (see
+/// [`grids_aligned`]).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GridRelation {
+    /// The grids are the same: each pixel of the first
+    /// raster is the pixel with the same index in the second.
+    Identical,
+    /// The pixels are of the same size and orientation, but
+    /// the grid of the first raster is shifted by `(dx, dy)`
+    /// pixels relative to the second. If the shift is
+    /// integral, each pixel of the first raster is a pixel
+    /// of the second.
+    SameResolutionShifted { dx: f64, dy: f64 },
+    /// The pixels differ in size or orientation, so the
+    /// second raster must be resampled. The scales are the
+    /// width and height of a pixel of the first raster, in
+    /// pixels of the second.
+    ResampleNeeded { scale_x: f64, scale_y: f64 },
+}
+
+impl GridRelation {
+    /// Whether the grids are shifted by a fraction of a
+    /// pixel (more than `tol`), in which case the pixel
+    /// centers of the first raster are not those of the
+    /// second.
+    pub fn has_subpixel_shift(&self, tol: f64) -> bool {
+        match *self {
+            GridRelation::SameResolutionShifted { dx, dy } => {
+                (dx - dx.round()).abs() > tol || (dy - dy.round()).abs() > tol
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Compare the pixel grids of two rasters with the geo.
+/// transforms `t1` and `t2`. The grids are taken to be the
+/// same if they differ by at most `tol` pixels (of the
+/// second raster) over the extent of a pixel; this ignores
+/// the rounding noise in the transforms. If `t2` is not
+/// invertible, the scales are infinite.
+pub fn grids_aligned(t1: &PixelTransform, t2: &PixelTransform, tol: f64) -> GridRelation {
+    let between = match t2.try_inverse() {
+        Some(inverse) => inverse * t1,
+        None => {
+            return GridRelation::ResampleNeeded {
+                scale_x: f64::INFINITY,
+                scale_y: f64::INFINITY,
+            }
+        }
+    };
+
+    let linear = between.fixed_slice::<2, 2>(0, 0);
+    let same_resolution = (linear - nalgebra::Matrix2::identity())
+        .iter()
+        .all(|entry| entry.abs() <= tol);
+    if !same_resolution {
+        return GridRelation::ResampleNeeded {
+            scale_x: linear.column(0).norm(),
+            scale_y: linear.column(1).norm(),
+        };
+    }
 
-    Rect::new((t_lt.x, t_lt.y), (t_rb.x, t_rb.y)).window_from_bounds(dim)
+    let (dx, dy) = (between[(0, 2)], between[(1, 2)]);
+    if dx.abs() <= tol && dy.abs() <= tol {
+        GridRelation::Identical
+    } else {
+        GridRelation::SameResolutionShifted { dx, dy }
+    }
 }
 
 #[cfg(feature = "gdal")]
 /// Compute affine transform to transfer from pixel
 /// coordinates of the first dataset to the second dataset.
+/// Returns an error if the datasets are in different CRS,
+/// as the mapping is then not affine (see
+/// [`transform_between_crs`]). A dataset without a
+/// projection is taken to be in the CRS of the other.
 pub fn transform_between(ds_1: &gdal::Dataset, ds_2: &gdal::Dataset) -> anyhow::Result<PixelTransform> {
-    use anyhow::*;
-    let transform_1 = transform_from_dataset(&ds_1);
-    let transform_2 = transform_from_dataset(&ds_2);
+    if let Some((srs_1, srs_2)) = crs_mismatch(ds_1, ds_2)? {
+        anyhow::bail!(
+            "inputs are in different CRS ({} and {}); reproject one of them (eg. with gdalwarp)",
+            crs_name(&srs_1),
+            crs_name(&srs_2)
+        );
+    }
+    let transform_1 = GeoTransform::from_dataset(ds_1);
+    let inverse_2 = GeoTransform::from_dataset(ds_2)
+        .inverse()
+        .map_err(|_| anyhow::format_err!("input_b: couldn't invert transform"))?;
+    Ok(transform_1.compose(&inverse_2).into())
+}
+
+#[cfg(feature = "gdal")]
+/// Map the pixel coordinates of the first dataset to the
+/// second dataset, reprojecting between their CRS if they
+/// differ. Unlike [`transform_between`], the mapping need
+/// not be affine, and is evaluated at each point (eg. the
+/// corners of a chunk).
+pub fn transform_between_crs(
+    ds_1: &gdal::Dataset,
+    ds_2: &gdal::Dataset,
+) -> anyhow::Result<impl Fn(f64, f64) -> anyhow::Result<(f64, f64)>> {
+    use gdal::spatial_ref::CoordTransform;
+    let transform_1 = GeoTransform::from_dataset(ds_1);
+    let inverse_2 = GeoTransform::from_dataset(ds_2)
+        .inverse()
+        .map_err(|_| anyhow::format_err!("input_b: couldn't invert transform"))?;
+    let coord_transform = crs_mismatch(ds_1, ds_2)?
+        .map(|(srs_1, srs_2)| CoordTransform::new(&srs_1, &srs_2))
+        .transpose()?;
+
+    Ok(move |x, y| {
+        let (x, y) = transform_1.apply(x, y);
+        let (mut xs, mut ys, mut zs) = ([x], [y], [0.]);
+        if let Some(coord_transform) = &coord_transform {
+            coord_transform.transform_coords(&mut xs, &mut ys, &mut zs)?;
+        }
+        Ok(inverse_2.apply(xs[0], ys[0]))
+    })
+}
+
+#[cfg(feature = "gdal")]
+/// The CRS of the datasets, if both have one, and they
+/// differ. The CRS of a dataset uses the (easting,
+/// northing) axis order of its geo. transform.
+fn crs_mismatch(
+    ds_1: &gdal::Dataset,
+    ds_2: &gdal::Dataset,
+) -> anyhow::Result<Option<(gdal::spatial_ref::SpatialRef, gdal::spatial_ref::SpatialRef)>> {
+    if ds_1.projection().is_empty() || ds_2.projection().is_empty() {
+        return Ok(None);
+    }
+    let (srs_1, srs_2) = (ds_1.spatial_ref()?, ds_2.spatial_ref()?);
+    Ok(if srs_1 == srs_2 {
+        None
+    } else {
+        Some((srs_1, srs_2))
+    })
+}
+
+#[cfg(feature = "gdal")]
+/// A short name of a CRS for messages: the authority code
+/// (eg. `EPSG:4326`) if any.
+fn crs_name(srs: &gdal::spatial_ref::SpatialRef) -> String {
+    match (srs.auth_name(), srs.auth_code()) {
+        (Ok(name), Ok(code)) => format!("{}:{}", name, code),
+        _ => String::from("custom CRS"),
+    }
+}
+
+/// Resolution of the grid computed by [`common_grid`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GridResolution {
+    /// The smallest width (and height) of the pixels of the
+    /// inputs.
+    Finest,
+    /// The largest width (and height) of the pixels of the
+    /// inputs.
+    Coarsest,
+    /// The given width and height of the pixels, in the
+    /// units of the CRS.
+    Explicit(f64, f64),
+}
+
+#[cfg(feature = "gdal")]
+/// The smallest grid (the geo. transform, and dimensions)
+/// of pixels of the given `resolution`, that covers the
+/// `datasets`. The grid has the orientation of the first
+/// dataset, and its origin is on the grid (of the given
+/// resolution) starting at the origin of the first; thus,
+/// at the resolution of the first, its pixels are those of
+/// the first. Returns an error if the datasets are in
+/// different CRS (those without a projection are taken to
+/// be in the CRS of the others), or are not axis-aligned.
+pub fn common_grid(
+    datasets: &[&gdal::Dataset],
+    resolution: GridResolution,
+) -> Result<(PixelTransform, RasterDims)> {
+    if let Some(reference) = datasets.iter().find(|ds| !ds.projection().is_empty()) {
+        for ds in datasets {
+            if let Some((srs_1, srs_2)) = crs_mismatch(reference, ds)? {
+                anyhow::bail!(
+                    "inputs are in different CRS ({} and {}); reproject them (eg. with gdalwarp)",
+                    crs_name(&srs_1),
+                    crs_name(&srs_2)
+                );
+            }
+        }
+    }
+    let grids: Vec<_> = datasets
+        .iter()
+        .map(|ds| (transform_from_dataset(ds), ds.raster_size()))
+        .collect();
+    covering_grid(&grids, resolution)
+}
+
+/// The grid computed by [`common_grid`], given the
+/// transforms and dimensions of the inputs.
+#[cfg_attr(not(feature = "gdal"), allow(dead_code))]
+fn covering_grid(
+    grids: &[(PixelTransform, RasterDims)],
+    resolution: GridResolution,
+) -> Result<(PixelTransform, RasterDims)> {
+    // Bounds are snapped to whole pixels, ignoring the
+    // rounding noise in the transforms
+    const EPS: f64 = 1e-6;
+
+    let first = match grids.first() {
+        Some((t, _)) => t,
+        None => anyhow::bail!("no inputs to compute a common grid"),
+    };
+    if let Some(idx) = grids.iter().position(|(t, _)| !is_axis_aligned(t, 1e-5)) {
+        anyhow::bail!("input {} is not axis aligned", idx + 1);
+    }
+
+    let sizes = grids
+        .iter()
+        .map(|(t, _)| (t[(0, 0)].abs(), t[(1, 1)].abs()));
+    let (width, height) = match resolution {
+        GridResolution::Finest => sizes.fold((f64::INFINITY, f64::INFINITY), |(w, h), (x, y)| {
+            (w.min(x), h.min(y))
+        }),
+        GridResolution::Coarsest => sizes.fold((0f64, 0f64), |(w, h), (x, y)| (w.max(x), h.max(y))),
+        GridResolution::Explicit(width, height) => (width, height),
+    };
+    if !(width > 0. && height > 0. && width.is_finite() && height.is_finite()) {
+        anyhow::bail!("invalid resolution: {} x {}", width, height);
+    }
 
-    transform_2
-        .try_inverse()
-        .ok_or_else(|| anyhow!("input_b: couldn't invert transform"))
-        .map(|inv| inv * transform_1)
+    let t = PixelTransform::new(
+        width.copysign(first[(0, 0)]),
+        0.,
+        first[(0, 2)],
+        0.,
+        height.copysign(first[(1, 1)]),
+        first[(1, 2)],
+        0.,
+        0.,
+        1.,
+    );
+    let inverse = t.try_inverse().expect("pixel size is non-zero");
+
+    // Bounds of the inputs, in the pixel coordinates of `t`
+    let bounds = Bounds::from_points(grids.iter().flat_map(|(t_i, (w, h))| {
+        let between = inverse * t_i;
+        let (w, h) = (*w as f64, *h as f64);
+        [(0., 0.), (w, 0.), (0., h), (w, h)].map(|(x, y)| {
+            let pt = between.transform_point(&Point2::new(x, y));
+            (pt.x, pt.y)
+        })
+    }))
+    .expect("inputs have corners");
+
+    let (min_x, min_y) = (
+        (bounds.min().x + EPS).floor(),
+        (bounds.min().y + EPS).floor(),
+    );
+    let (max_x, max_y) = ((bounds.max().x - EPS).ceil(), (bounds.max().y - EPS).ceil());
+    Ok((
+        translate_transform(&t, min_x, min_y),
+        ((max_x - min_x) as usize, (max_y - min_y) as usize),
+    ))
 }
 
 /// Calculate residue of an transform for a pair of offsets.
@@ -143,12 +476,310 @@ pub fn index_transformer(
     }
 }
 
+/// Strategy used to sample the target raster at a point.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SampleMode {
+    /// Value of the pixel containing the point.
+    #[default]
+    Nearest,
+    /// Bilinear interpolation of the (upto) four pixels
+    /// whose centers surround the point.
+    Bilinear,
+}
+
+/// Converts a [`chunk_transform`] into a function that
+/// samples the target chunk (of dimension `dim`) at the
+/// position corresponding to the input (integer) indices.
+///
+/// With [`SampleMode::Nearest`], this is equivalent to
+/// reading the index computed by [`index_transformer`].
+/// With [`SampleMode::Bilinear`], the four neighboring
+/// cells are interpolated. Returns `None` if any of the
+/// cells read is out of bounds, or if the sample is `NaN`.
+pub fn sampler(
+    chunk_t: PixelTransform,
+    dim: RasterDims,
+    mode: SampleMode,
+) -> impl Fn(RasterDims, &Array2<f64>) -> Option<f64> {
+    let (cols, rows) = dim;
+    let nearest = index_transformer(chunk_t, dim);
+
+    move |(i, j), arr| {
+        let val = match mode {
+            SampleMode::Nearest => arr[nearest((i, j))?],
+            SampleMode::Bilinear => {
+                // Transform indices, and shift to position
+                // relative to the pixel centers.
+                let pt = chunk_t.transform_point(&Point2::new(j as f64, i as f64));
+                let (x, y) = (pt.x - 0.5, pt.y - 0.5);
+                if x < 0. || y < 0. {
+                    return None;
+                }
+
+                let j_0 = x.floor() as usize;
+                let i_0 = y.floor() as usize;
+                let (dx, dy) = (x - j_0 as f64, y - i_0 as f64);
+
+                // Avoid reading past the edge when the point
+                // is exactly on a pixel center.
+                let j_1 = if dx > 0. { j_0 + 1 } else { j_0 };
+                let i_1 = if dy > 0. { i_0 + 1 } else { i_0 };
+                if j_1 >= cols || i_1 >= rows {
+                    return None;
+                }
+
+                let top = lerp(arr[(i_0, j_0)], arr[(i_0, j_1)], dx);
+                let bottom = lerp(arr[(i_1, j_0)], arr[(i_1, j_1)], dx);
+                lerp(top, bottom, dy)
+            }
+        };
+
+        if val.is_nan() {
+            None
+        } else {
+            Some(val)
+        }
+    }
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+/// Resample a chunk `src` (at offset `src_off`) of a raster
+/// onto the window `dst_window` of another raster. The
+/// `transform` maps the pixels of the destination raster
+/// to those of the source (see [`transform_between`]), and
+/// the chunk should cover the transformed window (see
+/// [`transform_window`]).
+///
+/// The values that are NaN or `nodata` are invalid. With
+/// [`SampleMode::Nearest`], an invalid value is passed
+/// through, while [`SampleMode::Bilinear`] interpolates the
+/// valid neighbors only. The pixels that are invalid, or
+/// outside the chunk, are `nodata` in the output.
+pub fn resample_chunk(
+    src: &Array2<f64>,
+    src_off: RasterOffset,
+    dst_window: RasterWindow,
+    transform: &PixelTransform,
+    mode: SampleMode,
+    nodata: f64,
+) -> Array2<f64> {
+    let ((x, y), (width, height)) = dst_window;
+    let (rows, cols) = src.dim();
+    let chunk_t = chunk_transform(
+        transform,
+        Vector2::new(x as f64 + 0.5, y as f64 + 0.5),
+        Vector2::new(src_off.0 as f64, src_off.1 as f64),
+    );
+    let is_valid = |val: f64| !val.is_nan() && val != nodata;
+
+    Array2::from_shape_fn((height, width), |(i, j)| {
+        let pt = chunk_t.transform_point(&Point2::new(j as f64, i as f64));
+        if pt.x < 0. || pt.y < 0. || pt.x >= cols as f64 || pt.y >= rows as f64 {
+            return nodata;
+        }
+
+        let val = match mode {
+            SampleMode::Nearest => src[(pt.y.floor() as usize, pt.x.floor() as usize)],
+            SampleMode::Bilinear => {
+                // Position relative to the pixel centers; the
+                // neighbors outside the chunk are skipped.
+                let (x, y) = (pt.x - 0.5, pt.y - 0.5);
+                let (j_0, i_0) = (x.floor(), y.floor());
+                let (dx, dy) = (x - j_0, y - i_0);
+
+                let (mut sum, mut total) = (0., 0.);
+                for &(di, wy) in &[(0., 1. - dy), (1., dy)] {
+                    for &(dj, wx) in &[(0., 1. - dx), (1., dx)] {
+                        let (i_n, j_n) = (i_0 + di, j_0 + dj);
+                        let weight = wx * wy;
+                        if weight == 0.
+                            || i_n < 0.
+                            || j_n < 0.
+                            || i_n >= rows as f64
+                            || j_n >= cols as f64
+                        {
+                            continue;
+                        }
+                        let val = src[(i_n as usize, j_n as usize)];
+                        if is_valid(val) {
+                            sum += weight * val;
+                            total += weight;
+                        }
+                    }
+                }
+                if total > 0. {
+                    sum / total
+                } else {
+                    nodata
+                }
+            }
+        };
+
+        if is_valid(val) {
+            val
+        } else {
+            nodata
+        }
+    })
+}
+
+/// Reads the aligned chunks of a pair of rasters: a window
+/// of the first raster `A`, and the window of the second
+/// raster `B` covering it. The invalid pixels of both (see
+/// [`read_as_array_masked`][ChunkReader::read_as_array_masked])
+/// are read as NaN.
+#[cfg(feature = "gdal")]
+pub struct AlignedPairReader<RA, RB> {
+    reader_a: RA,
+    reader_b: RB,
+    transform: PixelTransform,
+    dim_b: RasterDims,
+    mode: SampleMode,
+}
+
+#[cfg(feature = "gdal")]
+impl<RA: ChunkReader, RB: ChunkReader> AlignedPairReader<RA, RB> {
+    /// Align `reader_b` to `reader_a`. The `transform` maps
+    /// the pixels of `A` to those of `B` (see
+    /// [`transform_between`]).
+    pub fn new(reader_a: RA, reader_b: RB, transform: PixelTransform) -> Result<Self> {
+        let dim_b = reader_b.raster_size()?;
+        Ok(AlignedPairReader {
+            reader_a,
+            reader_b,
+            transform,
+            dim_b,
+            mode: SampleMode::Nearest,
+        })
+    }
+
+    /// Sample `B` using `mode` (defaults to
+    /// [`SampleMode::Nearest`]).
+    pub fn with_mode(mut self, mode: SampleMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Transform `win_a` from `A`, and calculate the
+    /// corresponding window to read from `B`. With bilinear
+    /// sampling, the window is extended by a pixel on each
+    /// side, and may extend past the edges of `B`.
+    pub fn window_b(&self, win_a: RasterWindow) -> RasterWindow {
+        let win_b = transform_window(win_a, self.transform, self.dim_b);
+        if self.mode == SampleMode::Nearest || win_b.1 == (0, 0) {
+            return win_b;
+        }
+
+        // Extend by a pixel on each side to read all the
+        // neighbors required to interpolate.
+        let ((x, y), (w, h)) = win_b;
+        ((x - 1, y - 1), (w + 2, h + 2))
+    }
+
+    /// Read the window `win_a` of `A`, and the corresponding
+    /// window of `B` (see [`window_b`]). The pixels of the
+    /// latter outside `B` are NaN.
+    ///
+    /// [`window_b`]: AlignedPairReader::window_b
+    pub fn read_window(&self, win_a: RasterWindow) -> Result<AlignedChunk> {
+        let (off_a, size_a) = win_a;
+        let data_a = mask_invalid(self.reader_a.read_as_array_masked::<f64>(off_a, size_a)?);
+
+        let (off_b, size_b) = self.window_b(win_a);
+        let data_b = mask_invalid(self.reader_b.read_as_array_masked_clamped::<f64>(
+            off_b,
+            size_b,
+            f64::NAN,
+        )?);
+
+        let chunk_t = chunk_transform(
+            &self.transform,
+            Vector2::new(off_a.0 as f64 + 0.5, off_a.1 as f64 + 0.5),
+            Vector2::new(off_b.0 as f64, off_b.1 as f64),
+        );
+        Ok(AlignedChunk {
+            off_a,
+            data_a,
+            off_b,
+            data_b,
+            chunk_t,
+            mode: self.mode,
+        })
+    }
+}
+
+/// Set the invalid pixels of a masked read to NaN (see
+/// [`NoData::NAN`]).
+#[cfg(feature = "gdal")]
+fn mask_invalid((mut data, valid): (Array2<f64>, Array2<bool>)) -> Array2<f64> {
+    data.zip_mut_with(&valid, |val, &valid| {
+        if !valid {
+            *val = f64::NAN;
+        }
+    });
+    data
+}
+
+/// A pair of aligned chunks (see
+/// [`AlignedPairReader::read_window`]).
+#[cfg(feature = "gdal")]
+pub struct AlignedChunk {
+    /// Offset of the chunk of `A`.
+    pub off_a: RasterOffset,
+    /// The chunk of `A`; invalid pixels are NaN.
+    pub data_a: Array2<f64>,
+    /// Offset of the chunk of `B`.
+    pub off_b: RasterOffset,
+    /// The chunk of `B`; invalid pixels are NaN.
+    pub data_b: Array2<f64>,
+    /// The [`chunk_transform`] from the pixel centers of
+    /// `data_a` to `data_b`.
+    chunk_t: PixelTransform,
+    mode: SampleMode,
+}
+
+#[cfg(feature = "gdal")]
+impl AlignedChunk {
+    /// Map an index of `data_a` to the index of the pixel of
+    /// `data_b` containing its center (see
+    /// [`index_transformer`]).
+    pub fn index_b(&self) -> impl Fn(RasterDims) -> Option<RasterDims> {
+        let (rows, cols) = self.data_b.dim();
+        index_transformer(self.chunk_t, (cols, rows))
+    }
+
+    /// Sample `data_b` at the center of the pixel of each
+    /// index of `data_a` (see [`sampler`]).
+    pub fn sampler_b(&self) -> impl Fn(RasterDims) -> Option<f64> + '_ {
+        let (rows, cols) = self.data_b.dim();
+        let sample = sampler(self.chunk_t, (cols, rows), self.mode);
+        move |idx| sample(idx, &self.data_b)
+    }
+
+    /// Iterate the pixels of `data_a` that are valid, along
+    /// with the (valid) sample of `data_b` at each; yields
+    /// the index into `data_a`, and the two values.
+    pub fn pairs(&self) -> impl Iterator<Item = (RasterDims, f64, f64)> + '_ {
+        let sample = self.sampler_b();
+        self.data_a.indexed_iter().filter_map(move |(idx, &val_a)| {
+            if NoData::NAN.is_nodata(val_a) {
+                return None;
+            }
+            Some((idx, val_a, sample(idx)?))
+        })
+    }
+}
+
 #[cfg(feature = "gdal")]
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::path::Path;
     use gdal::Dataset;
+    use crate::prelude::{is_axis_aligned, transform_from_dataset};
 
     fn print_mat3x3(t: &PixelTransform) {
         for i in 0..3 {
@@ -184,4 +815,402 @@ mod tests {
         eprintln!("transform chunk: ");
         print_mat3x3(&tchunk);
     }
+
+    #[test]
+    fn test_transform_between_crs() -> Result<()> {
+        use gdal::{spatial_ref::SpatialRef, DriverManager};
+        let driver = DriverManager::get_driver_by_name("MEM")?;
+        let create = |epsg: u32, t: [f64; 6]| -> Result<Dataset> {
+            let mut ds = driver.create_with_band_type::<f64, _>("", 20, 20, 1)?;
+            ds.set_projection(&SpatialRef::from_epsg(epsg)?.to_wkt()?)?;
+            ds.set_geo_transform(&t)?;
+            Ok(ds)
+        };
+
+        // The center of pixel (0, 0) of the first is at
+        // (75E, 0N), which is (500000, 0) in UTM zone 43N,
+        // the top-left corner of pixel (10, 10) of the
+        // second.
+        let ds_1 = create(4326, [75. - 0.0005, 0.001, 0., 0.0005, 0., -0.001])?;
+        let ds_2 = create(32643, [499990., 1., 0., 10., 0., -1.])?;
+        let err = transform_between(&ds_1, &ds_2).unwrap_err();
+        assert!(
+            err.to_string().contains("EPSG:4326 and EPSG:32643"),
+            "{}",
+            err
+        );
+
+        let (x, y) = transform_between_crs(&ds_1, &ds_2)?(0.5, 0.5)?;
+        assert!(
+            (x - 10.).abs() < 1e-6 && (y - 10.).abs() < 1e-6,
+            "{:?}",
+            (x, y)
+        );
+
+        // Same CRS: the affine transform
+        let ds_3 = create(32643, [499980., 2., 0., 20., 0., -2.])?;
+        let t = transform_between(&ds_2, &ds_3)?;
+        let (x, y) = transform_between_crs(&ds_2, &ds_3)?(10.5, 10.5)?;
+        let pt = t.transform_point(&Point2::new(10.5, 10.5));
+        assert!((x - pt.x).abs() < 1e-9 && (y - pt.y).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_common_grid() -> Result<()> {
+        use crate::prelude::transform_from_gdal;
+        use gdal::{spatial_ref::SpatialRef, DriverManager};
+        let driver = DriverManager::get_driver_by_name("MEM")?;
+        let create = |size: (isize, isize), t: [f64; 6]| -> Result<Dataset> {
+            let mut ds = driver.create_with_band_type::<f64, _>("", size.0, size.1, 1)?;
+            ds.set_geo_transform(&t)?;
+            Ok(ds)
+        };
+
+        // The second input overlaps the bottom-right of the
+        // first, and is shifted by half a pixel (of the
+        // first)
+        let ds_1 = create((10, 10), [100., 1., 0., 200., 0., -1.])?;
+        let ds_2 = create((10, 5), [105.5, 2., 0., 195., 0., -2.])?;
+        let inputs = [&ds_1, &ds_2];
+        assert_eq!(
+            common_grid(&inputs, GridResolution::Finest)?,
+            (
+                transform_from_gdal(&[100., 1., 0., 200., 0., -1.]),
+                (26, 15)
+            )
+        );
+        assert_eq!(
+            common_grid(&inputs, GridResolution::Coarsest)?,
+            (transform_from_gdal(&[100., 2., 0., 200., 0., -2.]), (13, 8))
+        );
+        assert_eq!(
+            common_grid(&inputs, GridResolution::Explicit(0.5, 0.5))?,
+            (
+                transform_from_gdal(&[100., 0.5, 0., 200., 0., -0.5]),
+                (51, 30)
+            )
+        );
+
+        // A disjoint input to the top-left: the origin moves
+        // by whole pixels of the first
+        let ds_3 = create((5, 5), [80.5, 1., 0., 230., 0., -1.])?;
+        assert_eq!(
+            common_grid(&[&ds_1, &ds_3], GridResolution::Finest)?,
+            (transform_from_gdal(&[80., 1., 0., 230., 0., -1.]), (30, 40))
+        );
+        assert_eq!(
+            common_grid(&[&ds_3, &ds_1], GridResolution::Finest)?,
+            (
+                transform_from_gdal(&[80.5, 1., 0., 230., 0., -1.]),
+                (30, 40)
+            )
+        );
+
+        // Rotated, or in different CRS
+        let rotated = create((10, 10), [100., 1., 0.1, 200., 0.1, -1.])?;
+        assert!(common_grid(&[&ds_1, &rotated], GridResolution::Finest).is_err());
+        let mut ds_4 = create((10, 10), [100., 1., 0., 200., 0., -1.])?;
+        ds_4.set_projection(&SpatialRef::from_epsg(32643)?.to_wkt()?)?;
+        let mut ds_5 = create((10, 10), [100., 1., 0., 200., 0., -1.])?;
+        ds_5.set_projection(&SpatialRef::from_epsg(4326)?.to_wkt()?)?;
+        assert!(common_grid(&[&ds_4, &ds_1], GridResolution::Finest).is_ok());
+        assert!(common_grid(&[&ds_1, &ds_4, &ds_5], GridResolution::Finest).is_err());
+        assert!(common_grid(&[], GridResolution::Finest).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sampler() {
+        // Target pixels are twice as large as the source.
+        let arr = Array2::from_shape_fn((4, 4), |(i, j)| (i * 4 + j) as f64);
+        let chunk_t = chunk_transform(
+            &PixelTransform::new(0.5, 0., 0., 0., 0.5, 0., 0., 0., 1.),
+            Vector2::new(0.5, 0.5),
+            Vector2::new(0., 0.),
+        );
+
+        let nearest = sampler(chunk_t, (4, 4), SampleMode::Nearest);
+        assert_eq!(nearest((0, 0), &arr), Some(0.));
+        assert_eq!(nearest((3, 2), &arr), Some(5.));
+
+        let bilinear = sampler(chunk_t, (4, 4), SampleMode::Bilinear);
+        // (0.25, 0.25) is outside the pixel centers.
+        assert_eq!(bilinear((0, 0), &arr), None);
+        // (1.75, 1.25) interpolates row 0 and 1 between
+        // cols 1 and 2.
+        assert_eq!(bilinear((2, 3), &arr), Some(0.75 * 4. + 1.25));
+        // (3.75, 3.75) is outside the last pixel center.
+        assert_eq!(bilinear((7, 7), &arr), None);
+
+        let mut arr = arr;
+        arr[(1, 2)] = f64::NAN;
+        assert_eq!(bilinear((2, 3), &arr), None);
+    }
+
+    #[test]
+    fn test_aligned_pair_reader() -> Result<()> {
+        use crate::prelude::MemoryReader;
+        use ndarray::array;
+
+        // Pixels of B are twice as large as those of A
+        let t = PixelTransform::new(0.5, 0., 0., 0., 0.5, 0., 0., 0., 1.);
+        let arr_a = Array2::from_shape_fn((4, 4), |(i, j)| (i * 4 + j) as f64);
+        let reader = AlignedPairReader::new(
+            MemoryReader(arr_a.clone(), Some(5.)),
+            MemoryReader(array![[10., 20.], [30., f64::NAN]], None),
+            t,
+        )?;
+
+        let chunk = reader.read_window(((1, 1), (2, 2)))?;
+        assert_eq!((chunk.off_a, chunk.off_b), ((1, 1), (0, 0)));
+        assert!(chunk.data_a[(0, 0)].is_nan());
+        assert_eq!(chunk.index_b()((1, 1)), Some((1, 1)));
+        // The no-data of A and the NaN of B are skipped
+        assert_eq!(
+            chunk.pairs().collect::<Vec<_>>(),
+            vec![((0, 1), 6., 20.), ((1, 0), 9., 30.)]
+        );
+
+        // Bilinear sampling reads an extra pixel on each
+        // side, past the edges of B
+        let reader = AlignedPairReader::new(
+            MemoryReader(arr_a, None),
+            MemoryReader(array![[10., 20.], [30., 40.]], None),
+            t,
+        )?
+        .with_mode(SampleMode::Bilinear);
+        let chunk = reader.read_window(((1, 1), (2, 2)))?;
+        assert_eq!(chunk.off_b, (-1, -1));
+        assert_eq!(chunk.data_b.dim(), (4, 4));
+        assert!(chunk.data_b[(0, 0)].is_nan());
+        assert_eq!(chunk.sampler_b()((0, 0)), Some(17.5));
+        assert_eq!(chunk.sampler_b()((1, 1)), Some(32.5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resample_chunk() {
+        use ndarray::array;
+
+        // Source pixels are twice as large, and the chunk
+        // starts at the second row of the source
+        let t = PixelTransform::new(0.5, 0., 0., 0., 0.5, 0., 0., 0., 1.);
+        let src = array![[10., 20.], [30., -1.]];
+        let resample = |mode| resample_chunk(&src, (0, 1), ((0, 1), (4, 4)), &t, mode, -1.);
+
+        let nearest = resample(SampleMode::Nearest);
+        // Row 0 is above the chunk
+        assert_eq!(nearest.row(0).to_vec(), vec![-1.; 4]);
+        assert_eq!(nearest.row(1).to_vec(), vec![10., 10., 20., 20.]);
+        // No-data is passed through
+        assert_eq!(nearest.row(3).to_vec(), vec![30., 30., -1., -1.]);
+
+        let bilinear = resample(SampleMode::Bilinear);
+        assert_eq!(bilinear.row(0).to_vec(), vec![-1.; 4]);
+        // (0.75, 0.25) in the chunk: between columns 0 and 1
+        // of the first row
+        assert_eq!(bilinear[(1, 1)], 12.5);
+        // (0.75, 0.75) in the chunk: the no-data neighbor is
+        // skipped
+        let expected = (10. * 0.5625 + 20. * 0.1875 + 30. * 0.1875) / 0.9375;
+        assert!((bilinear[(2, 1)] - expected).abs() < 1e-12);
+        // The neighbors outside the chunk are skipped too
+        assert_eq!(bilinear[(1, 0)], 10.);
+        assert_eq!(bilinear[(3, 3)], 20.);
+
+        // NaN is invalid too
+        let src = array![[f64::NAN, 20.], [30., 40.]];
+        let out = resample_chunk(&src, (0, 0), ((0, 0), (2, 2)), &t, SampleMode::Nearest, -1.);
+        assert_eq!(out, array![[-1., -1.], [-1., -1.]]);
+    }
+
+    #[test]
+    fn test_grids_aligned() {
+        use crate::prelude::transform_from_gdal;
+        let tol = 1e-6;
+        let t1 = transform_from_gdal(&[363737.54688808107, 0.1, 0., 2059515.3774022115, 0., -0.1]);
+
+        // Rounding noise in the transform
+        let noisy = transform_from_gdal(&[
+            363737.546888081,
+            0.1 + 1e-12,
+            0.,
+            2059515.377402212,
+            1e-13,
+            -0.1,
+        ]);
+        assert_eq!(grids_aligned(&t1, &noisy, tol), GridRelation::Identical);
+        assert_eq!(grids_aligned(&t1, &t1, 0.), GridRelation::Identical);
+
+        // Shifted by (2, -3) pixels, with noise
+        let shifted =
+            transform_from_gdal(&[363737.34688808107, 0.1, 0., 2059515.0774022115, 0., -0.1]);
+        match grids_aligned(&t1, &shifted, tol) {
+            GridRelation::SameResolutionShifted { dx, dy } => {
+                assert!((dx - 2.).abs() < tol && (dy + 3.).abs() < tol);
+            }
+            relation => panic!("unexpected relation: {:?}", relation),
+        }
+        assert!(!grids_aligned(&t1, &shifted, tol).has_subpixel_shift(tol));
+
+        // Shifted by half a pixel
+        let half =
+            transform_from_gdal(&[363737.59688808107, 0.1, 0., 2059515.3774022115, 0., -0.1]);
+        let relation = grids_aligned(&t1, &half, tol);
+        assert!(relation.has_subpixel_shift(tol), "{:?}", relation);
+
+        // Pixels of the second raster are twice as wide and
+        // thrice as tall
+        let coarse =
+            transform_from_gdal(&[363737.54688808107, 0.2, 0., 2059515.3774022115, 0., -0.3]);
+        match grids_aligned(&t1, &coarse, tol) {
+            GridRelation::ResampleNeeded { scale_x, scale_y } => {
+                assert!((scale_x - 0.5).abs() < tol && (scale_y - 1. / 3.).abs() < tol);
+            }
+            relation => panic!("unexpected relation: {:?}", relation),
+        }
+        assert!(!grids_aligned(&t1, &coarse, tol).has_subpixel_shift(tol));
+
+        // A rotation needs resampling too
+        let (sin, cos) = 0.01_f64.sin_cos();
+        let rot = PixelTransform::new(cos, -sin, 0., sin, cos, 0., 0., 0., 1.);
+        assert!(matches!(
+            grids_aligned(&t1, &(t1 * rot), tol),
+            GridRelation::ResampleNeeded { .. }
+        ));
+    }
+
+    #[test]
+    fn test_transform_window() {
+        // Target pixels are twice as large, offset by 1
+        let t = PixelTransform::new(0.5, 0., 1., 0., 0.5, 1., 0., 0., 1.);
+        assert!(is_axis_aligned(&t, 0.));
+        let window = |win| transform_window(win, t, (10, 10));
+        assert_eq!(window(((2, 2), (4, 3))), ((2, 2), (2, 2)));
+        assert_eq!(window(((-4, 0), (4, 2))), ((0, 1), (1, 1)));
+        assert_eq!(window(((-8, 0), (4, 2))), ((0, 0), (0, 0)));
+
+        // A small rotation (about 3 degrees) about the center
+        // of a 20x20 raster
+        let (sin, cos) = 0.05_f64.sin_cos();
+        let rot = PixelTransform::new(cos, -sin, 0., sin, cos, 0., 0., 0., 1.);
+        let center = PixelTransform::new(1., 0., 10., 0., 1., 10., 0., 0., 1.);
+        let t = center * rot * center.try_inverse().unwrap();
+        assert!(!is_axis_aligned(&t, 1e-3));
+        assert!(is_axis_aligned(&t, 0.1));
+
+        let dim = (20, 20);
+        for &win in &[((5, 5), (10, 10)), ((0, 0), (20, 20)), ((3, 12), (7, 2))] {
+            let ((x, y), (w, h)) = transform_window(win, t, dim);
+            let ((wx, wy), (ww, wh)) = win;
+            // Each pixel (with its corners) of the window maps
+            // within the output, unless it is outside the
+            // target raster.
+            for i in wy..wy + wh as isize {
+                for j in wx..wx + ww as isize {
+                    for &(dx, dy) in &[(0., 0.), (1., 0.), (0., 1.), (1., 1.)] {
+                        let pt = t.transform_point(&Point2::new(j as f64 + dx, i as f64 + dy));
+                        let (px, py) = (pt.x.clamp(0., 20.), pt.y.clamp(0., 20.));
+                        assert!(
+                            x as f64 <= px
+                                && px <= (x + w as isize) as f64
+                                && y as f64 <= py
+                                && py <= (y + h as isize) as f64,
+                            "{:?} not in {:?}",
+                            (px, py),
+                            ((x, y), (w, h))
+                        );
+                    }
+                }
+            }
+            // The window is larger than the input, except at
+            // the edges of the target raster
+            assert!(w >= ww.min(19) && h >= wh.min(19));
+        }
+    }
+
+    #[test]
+    fn test_transform_window_inner() {
+        // Target pixels are twice as large, offset by 1
+        let t = PixelTransform::new(0.5, 0., 1., 0., 0.5, 1., 0., 0., 1.);
+        let inner = |win| transform_window_inner(win, t, (10, 10));
+        let outer = |win| transform_window(win, t, (10, 10));
+
+        // The edges are on pixel boundaries: both agree
+        assert_eq!(inner(((2, 2), (4, 6))), ((2, 2), (2, 3)));
+        assert_eq!(outer(((2, 2), (4, 6))), ((2, 2), (2, 3)));
+
+        // Partially covered pixels are excluded
+        assert_eq!(inner(((2, 2), (5, 3))), ((2, 2), (2, 1)));
+        assert_eq!(outer(((2, 2), (5, 3))), ((2, 2), (3, 2)));
+        assert_eq!(inner(((1, 1), (2, 2))), ((0, 0), (0, 0)));
+        assert_eq!(inner(((1, 1), (4, 4))), ((2, 2), (1, 1)));
+
+        // The inner windows of adjacent chunks do not
+        // overlap: the row partially covered by the first two
+        // is in neither, while it is in both outer windows
+        let chunks = [((-2, -2), (20, 5)), ((-2, 3), (20, 7)), ((-2, 10), (20, 8))];
+        let windows: Vec<_> = chunks.iter().map(|&win| inner(win)).collect();
+        assert_eq!(
+            windows,
+            vec![((0, 0), (10, 2)), ((0, 3), (10, 3)), ((0, 6), (10, 4))]
+        );
+        assert_eq!(outer(chunks[0]), ((0, 0), (10, 3)));
+        assert_eq!(outer(chunks[1]), ((0, 2), (10, 4)));
+
+        // Rounding noise at the boundaries is ignored
+        let noisy = PixelTransform::new(0.5 + 1e-12, 0., 1. - 1e-12, 0., 0.5, 1., 0., 0., 1.);
+        assert_eq!(
+            transform_window_inner(((2, 2), (4, 6)), noisy, (10, 10)),
+            ((2, 2), (2, 3))
+        );
+    }
+}
+
+#[cfg(test)]
+mod grid_tests {
+    use super::*;
+    use crate::prelude::transform_from_gdal;
+
+    #[test]
+    fn test_covering_grid() -> Result<()> {
+        // The second input overlaps the bottom-right of the
+        // first, and is shifted by half a pixel (of the
+        // first)
+        let grids = [
+            (
+                transform_from_gdal(&[100., 1., 0., 200., 0., -1.]),
+                (10, 10),
+            ),
+            (
+                transform_from_gdal(&[105.5, 2., 0., 195., 0., -2.]),
+                (10, 5),
+            ),
+        ];
+        assert_eq!(
+            covering_grid(&grids, GridResolution::Finest)?,
+            (
+                transform_from_gdal(&[100., 1., 0., 200., 0., -1.]),
+                (26, 15)
+            )
+        );
+        assert_eq!(
+            covering_grid(&grids, GridResolution::Coarsest)?,
+            (transform_from_gdal(&[100., 2., 0., 200., 0., -2.]), (13, 8))
+        );
+        assert_eq!(
+            covering_grid(&grids, GridResolution::Explicit(0.5, 0.5))?,
+            (
+                transform_from_gdal(&[100., 0.5, 0., 200., 0., -0.5]),
+                (51, 30)
+            )
+        );
+
+        assert!(covering_grid(&grids, GridResolution::Explicit(0., 1.)).is_err());
+        assert!(covering_grid(&[], GridResolution::Coarsest).is_err());
+        let rotated = transform_from_gdal(&[100., 1., 0.1, 200., 0.1, -1.]);
+        assert!(covering_grid(&[grids[0], (rotated, (10, 10))], GridResolution::Coarsest).is_err());
+        Ok(())
+    }
 }
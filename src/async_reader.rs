@@ -0,0 +1,180 @@
+//! Asynchronous reads of chunks, eg. to prefetch the next
+//! chunk of a remote raster while processing the current
+//! one. GDAL is not async-native, so the reads are run on
+//! the blocking thread pool of a [`tokio`] runtime.
+//!
+//! This module is only available with the "async" feature.
+
+use crate::chunking::{ChunkConfig, ChunkWindow};
+use crate::geometry::{RasterDims, RasterOffset};
+use crate::reader::ChunkReader;
+use crate::Result;
+use anyhow::format_err;
+use gdal::raster::GdalType;
+use ndarray::Array2;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The future returned by asynchronous reads.
+pub type ReadFuture<T> = Pin<Box<dyn Future<Output = Result<Array2<T>>> + Send + 'static>>;
+
+/// Asynchronous counterpart of [`ChunkReader`].
+pub trait AsyncChunkReader {
+    /// Read a window into an ndarray (see
+    /// [`ChunkReader::read_as_array`]).
+    fn read_as_array_async<T>(&self, off: RasterOffset, size: RasterDims) -> ReadFuture<T>
+    where
+        T: GdalType + Copy + Send + 'static;
+
+    /// Helper to read ndarray from output of
+    /// [`ChunkConfig`] iterator
+    ///
+    /// [`ChunkConfig`]: crate::chunking::ChunkConfig
+    fn read_chunk_async<T>(&self, chunk: ChunkWindow<'_>) -> ReadFuture<T>
+    where
+        T: GdalType + Copy + Send + 'static,
+    {
+        let (off, size) = chunk.raster_window();
+        self.read_as_array_async(off, size)
+    }
+
+    /// Read the chunks of `cfg` in order, as a
+    /// [`ChunkStream`].
+    fn read_stream<'a, T>(&'a self, cfg: &'a ChunkConfig) -> ChunkStream<'a, Self, T>
+    where
+        Self: Sized,
+        T: GdalType + Copy + Send + 'static,
+    {
+        ChunkStream {
+            reader: self,
+            cfg,
+            next: 0,
+            pending: None,
+        }
+    }
+}
+
+/// Stream of the chunks of a [`ChunkConfig`] read by an
+/// [`AsyncChunkReader`] (see
+/// [`AsyncChunkReader::read_stream`]). The read of the next
+/// chunk is started when a chunk is yielded, so that it
+/// overlaps with the processing of the chunk.
+///
+/// As with [`ChunkReader`], a failed read is yielded as an
+/// error, and the stream continues with the next chunk.
+/// Dropping the stream cancels the pending read; however,
+/// a read already running (eg. on a blocking thread) runs
+/// to completion, and its data is discarded.
+pub struct ChunkStream<'a, R, T> {
+    reader: &'a R,
+    cfg: &'a ChunkConfig,
+    next: usize,
+    pending: Option<(ChunkWindow<'a>, ReadFuture<T>)>,
+}
+
+impl<'a, R, T> ChunkStream<'a, R, T>
+where
+    R: AsyncChunkReader,
+    T: GdalType + Copy + Send + 'static,
+{
+    /// The next chunk, along with its window, or `None`
+    /// after the last chunk.
+    pub async fn next(&mut self) -> Option<Result<(ChunkWindow<'a>, Array2<T>)>> {
+        let (win, data) = match self.pending.take() {
+            Some(pending) => pending,
+            None => self.fetch()?,
+        };
+        self.pending = self.fetch();
+        Some(data.await.map(|data| (win, data)))
+    }
+
+    fn fetch(&mut self) -> Option<(ChunkWindow<'a>, ReadFuture<T>)> {
+        let win = self.cfg.window_at(self.next)?;
+        self.next += 1;
+        Some((win, self.reader.read_chunk_async(win)))
+    }
+}
+
+/// Adapts a `ChunkReader` to an [`AsyncChunkReader`] by
+/// running each read via [`tokio::task::spawn_blocking`].
+/// The read is spawned when the method is called (not when
+/// the future is first polled), so that a chunk may be
+/// prefetched. Thus, the methods must be called from
+/// within a tokio runtime.
+///
+/// The reader is shared with the blocking threads, and
+/// hence must be `Send + Sync` (eg. a [`PooledReader`]).
+///
+/// [`PooledReader`]: crate::reader::PooledReader
+pub struct BlockingReader<R>(Arc<R>);
+
+impl<R> BlockingReader<R>
+where
+    R: ChunkReader + Send + Sync + 'static,
+{
+    pub fn new(reader: R) -> Self {
+        BlockingReader(Arc::new(reader))
+    }
+
+    /// The underlying reader.
+    pub fn reader(&self) -> &R {
+        &self.0
+    }
+}
+
+impl<R> Clone for BlockingReader<R> {
+    fn clone(&self) -> Self {
+        BlockingReader(self.0.clone())
+    }
+}
+
+impl<R> AsyncChunkReader for BlockingReader<R>
+where
+    R: ChunkReader + Send + Sync + 'static,
+{
+    fn read_as_array_async<T>(&self, off: RasterOffset, size: RasterDims) -> ReadFuture<T>
+    where
+        T: GdalType + Copy + Send + 'static,
+    {
+        let reader = self.0.clone();
+        let handle = tokio::task::spawn_blocking(move || reader.read_as_array::<T>(off, size));
+        Box::pin(async move {
+            handle
+                .await
+                .map_err(|e| format_err!("read task failed: {}", e))?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::MemoryReader;
+
+    #[test]
+    fn test_blocking_reader() -> Result<()> {
+        let arr = Array2::from_shape_fn((6, 5), |(i, j)| (10 * i + j) as f64);
+        let reader = BlockingReader::new(MemoryReader(arr.clone(), None));
+        let cfg = ChunkConfig::for_reader(reader.reader())?.with_tile_dims(5, 2);
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+        runtime.block_on(async {
+            let mut chunks = reader.read_stream::<f64>(&cfg);
+            let mut count = 0;
+            while let Some(chunk) = chunks.next().await {
+                let (win, data) = chunk?;
+                for ((i, j), &val) in data.indexed_iter() {
+                    assert_eq!(val, arr[(win.y() + i, win.x() + j)]);
+                }
+                count += 1;
+            }
+            assert_eq!(count, cfg.iter().len());
+            assert!(chunks.next().await.is_none());
+
+            let outside = reader.read_as_array_async::<f64>((4, 0), (2, 1));
+            assert!(outside.await.is_err());
+            Ok(())
+        })
+    }
+}
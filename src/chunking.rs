@@ -30,12 +30,34 @@
 //! pixel. In particular, the chunks supported by this
 //! module have the following properties:
 //!
-//! - **Full Width.** Each chunk spans the full width of the
-//! raster. This simplifies the iteration logic, and is
-//! currently the only supported mode.
+//! - **Full Width.** By default, each chunk spans the full
+//! width of the raster (or of the range of columns to
+//! process, see [`with_x_start`]). This simplifies the
+//! iteration logic. Alternatively, the _column mode_ (see
+//! [`with_column_mode`] and [`with_tile_dims`]) tiles the
+//! raster along both the axes, which is useful for tall
+//! and narrow, or very wide rasters.
 //!
 //! - **Fixed Padding.** Each chunk may additionally use a
-//! fixed number of rows above and below it.
+//! fixed number of rows above and below it. In column mode,
+//! each chunk may also use a fixed number of columns on the
+//! left and right of it (see [`with_x_padding`]), clamped
+//! to the raster boundary.
+//!
+//! - **Valid Region.** Optionally, a window of the raster
+//! that contains all the valid data may be specified (see
+//! [`with_valid_window`]). Chunks whose data lies entirely
+//! outside this window are marked as skipped (see
+//! [`ChunkWindow::is_skipped`]), and need not be read.
+//!
+//! [`with_column_mode`]: ChunkConfig::with_column_mode
+//! [`with_tile_dims`]: ChunkConfig::with_tile_dims
+//! [`with_x_padding`]: ChunkConfig::with_x_padding
+//! [`with_valid_window`]: ChunkConfig::with_valid_window
+//! [`with_x_start`]: ChunkConfig::with_x_start
+
+use crate::geometry::RasterWindow;
+use serde_derive::{Deserialize, Serialize};
 
 /// Builder to configure chunking. Supports configuring the
 /// following paramaters.
@@ -53,43 +75,116 @@
 /// value is always maintained as an integer multiple of
 /// `block_size` for efficiency.
 ///
+/// - `max_data_height` - an optional upper bound on the
+/// number of data rows in each chunk (see
+/// [`with_max_data_height`]). Takes precedence over the
+/// minimum, and over the block alignment if it is smaller
+/// than `block_size`.
+///
+/// - `data_width` - the number of columns (not including
+/// padding) in each chunk if the column mode is enabled
+/// (see [`with_column_mode`]). Otherwise, each chunk
+/// spans the full width of the raster.
+///
 /// - `padding` - the number of additional rows required on
 /// either size of the data.
 ///
+/// - `x_padding` - the number of additional columns
+/// required on either side of the data in column mode. The
+/// columns are clamped to the raster boundary, so chunks at
+/// the left and right edges may have fewer padding columns.
+///
 /// - `start`,`end` - the semi-open range (i.e. `start..end`
 /// in the usual notation) to process (not including
 /// padding). The `start` is always at least the `padding`
 /// value.
 ///
+/// - `x_start`,`x_end` - the semi-open range of columns to
+/// process (not including padding columns). Defaults to
+/// all the columns (see [`with_x_start`]).
+///
+/// - `valid` - an optional window of the raster containing
+/// all the valid data (see [`with_valid_window`]). The
+/// iteration still produces every chunk, but marks those
+/// outside the window as skipped.
+///
+/// The configuration may be serialized (eg. to record the
+/// chunks processed by a job). Deserialization does not
+/// check the configuration, so call [`validate`] on a
+/// deserialized one before iterating its chunks.
+///
 /// [`add_block_size`]: ChunkConfig::add_block_size
+/// [`validate`]: ChunkConfig::validate
+/// [`with_column_mode`]: ChunkConfig::with_column_mode
+/// [`with_valid_window`]: ChunkConfig::with_valid_window
+/// [`with_max_data_height`]: ChunkConfig::with_max_data_height
+/// [`with_x_start`]: ChunkConfig::with_x_start
 /// [`Dataset`]: gdal::Dataset
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct ChunkConfig {
     width: usize,
     height: usize,
 
     block_size: usize,
     data_height: usize,
+    max_data_height: Option<usize>,
+    data_width: Option<usize>,
     padding: usize,
+    x_padding: usize,
 
     start: usize,
     end: usize,
+    x_start: usize,
+    x_end: usize,
+
+    valid: Option<RasterWindow>,
 }
 
 /// The type of item produced by the iterations. Consists
 /// of:
 ///
 /// 1. reference to the underlying `ChunkConfig`
-/// 1. the start index of this chunk
-/// 1. the number of rows (incl. padding) for this chunk
-pub type ChunkWindow<'a> = (&'a ChunkConfig, usize, usize);
+/// 1. the start column and row of this chunk
+/// 1. the number of columns and rows (incl. padding) for
+/// this chunk
+/// 1. the index of this chunk in the iteration. The index
+/// only depends on the configuration, and may be used to
+/// resume an iteration (see [`skip_to`]).
+///
+/// In the default (full width) mode, the chunk always
+/// starts at column `0` and spans the width of the raster.
+///
+/// [`skip_to`]: ChunkConfig::skip_to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkWindow<'a> {
+    cfg: &'a ChunkConfig,
+    index: usize,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+
+    data_x: usize,
+    data_width: usize,
+}
 
 mod builder;
 mod iters;
+mod window;
 
 #[cfg(feature = "use-rayon")]
 mod par_iters;
 
+#[cfg(feature = "gdal")]
+mod prefetch;
+#[cfg(feature = "gdal")]
+pub use prefetch::*;
+
+#[cfg(feature = "gdal")]
+mod read_iters;
+#[cfg(feature = "gdal")]
+pub use read_iters::*;
+
 #[inline]
 fn mod_ceil(num: usize, m: usize) -> usize {
     let rem = num % m;
@@ -106,14 +201,25 @@ mod tests {
 
     fn debug_cfg(cfg: ChunkConfig) {
         eprintln!("{:?}", cfg);
-        for (_, ls, size) in &cfg {
-            eprintln!("{} -> {}", ls, ls + size);
+        for win in &cfg {
+            eprintln!("{} -> {}", win.y(), win.y() + win.height());
         }
     }
 
     fn check_cfg(cfg: ChunkConfig, output: Vec<(usize, usize)>) {
         assert_eq!(
-            cfg.into_iter().map(|(_, a, b)| (a, b)).collect::<Vec<_>>(),
+            cfg.into_iter()
+                .map(|win| (win.y(), win.height()))
+                .collect::<Vec<_>>(),
+            output
+        );
+    }
+
+    fn check_cfg_2d(cfg: ChunkConfig, output: Vec<(usize, usize, usize, usize)>) {
+        assert_eq!(
+            cfg.into_iter()
+                .map(|win| (win.x(), win.y(), win.width(), win.height()))
+                .collect::<Vec<_>>(),
             output
         );
     }
@@ -150,4 +256,409 @@ mod tests {
             vec![(0, 16), (2, 15)],
         )
     }
+
+    #[test]
+    fn test_column_mode() {
+        check_cfg_2d(
+            ChunkConfig::with_dims(32, 20)
+                .with_column_mode(10)
+                .with_min_data_height(10),
+            vec![
+                (0, 0, 10, 10),
+                (10, 0, 10, 10),
+                (20, 0, 10, 10),
+                (30, 0, 2, 10),
+                (0, 10, 10, 10),
+                (10, 10, 10, 10),
+                (20, 10, 10, 10),
+                (30, 10, 2, 10),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_column_mode_padding() {
+        check_cfg_2d(
+            ChunkConfig::with_dims(32, 20)
+                .with_column_mode(10)
+                .with_min_data_height(10)
+                .with_padding(2)
+                .with_x_padding(2),
+            vec![
+                (0, 0, 12, 14),
+                (8, 0, 14, 14),
+                (18, 0, 14, 14),
+                (28, 0, 4, 14),
+                (0, 10, 12, 10),
+                (8, 10, 14, 10),
+                (18, 10, 14, 10),
+                (28, 10, 4, 10),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_x_padding_clamped() {
+        let cfg = ChunkConfig::with_dims(32, 20)
+            .with_column_mode(10)
+            .with_min_data_height(20)
+            .with_x_padding(3);
+        check_cfg_2d(
+            cfg.clone(),
+            vec![
+                (0, 0, 13, 20),
+                (7, 0, 16, 20),
+                (17, 0, 15, 20),
+                (27, 0, 5, 20),
+            ],
+        );
+
+        let win = cfg.window_at(1).unwrap();
+        assert_eq!(win.raster_window(), ((7, 0), (16, 20)));
+        assert_eq!(win.y_offset(), 0);
+
+        // Padding is asymmetric at the left and right edges
+        assert_eq!(
+            cfg.iter()
+                .map(|win| win.data_col_range())
+                .collect::<Vec<_>>(),
+            vec![0..10, 3..13, 3..13, 3..5]
+        );
+
+        // The data columns cover the raster exactly once
+        let mut next = 0;
+        for win in &cfg {
+            let cols = win.data_col_range();
+            assert_eq!(win.x() + cols.start, next);
+            next = win.x() + cols.end;
+        }
+        assert_eq!(next, cfg.width());
+    }
+
+    #[test]
+    fn test_x_range() {
+        let cfg = ChunkConfig::with_dims(100, 20)
+            .with_tile_dims(10, 20)
+            .with_x_padding(2)
+            .with_x_start(35)
+            .with_x_end(60);
+        check_cfg_2d(
+            cfg.clone(),
+            vec![(33, 0, 14, 20), (43, 0, 14, 20), (53, 0, 9, 20)],
+        );
+        assert_eq!(
+            cfg.iter()
+                .map(|win| win.data_offset().0)
+                .collect::<Vec<_>>(),
+            vec![35, 45, 55]
+        );
+
+        // Full width mode spans the range of columns
+        let cfg = ChunkConfig::with_dims(100, 20)
+            .with_min_data_height(10)
+            .with_x_start(95);
+        check_cfg_2d(cfg.clone(), vec![(95, 0, 5, 10), (95, 10, 5, 10)]);
+        assert!(cfg.clone().validate().is_ok());
+
+        // Empty range of columns
+        let cfg = ChunkConfig::with_dims(100, 20)
+            .with_x_start(50)
+            .with_x_end(50);
+        assert_eq!(cfg.iter().len(), 0);
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn test_tile_dims_truncated() {
+        let cfg = ChunkConfig::with_dims(25, 23)
+            .add_block_size(4)
+            .with_tile_dims(8, 5);
+        assert_eq!(cfg.data_height(), 8);
+
+        let windows: Vec<_> = cfg.iter().collect();
+        assert_eq!(windows.len(), 12);
+        for win in &windows {
+            assert!(win.x() + win.width() <= cfg.width());
+            assert!(win.y() + win.height() <= cfg.height());
+        }
+        check_cfg_2d(
+            cfg.clone(),
+            vec![
+                (0, 0, 8, 8),
+                (8, 0, 8, 8),
+                (16, 0, 8, 8),
+                (24, 0, 1, 8),
+                (0, 8, 8, 8),
+                (8, 8, 8, 8),
+                (16, 8, 8, 8),
+                (24, 8, 1, 8),
+                (0, 16, 8, 7),
+                (8, 16, 8, 7),
+                (16, 16, 8, 7),
+                (24, 16, 1, 7),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_data_range() {
+        let cfg = ChunkConfig::with_dims(32, 20)
+            .add_block_size(2)
+            .with_padding(7)
+            .with_end(10);
+        assert_eq!(
+            cfg.iter()
+                .map(|win| {
+                    let rows = win.data_range();
+                    (win.y() + rows.start, win.y() + rows.end)
+                })
+                .collect::<Vec<_>>(),
+            vec![(7, 9), (9, 10)]
+        );
+
+        let cfg = ChunkConfig::with_dims(32, 20)
+            .add_block_size(4)
+            .with_min_data_height(3)
+            .with_padding(2)
+            .with_start(3);
+        let mut next = cfg.start();
+        for win in &cfg {
+            let rows = win.data_range();
+            assert_eq!(win.y() + rows.start, next);
+            next = win.y() + rows.end;
+        }
+        assert_eq!(next, cfg.height() - cfg.padding());
+    }
+
+    #[test]
+    fn test_max_data_height() {
+        // Maximum takes precedence over the minimum
+        let cfg = ChunkConfig::with_dims(32, 100)
+            .add_block_size(8)
+            .with_min_data_height(40)
+            .with_max_data_height(20);
+        assert_eq!(cfg.data_height(), 16);
+        check_cfg(
+            cfg,
+            vec![
+                (0, 16),
+                (16, 16),
+                (32, 16),
+                (48, 16),
+                (64, 16),
+                (80, 16),
+                (96, 4),
+            ],
+        );
+
+        // The first chunk is not extended beyond the cap
+        let cfg = ChunkConfig::with_dims(32, 100)
+            .add_block_size(8)
+            .with_max_data_height(10)
+            .with_start(5)
+            .with_end(30);
+        check_cfg(cfg, vec![(5, 3), (8, 8), (16, 8), (24, 6)]);
+
+        // A cap smaller than the block size
+        let cfg = ChunkConfig::with_dims(32, 30)
+            .add_block_size(8)
+            .with_max_data_height(5)
+            .with_padding(1);
+        assert_eq!(cfg.data_height(), 5);
+        assert!(cfg.clone().validate().is_ok());
+        check_cfg(
+            cfg.clone(),
+            vec![(0, 7), (5, 7), (10, 7), (15, 7), (20, 7), (25, 5)],
+        );
+        for win in &cfg {
+            assert!(win.data_range().len() <= 5);
+        }
+
+        // Small end with padding
+        let cfg = ChunkConfig::with_dims(32, 100)
+            .add_block_size(8)
+            .with_min_data_height(16)
+            .with_max_data_height(12)
+            .with_padding(2)
+            .with_end(7);
+        check_cfg(cfg, vec![(0, 9)]);
+    }
+
+    #[test]
+    fn test_trim_padding() {
+        use ndarray::{s, Array2};
+
+        // Configurations of `test_simple` and
+        // `test_column_mode_padding`
+        let cfgs = vec![
+            ChunkConfig::with_dims(32, 20)
+                .add_block_size(2)
+                .with_padding(7)
+                .with_end(10),
+            ChunkConfig::with_dims(32, 20)
+                .with_column_mode(10)
+                .with_min_data_height(10)
+                .with_padding(2)
+                .with_x_padding(2),
+        ];
+
+        // Each pixel of the raster holds its index
+        let raster = Array2::from_shape_fn((20, 32), |(i, j)| i * 32 + j);
+        for cfg in cfgs {
+            let mut covered = Array2::<usize>::zeros((20, 32));
+            for win in &cfg {
+                let arr = raster
+                    .slice(s![
+                        win.y()..win.y() + win.height(),
+                        win.x()..win.x() + win.width()
+                    ])
+                    .to_owned();
+
+                let (x, y) = win.data_offset();
+                for ((i, j), &val) in win.trim_padding(&arr).indexed_iter() {
+                    assert_eq!(val, (y + i) * 32 + x + j);
+                    covered[(y + i, x + j)] += 1;
+                }
+            }
+
+            // The rows in the processed range are covered
+            // exactly once.
+            let end = cfg.end().min(cfg.height() - cfg.padding());
+            for ((i, _), &count) in covered.indexed_iter() {
+                let expected = if i >= cfg.start() && i < end { 1 } else { 0 };
+                assert_eq!(count, expected, "row {}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let budget = 100 * 8 * (64 + 4 + 15);
+        let cfg = ChunkConfig::with_dims(100, 1000)
+            .add_block_size(16)
+            .with_padding(2)
+            .with_start(5)
+            .with_memory_budget(budget, 1, 8)
+            .unwrap();
+        assert_eq!(cfg.data_height(), 64);
+        for win in &cfg {
+            assert!(win.width() * win.height() * 8 <= budget);
+        }
+
+        // Column mode only loads the tile width
+        let cfg = ChunkConfig::with_dims(1000, 1000)
+            .with_tile_dims(10, 1)
+            .with_x_padding(1)
+            .with_memory_budget(1200, 2, 4)
+            .unwrap();
+        assert_eq!(cfg.data_height(), 12);
+
+        // A single block doesn't fit
+        assert!(ChunkConfig::with_dims(100, 1000)
+            .add_block_size(16)
+            .with_memory_budget(100 * 8 * 15, 1, 8)
+            .is_err());
+
+        // No columns to read: unchanged
+        let cfg = ChunkConfig::with_dims(100, 1000)
+            .with_x_start(40)
+            .with_x_end(40)
+            .with_min_data_height(10)
+            .with_memory_budget(1, 1, 8)
+            .unwrap();
+        assert_eq!(cfg.data_height(), 10);
+    }
+
+    #[test]
+    fn test_valid_window() {
+        let cfg = ChunkConfig::with_dims(100, 100)
+            .with_min_data_height(10)
+            .with_padding(2)
+            .with_valid_window(((0, 25), (100, 18)));
+        assert_eq!(
+            cfg.iter()
+                .filter(|win| !win.is_skipped())
+                .map(|win| win.index())
+                .collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+        assert_eq!(cfg.iter().count(), 10);
+
+        // Only the data columns are considered
+        let cfg = ChunkConfig::with_dims(100, 100)
+            .with_tile_dims(10, 50)
+            .with_x_padding(5)
+            .with_valid_window(((40, 60), (10, 5)));
+        assert_eq!(
+            cfg.iter()
+                .filter(|win| !win.is_skipped())
+                .map(|win| (win.x(), win.y()))
+                .collect::<Vec<_>>(),
+            vec![(35, 50)]
+        );
+
+        // An empty window skips everything
+        let cfg = ChunkConfig::with_dims(100, 100).with_valid_window(((0, 0), (0, 0)));
+        assert!(cfg.iter().all(|win| win.is_skipped()));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(ChunkConfig::try_with_dims(0, 10).is_err());
+        assert!(ChunkConfig::try_with_dims(10, 0).is_err());
+        assert!(ChunkConfig::with_dims(10, 10)
+            .try_add_block_size(0)
+            .is_err());
+
+        let cfg = || ChunkConfig::with_dims(10, 10).add_block_size(2);
+        assert!(cfg().with_padding(4).validate().is_ok());
+
+        // Padding leaves no data rows
+        assert!(cfg().with_padding(5).validate().is_err());
+
+        // Empty range
+        assert!(cfg().with_start(6).with_end(6).validate().is_err());
+        assert!(cfg().with_start(7).with_end(3).validate().is_err());
+        assert!(cfg().with_start(11).validate().is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "dimensions must both be at least 1")]
+    fn test_with_dims_panics() {
+        ChunkConfig::with_dims(0, 10);
+    }
+
+    #[test]
+    fn test_resume() {
+        let cfg = || {
+            ChunkConfig::with_dims(100, 1000)
+                .add_block_size(7)
+                .with_tile_dims(30, 20)
+                .with_padding(3)
+                .with_start(11)
+        };
+
+        let full = cfg();
+        let all: Vec<_> = full
+            .iter()
+            .map(|win| (win.x(), win.y(), win.index()))
+            .collect();
+
+        // Interrupt the iteration midway, and record the
+        // index of the last processed window
+        let last = full.iter().take(17).last().unwrap().index();
+        assert_eq!(last, 16);
+
+        let resumed = cfg();
+        let rest: Vec<_> = resumed
+            .skip_to(last + 1)
+            .map(|win| (win.x(), win.y(), win.index()))
+            .collect();
+        assert_eq!(&all[last + 1..], &rest[..]);
+
+        let win = resumed.window_at(last).unwrap();
+        assert_eq!((win.x(), win.y(), win.index()), all[last]);
+        assert!(resumed.window_at(all.len()).is_none());
+        assert_eq!(resumed.skip_to(all.len() + 5).len(), 0);
+    }
 }
@@ -1,46 +1,111 @@
 use super::{mod_ceil, ChunkConfig};
+use crate::geometry::RasterWindow;
 
 /// Constructors
 impl ChunkConfig {
     /// Construct a `ChunkConfig` with a given raster size.
+    /// Panics if either dimension is zero; see
+    /// [`try_with_dims`] for a fallible version.
+    ///
+    /// [`try_with_dims`]: ChunkConfig::try_with_dims
     pub fn with_dims(width: usize, height: usize) -> Self {
+        ChunkConfig::try_with_dims(width, height).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Construct a `ChunkConfig` with a given raster size.
+    /// Returns an error if either dimension is zero.
+    pub fn try_with_dims(width: usize, height: usize) -> crate::Result<Self> {
         if width < 1 || height < 1 {
-            panic!("dimensions must both be at least 1");
+            anyhow::bail!(
+                "dimensions must both be at least 1 (got {}x{})",
+                width,
+                height
+            );
         }
-        ChunkConfig {
+        Ok(ChunkConfig {
             width,
             height,
 
             block_size: 1,
             data_height: 1,
+            max_data_height: None,
+            data_width: None,
             padding: 0,
+            x_padding: 0,
 
             start: 0,
             end: height,
-        }
+            x_start: 0,
+            x_end: width,
+
+            valid: None,
+        })
     }
 
     #[cfg(feature = "gdal")]
     /// Construct a `ChunkConfig` from a raster [`Dataset`],
     /// reading the size from it. An optional list of bands
     /// may be specified to configure the `block_size`.
+    ///
+    /// [`Dataset`]: gdal::Dataset
     pub fn for_dataset<I: IntoIterator<Item = isize>>(
         ds: &gdal::Dataset,
         bands: Option<I>,
     ) -> crate::Result<Self> {
-        use anyhow::Context;
         let size = ds.raster_size();
-        let mut cfg = ChunkConfig::with_dims(size.0, size.1);
+        ChunkConfig::with_dims(size.0, size.1).add_dataset(ds, bands)
+    }
 
-        if let Some(bands) = bands {
-            for band_idx in bands {
-                let band = ds
-                    .rasterband(band_idx)
-                    .with_context(|| format!("unable to open rasterband {}", band_idx))?;
-                cfg = cfg.add_block_size(band.block_size().1);
-            }
-        }
+    #[cfg(feature = "gdal")]
+    /// Construct a `ChunkConfig` with the dimensions of a
+    /// [`ChunkReader`] (see [`ChunkReader::raster_size`]).
+    /// Useful to chunk readers not backed by a dataset (eg.
+    /// a [`MemoryReader`]).
+    ///
+    /// [`ChunkReader`]: crate::reader::ChunkReader
+    /// [`ChunkReader::raster_size`]: crate::reader::ChunkReader::raster_size
+    /// [`MemoryReader`]: crate::reader::MemoryReader
+    pub fn for_reader<R: crate::reader::ChunkReader>(reader: &R) -> crate::Result<Self> {
+        let (width, height) = reader.raster_size()?;
+        ChunkConfig::try_with_dims(width, height)
+    }
+
+    #[cfg(feature = "gdal")]
+    /// Construct a `ChunkConfig` to read an overview with an
+    /// [`OverviewReader`], with the dimensions of the
+    /// overview. The `block_size` is configured from the
+    /// overview band.
+    ///
+    /// [`OverviewReader`]: crate::reader::OverviewReader
+    pub fn for_overview(reader: &crate::reader::OverviewReader) -> crate::Result<Self> {
+        use anyhow::Context;
+        let (width, height) = reader.dims();
+        let band = reader.overview_band()?;
+        ChunkConfig::try_with_dims(width, height)?
+            .try_add_block_size(band.block_size().1)
+            .with_context(|| format!("overview level {}", reader.level()))
+    }
+
+    #[cfg(feature = "gdal")]
+    /// Construct a `ChunkConfig` from a list of raster
+    /// [`Dataset`]s of the same size. The `block_size` is
+    /// configured from the given bands of all the datasets
+    /// (see [`add_dataset`]).
+    ///
+    /// [`Dataset`]: gdal::Dataset
+    /// [`add_dataset`]: ChunkConfig::add_dataset
+    pub fn for_datasets<I: IntoIterator<Item = isize> + Clone>(
+        datasets: &[&gdal::Dataset],
+        bands: Option<I>,
+    ) -> crate::Result<Self> {
+        let (first, rest) = datasets
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("no datasets to configure chunks"))?;
 
+        let mut cfg = ChunkConfig::for_dataset(first, bands.clone())?;
+        for ds in rest {
+            cfg = cfg.add_dataset(ds, bands.clone())?;
+        }
         Ok(cfg)
     }
 }
@@ -49,23 +114,98 @@ impl ChunkConfig {
 impl ChunkConfig {
     /// Accumulate the given `block_size` to the
     /// configuration by calculating the least common
-    /// multiple with the current value.
-    pub fn add_block_size(mut self, block_size: usize) -> Self {
+    /// multiple with the current value. Panics if
+    /// `block_size` is zero; see [`try_add_block_size`] for
+    /// a fallible version.
+    ///
+    /// [`try_add_block_size`]: ChunkConfig::try_add_block_size
+    pub fn add_block_size(self, block_size: usize) -> Self {
+        self.try_add_block_size(block_size)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Accumulate the given `block_size` (see
+    /// [`add_block_size`]). Returns an error if `block_size`
+    /// is zero.
+    ///
+    /// [`add_block_size`]: ChunkConfig::add_block_size
+    pub fn try_add_block_size(mut self, block_size: usize) -> crate::Result<Self> {
         if block_size < 1 {
-            panic!("block_size should be at least 1");
+            anyhow::bail!("block_size should be at least 1");
         }
         self.block_size = lcm(self.block_size, block_size);
         self.adjust_block_height();
-        self
+        Ok(self)
+    }
+
+    #[cfg(feature = "gdal")]
+    /// Accumulate the block sizes of the given bands of a
+    /// [`Dataset`] (see [`add_block_size`]). Returns an error
+    /// if the dataset size does not match the configured
+    /// dimensions.
+    ///
+    /// [`Dataset`]: gdal::Dataset
+    /// [`add_block_size`]: ChunkConfig::add_block_size
+    pub fn add_dataset<I: IntoIterator<Item = isize>>(
+        mut self,
+        ds: &gdal::Dataset,
+        bands: Option<I>,
+    ) -> crate::Result<Self> {
+        use anyhow::{bail, Context};
+        let size = ds.raster_size();
+        if size != (self.width, self.height) {
+            bail!(
+                "dataset dimensions ({}x{}) do not match chunk dimensions ({}x{})",
+                size.0,
+                size.1,
+                self.width,
+                self.height
+            );
+        }
+
+        if let Some(bands) = bands {
+            for band_idx in bands {
+                let band = ds
+                    .rasterband(band_idx)
+                    .with_context(|| format!("unable to open rasterband {}", band_idx))?;
+                self = self
+                    .try_add_block_size(band.block_size().1)
+                    .with_context(|| format!("rasterband {}", band_idx))?;
+            }
+        }
+
+        Ok(self)
     }
+
     /// Set the minimum `data_height` for the chunking. The
     /// actual `data_height` is the least multiple of
-    /// `block_size` larger or equal to the given value.
+    /// `block_size` larger or equal to the given value,
+    /// subject to the maximum (see [`with_max_data_height`]).
+    ///
+    /// [`with_max_data_height`]: ChunkConfig::with_max_data_height
     pub fn with_min_data_height(mut self, min_data_height: usize) -> Self {
         self.data_height = min_data_height.max(1);
         self.adjust_block_height();
         self
     }
+
+    /// Set the maximum number of data rows (not including
+    /// padding) in each chunk. The `data_height` is capped
+    /// to the largest multiple of `block_size` that is at
+    /// most `max_data_height`, and this cap also applies to
+    /// the values configured subsequently. Thus, the maximum
+    /// takes precedence if it conflicts with the minimum.
+    ///
+    /// If `max_data_height` is smaller than `block_size`,
+    /// the `data_height` is set to `max_data_height` and the
+    /// chunks are no longer aligned to the blocks. The first
+    /// chunk, which is usually extended to end at a block
+    /// boundary, is also never extended beyond the cap.
+    pub fn with_max_data_height(mut self, max_data_height: usize) -> Self {
+        self.max_data_height = Some(max_data_height.max(1));
+        self.adjust_block_height();
+        self
+    }
     /// Set the minimum `data_height` by specifying minimum
     /// number of data pixels expected in each chunk.
     pub fn with_min_data_size(self, min_data_size: usize) -> Self {
@@ -73,6 +213,78 @@ impl ChunkConfig {
         self.with_min_data_height(min_height)
     }
 
+    /// Set the `data_height` to the largest multiple of
+    /// `block_size` such that each chunk (incl. padding)
+    /// fits within `bytes` of memory, when reading `bands`
+    /// bands with pixels of size `bytes_per_pixel`. Accounts
+    /// for the additional rows read by the first chunk to
+    /// end at a block boundary.
+    ///
+    /// Unlike [`with_min_data_height`], this bounds the
+    /// `data_height` from above, and overrides any
+    /// previously configured value. Hence, it should be
+    /// called after configuring the block size, padding and
+    /// column mode. Returns an error if a chunk with a
+    /// single block of data does not fit within the budget.
+    /// If the chunks are empty (no columns to read), any
+    /// height fits, and the configuration is unchanged.
+    ///
+    /// [`with_min_data_height`]: ChunkConfig::with_min_data_height
+    pub fn with_memory_budget(
+        mut self,
+        bytes: usize,
+        bands: usize,
+        bytes_per_pixel: usize,
+    ) -> crate::Result<Self> {
+        let data_width = self.x_end.saturating_sub(self.x_start);
+        let chunk_width = (self.data_width.map_or(data_width, |w| w.min(data_width))
+            + 2 * self.x_padding)
+            .min(self.width);
+        let row_bytes = chunk_width * bands.max(1) * bytes_per_pixel.max(1);
+        if row_bytes == 0 {
+            return Ok(self);
+        }
+
+        let max_rows = bytes / row_bytes;
+        let extra_rows = 2 * self.padding + self.block_size - 1;
+        let data_height = max_rows.saturating_sub(extra_rows) / self.block_size * self.block_size;
+        if data_height < 1 {
+            let rows = self.block_size + extra_rows;
+            anyhow::bail!(
+                "memory budget of {} bytes is too small: a chunk of {} rows requires {} bytes",
+                bytes,
+                rows,
+                rows * row_bytes
+            );
+        }
+
+        self.data_height = data_height;
+        self.adjust_block_height();
+        Ok(self)
+    }
+
+    /// Enable the column mode: each chunk spans (at most)
+    /// `data_width` columns (not including padding) instead
+    /// of the full width of the raster. The chunks are
+    /// iterated in row-major order.
+    pub fn with_column_mode(mut self, data_width: usize) -> Self {
+        self.data_width = Some(data_width.max(1));
+        self
+    }
+
+    /// Configure 2D tiled chunks of (at least) the given
+    /// data dimensions. This enables the column mode with
+    /// the given `width`, and sets the minimum
+    /// `data_height`. As with [`with_min_data_height`], the
+    /// height is rounded up to a multiple of `block_size`.
+    /// The tiles at the right and bottom edges are
+    /// truncated to the raster dimensions.
+    ///
+    /// [`with_min_data_height`]: ChunkConfig::with_min_data_height
+    pub fn with_tile_dims(self, width: usize, height: usize) -> Self {
+        self.with_column_mode(width).with_min_data_height(height)
+    }
+
     /// Set the padding required for each chunk.
     pub fn with_padding(mut self, padding: usize) -> Self {
         self.padding = padding;
@@ -80,6 +292,24 @@ impl ChunkConfig {
         self
     }
 
+    /// Set the number of padding columns required on
+    /// either side of each chunk. Only used in the column
+    /// mode (see [`with_column_mode`]), or if the range of
+    /// columns is restricted (see [`with_x_start`] and
+    /// [`with_x_end`]). Unlike the rows, the
+    /// padding columns are clamped to the raster boundary;
+    /// use [`ChunkWindow::data_col_range`] to locate the data
+    /// columns within a chunk.
+    ///
+    /// [`with_column_mode`]: ChunkConfig::with_column_mode
+    /// [`with_x_start`]: ChunkConfig::with_x_start
+    /// [`with_x_end`]: ChunkConfig::with_x_end
+    /// [`ChunkWindow::data_col_range`]: super::ChunkWindow::data_col_range
+    pub fn with_x_padding(mut self, x_padding: usize) -> Self {
+        self.x_padding = x_padding;
+        self
+    }
+
     /// Set the start index of the iteration range.
     pub fn with_start(mut self, start: usize) -> Self {
         self.start = start;
@@ -94,11 +324,133 @@ impl ChunkConfig {
         self
     }
 
+    /// Set the first column of the data to process. Unlike
+    /// the rows, no padding is required to the left of the
+    /// column, as the padding columns are clamped to the
+    /// raster boundary. The chunks only load the columns in
+    /// `x_start..x_end` (and the padding columns); use
+    /// [`ChunkWindow::x`] to locate them in the raster.
+    ///
+    /// [`ChunkWindow::x`]: super::ChunkWindow::x
+    pub fn with_x_start(mut self, x_start: usize) -> Self {
+        self.x_start = x_start;
+        self
+    }
+
+    /// Set the end (not included) column of the data to
+    /// process (see [`with_x_start`]).
+    ///
+    /// [`with_x_start`]: ChunkConfig::with_x_start
+    pub fn with_x_end(mut self, x_end: usize) -> Self {
+        self.x_end = x_end.min(self.width);
+        self
+    }
+
+    /// Set the window of the raster that contains all the
+    /// valid data. Chunks whose data (excluding padding)
+    /// does not intersect the window are marked as skipped
+    /// (see [`ChunkWindow::is_skipped`]). A window of size
+    /// `(0, 0)` (eg. from
+    /// [`window_from_bounds`][crate::geometry::BoundsExt::window_from_bounds]
+    /// of a disjoint region) marks every chunk as skipped.
+    ///
+    /// [`ChunkWindow::is_skipped`]: super::ChunkWindow::is_skipped
+    pub fn with_valid_window(mut self, valid: RasterWindow) -> Self {
+        self.valid = Some(valid);
+        self
+    }
+
+    /// Check that the configuration is consistent, and
+    /// yields at least one data row. Returns a descriptive
+    /// error otherwise. Useful when the parameters are
+    /// obtained from user input, or a deserialized
+    /// configuration.
+    pub fn validate(self) -> crate::Result<Self> {
+        use anyhow::bail;
+        if self.width < 1 || self.height < 1 {
+            bail!(
+                "dimensions must both be at least 1 (got {}x{})",
+                self.width,
+                self.height
+            );
+        }
+        if self.block_size < 1 {
+            bail!("block_size should be at least 1");
+        }
+        if self.data_height < 1 || !self.is_block_height_valid() {
+            bail!(
+                "data_height ({}) must be a positive multiple of block_size ({})",
+                self.data_height,
+                self.block_size
+            );
+        }
+        if self.data_width == Some(0) {
+            bail!("data_width should be at least 1");
+        }
+        if 2 * self.padding >= self.height {
+            bail!(
+                "padding of {} rows on either side leaves no data in a raster of {} rows",
+                self.padding,
+                self.height
+            );
+        }
+        if self.start < self.padding {
+            bail!(
+                "start ({}) must be at least the padding ({})",
+                self.start,
+                self.padding
+            );
+        }
+        if self.end > self.height {
+            bail!(
+                "end ({}) is beyond the raster height ({})",
+                self.end,
+                self.height
+            );
+        }
+        if self.start >= self.end {
+            bail!("start ({}) must be before end ({})", self.start, self.end);
+        }
+        if self.x_end > self.width {
+            bail!(
+                "x_end ({}) is beyond the raster width ({})",
+                self.x_end,
+                self.width
+            );
+        }
+        if self.x_start >= self.x_end {
+            bail!(
+                "x_start ({}) must be before x_end ({})",
+                self.x_start,
+                self.x_end
+            );
+        }
+        Ok(self)
+    }
+
     /// Ensure that block height is non-zero, and a multiple
-    /// of block size.
+    /// of block size, unless the maximum is smaller than
+    /// the block size.
     #[inline]
     fn adjust_block_height(&mut self) {
         self.data_height = mod_ceil(self.data_height, self.block_size);
+        if let Some(max) = self.max_data_height {
+            let cap = max / self.block_size * self.block_size;
+            self.data_height = if cap > 0 {
+                self.data_height.min(cap)
+            } else {
+                max
+            };
+        }
+    }
+
+    /// Check that the block height is a multiple of block
+    /// size, or equal to a maximum smaller than block size.
+    #[inline]
+    pub(super) fn is_block_height_valid(&self) -> bool {
+        self.data_height % self.block_size == 0
+            || (Some(self.data_height) == self.max_data_height
+                && self.data_height < self.block_size)
     }
 
     /// Ensure start is always greater than padding
@@ -123,9 +475,18 @@ impl ChunkConfig {
     pub fn data_height(&self) -> usize {
         self.data_height
     }
+    pub fn max_data_height(&self) -> Option<usize> {
+        self.max_data_height
+    }
+    pub fn data_width(&self) -> Option<usize> {
+        self.data_width
+    }
     pub fn padding(&self) -> usize {
         self.padding
     }
+    pub fn x_padding(&self) -> usize {
+        self.x_padding
+    }
 
     pub fn start(&self) -> usize {
         self.start
@@ -133,6 +494,15 @@ impl ChunkConfig {
     pub fn end(&self) -> usize {
         self.end
     }
+    pub fn x_start(&self) -> usize {
+        self.x_start
+    }
+    pub fn x_end(&self) -> usize {
+        self.x_end
+    }
+    pub fn valid_window(&self) -> Option<RasterWindow> {
+        self.valid
+    }
 }
 
 #[inline]
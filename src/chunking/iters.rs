@@ -17,7 +17,8 @@ impl ChunkConfig {
             self.block_size > 0
                 && self.start >= self.padding
                 && self.end <= self.height
-                && self.data_height % self.block_size == 0,
+                && self.x_end <= self.width
+                && self.is_block_height_valid(),
             "ChunkConfig preconditions failed"
         );
     }
@@ -36,6 +37,21 @@ impl ChunkConfig {
         let mut load_end = mod_ceil(data_end + self.padding, self.block_size).min(self.height);
         debug_assert!(load_end > self.start);
 
+        // The extension must respect the maximum data height.
+        // Use the last block boundary within it if there is
+        // one, and otherwise give up the alignment.
+        if let Some(max) = self.max_data_height {
+            let max_load_end = self.start + max + self.padding;
+            if load_end > max_load_end {
+                let aligned = max_load_end / self.block_size * self.block_size;
+                load_end = if aligned > self.start + self.padding {
+                    aligned
+                } else {
+                    max_load_end
+                };
+            }
+        }
+
         // The whole raster may be too narrow for the given
         // padding, but we still yield it as the padding
         // might be an upper bound.
@@ -56,17 +72,40 @@ impl ChunkConfig {
             let lcount = mod_ceil(self.height - load_end, self.data_height) / self.data_height;
             dcount.min(lcount)
         } + 1;
-        debug_assert!(count == 1 || load_end % self.block_size == 0);
+        debug_assert!(
+            count == 1 || load_end % self.block_size == 0 || self.data_height < self.block_size
+        );
 
         [count, data_end, load_end]
     }
 
+    /// Calculate the number of chunks along a row, and the
+    /// range of data columns. In full width mode, there is
+    /// exactly one chunk spanning all the columns to be
+    /// processed.
+    fn calc_columns(&self) -> [usize; 3] {
+        // The padding columns are clamped to the raster, so
+        // the data spans all the columns in the range.
+        let (start, end) = (self.x_start, self.x_end);
+        if start >= end {
+            return [0, 0, 0];
+        }
+
+        let count = match self.data_width {
+            Some(w) => mod_ceil(end - start, w) / w,
+            None => 1,
+        };
+        [count, start, end]
+    }
+
     pub(super) fn iter_mapper<'a>(&'a self) -> (usize, impl Fn(usize) -> ChunkWindow<'a> + 'a) {
         self.check_preconditions();
 
         let [count, initial_data_end, initial_load_end] = self.calc_initial_chunk();
+        let [cols, data_x_start, data_x_end] = self.calc_columns();
 
-        (count, move |i| {
+        (count * cols, move |idx| {
+            let (i, j) = (idx / cols, idx % cols);
             let (data_start, _, load_end) = if i == 0 {
                 (self.start, initial_data_end, initial_load_end)
             } else if i < count - 1 {
@@ -82,13 +121,66 @@ impl ChunkConfig {
                 (data_start, data_end, load_end)
             };
             let load_start = data_start - self.padding;
-            (self, load_start, (load_end - load_start) as usize)
+
+            let (data_x, data_width) = if let Some(data_width) = self.data_width {
+                let data_start = data_x_start + j * data_width;
+                let data_end = (data_start + data_width).min(data_x_end);
+                (data_start, data_end - data_start)
+            } else {
+                (data_x_start, data_x_end - data_x_start)
+            };
+            let x = data_x.saturating_sub(self.x_padding);
+            let x_end = (data_x + data_width + self.x_padding).min(self.width);
+
+            ChunkWindow {
+                cfg: self,
+                index: idx,
+                x,
+                y: load_start,
+                width: x_end - x,
+                height: load_end - load_start,
+
+                data_x,
+                data_width,
+            }
         })
     }
 
     /// Create an [ `ExactSizeIterator` ] from the configuration.
-    pub fn iter(&self) -> impl ExactSizeIterator<Item = ChunkWindow> + '_ {
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = ChunkWindow<'_>> + '_ {
         let (count, func) = self.iter_mapper();
         (0..count).map(func)
     }
+
+    /// Create an [ `ExactSizeIterator` ] from the configuration
+    /// that starts at the window with the given index. The
+    /// indices (see [`ChunkWindow::index`]) are the same as
+    /// the other iterators on the configuration; thus an
+    /// interrupted iteration may be resumed from the index
+    /// of the next window, on a configuration with the same
+    /// parameters.
+    pub fn skip_to(&self, index: usize) -> impl ExactSizeIterator<Item = ChunkWindow<'_>> + '_ {
+        let (count, func) = self.iter_mapper();
+        (index.min(count)..count).map(func)
+    }
+
+    /// Get the window with the given index in the
+    /// iteration. Returns `None` if the index is beyond the
+    /// number of windows.
+    pub fn window_at(&self, index: usize) -> Option<ChunkWindow<'_>> {
+        let (count, func) = self.iter_mapper();
+        if index < count {
+            Some(func(index))
+        } else {
+            None
+        }
+    }
+
+    /// Collect the `(x, y, width, height)` extents of all
+    /// the windows of the iteration.
+    pub fn windows(&self) -> Vec<(usize, usize, usize, usize)> {
+        self.iter()
+            .map(|win| (win.x(), win.y(), win.width(), win.height()))
+            .collect()
+    }
 }
@@ -8,7 +8,7 @@ impl ChunkConfig {
     /// Create an [ `IndexedParallelIterator` ] from the configuration.
     ///
     /// This function is only available with the "use-rayon" feature.
-    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = ChunkWindow> {
+    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = ChunkWindow<'_>> {
         let (count, func) = self.iter_mapper();
         (0..count).into_par_iter().map(func)
     }
@@ -49,4 +49,46 @@ mod tests {
 
         assert_eq!(output1, output2);
     }
+
+    #[test]
+    fn test_same_output_tiled() {
+        let cfg = ChunkConfig::with_dims(1000, 1024)
+            .add_block_size(16)
+            .with_tile_dims(96, 40)
+            .with_padding(2)
+            .with_end(999);
+
+        let output1: Vec<_> = cfg.iter().collect();
+
+        let mut output2 = vec![];
+        cfg.par_iter().collect_into_vec(&mut output2);
+
+        assert_eq!(output1, output2);
+    }
+
+    #[cfg(feature = "gdal")]
+    #[test]
+    fn test_prefetch_par_bridge() -> crate::Result<()> {
+        use crate::reader::MemoryReader;
+
+        let arr = ndarray::Array2::from_shape_fn((30, 20), |(i, j)| (100 * i + j) as f64);
+        let cfg = ChunkConfig::with_dims(20, 30)
+            .with_tile_dims(8, 4)
+            .with_padding(1);
+
+        // Processed in parallel, in any order
+        let mut sums: Vec<_> = prefetch::<f64, _>(MemoryReader(arr.clone(), None), &cfg, 2)
+            .par_bridge()
+            .map(|chunk| chunk.map(|(index, data)| (index, data.sum())))
+            .collect::<crate::Result<_>>()?;
+        sums.sort_by_key(|&(index, _)| index);
+        assert_eq!(sums.len(), cfg.iter().len());
+        for ((index, sum), win) in sums.into_iter().zip(cfg.iter()) {
+            assert_eq!(index, win.index());
+            let (x, y) = (win.x(), win.y());
+            let data = arr.slice(ndarray::s![y..y + win.height(), x..x + win.width()]);
+            assert_eq!(sum, data.sum());
+        }
+        Ok(())
+    }
 }
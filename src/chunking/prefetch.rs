@@ -0,0 +1,224 @@
+use super::*;
+use crate::reader::ChunkReader;
+use crate::Result;
+use gdal::raster::GdalType;
+use ndarray::Array2;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread::JoinHandle;
+
+/// Read the chunks of `cfg` from `reader` on a background
+/// thread, up to `depth` chunks ahead of the consumer, so
+/// that IO overlaps with the processing of the chunks. The
+/// chunks are yielded in order, along with their index
+/// (see [`ChunkConfig::window_at`]). A failed read is
+/// yielded as an error, and the following chunks are still
+/// read. Dropping the iterator stops the background thread
+/// (once the read in progress, if any, completes).
+///
+/// Trades memory for throughput: besides the chunks being
+/// processed, up to `depth + 1` chunks are buffered. The
+/// chunks may be processed in parallel, eg. via `rayon`'s
+/// `par_bridge`, so that expensive per-chunk work does not
+/// stall the reads; the data of the chunks is then
+/// buffered until processed.
+pub fn prefetch<T, R>(reader: R, cfg: &ChunkConfig, depth: usize) -> ChunkPrefetch<T>
+where
+    T: GdalType + Copy + Send + 'static,
+    R: ChunkReader + Send + 'static,
+{
+    let (sender, receiver) = sync_channel(depth);
+    let len = cfg.iter().len();
+    let cfg = cfg.clone();
+    let thread = std::thread::spawn(move || {
+        for win in cfg.iter() {
+            let index = win.index();
+            let chunk = reader.read_chunk(win).map(|data| (index, data));
+            if sender.send(chunk).is_err() {
+                break;
+            }
+        }
+    });
+    ChunkPrefetch {
+        receiver: Some(receiver),
+        thread: Some(thread),
+        next: 0,
+        len,
+    }
+}
+
+/// A chunk read by [`prefetch`], with its index.
+type Prefetched<T> = Result<(usize, Array2<T>)>;
+
+/// Iterator returned by [`prefetch`].
+pub struct ChunkPrefetch<T> {
+    receiver: Option<Receiver<Prefetched<T>>>,
+    thread: Option<JoinHandle<()>>,
+    next: usize,
+    len: usize,
+}
+
+impl<T> Iterator for ChunkPrefetch<T> {
+    type Item = Prefetched<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.len {
+            return None;
+        }
+        self.next += 1;
+        match self.receiver.as_ref()?.recv() {
+            Ok(chunk) => Some(chunk),
+            Err(_) => {
+                self.next = self.len;
+                Some(Err(anyhow::format_err!("prefetch thread panicked")))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let rem = self.len - self.next;
+        (rem, Some(rem))
+    }
+}
+
+impl<T> ExactSizeIterator for ChunkPrefetch<T> {}
+
+impl<T> Drop for ChunkPrefetch<T> {
+    fn drop(&mut self) {
+        // Disconnect first, so that the thread stops at its
+        // next send instead of blocking
+        drop(self.receiver.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{RasterDims, RasterOffset};
+    use crate::reader::DatasetReader;
+    use gdal::{raster::Buffer, Dataset, DriverManager};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// A reader counting the reads of an inner reader.
+    struct CountingReader<R> {
+        reader: R,
+        reads: Arc<AtomicUsize>,
+    }
+
+    impl<R: ChunkReader> ChunkReader for CountingReader<R> {
+        fn read_into_slice<T>(
+            &self,
+            out: &mut [T],
+            off: RasterOffset,
+            size: RasterDims,
+        ) -> Result<()>
+        where
+            T: GdalType + Copy,
+        {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.reader.read_into_slice(out, off, size)
+        }
+    }
+
+    /// A dataset of `width` x `height` pixels, with the
+    /// value `y * width + x` at `(x, y)`.
+    fn ramp_dataset(path: &str, driver: &str, width: usize, height: usize) -> Result<Dataset> {
+        let data = (0..width * height).map(|i| i as f64).collect();
+        let driver = DriverManager::get_driver_by_name(driver)?;
+        let ds =
+            driver.create_with_band_type::<f64, _>(path, width as isize, height as isize, 1)?;
+        ds.rasterband(1)?
+            .write((0, 0), (width, height), &Buffer::new((width, height), data))?;
+        Ok(ds)
+    }
+
+    #[test]
+    fn test_prefetch_order_and_errors() -> Result<()> {
+        // The dataset covers the first 10 rows of the
+        // configuration: the later chunks fail to read
+        let ds = ramp_dataset("", "MEM", 20, 10)?;
+        let cfg = ChunkConfig::with_dims(20, 30).with_min_data_height(5);
+        let chunks: Vec<_> = prefetch::<f64, _>(DatasetReader::new(ds, 1)?, &cfg, 2).collect();
+        assert_eq!(chunks.len(), 6);
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            if index < 2 {
+                let (chunk_index, data) = chunk?;
+                assert_eq!(chunk_index, index);
+                let y = cfg.window_at(index).unwrap().y();
+                for ((i, j), &val) in data.indexed_iter() {
+                    assert_eq!(val, ((y + i) * 20 + j) as f64);
+                }
+            } else {
+                assert!(chunk.is_err(), "chunk {} out of bounds", index);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefetch_early_drop() -> Result<()> {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let reader = CountingReader {
+            reader: DatasetReader::new(ramp_dataset("", "MEM", 10, 100)?, 1)?,
+            reads: reads.clone(),
+        };
+        let cfg = ChunkConfig::with_dims(10, 100);
+        let mut chunks = prefetch::<f64, _>(reader, &cfg, 2);
+        assert_eq!(chunks.next().unwrap()?.0, 0);
+        drop(chunks);
+
+        // The thread is stopped (and joined) once dropped:
+        // besides the chunk consumed, at most `depth` chunks
+        // are buffered, and one is blocked on the channel
+        let count = reads.load(Ordering::SeqCst);
+        assert!(count <= 4, "{} reads", count);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefetch_read_ahead() -> Result<()> {
+        // A GTiff of 8 chunks of 16 rows
+        let (width, height) = (64, 128);
+        let path =
+            std::env::temp_dir().join(format!("rasters-prefetch-{}.tif", std::process::id()));
+        ramp_dataset(path.to_str().unwrap(), "GTIFF", width, height)?;
+        let cfg = ChunkConfig::with_dims(width, height).with_min_data_height(16);
+        let reads = Arc::new(AtomicUsize::new(0));
+        let reader = CountingReader {
+            reader: DatasetReader::new(Dataset::open(&path)?, 1)?,
+            reads: reads.clone(),
+        };
+
+        // While the first chunk is processed, the next
+        // `depth` chunks are buffered, and one more is read
+        // (blocked on the channel)
+        let depth = 2;
+        let mut chunks = prefetch::<f64, _>(reader, &cfg, depth);
+        let (index, first) = chunks.next().unwrap()?;
+        assert_eq!(index, 0);
+        for _ in 0..1000 {
+            if reads.load(Ordering::SeqCst) >= depth + 2 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert_eq!(reads.load(Ordering::SeqCst), depth + 2);
+
+        // Every chunk is yielded, in order
+        let mut sum = first.sum();
+        for (expected, chunk) in (1..).zip(chunks) {
+            let (index, data) = chunk?;
+            assert_eq!(index, expected);
+            sum += data.sum();
+        }
+        std::fs::remove_file(&path)?;
+
+        let count = width * height;
+        assert_eq!(reads.load(Ordering::SeqCst), cfg.iter().len());
+        assert_eq!(sum, (count * (count - 1) / 2) as f64);
+        Ok(())
+    }
+}
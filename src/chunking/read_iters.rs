@@ -0,0 +1,101 @@
+use super::{ChunkConfig, ChunkWindow};
+use crate::reader::ChunkReader;
+use crate::Result;
+use gdal::raster::GdalType;
+use ndarray::Array2;
+
+/// Iterate over the chunks of a configuration, reading the
+/// data of each window (including padding) from `reader`.
+/// The window is yielded along with the data to locate it
+/// in the raster (see [`ChunkWindow::data_range`] and
+/// [`ChunkWindow::data_col_range`]).
+///
+/// Use eg. [`RasterPathReader`] to read from a path, or a
+/// [`DatasetReader`] to read from an open dataset.
+///
+/// [`RasterPathReader`]: crate::reader::RasterPathReader
+/// [`DatasetReader`]: crate::reader::DatasetReader
+pub fn read_iter<'a, T, R>(
+    reader: R,
+    cfg: &'a ChunkConfig,
+) -> impl ExactSizeIterator<Item = Result<(ChunkWindow<'a>, Array2<T>)>> + 'a
+where
+    T: GdalType + Copy + 'a,
+    R: ChunkReader + 'a,
+{
+    cfg.iter()
+        .map(move |win| -> Result<_> { Ok((win, reader.read_chunk(win)?)) })
+}
+
+#[cfg(feature = "use-rayon")]
+/// Parallel counterpart of [`read_iter`]. Opens the raster
+/// at `path` once per thread (via `rayon`'s `map_init`)
+/// and reads the chunks from `band`. A failure to open the
+/// raster is reported as an error for each chunk processed
+/// by the thread.
+///
+/// This function is only available with the "use-rayon"
+/// feature.
+pub fn par_read_iter<'a, T, P>(
+    path: &'a P,
+    band: isize,
+    cfg: &'a ChunkConfig,
+) -> impl rayon::iter::IndexedParallelIterator<Item = Result<(ChunkWindow<'a>, Array2<T>)>> + 'a
+where
+    T: GdalType + Copy + Send + 'a,
+    P: AsRef<std::path::Path> + Sync + ?Sized,
+{
+    use crate::reader::DatasetReader;
+    use anyhow::format_err;
+    use rayon::prelude::*;
+
+    cfg.par_iter().map_init(
+        move || {
+            gdal::Dataset::open(path.as_ref())
+                .map_err(Into::into)
+                .and_then(|ds| DatasetReader::new(ds, band))
+        },
+        move |reader, win| -> Result<_> {
+            let reader = reader
+                .as_ref()
+                .map_err(|e| format_err!("opening dataset {}: {:#}", path.as_ref().display(), e))?;
+            Ok((win, reader.read_chunk(win)?))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::DatasetReader;
+    use crate::test_util::mem_dataset;
+
+    #[test]
+    fn test_read_iter() -> Result<()> {
+        let (width, height) = (20, 30);
+        let data =
+            ndarray::Array::from_shape_fn((1, height, width), |(_, y, x)| (y * width + x) as f64);
+        let ds = mem_dataset(width, height, 1, data.view())?;
+
+        let cfg = ChunkConfig::with_dims(width, height)
+            .with_tile_dims(8, 4)
+            .with_padding(1)
+            .with_x_padding(1);
+        let chunks = read_iter::<f64, _>(DatasetReader::new(ds, 1)?, &cfg);
+        assert_eq!(chunks.len(), cfg.iter().len());
+
+        let mut count = 0;
+        for chunk in chunks {
+            let (win, arr) = chunk?;
+            assert_eq!(arr.dim(), (win.height(), win.width()));
+
+            // Every value is its index in the raster
+            for ((i, j), &val) in arr.indexed_iter() {
+                assert_eq!(val, ((win.y() + i) * width + win.x() + j) as f64);
+            }
+            count += 1;
+        }
+        assert_eq!(count, cfg.iter().len());
+        Ok(())
+    }
+}
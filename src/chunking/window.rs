@@ -0,0 +1,135 @@
+use super::{ChunkConfig, ChunkWindow};
+use crate::geometry::RasterWindow;
+use ndarray::{s, Array2, ArrayView2};
+use std::ops::Range;
+
+/// Getter methods to read the extents of the window
+impl<'a> ChunkWindow<'a> {
+    pub fn config(&self) -> &'a ChunkConfig {
+        self.cfg
+    }
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn x(&self) -> usize {
+        self.x
+    }
+    pub fn y(&self) -> usize {
+        self.y
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The window of the raster loaded by this chunk
+    /// (including padding). This is the window read by
+    /// [`read_chunk`][crate::reader::ChunkReader::read_chunk].
+    pub fn raster_window(&self) -> RasterWindow {
+        (
+            (self.x as isize, self.y as isize),
+            (self.width, self.height),
+        )
+    }
+
+    /// The starting row of the chunk (including padding) as
+    /// a raster offset.
+    pub fn y_offset(&self) -> isize {
+        self.y as isize
+    }
+}
+
+/// Helpers to locate the data within a loaded chunk
+impl<'a> ChunkWindow<'a> {
+    /// The range of rows within the loaded chunk (i.e. the
+    /// array read using this window) that excludes the
+    /// padding. The data rows of the chunks produced by an
+    /// iteration are consecutive, and together cover the
+    /// `start..end` range of the configuration exactly
+    /// once.
+    ///
+    /// The range always starts at `padding`: as `start` is
+    /// at least `padding`, the first chunk has the complete
+    /// padding above it, although it may load additional
+    /// rows below to end at a block boundary. Similarly,
+    /// the last chunk is clamped so that the complete
+    /// padding below the data is within the raster. If the
+    /// chunk is too short to contain any data after
+    /// excluding padding (eg. a raster shorter than twice
+    /// the padding), the range is empty.
+    ///
+    /// The data rows of an array read via
+    /// [`read_chunk`][crate::reader::ChunkReader::read_chunk]
+    /// may be obtained as `arr.slice(s![win.data_range(), ..])`.
+    pub fn data_range(&self) -> Range<usize> {
+        let padding = self.cfg.padding();
+        let start = padding.min(self.height);
+        let end = self.height.saturating_sub(padding).max(start);
+        start..end
+    }
+
+    /// The range of columns within the loaded chunk that
+    /// excludes the padding columns (see
+    /// [`ChunkConfig::with_x_padding`]). As the padding is
+    /// clamped to the raster boundary, chunks at the left
+    /// (resp. right) edge of the raster have fewer padding
+    /// columns on the left (resp. right). In the full width
+    /// mode, this is all the columns of the chunk.
+    pub fn data_col_range(&self) -> Range<usize> {
+        let start = self.data_x - self.x;
+        start..start + self.data_width
+    }
+
+    /// The position `(x, y)` in the raster of the first
+    /// data pixel of the chunk (i.e. excluding padding).
+    /// This is the offset to write the output of
+    /// [`trim_padding`] at.
+    ///
+    /// [`trim_padding`]: ChunkWindow::trim_padding
+    pub fn data_offset(&self) -> (usize, usize) {
+        (self.data_x, self.y + self.data_range().start)
+    }
+
+    /// View of the data in an array read using this window,
+    /// excluding the padding rows (see [`data_range`]) and
+    /// columns (see [`data_col_range`]). The views of the
+    /// chunks of an iteration, placed at the respective
+    /// [`data_offset`], cover the processed region exactly
+    /// once. Panics if the array does not have the
+    /// dimensions of the chunk.
+    ///
+    /// [`data_range`]: ChunkWindow::data_range
+    /// [`data_col_range`]: ChunkWindow::data_col_range
+    /// [`data_offset`]: ChunkWindow::data_offset
+    pub fn trim_padding<'b, T>(&self, arr: &'b Array2<T>) -> ArrayView2<'b, T> {
+        assert_eq!(
+            arr.dim(),
+            (self.height, self.width),
+            "array dimensions do not match the chunk"
+        );
+        arr.slice(s![self.data_range(), self.data_col_range()])
+    }
+
+    /// Whether the data of this chunk (excluding padding)
+    /// lies entirely outside the valid window of the config
+    /// (see [`ChunkConfig::with_valid_window`]). Such chunks
+    /// need not be read. Always `false` if no valid window
+    /// is configured.
+    pub fn is_skipped(&self) -> bool {
+        let ((x, y), (width, height)) = match self.cfg.valid_window() {
+            Some(valid) => valid,
+            None => return false,
+        };
+        let (x, y) = (x.max(0) as usize, y.max(0) as usize);
+
+        let rows = self.data_range();
+        let (y_0, y_1) = (self.y + rows.start, self.y + rows.end);
+        let (x_0, x_1) = (self.data_x, self.data_x + self.data_width);
+
+        !(x_0 < x + width && x < x_1 && y_0 < y + height && y < y_1)
+    }
+}
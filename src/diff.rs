@@ -0,0 +1,220 @@
+//! Compute the difference of a pair of rasters.
+//!
+//! A [`DiffProcessor`] reads the aligned chunks of the two
+//! rasters (see [`AlignedPairReader`]), and visits the
+//! pairs of valid pixels of each chunk, restricted to an
+//! extent and a mask (if any). The accumulation of the
+//! values (eg. stats, histogram) is left to the caller.
+
+use geo::MultiPolygon;
+use nalgebra::Vector2;
+use ndarray::Array2;
+
+use crate::align::*;
+use crate::geometry::*;
+use crate::reader::{ChunkReader, NodataOverride};
+use crate::Result;
+
+/// A chunk of a mask raster: its offset, and the values.
+pub type MaskChunk = (RasterOffset, Array2<u8>);
+
+/// Processes the aligned chunks of a pair of rasters (`A`
+/// and `B`).
+#[derive(Debug, Clone)]
+pub struct DiffProcessor {
+    transform: PixelTransform,
+    no_vals: (Option<f64>, Option<f64>),
+    extent: Option<MultiPolygon<f64>>,
+    mode: SampleMode,
+    mask: Option<(PixelTransform, RasterDims)>,
+}
+
+impl DiffProcessor {
+    /// Construct a processor. The `transform` maps the
+    /// pixels of `A` to those of `B` (see
+    /// [`transform_between`]). The `no_vals` override the
+    /// no-data values of `A` and `B` respectively, if any.
+    /// The `extent` (in the pixel coordinates of `A`), if
+    /// any, restricts the pixels processed.
+    pub fn new(
+        transform: PixelTransform,
+        no_vals: (Option<f64>, Option<f64>),
+        extent: Option<MultiPolygon<f64>>,
+    ) -> Self {
+        DiffProcessor {
+            transform,
+            no_vals,
+            extent,
+            mode: SampleMode::Nearest,
+            mask: None,
+        }
+    }
+
+    /// Sample `B` using `mode` (defaults to
+    /// [`SampleMode::Nearest`]).
+    pub fn with_mode(mut self, mode: SampleMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Restrict the processing to the pixels where a mask
+    /// raster (of dimension `dim_m`) is non-zero. The
+    /// `transform` maps pixels of `A` to the mask.
+    pub fn with_mask(mut self, transform: PixelTransform, dim_m: RasterDims) -> Self {
+        self.mask = Some((transform, dim_m));
+        self
+    }
+
+    /// The transform from the pixels of `A` to those of
+    /// `B`.
+    pub fn transform(&self) -> &PixelTransform {
+        &self.transform
+    }
+
+    /// The sampling mode of `B`.
+    pub fn mode(&self) -> SampleMode {
+        self.mode
+    }
+
+    /// Read the window `win_a` of `A`, and the window of `B`
+    /// covering it (see [`AlignedPairReader::read_window`]),
+    /// with the no-data values overridden (if given).
+    pub fn read_window<RA, RB>(
+        &self,
+        reader_a: RA,
+        reader_b: RB,
+        win_a: RasterWindow,
+    ) -> Result<AlignedChunk>
+    where
+        RA: ChunkReader,
+        RB: ChunkReader,
+    {
+        AlignedPairReader::new(
+            NodataOverride::new(reader_a, self.no_vals.0),
+            NodataOverride::new(reader_b, self.no_vals.1),
+            self.transform,
+        )?
+        .with_mode(self.mode)
+        .read_window(win_a)
+    }
+
+    /// Read the chunk of the mask (if any; see
+    /// [`with_mask`]) covering `win_a` from `A`.
+    ///
+    /// [`with_mask`]: DiffProcessor::with_mask
+    pub fn read_mask_window<R: ChunkReader>(
+        &self,
+        reader: &R,
+        win_a: RasterWindow,
+    ) -> Result<Option<MaskChunk>> {
+        let (transform, dim_m) = match &self.mask {
+            Some(mask) => mask,
+            None => return Ok(None),
+        };
+        let (off, size) = transform_window(win_a, *transform, *dim_m);
+        Ok(Some((off, reader.read_as_array::<u8>(off, size)?)))
+    }
+
+    /// Call `f` with the index (into the chunk of `A`) and
+    /// the values of each pair of valid pixels of `chunk`,
+    /// within the extent and the mask (if any). Returns an
+    /// error if the extent can't be rasterized (see
+    /// [`rasterize_polygon`]).
+    pub fn process<F: FnMut(RasterDims, f64, f64)>(
+        &self,
+        f: &mut F,
+        chunk: &AlignedChunk,
+        mask: Option<&MaskChunk>,
+    ) -> Result<()> {
+        // Pixels of the chunk inside the extent (which is in
+        // the pixel coords of A)
+        let (rows, cols) = chunk.data_a.dim();
+        let extent = self
+            .extent
+            .as_ref()
+            .map(|poly| rasterize_polygon(poly, (chunk.off_a, (cols, rows))))
+            .transpose()?;
+
+        // Index of the mask pixel containing each pixel
+        let off_a = Vector2::new(chunk.off_a.0 as f64 + 0.5, chunk.off_a.1 as f64 + 0.5);
+        let mask = self
+            .mask
+            .as_ref()
+            .zip(mask)
+            .map(|((transform, _), (off_m, arr_m))| {
+                let off_m = Vector2::new(off_m.0 as f64, off_m.1 as f64);
+                let (r, c) = arr_m.dim();
+                (
+                    index_transformer(chunk_transform(transform, off_a, off_m), (c, r)),
+                    arr_m,
+                )
+            });
+
+        for (idx, val_a, val_b) in chunk.pairs() {
+            // Ignore if point is outside extents
+            if let Some(extent) = &extent {
+                if !extent[idx] {
+                    continue;
+                }
+            }
+
+            // Ignore if masked, or outside the mask
+            if let Some((index, arr_m)) = &mask {
+                if index(idx).map_or(true, |idx| arr_m[idx] == 0) {
+                    continue;
+                }
+            }
+
+            f(idx, val_a, val_b);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::MemoryReader;
+
+    #[test]
+    fn test_diff_processor() -> Result<()> {
+        // B is A shifted by a pixel to the right, with a
+        // no-data value of -1
+        let a = Array2::from_shape_fn((4, 6), |(i, j)| (10 * i + j) as f64);
+        let b = Array2::from_shape_fn((4, 6), |(i, j)| {
+            if (i, j) == (2, 2) {
+                -1.
+            } else {
+                (10 * i + j) as f64 + 100.
+            }
+        });
+        let transform = transform_from_gdal(&[1., 1., 0., 0., 0., 1.]);
+        let proc = DiffProcessor::new(transform, (None, Some(-1.)), None);
+
+        let win_a = ((0, 0), (6, 4));
+        let chunk =
+            proc.read_window(MemoryReader(a.clone(), None), MemoryReader(b, None), win_a)?;
+        let mut count = 0;
+        proc.process(
+            &mut |(i, j), val_a, val_b| {
+                assert_eq!(val_a, a[(i, j)]);
+                assert_eq!(val_b, val_a + 101.);
+                count += 1;
+            },
+            &chunk,
+            None,
+        )?;
+        // The last column is outside B, and a pixel is
+        // no-data
+        assert_eq!(count, 4 * 5 - 1);
+
+        // Restricted to a mask of half the resolution
+        let mask = Array2::from_shape_fn((2, 3), |(i, j)| (i == 0 && j == 0) as u8);
+        let proc = proc.with_mask(transform_from_gdal(&[0., 0.5, 0., 0., 0., 0.5]), (3, 2));
+        let mask = proc.read_mask_window(&MemoryReader(mask, None), win_a)?;
+        let mut count = 0;
+        proc.process(&mut |_, _, _| count += 1, &chunk, mask.as_ref())?;
+        assert_eq!(count, 4);
+        Ok(())
+    }
+}
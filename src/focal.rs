@@ -0,0 +1,384 @@
+//! Focal (neighborhood) filters.
+//!
+//! A focal filter computes each output pixel from the
+//! square window of input pixels centered on it (eg. the
+//! mean of the `3x3` neighborhood). This satisfies the
+//! locality constraint of the [`chunking`] module, so the
+//! raster is processed in chunks padded by the radius of
+//! the window (see [`focal_apply`]).
+//!
+//! The pixels outside the raster, and the invalid pixels
+//! (see
+//! [`read_as_array_masked`][ChunkReader::read_as_array_masked])
+//! are NaN in the windows. The reducers [`mean`], [`min`]
+//! and [`max`] ignore them, while a [`convolution`] is NaN
+//! if the window has any of them.
+//!
+//! The terrain [`slope`], [`aspect`] and [`hillshade`] of a
+//! DEM are computed from the `3x3` window (see
+//! [`horn_gradient`]), and are also NaN if the window has
+//! any NaN.
+//!
+//! [`chunking`]: crate::chunking
+
+use ndarray::{s, Array2, ArrayView2};
+
+use crate::chunking::ChunkConfig;
+use crate::reader::{ChunkReader, NoData};
+use crate::Result;
+
+/// Apply a focal filter to the raster read by `reader`.
+/// The function `f` is called with the `(2 * radius + 1)`
+/// square window centered on each pixel, and returns the
+/// output value of the pixel. The output is NaN where the
+/// pixel itself is invalid (or NaN).
+///
+/// The chunks of `cfg` (padded by `radius` rows; see
+/// [`ChunkConfig::with_padding`]) are processed in order,
+/// and `write` is called with the output of each, along
+/// with its offset `(x, y)` in the raster. As the first
+/// (resp. last) chunk of a padded configuration does not
+/// include the rows within `radius` of the top (resp.
+/// bottom) of the raster, the output of such a chunk is
+/// extended to include them.
+pub fn focal_apply<R, F, W>(
+    reader: &R,
+    cfg: &ChunkConfig,
+    radius: usize,
+    f: F,
+    mut write: W,
+) -> Result<()>
+where
+    R: ChunkReader,
+    F: Fn(ArrayView2<'_, f64>) -> f64,
+    W: FnMut((usize, usize), Array2<f64>) -> Result<()>,
+{
+    let cfg = cfg.clone().with_padding(radius);
+    let size = 2 * radius + 1;
+
+    for win in cfg.iter() {
+        // Data rows and columns of the chunk, extended to
+        // the edges of the raster if the padding reaches it
+        let rows = win.data_range();
+        let y_0 = if win.y() == 0 {
+            0
+        } else {
+            win.y() + rows.start
+        };
+        let y_1 = if win.y() + win.height() == cfg.height() {
+            cfg.height()
+        } else {
+            win.y() + rows.end
+        };
+        let cols = win.data_col_range();
+        let (x_0, x_1) = (win.x() + cols.start, win.x() + cols.end);
+        if y_0 >= y_1 || x_0 >= x_1 {
+            continue;
+        }
+        let (width, height) = (x_1 - x_0, y_1 - y_0);
+
+        // Read the padded window; the pixels outside the
+        // raster are NaN
+        let (mut data, valid) = reader.read_as_array_masked_clamped::<f64>(
+            (
+                x_0 as isize - radius as isize,
+                y_0 as isize - radius as isize,
+            ),
+            (width + 2 * radius, height + 2 * radius),
+            f64::NAN,
+        )?;
+        data.zip_mut_with(&valid, |val, &valid| {
+            if !valid {
+                *val = f64::NAN;
+            }
+        });
+
+        let out = Array2::from_shape_fn((height, width), |(i, j)| {
+            if NoData::NAN.is_nodata(data[(i + radius, j + radius)]) {
+                return f64::NAN;
+            }
+            f(data.slice(s![i..i + size, j..j + size]))
+        });
+        write((x_0, y_0), out)?;
+    }
+    Ok(())
+}
+
+/// Mean of the values of `win` that are not NaN; NaN if
+/// there are none.
+pub fn mean(win: ArrayView2<'_, f64>) -> f64 {
+    let (sum, count) = win
+        .iter()
+        .filter(|val| !val.is_nan())
+        .fold((0., 0), |(sum, count), val| (sum + val, count + 1));
+    if count == 0 {
+        f64::NAN
+    } else {
+        sum / count as f64
+    }
+}
+
+/// Minimum of the values of `win` that are not NaN; NaN if
+/// there are none.
+pub fn min(win: ArrayView2<'_, f64>) -> f64 {
+    win.iter().cloned().fold(f64::NAN, f64::min)
+}
+
+/// Maximum of the values of `win` that are not NaN; NaN if
+/// there are none.
+pub fn max(win: ArrayView2<'_, f64>) -> f64 {
+    win.iter().cloned().fold(f64::NAN, f64::max)
+}
+
+/// The weighted sum of a window by `kernel`, which must be
+/// of the dimensions of the window. As is usual for image
+/// filters, the kernel is not flipped (eg. the top-left
+/// weight applies to the top-left pixel). The output is
+/// NaN if any pixel of the window is NaN.
+pub fn convolution(kernel: Array2<f64>) -> impl Fn(ArrayView2<'_, f64>) -> f64 {
+    move |win| {
+        assert_eq!(
+            win.dim(),
+            kernel.dim(),
+            "kernel dimensions do not match the window"
+        );
+        win.iter().zip(kernel.iter()).map(|(val, k)| val * k).sum()
+    }
+}
+
+/// The gradient `(dz/dx, dz/dy)` at the center of a `3x3`
+/// window by Horn's method, along the x and y axes of the
+/// CRS. The `cell_size` is the signed size `(width,
+/// height)` of a pixel, as in the geo. transform (the
+/// height is negative for north-up rasters); the rotation
+/// of the pixels is ignored. NaN if any value of the
+/// window is NaN.
+pub fn horn_gradient(win: ArrayView2<'_, f64>, cell_size: (f64, f64)) -> (f64, f64) {
+    assert_eq!(win.dim(), (3, 3), "Horn's method requires a 3x3 window");
+    let z = |i: usize, j: usize| win[(i, j)];
+    let dx = (z(0, 2) + 2. * z(1, 2) + z(2, 2)) - (z(0, 0) + 2. * z(1, 0) + z(2, 0));
+    let dy = (z(2, 0) + 2. * z(2, 1) + z(2, 2)) - (z(0, 0) + 2. * z(0, 1) + z(0, 2));
+    (dx / (8. * cell_size.0), dy / (8. * cell_size.1))
+}
+
+/// Units of the [`slope`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlopeUnits {
+    /// Angle from the horizontal, in degrees.
+    Degrees,
+    /// Rise over run, as a percentage.
+    Percent,
+}
+
+/// The slope at the center of a `3x3` window (see
+/// [`horn_gradient`]), to be applied with a radius of `1`.
+/// The values should be in the units of the CRS (eg.
+/// meters).
+pub fn slope(cell_size: (f64, f64), units: SlopeUnits) -> impl Fn(ArrayView2<'_, f64>) -> f64 {
+    move |win| {
+        let (dx, dy) = horn_gradient(win, cell_size);
+        let rise = dx.hypot(dy);
+        match units {
+            SlopeUnits::Degrees => rise.atan().to_degrees(),
+            SlopeUnits::Percent => 100. * rise,
+        }
+    }
+}
+
+/// The aspect at the center of a `3x3` window (see
+/// [`horn_gradient`]), to be applied with a radius of `1`:
+/// the downhill direction, in degrees clockwise from the
+/// y axis of the CRS (north), in `[0, 360)`. NaN if the
+/// window is flat.
+pub fn aspect(cell_size: (f64, f64)) -> impl Fn(ArrayView2<'_, f64>) -> f64 {
+    move |win| {
+        let (dx, dy) = horn_gradient(win, cell_size);
+        if dx == 0. && dy == 0. {
+            return f64::NAN;
+        }
+        let azimuth = (-dx).atan2(-dy).to_degrees();
+        if azimuth < 0. {
+            azimuth + 360.
+        } else {
+            azimuth
+        }
+    }
+}
+
+/// The shaded relief at the center of a `3x3` window (see
+/// [`horn_gradient`]), to be applied with a radius of `1`:
+/// the cosine of the angle between the normal of the
+/// surface and the direction of the sun, in `[0, 1]` (`0`
+/// if the surface faces away from the sun). The sun is at
+/// `azimuth` (degrees clockwise from north), and `altitude`
+/// (degrees above the horizon). The values are scaled by
+/// `z_factor` (eg. to convert them to the units of the
+/// CRS).
+pub fn hillshade(
+    cell_size: (f64, f64),
+    azimuth: f64,
+    altitude: f64,
+    z_factor: f64,
+) -> impl Fn(ArrayView2<'_, f64>) -> f64 {
+    let (azimuth, altitude) = (azimuth.to_radians(), altitude.to_radians());
+    let sun = (
+        azimuth.sin() * altitude.cos(),
+        azimuth.cos() * altitude.cos(),
+        altitude.sin(),
+    );
+    move |win| {
+        let (dx, dy) = horn_gradient(win, cell_size);
+        let (dx, dy) = (z_factor * dx, z_factor * dy);
+        // The normal of the surface is (-dx, -dy, 1)
+        let cos = (sun.2 - dx * sun.0 - dy * sun.1) / (1. + dx * dx + dy * dy).sqrt();
+        // NaN is kept (unlike `f64::max`)
+        if cos < 0. {
+            0.
+        } else {
+            cos
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::MemoryReader;
+
+    /// Apply `f` to `arr` in chunks of (at most) `rows` rows,
+    /// and collect the output.
+    fn apply<F>(arr: &Array2<f64>, no_val: Option<f64>, rows: usize, f: F) -> Result<Array2<f64>>
+    where
+        F: Fn(ArrayView2<'_, f64>) -> f64,
+    {
+        let (height, width) = arr.dim();
+        let reader = MemoryReader(arr.clone(), no_val);
+        let cfg = ChunkConfig::with_dims(width, height).with_max_data_height(rows);
+
+        let mut out = Array2::from_elem((height, width), -1.);
+        focal_apply(&reader, &cfg, 1, f, |(x, y), data| {
+            let (h, w) = data.dim();
+            let mut view = out.slice_mut(s![y..y + h, x..x + w]);
+            // Each pixel is written exactly once
+            assert!(view.iter().all(|&val| val == -1.));
+            view.assign(&data);
+            Ok(())
+        })?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_focal_mean() -> Result<()> {
+        let arr = Array2::from_shape_fn((5, 4), |(i, j)| (i * 4 + j) as f64);
+        for &rows in &[1, 2, 10] {
+            let out = apply(&arr, Some(9.), rows, mean)?;
+
+            // The no-data pixel is skipped, and excluded from
+            // the mean of its neighbors
+            assert!(out[(2, 1)].is_nan());
+            assert_eq!(out[(1, 1)], (0. + 1. + 2. + 4. + 5. + 6. + 8. + 10.) / 8.);
+            assert_eq!(
+                out[(3, 2)],
+                (10. + 11. + 13. + 14. + 15. + 17. + 18. + 19.) / 8.
+            );
+
+            // The pixels outside the raster are ignored at the
+            // edges and corners
+            assert_eq!(out[(0, 0)], (0. + 1. + 4. + 5.) / 4.);
+            assert_eq!(out[(4, 3)], (14. + 15. + 18. + 19.) / 4.);
+            assert_eq!(out[(0, 2)], (1. + 2. + 3. + 5. + 6. + 7.) / 6.);
+
+            assert_eq!(apply(&arr, None, rows, max)?[(4, 0)], 17.);
+            assert_eq!(apply(&arr, None, rows, min)?[(2, 2)], 5.);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_focal_convolution() -> Result<()> {
+        let arr = Array2::from_shape_fn((5, 4), |(i, j)| (i * 4 + j) as f64);
+
+        // Horizontal gradient: the difference of the
+        // right and left neighbors
+        let kernel = Array2::from_shape_fn((3, 3), |(_, j)| j as f64 - 1.);
+        let out = apply(&arr, None, 2, convolution(kernel))?;
+        for ((i, j), &val) in out.indexed_iter() {
+            if i == 0 || j == 0 || i == 4 || j == 3 {
+                assert!(val.is_nan(), "{:?}", (i, j));
+            } else {
+                assert_eq!(val, 6., "{:?}", (i, j));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_slope_aspect() -> Result<()> {
+        // The plane z = 3x + 4y, with 10m pixels of a
+        // north-up raster
+        let cell_size = (10., -10.);
+        let plane = |i: usize, j: usize| 30. * j as f64 - 40. * i as f64;
+        let win = Array2::from_shape_fn((3, 3), |(i, j)| plane(i, j));
+        let (dx, dy) = horn_gradient(win.view(), cell_size);
+        assert!((dx - 3.).abs() < 1e-12 && (dy - 4.).abs() < 1e-12);
+
+        assert!((slope(cell_size, SlopeUnits::Percent)(win.view()) - 500.).abs() < 1e-9);
+        let degrees = slope(cell_size, SlopeUnits::Degrees)(win.view());
+        assert!((degrees - 5f64.atan().to_degrees()).abs() < 1e-9);
+        // Downhill is towards (-3, -4): south-southwest
+        let azimuth = aspect(cell_size)(win.view());
+        assert!((azimuth - (180. + 0.75f64.atan().to_degrees())).abs() < 1e-9);
+
+        // Rising eastwards faces west; flat is undefined
+        let east = Array2::from_shape_fn((3, 3), |(_, j)| j as f64);
+        assert!((aspect(cell_size)(east.view()) - 270.).abs() < 1e-9);
+        assert!(aspect(cell_size)(Array2::zeros((3, 3)).view()).is_nan());
+
+        // Applied to a raster: the edges, and the neighbors
+        // of the no-data pixel are no-data
+        let mut arr = Array2::from_shape_fn((5, 6), |(i, j)| plane(i, j));
+        arr[(3, 4)] = -9999.;
+        let out = apply(&arr, Some(-9999.), 2, slope(cell_size, SlopeUnits::Percent))?;
+        for ((i, j), &val) in out.indexed_iter() {
+            let edge = i == 0 || j == 0 || i == 4 || j == 5;
+            if edge || ((i as isize - 3).abs() <= 1 && (j as isize - 4).abs() <= 1) {
+                assert!(val.is_nan(), "{:?}", (i, j));
+            } else {
+                assert!((val - 500.).abs() < 1e-9, "{:?}", (i, j));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_hillshade() -> Result<()> {
+        let cell_size = (10., -10.);
+
+        // A flat surface is lit by the sine of the altitude
+        let flat = Array2::from_elem((3, 3), 5.);
+        let shade = hillshade(cell_size, 315., 30., 1.)(flat.view());
+        assert!((shade - 0.5).abs() < 1e-12);
+
+        // The plane z = 3x + 4y is fully lit by the sun along
+        // its normal (-3, -4, 1), and unlit from the opposite
+        // direction
+        let plane = Array2::from_shape_fn((3, 3), |(i, j)| 30. * j as f64 - 40. * i as f64);
+        let azimuth = 180. + 0.75f64.atan().to_degrees();
+        let altitude = 90. - 5f64.atan().to_degrees();
+        let shade = hillshade(cell_size, azimuth, altitude, 1.)(plane.view());
+        assert!((shade - 1.).abs() < 1e-12);
+        assert_eq!(
+            hillshade(cell_size, azimuth - 180., 10., 1.)(plane.view()),
+            0.
+        );
+
+        // Scaling the values flattens (or steepens) the plane
+        let shade = hillshade(cell_size, azimuth, 90., 0.)(plane.view());
+        assert!((shade - 1.).abs() < 1e-12);
+
+        let mut hole = flat;
+        hole[(0, 1)] = f64::NAN;
+        assert!(hillshade(cell_size, 315., 45., 1.)(hole.view()).is_nan());
+        Ok(())
+    }
+}
@@ -1,7 +1,15 @@
 //! Geometry manipulation utilities
+//!
+//! The raster windows ([`RasterWindow`]) serialize (with
+//! serde) as nested arrays. [`GeoTransform`] serializes as a
+//! [`SerializableTransform`]. With the `serde-1` feature,
+//! [`PixelTransform`] and [`Bounds`] also implement the
+//! serde traits (as serialized by nalgebra and geo).
 
-use geo::Rect;
-use nalgebra::Matrix3;
+use geo::{MultiPolygon, Rect};
+use nalgebra::{Matrix3, Point2};
+use ndarray::Array2;
+use serde_derive::{Deserialize, Serialize};
 
 /// Matrix representation of the affine geo. transform from
 /// pixel coordinates to "world" coordinates of a GDAL
@@ -25,6 +33,304 @@ pub fn transform_from_gdal(t: &[f64]) -> PixelTransform {
     Matrix3::new(t[1], t[2], t[0], t[4], t[5], t[3], 0., 0., 1.)
 }
 
+/// Converts a `PixelTransform` into the raw GDAL transform
+/// `[f64; 6]` (eg. for `Dataset::set_geo_transform`); the
+/// inverse of [`transform_from_gdal`]. Returns an error if
+/// the transform is not affine (the bottom row is not `[0,
+/// 0, 1]`).
+pub fn transform_to_gdal(t: &PixelTransform) -> crate::Result<[f64; 6]> {
+    if t[(2, 0)] != 0. || t[(2, 1)] != 0. || t[(2, 2)] != 1. {
+        anyhow::bail!("geo. transform is not affine");
+    }
+    Ok(GeoTransform::new(*t).to_gdal())
+}
+
+/// The transform with its origin moved to the pixel
+/// coordinates `(dx, dy)` of `t`, eg. the transform of a
+/// raster cropped to a window at offset `(dx, dy)`.
+pub fn translate_transform(t: &PixelTransform, dx: f64, dy: f64) -> PixelTransform {
+    t * Matrix3::new_translation(&nalgebra::Vector2::new(dx, dy))
+}
+
+/// Whether the transform is axis-aligned, ie. it has no
+/// rotation or shear: the off-diagonal entries of its
+/// linear part are at most `tolerance` times the diagonal
+/// entries (in absolute value).
+pub fn is_axis_aligned(t: &PixelTransform, tolerance: f64) -> bool {
+    t[(0, 1)].abs() <= tolerance * t[(0, 0)].abs() && t[(1, 0)].abs() <= tolerance * t[(1, 1)].abs()
+}
+
+/// A `PixelTransform` as its 9 entries in row-major order,
+/// for serialization. Eg. the GDAL transform `[x0, dx, rx,
+/// y0, ry, dy]` is `[dx, rx, x0, ry, dy, y0, 0, 0, 1]`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SerializableTransform(pub [f64; 9]);
+
+impl From<PixelTransform> for SerializableTransform {
+    fn from(t: PixelTransform) -> Self {
+        let mut entries = [0.; 9];
+        for (i, entry) in entries.iter_mut().enumerate() {
+            *entry = t[(i / 3, i % 3)];
+        }
+        SerializableTransform(entries)
+    }
+}
+
+impl From<SerializableTransform> for PixelTransform {
+    fn from(t: SerializableTransform) -> Self {
+        Matrix3::from_row_slice(&t.0)
+    }
+}
+
+/// An affine transform (eg. the geo. transform from pixel
+/// coordinates to "world" coordinates of a GDAL dataset),
+/// along with its inverse, computed once. Serializes as a
+/// [`SerializableTransform`].
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "SerializableTransform", into = "SerializableTransform")]
+pub struct GeoTransform {
+    matrix: PixelTransform,
+    inverse: Option<PixelTransform>,
+}
+
+impl GeoTransform {
+    pub fn new(matrix: PixelTransform) -> Self {
+        GeoTransform {
+            matrix,
+            inverse: matrix.try_inverse(),
+        }
+    }
+
+    pub fn identity() -> Self {
+        GeoTransform::new(Matrix3::identity())
+    }
+
+    /// Convert the raw GDAL transform `[f64; 6]` (see
+    /// [`transform_from_gdal`]).
+    pub fn from_gdal(t: &[f64; 6]) -> Self {
+        GeoTransform::new(transform_from_gdal(t))
+    }
+
+    #[cfg(feature = "gdal")]
+    /// The geo. transform of a `Dataset` (see
+    /// [`transform_from_dataset`]).
+    pub fn from_dataset(ds: &gdal::Dataset) -> Self {
+        GeoTransform::new(transform_from_dataset(ds))
+    }
+
+    /// Convert to the raw GDAL transform `[f64; 6]`.
+    pub fn to_gdal(&self) -> [f64; 6] {
+        let m = &self.matrix;
+        [
+            m[(0, 2)],
+            m[(0, 0)],
+            m[(0, 1)],
+            m[(1, 2)],
+            m[(1, 0)],
+            m[(1, 1)],
+        ]
+    }
+
+    pub fn matrix(&self) -> &PixelTransform {
+        &self.matrix
+    }
+
+    /// The inverse transform. Returns an error if the
+    /// transform is not invertible.
+    pub fn inverse(&self) -> crate::Result<GeoTransform> {
+        let inverse = self
+            .inverse
+            .ok_or_else(|| anyhow::format_err!("geo. transform is not invertible"))?;
+        Ok(GeoTransform {
+            matrix: inverse,
+            inverse: Some(self.matrix),
+        })
+    }
+
+    /// Apply the transform to the point `(x, y)`.
+    pub fn apply(&self, x: f64, y: f64) -> (f64, f64) {
+        let pt = self.matrix.transform_point(&Point2::new(x, y));
+        (pt.x, pt.y)
+    }
+
+    /// The world coordinates of the pixel coordinates
+    /// `(x, y)`.
+    pub fn pixel_to_world(&self, x: f64, y: f64) -> (f64, f64) {
+        self.apply(x, y)
+    }
+
+    /// The world coordinates of the top-left corner of the
+    /// pixel at `off`.
+    pub fn offset_to_world(&self, off: RasterOffset) -> (f64, f64) {
+        self.apply(off.0 as f64, off.1 as f64)
+    }
+
+    /// The pixel coordinates of the world coordinates
+    /// `(x, y)`. Returns an error if the transform is not
+    /// invertible.
+    pub fn world_to_pixel(&self, x: f64, y: f64) -> crate::Result<(f64, f64)> {
+        Ok(self.inverse()?.apply(x, y))
+    }
+
+    /// The transform applying `self`, and then `other`; eg.
+    /// the pixel coordinates of a dataset to those of
+    /// another is the transform of the first, composed with
+    /// the inverse of the second (see
+    /// [`transform_between`]).
+    ///
+    /// [`transform_between`]: crate::align::transform_between
+    pub fn compose(&self, other: &GeoTransform) -> GeoTransform {
+        GeoTransform::new(other.matrix * self.matrix)
+    }
+
+    /// Whether the transform is axis-aligned (see
+    /// [`is_axis_aligned`]).
+    pub fn is_axis_aligned(&self, tolerance: f64) -> bool {
+        is_axis_aligned(&self.matrix, tolerance)
+    }
+
+    /// The (unsigned) area of the image of a unit square,
+    /// eg. the area of a pixel in world units.
+    pub fn determinant_area(&self) -> f64 {
+        self.matrix.fixed_slice::<2, 2>(0, 0).determinant().abs()
+    }
+}
+
+impl From<PixelTransform> for GeoTransform {
+    fn from(matrix: PixelTransform) -> Self {
+        GeoTransform::new(matrix)
+    }
+}
+
+impl From<GeoTransform> for PixelTransform {
+    fn from(transform: GeoTransform) -> Self {
+        transform.matrix
+    }
+}
+
+impl From<SerializableTransform> for GeoTransform {
+    fn from(t: SerializableTransform) -> Self {
+        GeoTransform::new(t.into())
+    }
+}
+
+impl From<GeoTransform> for SerializableTransform {
+    fn from(transform: GeoTransform) -> Self {
+        transform.matrix.into()
+    }
+}
+
+#[cfg(feature = "gdal")]
+/// Area (in square meters) of a pixel in the `row` of a
+/// raster with the geo. transform `transform`, and the
+/// projection `projection_wkt`.
+///
+/// For a geographic CRS, the area varies with the latitude:
+/// the area in square degrees is scaled by the cosine of the
+/// latitude of the center of the row (on a sphere with the
+/// semi-major axis as the radius). For a projected CRS, it
+/// is the area of the pixel (the determinant of the
+/// transform) scaled to meters. Without a projection (an
+/// empty `projection_wkt`), it is the area in the units of
+/// the transform.
+///
+/// Use a [`RowAreaTable`] to compute the areas of all the
+/// rows of a raster.
+pub fn pixel_area_for_row(
+    transform: &PixelTransform,
+    projection_wkt: &str,
+    row: usize,
+) -> crate::Result<f64> {
+    Ok(AreaModel::new(projection_wkt)?.area(transform, row))
+}
+
+#[cfg(feature = "gdal")]
+/// The area of a pixel of each row of a raster (see
+/// [`pixel_area_for_row`]).
+#[derive(Clone, Debug)]
+pub struct RowAreaTable {
+    areas: Vec<f64>,
+}
+
+#[cfg(feature = "gdal")]
+impl RowAreaTable {
+    /// Compute the area of the pixels of each of the
+    /// `height` rows.
+    pub fn new(
+        transform: &PixelTransform,
+        projection_wkt: &str,
+        height: usize,
+    ) -> crate::Result<Self> {
+        let model = AreaModel::new(projection_wkt)?;
+        Ok(RowAreaTable {
+            areas: (0..height).map(|row| model.area(transform, row)).collect(),
+        })
+    }
+
+    /// Compute the area of the pixels of each row of a
+    /// `Dataset`.
+    pub fn from_dataset(ds: &gdal::Dataset) -> crate::Result<Self> {
+        RowAreaTable::new(
+            &transform_from_dataset(ds),
+            &ds.projection(),
+            ds.raster_size().1,
+        )
+    }
+
+    /// The area of a pixel in `row`. Panics if the row is
+    /// out of range.
+    pub fn area(&self, row: usize) -> f64 {
+        self.areas[row]
+    }
+}
+
+#[cfg(feature = "gdal")]
+/// Conversion of pixel areas to square meters.
+enum AreaModel {
+    Geographic { radius: f64, radians_per_unit: f64 },
+    Projected { meters_per_unit: f64 },
+    Unknown,
+}
+
+#[cfg(feature = "gdal")]
+impl AreaModel {
+    fn new(projection_wkt: &str) -> crate::Result<Self> {
+        use gdal::spatial_ref::SpatialRef;
+        if projection_wkt.is_empty() {
+            return Ok(AreaModel::Unknown);
+        }
+        let srs = SpatialRef::from_wkt(projection_wkt)?;
+        Ok(if srs.is_geographic() {
+            AreaModel::Geographic {
+                radius: srs.semi_major()?,
+                radians_per_unit: srs.angular_units(),
+            }
+        } else {
+            AreaModel::Projected {
+                meters_per_unit: srs.linear_units(),
+            }
+        })
+    }
+
+    fn area(&self, transform: &PixelTransform, row: usize) -> f64 {
+        let area = GeoTransform::new(*transform).determinant_area();
+        match *self {
+            AreaModel::Geographic {
+                radius,
+                radians_per_unit,
+            } => {
+                let lat = transform
+                    .transform_point(&Point2::new(0.5, row as f64 + 0.5))
+                    .y;
+                let meters_per_unit = radius * radians_per_unit;
+                area * meters_per_unit * meters_per_unit * (lat * radians_per_unit).cos()
+            }
+            AreaModel::Projected { meters_per_unit } => area * meters_per_unit * meters_per_unit,
+            AreaModel::Unknown => area,
+        }
+    }
+}
+
 /// Represents pixel offset into a raster.
 pub type RasterOffset = (isize, isize);
 
@@ -41,6 +347,13 @@ pub type Bounds = Rect<f64>;
 
 /// Utilities to calculate using [`Bounds`].
 pub trait BoundsExt {
+    /// Compute the smallest bounds containing the points.
+    /// Returns `None` if there are no points.
+    fn from_points<I>(pts: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = (f64, f64)>,
+        Self: Sized;
+
     /// Compute the area represented by the bounds.
     fn area(&self) -> f64;
 
@@ -51,6 +364,41 @@ pub trait BoundsExt {
     where
         Self: Sized;
 
+    /// Compute the smallest bounds containing both `self`
+    /// and another bounds.
+    fn union(&self, other: &Self) -> Self
+    where
+        Self: Sized;
+
+    /// Whether the point `(x, y)` is in the region. Points on
+    /// the left and top edges are contained, but not those
+    /// on the right and bottom edges.
+    fn contains_point(&self, x: f64, y: f64) -> bool;
+
+    /// Whether another bounds lies entirely within the
+    /// region (edges included).
+    fn contains_bounds(&self, other: &Self) -> bool;
+
+    /// Expand the region by `margin_x` on the left and right,
+    /// and by `margin_y` on the top and bottom. A negative
+    /// margin shrinks the region; it is clamped to an empty
+    /// range (at the center) if the dimension is shorter than
+    /// twice the margin.
+    fn expand_by(&self, margin_x: f64, margin_y: f64) -> Self
+    where
+        Self: Sized;
+
+    /// Expand the region by `delta` on all sides (see
+    /// [`expand_by`]).
+    ///
+    /// [`expand_by`]: BoundsExt::expand_by
+    fn buffer(&self, delta: f64) -> Self
+    where
+        Self: Sized,
+    {
+        self.expand_by(delta, delta)
+    }
+
     /// Compute the largest valid `RasterWindow` within the
     /// region (including partial pixels). Returns a window
     /// with size `(0, 0)` if the region is completely
@@ -64,6 +412,16 @@ pub trait BoundsExt {
 }
 
 impl BoundsExt for Bounds {
+    fn from_points<I>(pts: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = (f64, f64)>,
+        Self: Sized,
+    {
+        pts.into_iter()
+            .map(|pt| Rect::new(pt, pt))
+            .reduce(|acc, rect| acc.union(&rect))
+    }
+
     fn area(&self) -> f64 {
         use geo::prelude::Area;
         Area::unsigned_area(self)
@@ -89,6 +447,50 @@ impl BoundsExt for Bounds {
         }
     }
 
+    fn union(&self, other: &Self) -> Self
+    where
+        Self: Sized,
+    {
+        Rect::new(
+            (
+                self.min().x.min(other.min().x),
+                self.min().y.min(other.min().y),
+            ),
+            (
+                self.max().x.max(other.max().x),
+                self.max().y.max(other.max().y),
+            ),
+        )
+    }
+
+    fn contains_point(&self, x: f64, y: f64) -> bool {
+        self.min().x <= x && x < self.max().x && self.min().y <= y && y < self.max().y
+    }
+
+    fn contains_bounds(&self, other: &Self) -> bool {
+        self.min().x <= other.min().x
+            && self.min().y <= other.min().y
+            && other.max().x <= self.max().x
+            && other.max().y <= self.max().y
+    }
+
+    fn expand_by(&self, margin_x: f64, margin_y: f64) -> Self
+    where
+        Self: Sized,
+    {
+        let expand = |min: f64, max: f64, margin: f64| {
+            if max - min + 2. * margin > 0. {
+                (min - margin, max + margin)
+            } else {
+                let center = (min + max) / 2.;
+                (center, center)
+            }
+        };
+        let (min_x, max_x) = expand(self.min().x, self.max().x, margin_x);
+        let (min_y, max_y) = expand(self.min().y, self.max().y, margin_y);
+        Rect::new((min_x, min_y), (max_x, max_y))
+    }
+
     fn window_from_bounds(&self, dim: RasterDims) -> RasterWindow {
         let raster_bounds = Rect::new((0., 0.), (dim.0 as f64, dim.1 as f64));
 
@@ -110,9 +512,452 @@ impl BoundsExt for Bounds {
     }
 }
 
-#[cfg(feature = "gdal")]
+/// Compute the intersection of two windows. Returns `None`
+/// if the windows do not overlap (or either is empty).
+pub fn intersect_windows(a: RasterWindow, b: RasterWindow) -> Option<RasterWindow> {
+    let range = |start_a: isize, len_a: usize, start_b: isize, len_b: usize| {
+        let start = start_a.max(start_b);
+        let end = (start_a + len_a as isize).min(start_b + len_b as isize);
+        if start < end {
+            Some((start, (end - start) as usize))
+        } else {
+            None
+        }
+    };
+    let ((xa, ya), (wa, ha)) = a;
+    let ((xb, yb), (wb, hb)) = b;
+    let (x, w) = range(xa, wa, xb, wb)?;
+    let (y, h) = range(ya, ha, yb, hb)?;
+    Some(((x, y), (w, h)))
+}
+
+/// The offset of `inner` relative to `outer`. Returns
+/// `None` if `inner` does not lie entirely within `outer`.
+/// An empty `inner` lies within `outer` if its offset is
+/// within (or on the edges of) `outer`.
+pub fn offset_within(outer: RasterWindow, inner: RasterWindow) -> Option<RasterOffset> {
+    let offset = |start_o: isize, len_o: usize, start_i: isize, len_i: usize| {
+        let off = start_i - start_o;
+        if off >= 0 && off + len_i as isize <= len_o as isize {
+            Some(off)
+        } else {
+            None
+        }
+    };
+    let ((xo, yo), (wo, ho)) = outer;
+    let ((xi, yi), (wi, hi)) = inner;
+    Some((offset(xo, wo, xi, wi)?, offset(yo, ho, yi, hi)?))
+}
+
+/// Clamp a window to the extent of a raster of dimension
+/// `dims`. Returns an empty window (at the clamped offset)
+/// if they do not overlap.
+pub fn clamp_to_dims(win: RasterWindow, dims: RasterDims) -> RasterWindow {
+    let ((x, y), (w, h)) = win;
+    let clamp = |start: isize, len: usize, max: usize| {
+        let lo = start.clamp(0, max as isize);
+        let hi = (start + len as isize).clamp(lo, max as isize);
+        (lo, (hi - lo) as usize)
+    };
+    let (x, w) = clamp(x, w, dims.0);
+    let (y, h) = clamp(y, h, dims.1);
+    ((x, y), (w, h))
+}
+
+/// Split `window` into 2D tiles of dimensions `tile`, in
+/// row-major order. Each tile is expanded by `overlap`
+/// pixels on every side, and clamped to `window`; thus, the
+/// tiles of the last column (resp. row) are narrower
+/// (resp. shorter) if the tile does not divide the window.
+/// Pass the extent of the raster (`((0, 0), dims)`) as
+/// `window` to tile the whole raster. Panics if either
+/// dimension of `tile` is zero.
+pub fn tiles(
+    window: RasterWindow,
+    tile: RasterDims,
+    overlap: usize,
+) -> impl Iterator<Item = RasterWindow> {
+    assert!(tile.0 > 0 && tile.1 > 0, "tile dimensions must be non-zero");
+    let ((x, y), (width, height)) = window;
+    let pad = overlap as isize;
+    (0..height).step_by(tile.1).flat_map(move |dy| {
+        (0..width).step_by(tile.0).map(move |dx| {
+            let expanded = (
+                (x + dx as isize - pad, y + dy as isize - pad),
+                (tile.0 + 2 * overlap, tile.1 + 2 * overlap),
+            );
+            intersect_windows(expanded, window).expect("tile within window")
+        })
+    })
+}
+
+/// Translate a window by `(dx, dy)` pixels.
+pub fn translate(win: RasterWindow, dx: isize, dy: isize) -> RasterWindow {
+    let ((x, y), size) = win;
+    ((x + dx, y + dy), size)
+}
+
+/// Rasterize a polygon (in pixel coordinates) on a window.
+/// A pixel is in the mask if its center is inside the
+/// polygon. A center on an edge is inside if the edge is
+/// on its left (or above it, for horizontal edges), in line
+/// with [`Bounds`]. Thus, a polygon that does not contain
+/// any pixel center (eg. one smaller than a pixel) yields
+/// an empty mask.
+///
+/// Uses a scan-line fill: the cost is proportional to the
+/// number of rows times the number of edges, instead of
+/// the number of pixels times the number of edges. The
+/// parts of a `MultiPolygon` may overlap; the mask is
+/// their union. Returns an error if the polygon has
+/// non-finite (eg. NaN) coordinates.
+///
+/// # Arguments
+///
+/// - `poly` - the polygon in pixel coordinates
+/// - `window` - the window of the raster to rasterize on;
+/// the output has shape `(height, width)`.
+pub fn rasterize_polygon(
+    poly: &MultiPolygon<f64>,
+    window: RasterWindow,
+) -> crate::Result<Array2<bool>> {
+    let finite = poly
+        .iter()
+        .flat_map(|part| std::iter::once(part.exterior()).chain(part.interiors()))
+        .flat_map(|ring| ring.coords())
+        .all(|c| c.x.is_finite() && c.y.is_finite());
+    if !finite {
+        anyhow::bail!("polygon has non-finite coordinates");
+    }
+
+    let ((x_off, y_off), (width, height)) = window;
+    let mut mask = Array2::from_elem((height, width), false);
+    let mut crossings = vec![];
+    for part in poly {
+        for i in 0..height {
+            let y = (y_off + i as isize) as f64 + 0.5;
+
+            // Crossings of the scan-line with the edges of the
+            // rings. Each edge includes its lower end-point, and
+            // excludes the upper one, so that a vertex on the
+            // line is counted once (or not at all).
+            crossings.clear();
+            for ring in std::iter::once(part.exterior()).chain(part.interiors()) {
+                for line in ring.lines() {
+                    let (a, b) = (line.start, line.end);
+                    if (a.y <= y) != (b.y <= y) {
+                        crossings.push(a.x + (y - a.y) * (b.x - a.x) / (b.y - a.y));
+                    }
+                }
+            }
+            crossings.sort_by(f64::total_cmp);
+
+            // Fill pixels with centers in `[x_0, x_1)` of each
+            // pair of crossings (even-odd rule).
+            let mut row = mask.row_mut(i);
+            for pair in crossings.chunks_exact(2) {
+                let col = |x: f64| {
+                    let col = (x - x_off as f64 - 0.5).ceil();
+                    col.max(0.).min(width as f64) as usize
+                };
+                for val in row.slice_mut(ndarray::s![col(pair[0])..col(pair[1])]) {
+                    *val = true;
+                }
+            }
+        }
+    }
+    Ok(mask)
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tiles() {
+        // 10x7 raster in 4x3 tiles: the last column is 2
+        // wide, and the last row is 1 high
+        let raster = ((0, 0), (10, 7));
+        let windows: Vec<_> = tiles(raster, (4, 3), 0).collect();
+        assert_eq!(windows.len(), 9);
+        assert_eq!(windows[0], ((0, 0), (4, 3)));
+        assert_eq!(windows[2], ((8, 0), (2, 3)));
+        assert_eq!(windows[8], ((8, 6), (2, 1)));
+        let area: usize = windows.iter().map(|(_, (w, h))| w * h).sum();
+        assert_eq!(area, 70);
+
+        // Overlapping tiles are clamped to the window
+        let windows: Vec<_> = tiles(raster, (4, 3), 1).collect();
+        assert_eq!(windows[0], ((0, 0), (5, 4)));
+        assert_eq!(windows[4], ((3, 2), (6, 5)));
+        assert_eq!(windows[8], ((7, 5), (3, 2)));
+        for win in &windows {
+            assert_eq!(clamp_to_dims(*win, (10, 7)), *win);
+        }
+
+        // A window within the raster
+        let windows: Vec<_> = tiles(((5, 2), (6, 4)), (4, 4), 2).collect();
+        assert_eq!(windows, vec![((5, 2), (6, 4)), ((7, 2), (4, 4))]);
+
+        assert_eq!(tiles(((3, 3), (0, 5)), (4, 4), 1).count(), 0);
+    }
+
+    #[test]
+    fn test_bounds_union_contains() {
+        let a = Bounds::new((0., 0.), (2., 2.));
+        let b = Bounds::new((1., -1.), (3., 1.));
+        assert_eq!(a.union(&b), Bounds::new((0., -1.), (3., 2.)));
+        assert_eq!(a.union(&a), a);
+
+        // Left and top edges are contained; right and bottom
+        // edges are not
+        assert!(a.contains_point(0., 0.));
+        assert!(a.contains_point(0., 1.));
+        assert!(a.contains_point(1., 0.));
+        assert!(a.contains_point(1.999, 1.999));
+        assert!(!a.contains_point(2., 1.));
+        assert!(!a.contains_point(1., 2.));
+        assert!(!a.contains_point(2., 2.));
+        assert!(!a.contains_point(-0.001, 1.));
+
+        // Empty bounds contain no point
+        assert!(!Bounds::new((1., 1.), (1., 1.)).contains_point(1., 1.));
+    }
+
+    #[test]
+    fn test_bounds_from_points_buffer() {
+        assert_eq!(Bounds::from_points(Vec::<(f64, f64)>::new()), None);
+        assert_eq!(
+            Bounds::from_points(vec![(1., 5.)]),
+            Some(Bounds::new((1., 5.), (1., 5.)))
+        );
+        let a = Bounds::from_points(vec![(1., 5.), (3., 2.), (2., 4.)]).unwrap();
+        assert_eq!(a, Bounds::new((1., 2.), (3., 5.)));
+
+        assert_eq!(a.buffer(1.), Bounds::new((0., 1.), (4., 6.)));
+        assert_eq!(a.buffer(0.), a);
+        assert_eq!(a.buffer(-0.5), Bounds::new((1.5, 2.5), (2.5, 4.5)));
+
+        // Shrinking clamps each dimension to an empty range
+        // at the center
+        assert_eq!(a.buffer(-1.25), Bounds::new((2., 3.25), (2., 3.75)));
+        let empty = a.buffer(-10.);
+        assert_eq!(empty, Bounds::new((2., 3.5), (2., 3.5)));
+        assert_eq!(empty.area(), 0.);
+    }
+
+    #[test]
+    fn test_bounds_expand_by() {
+        let a = Bounds::new((0., 0.), (4., 2.));
+        assert_eq!(a.expand_by(1., 2.), Bounds::new((-1., -2.), (5., 4.)));
+        assert_eq!(a.expand_by(-1., 0.), Bounds::new((1., 0.), (3., 2.)));
+        assert_eq!(a.expand_by(-1., -1.), Bounds::new((1., 1.), (3., 1.)));
+        assert_eq!(a.expand_by(-3., -3.), Bounds::new((2., 1.), (2., 1.)));
+        assert_eq!(a.expand_by(0.5, 0.5), a.buffer(0.5));
+
+        assert!(a.expand_by(1., 1.).contains_bounds(&a));
+        assert!(!a.contains_bounds(&a.expand_by(1., 0.)));
+        assert!(a.contains_bounds(&a.expand_by(-1., -1.)));
+    }
+
+    #[test]
+    fn test_bounds_properties() {
+        // Bounds over a small grid of corners, including
+        // empty, touching and disjoint pairs
+        let coords = [-2., 0., 1.5, 3.];
+        let mut all = vec![];
+        for &x0 in &coords {
+            for &x1 in coords.iter().filter(|&&x1| x1 >= x0) {
+                for &y0 in &coords {
+                    for &y1 in coords.iter().filter(|&&y1| y1 >= y0) {
+                        all.push(Bounds::new((x0, y0), (x1, y1)));
+                    }
+                }
+            }
+        }
+
+        for a in &all {
+            assert!(a.contains_bounds(a));
+            assert_eq!(a.union(a), *a);
+            assert_eq!(a.expand_by(0., 0.), *a);
+            for b in &all {
+                let u = a.union(b);
+                assert_eq!(u, b.union(a));
+                assert!(u.contains_bounds(a) && u.contains_bounds(b));
+                assert!(u.area() >= a.area().max(b.area()));
+
+                assert_eq!(u.intersect(a), a.intersect(&u));
+                if a.area() > 0. {
+                    assert_eq!(u.intersect(a), Some(*a));
+                }
+
+                match a.intersect(b) {
+                    Some(i) => {
+                        assert_eq!(Some(i), b.intersect(a));
+                        assert!(a.contains_bounds(&i) && b.contains_bounds(&i));
+                        assert!(i.area() > 0.);
+                    }
+                    None => assert!(!(a.contains_bounds(b) && b.area() > 0.)),
+                }
+                if a.contains_bounds(b) {
+                    assert_eq!(u, *a);
+                }
+
+                for c in &all {
+                    assert_eq!(a.union(b).union(c), a.union(&b.union(c)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rasterize_polygon() -> crate::Result<()> {
+        use geo::{polygon, MultiPolygon};
+
+        let mask_of = |poly: MultiPolygon<f64>, window| -> crate::Result<_> {
+            let mask = rasterize_polygon(&poly, window)?;
+
+            // Compare with the (slower) point-in-polygon test
+            use geo::algorithm::contains::Contains;
+            let ((x, y), _) = window;
+            for ((i, j), &val) in mask.indexed_iter() {
+                let pt =
+                    geo::Point::new((x + j as isize) as f64 + 0.5, (y + i as isize) as f64 + 0.5);
+                assert_eq!(val, poly.contains(&pt), "pixel ({}, {})", i, j);
+            }
+            Ok(mask)
+        };
+
+        // Square with a hole, and a triangle
+        let square = polygon!(
+            exterior: [
+                (x: 1., y: 1.),
+                (x: 7., y: 1.),
+                (x: 7., y: 6.),
+                (x: 1., y: 6.),
+            ],
+            interiors: [[
+                (x: 3., y: 2.),
+                (x: 5., y: 2.),
+                (x: 5., y: 4.),
+                (x: 3., y: 4.),
+            ]],
+        );
+        let triangle = polygon![
+            (x: 8., y: 0.),
+            (x: 12.3, y: 0.),
+            (x: 8., y: 4.7),
+        ];
+        let poly = MultiPolygon::new(vec![square, triangle]);
+        let mask = mask_of(poly.clone(), ((0, 0), (14, 8)))?;
+        assert_eq!(mask.iter().filter(|&&val| val).count(), 26 + 10);
+        assert!(mask[(1, 1)] && !mask[(2, 3)] && !mask[(0, 1)]);
+        assert!(mask[(0, 8)] && mask[(0, 11)] && !mask[(3, 11)]);
+
+        // Window with an offset, extending past the polygon
+        let shifted = mask_of(poly, ((-2, 3), (8, 6)))?;
+        assert_eq!(shifted.dim(), (6, 8));
+        assert!(shifted[(0, 3)] && !shifted[(0, 5)] && !shifted[(3, 3)]);
+
+        // Polygons smaller than a pixel contain no center,
+        // unless they happen to cover it
+        let tiny = polygon![(x: 2.1, y: 2.1), (x: 2.4, y: 2.1), (x: 2.4, y: 2.4)];
+        let mask = mask_of(MultiPolygon::new(vec![tiny]), ((0, 0), (4, 4)))?;
+        assert!(!mask.iter().any(|&val| val));
+        let tiny = polygon![(x: 2.4, y: 2.4), (x: 2.6, y: 2.4), (x: 2.6, y: 2.6), (x: 2.4, y: 2.6)];
+        let mask = mask_of(MultiPolygon::new(vec![tiny]), ((0, 0), (4, 4)))?;
+        assert_eq!(mask.iter().filter(|&&val| val).count(), 1);
+        assert!(mask[(2, 2)]);
+
+        // Overlapping parts are combined
+        let a = polygon![(x: 0., y: 0.), (x: 3., y: 0.), (x: 3., y: 3.), (x: 0., y: 3.)];
+        let b = polygon![(x: 1., y: 1.), (x: 4., y: 1.), (x: 4., y: 4.), (x: 1., y: 4.)];
+        let mask = rasterize_polygon(&MultiPolygon::new(vec![a, b]), ((0, 0), (5, 5)))?;
+        assert_eq!(mask.iter().filter(|&&val| val).count(), 9 + 9 - 4);
+
+        // Non-finite coordinates are invalid
+        let nan = polygon![(x: 0., y: 0.), (x: f64::NAN, y: 1.), (x: 0., y: 2.)];
+        assert!(rasterize_polygon(&MultiPolygon::new(vec![nan]), ((0, 0), (5, 5))).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_window_helpers() {
+        // Compare with the sets of pixels on small windows,
+        // including negative offsets and empty windows
+        let pixels = |((x, y), (w, h)): RasterWindow| {
+            let mut pixels = vec![];
+            for i in y..y + h as isize {
+                for j in x..x + w as isize {
+                    pixels.push((j, i));
+                }
+            }
+            pixels
+        };
+        let mut all = vec![];
+        for x in -2..2 {
+            for w in 0..3 {
+                for y in -1..2 {
+                    for h in 0..3 {
+                        all.push(((x, y), (w, h)));
+                    }
+                }
+            }
+        }
+
+        for &a in &all {
+            let pix_a = pixels(a);
+            assert_eq!(pixels(translate(a, 3, -2)).len(), pix_a.len());
+            assert_eq!(translate(translate(a, 3, -2), -3, 2), a);
+
+            let clamped = clamp_to_dims(a, (2, 1));
+            let inside: Vec<_> = pix_a
+                .iter()
+                .filter(|&&(x, y)| (0..2).contains(&x) && (0..1).contains(&y))
+                .cloned()
+                .collect();
+            assert_eq!(pixels(clamped), inside, "clamp {:?}", a);
+            assert!(offset_within(((0, 0), (2, 1)), clamped).is_some());
+
+            for &b in &all {
+                let pix_b = pixels(b);
+                let common: Vec<_> = pix_a
+                    .iter()
+                    .filter(|p| pix_b.contains(p))
+                    .cloned()
+                    .collect();
+                match intersect_windows(a, b) {
+                    Some(win) => {
+                        assert_eq!(pixels(win), common, "intersect {:?} {:?}", a, b);
+                        assert_eq!(intersect_windows(b, a), Some(win));
+                    }
+                    None => assert!(common.is_empty()),
+                }
+
+                let within = pix_b.iter().all(|p| pix_a.contains(p));
+                match offset_within(a, b) {
+                    Some(off) => {
+                        assert!(within, "within {:?} {:?}", a, b);
+                        assert_eq!(off, (b.0 .0 - a.0 .0, b.0 .1 - a.0 .1));
+                    }
+                    None => assert!(!within || pix_b.is_empty()),
+                }
+            }
+        }
+
+        // Empty windows are within a window if the offset is
+        // on its edges
+        let outer = ((-1, -1), (2, 2));
+        assert_eq!(offset_within(outer, ((1, 1), (0, 0))), Some((2, 2)));
+        assert_eq!(offset_within(outer, ((2, 1), (0, 0))), None);
+        assert_eq!(clamp_to_dims(((5, -3), (2, 2)), (4, 4)), ((4, 0), (0, 0)));
+        assert_eq!(intersect_windows(outer, ((0, 0), (0, 5))), None);
+    }
+}
+
+#[cfg(feature = "gdal")]
+#[cfg(test)]
+mod gdal_tests {
     use nalgebra::Point2;
     use gdal::Dataset;
 
@@ -136,4 +981,93 @@ mod tests {
         let pt = t.transform_point(&Point2::new(0.0, 0.0));
         eprintln!("(0, 0) -> ({:15.3},{:15.3})", pt.x, pt.y);
     }
+
+    #[test]
+    fn test_geo_transform() -> crate::Result<()> {
+        let gdal = [100., 2., 0., 50., 0., -3.];
+        let t = GeoTransform::from_gdal(&gdal);
+        assert_eq!(t.to_gdal(), gdal);
+        assert_eq!(t.offset_to_world((1, 2)), (102., 44.));
+        assert_eq!(t.world_to_pixel(102., 44.)?, (1., 2.));
+        assert_eq!(t.determinant_area(), 6.);
+
+        // Pixels of `t` to those of a raster at half its
+        // resolution, with the same origin
+        let u = GeoTransform::from_gdal(&[100., 4., 0., 50., 0., -6.]);
+        let between = t.compose(&u.inverse()?);
+        assert_eq!(between.apply(4., 2.), (2., 1.));
+        assert_eq!(between.determinant_area(), 0.25);
+
+        let singular = GeoTransform::from_gdal(&[0., 1., 0., 0., 0., 0.]);
+        assert!(singular.world_to_pixel(0., 0.).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_transform_gdal_round_trip() -> crate::Result<()> {
+        let aligned = [363737.5, 0.5, 0., 2059515.4, 0., -0.5];
+        let rotated = [363737.5, 0.4, 0.3, 2059515.4, 0.3, -0.4];
+        for gdal in [aligned, rotated].iter() {
+            let t = transform_from_gdal(gdal);
+            assert_eq!(&transform_to_gdal(&t)?, gdal);
+
+            // The origin of the cropped raster is the pixel
+            // (3, 4) of the input
+            let cropped = translate_transform(&t, 3., 4.);
+            let maps_to = |(x, y), (u, v)| {
+                let pt = cropped.transform_point(&Point2::new(x, y));
+                (pt - t.transform_point(&Point2::new(u, v))).norm() < 1e-6
+            };
+            assert!(maps_to((0., 0.), (3., 4.)));
+            assert!(maps_to((1., 2.), (4., 6.)));
+            let cropped = transform_to_gdal(&cropped)?;
+            assert_eq!(cropped[1..3], gdal[1..3]);
+            assert_eq!(cropped[4..6], gdal[4..6]);
+        }
+
+        let mut projective = transform_from_gdal(&aligned);
+        projective[(2, 0)] = 0.1;
+        assert!(transform_to_gdal(&projective).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_serializable_transform() {
+        let gdal = [363737.5, 0.4, 0.3, 2059515.4, 0.3, -0.4];
+        let t = transform_from_gdal(&gdal);
+        let entries = SerializableTransform::from(t);
+        assert_eq!(
+            entries.0,
+            [0.4, 0.3, 363737.5, 0.3, -0.4, 2059515.4, 0., 0., 1.]
+        );
+        assert_eq!(PixelTransform::from(entries), t);
+
+        let t = GeoTransform::from_gdal(&gdal);
+        assert_eq!(GeoTransform::from(SerializableTransform::from(t)), t);
+    }
+
+    #[test]
+    fn test_pixel_area_for_row() -> crate::Result<()> {
+        use gdal::spatial_ref::SpatialRef;
+
+        // 0.1 degree pixels, with the row 300 centered at 60N
+        let wgs84 = SpatialRef::from_epsg(4326)?.to_wkt()?;
+        let t = transform_from_gdal(&[0., 0.1, 0., 90.05, 0., -0.1]);
+        let table = RowAreaTable::new(&t, &wgs84, 901)?;
+        let equator = table.area(900);
+        let degree = 6378137. * std::f64::consts::PI / 180.;
+        assert!((equator - 0.01 * degree * degree).abs() < 1e-6 * equator);
+        assert!((table.area(300) / equator - 0.5).abs() < 1e-9);
+        assert_eq!(pixel_area_for_row(&t, &wgs84, 300)?, table.area(300));
+
+        // Projected CRS in meters: the area of the transform
+        let utm = SpatialRef::from_epsg(32643)?.to_wkt()?;
+        let t = transform_from_gdal(&[363737., 2., 0., 2059515., 0., -3.]);
+        assert_eq!(pixel_area_for_row(&t, &utm, 0)?, 6.);
+        assert_eq!(pixel_area_for_row(&t, &utm, 1000)?, 6.);
+
+        // No projection
+        assert_eq!(pixel_area_for_row(&t, "", 5)?, 6.);
+        Ok(())
+    }
 }
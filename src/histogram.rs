@@ -1,15 +1,33 @@
 //! Utilities to compute histogram
+//!
+//! A [`Histogram`] may be stored (as CBOR) with [`write`],
+//! and loaded back with [`read`]. Use an
+//! [`AdaptiveHistogram`] if the range of the values is not
+//! known up front. With the "gdal" and "use-rayon"
+//! features, `Histogram::from_reader` computes the
+//! histogram of a raster in parallel.
+//!
+//! A [`Histogram2d`] counts pairs of values (eg. with
+//! [`write_2d`] and [`read_2d`] to store it).
 
-use serde_derive::Serialize;
+use serde_derive::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::path::Path;
 
 /// Configuration to generate histogram. Can be constructed
-/// from min, max and either step-size or number of bins.
-#[derive(Debug, PartialEq, Clone, Serialize)]
+/// from min, max and either step-size or number of bins, or
+/// with non-uniform bins: log-spaced (see
+/// [`log_spaced`](Config::log_spaced)), or from arbitrary
+/// edges (see [`from_edges`](Config::from_edges)).
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Config {
     min: f64,
     max: f64,
     step: f64,
     len: usize,
+    /// Edges of the bins, if not uniform
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    edges: Option<Vec<f64>>,
 }
 
 impl Config {
@@ -21,6 +39,7 @@ impl Config {
             max,
             step,
             len,
+            edges: None,
         }
     }
     pub fn from_min_max_bins(min: f64, max: f64, len: usize) -> Self {
@@ -31,19 +50,86 @@ impl Config {
             max,
             step,
             len,
+            edges: None,
         }
     }
 
+    /// Construct from the edges of the bins, which must be
+    /// strictly increasing: bin `i` is `[edges[i],
+    /// edges[i + 1])`. Panics if there are less than two
+    /// edges.
+    pub fn from_edges(edges: Vec<f64>) -> Self {
+        assert!(edges.len() >= 2, "bins need at least two edges");
+        assert!(
+            edges.windows(2).all(|pair| pair[0] < pair[1]),
+            "edges must be strictly increasing"
+        );
+        let len = edges.len() - 1;
+        let (min, max) = (edges[0], edges[len]);
+        Config {
+            min,
+            max,
+            step: (max - min) / len as f64,
+            len,
+            edges: Some(edges),
+        }
+    }
+
+    /// Construct `len` bins over `[min, max)`, whose edges
+    /// are in a geometric progression. Requires `0 < min <
+    /// max`.
+    pub fn log_spaced(min: f64, max: f64, len: usize) -> Self {
+        Config::from_edges(log_edges(min, max, len))
+    }
+
+    /// Construct bins over `[-max, max)`, log-spaced (see
+    /// [`log_spaced`](Config::log_spaced)) by magnitude
+    /// in `[min, max)`, for values of either sign. Has
+    /// `len` bins of either sign, and a single bin for the
+    /// magnitudes below `min`: `2 * len + 1` bins in all.
+    /// Requires `0 < min < max`.
+    pub fn signed_log_spaced(min: f64, max: f64, len: usize) -> Self {
+        let positive = log_edges(min, max, len);
+        let edges = positive
+            .iter()
+            .rev()
+            .map(|edge| -edge)
+            .chain(positive.iter().copied())
+            .collect();
+        Config::from_edges(edges)
+    }
+
     #[inline]
     pub fn len(&self) -> usize {
         self.len
     }
 
+    /// The width of the bins; the mean width if the bins
+    /// are not uniform.
     #[inline]
     pub fn step(&self) -> f64 {
         self.step
     }
 
+    /// The edges of the bins, if they are not uniform.
+    #[inline]
+    pub fn edges(&self) -> Option<&[f64]> {
+        self.edges.as_deref()
+    }
+
+    /// The lower and upper edges of bin `bin`. The upper
+    /// edge of the last bin is clamped to the `max`.
+    #[inline]
+    pub fn bin_edges(&self, bin: usize) -> (f64, f64) {
+        if let Some(edges) = &self.edges {
+            (edges[bin], edges[bin + 1])
+        } else {
+            let lower = self.min + bin as f64 * self.step;
+            let upper = (self.min + (bin + 1) as f64 * self.step).min(self.max);
+            (lower, upper)
+        }
+    }
+
     #[inline]
     pub fn max(&self) -> f64 {
         self.max
@@ -54,6 +140,12 @@ impl Config {
         self.min
     }
 
+    /// Whether the range of the config is bounded.
+    #[inline]
+    fn is_finite(&self) -> bool {
+        self.min.is_finite() && self.max.is_finite()
+    }
+
     #[inline]
     pub fn bin_for(&self, val: f64) -> HistBin {
         use HistBin::*;
@@ -62,7 +154,11 @@ impl Config {
         } else if val < self.min {
             Min
         } else {
-            let bin = ((val - self.min) / self.step).floor() as usize;
+            let bin = if let Some(edges) = &self.edges {
+                edges.partition_point(|&edge| edge <= val) - 1
+            } else {
+                ((val - self.min) / self.step).floor() as usize
+            };
             if bin >= self.len {
                 Max
             } else {
@@ -72,6 +168,18 @@ impl Config {
     }
 }
 
+/// Edges of `len` log-spaced bins over `[min, max)`.
+fn log_edges(min: f64, max: f64, len: usize) -> Vec<f64> {
+    assert!(0. < min && min < max, "log-spaced bins need 0 < min < max");
+    assert!(len > 0, "log-spaced bins need a non-zero number of bins");
+    let ratio = (max / min).ln() / len as f64;
+    let mut edges: Vec<_> = (0..len)
+        .map(|idx| min * (ratio * idx as f64).exp())
+        .collect();
+    edges.push(max);
+    edges
+}
+
 /// Represent the location of a value with respect to a
 /// histogram configuration.
 #[derive(Debug)]
@@ -82,10 +190,12 @@ pub enum HistBin {
 }
 
 /// A histogram that can be built by accumulating individual
-/// values, or other histograms.
-#[derive(Debug, Clone, Serialize)]
+/// values, or other histograms. A histogram built with
+/// [`Histogram::new`] borrows its config, while one
+/// deserialized (eg. by [`read`]) owns it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Histogram<'a> {
-    cfg: &'a Config,
+    cfg: Cow<'a, Config>,
     hist: Vec<usize>,
     min: usize,
     max: usize,
@@ -94,16 +204,224 @@ pub struct Histogram<'a> {
 impl<'a> Histogram<'a> {
     pub fn new(cfg: &'a Config) -> Self {
         Histogram {
-            cfg,
+            cfg: Cow::Borrowed(cfg),
             hist: vec![0; cfg.len()],
             min: 0,
             max: 0,
             count: 0,
         }
     }
+
+    /// The configuration of the histogram.
+    #[inline]
+    pub fn config(&self) -> &Config {
+        &self.cfg
+    }
+
+    /// Number of values accumulated, including those
+    /// outside the range of the histogram.
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Number of values below the `min` of the config.
+    #[inline]
+    pub fn underflow(&self) -> usize {
+        self.min
+    }
+
+    /// Number of values at or above the `max` of the
+    /// config.
+    #[inline]
+    pub fn overflow(&self) -> usize {
+        self.max
+    }
+
+    /// The bins of the histogram as `(lower_edge,
+    /// upper_edge, count)` tuples (see
+    /// [`Config::bin_edges`]).
+    pub fn to_bins(&self) -> Vec<(f64, f64, usize)> {
+        self.hist
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| {
+                let (lower, upper) = self.cfg.bin_edges(i);
+                (lower, upper, count)
+            })
+            .collect()
+    }
+
+    /// Cumulative counts of values below the upper edge of
+    /// each bin (including the underflow). Divide by
+    /// [`count`](Histogram::count) to obtain the CDF.
+    pub fn cumulative(&self) -> Vec<usize> {
+        self.hist
+            .iter()
+            .scan(self.min, |acc, &count| {
+                *acc += count;
+                Some(*acc)
+            })
+            .collect()
+    }
+
+    /// Redistribute the counts into the bins of `cfg`. The
+    /// count of each bin is split among the bins it
+    /// overlaps, in proportion to the overlap (ie. assuming
+    /// the values are uniform within a bin), so the result
+    /// is approximate unless the bin edges line up. The
+    /// counts are rounded such that the total is preserved
+    /// exactly. The underflow and the overflow are added to
+    /// those of the target, as are the parts of the bins
+    /// outside its range.
+    ///
+    /// Panics if the range of either config is not finite.
+    pub fn rebin_into<'c>(&self, cfg: &'c Config) -> Histogram<'c> {
+        assert!(
+            self.cfg.is_finite() && cfg.is_finite(),
+            "rebinning histogram with an unbounded range"
+        );
+
+        // Index of the target bins, after the underflow and
+        // before the overflow
+        let slot = |val: f64| match cfg.bin_for(val) {
+            HistBin::Min => 0,
+            HistBin::Bin(bin) => bin + 1,
+            HistBin::Max => cfg.len() + 1,
+        };
+        let slot_edges = |slot: usize| {
+            if slot == 0 {
+                (f64::NEG_INFINITY, cfg.min)
+            } else if slot > cfg.len() {
+                (cfg.max, f64::INFINITY)
+            } else {
+                cfg.bin_edges(slot - 1)
+            }
+        };
+
+        let mut counts = vec![0.; cfg.len() + 2];
+        counts[0] = self.min as f64;
+        counts[cfg.len() + 1] = self.max as f64;
+        for (lower, upper, count) in self.to_bins() {
+            if count == 0 {
+                continue;
+            }
+            if upper <= lower {
+                counts[slot(lower)] += count as f64;
+                continue;
+            }
+            let (first, last) = (slot(lower), slot(upper));
+            for (idx, slot_count) in counts[first..=last].iter_mut().enumerate() {
+                let (slot_lower, slot_upper) = slot_edges(first + idx);
+                let overlap = upper.min(slot_upper) - lower.max(slot_lower);
+                if overlap > 0. {
+                    *slot_count += count as f64 * overlap / (upper - lower);
+                }
+            }
+        }
+
+        // Round the cumulative counts, so the rounding errors
+        // do not add up
+        let mut hist = Histogram::new(cfg);
+        let last = counts.len() - 1;
+        let mut acc = 0.;
+        let mut assigned = 0;
+        for (idx, count) in counts.into_iter().enumerate() {
+            acc += count;
+            let total = if idx == last {
+                self.count
+            } else {
+                (acc.round() as usize).min(self.count)
+            };
+            let count = total - assigned;
+            assigned = total;
+
+            if idx == 0 {
+                hist.min = count;
+            } else if idx == last {
+                hist.max = count;
+            } else {
+                hist.hist[idx - 1] = count;
+            }
+        }
+        hist.count = self.count;
+        hist
+    }
+
+    /// Add the counts of `other`, which is first rebinned
+    /// (see [`rebin_into`](Histogram::rebin_into)) if its
+    /// config differs. Returns an error (instead of
+    /// panicking) if it must be rebinned, but the range of
+    /// either config is not finite.
+    pub fn try_add_assign(&mut self, other: Histogram<'_>) -> crate::Result<()> {
+        if self.cfg == other.cfg {
+            *self += other;
+            return Ok(());
+        }
+        if !(self.cfg.is_finite() && other.cfg.is_finite()) {
+            anyhow::bail!("cannot rebin histogram with an unbounded range");
+        }
+        let cfg = self.cfg.clone().into_owned();
+        *self += other.rebin_into(&cfg);
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "gdal", feature = "use-rayon"))]
+impl<'a> Histogram<'a> {
+    /// Compute the histogram (of config `hist_cfg`) and the
+    /// stats of the values of `reader`, reading the chunks
+    /// of `cfg` in parallel. The padding of the chunks, and
+    /// the skipped chunks are excluded. NaN values, and
+    /// those equal to `no_data` (if any) are ignored; pass
+    /// `reader.no_data_value()?` to ignore the no-data
+    /// value of the raster.
+    ///
+    /// The reader is shared by the threads, so it must be
+    /// `Sync`: eg. a [`RasterPathReader`], or a
+    /// [`ThreadLocalReader`].
+    ///
+    /// This function is only available with the "gdal" and
+    /// "use-rayon" features.
+    ///
+    /// [`RasterPathReader`]: crate::reader::RasterPathReader
+    /// [`ThreadLocalReader`]: crate::reader::ThreadLocalReader
+    pub fn from_reader<R: crate::reader::ChunkReader + Sync>(
+        reader: &R,
+        cfg: &crate::chunking::ChunkConfig,
+        hist_cfg: &'a Config,
+        no_data: Option<f64>,
+    ) -> crate::Result<(Self, crate::stats::PixelStats)> {
+        use crate::reader::NoData;
+        use crate::stats::PixelStats;
+        use rayon::prelude::*;
+
+        let no_data = NoData::new(no_data);
+        let init = || (Histogram::new(hist_cfg), PixelStats::default());
+        cfg.par_iter()
+            .filter(|win| !win.is_skipped())
+            .try_fold(init, |(mut hist, mut stats), win| -> crate::Result<_> {
+                let data = reader.read_chunk::<f64>(win)?;
+                let data = data.slice(ndarray::s![win.data_range(), win.data_col_range()]);
+                for &val in data.iter() {
+                    if !no_data.is_nodata(val) {
+                        hist += val;
+                        stats += val;
+                    }
+                }
+                Ok((hist, stats))
+            })
+            .try_reduce(init, |(mut hist, mut stats), (other_hist, other_stats)| {
+                hist += other_hist;
+                stats += &other_stats;
+                Ok((hist, stats))
+            })
+    }
 }
 
 use std::ops::AddAssign;
+/// Panics if the configs differ; use
+/// [`Histogram::try_add_assign`] to rebin instead.
 impl<'a, 'b> AddAssign<Histogram<'b>> for Histogram<'a> {
     fn add_assign(&mut self, other: Histogram<'b>) {
         assert!(
@@ -135,3 +453,729 @@ impl<'a> AddAssign<f64> for Histogram<'a> {
         self.count += 1;
     }
 }
+
+/// A histogram of a fixed number of bins, whose range adapts
+/// to the values accumulated. The bins are cells of a grid
+/// of width `step` (a power of two) starting at `0`, and
+/// the range is a window of these cells. The initial step
+/// is relative to the first value (about `2^-20` times its
+/// magnitude), and is doubled, merging adjacent bins,
+/// whenever a value falls outside the window and the
+/// values seen do not fit in the number of bins. As the
+/// grids are aligned, histograms (of the same number of
+/// bins) are merged exactly. Convert to a [`Histogram`]
+/// with [`into_histogram`].
+///
+/// Infinite values are counted in the underflow (resp.
+/// overflow), and NaN values are ignored.
+///
+/// [`into_histogram`]: AdaptiveHistogram::into_histogram
+#[derive(Debug, Clone)]
+pub struct AdaptiveHistogram {
+    /// Width of the bins; `0` until a finite value is added
+    step: f64,
+    /// Index of the cell of the first bin
+    offset: i64,
+    hist: Vec<usize>,
+    min: usize,
+    max: usize,
+    count: usize,
+}
+
+/// Exponent (of two) of the initial step of an
+/// [`AdaptiveHistogram`], relative to the first value.
+const ADAPTIVE_STEP_EXP: i32 = -20;
+
+impl AdaptiveHistogram {
+    /// Construct a histogram of `bins` bins, which must be
+    /// even, and non-zero.
+    pub fn new(bins: usize) -> Self {
+        assert!(
+            bins > 0 && bins % 2 == 0,
+            "adaptive histogram needs an even number of bins"
+        );
+        AdaptiveHistogram {
+            step: 0.,
+            offset: 0,
+            hist: vec![0; bins],
+            min: 0,
+            max: 0,
+            count: 0,
+        }
+    }
+
+    /// Number of values accumulated, including the infinite
+    /// values.
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Convert to a [`Histogram`] over the current range,
+    /// or over `[0, 1)` if no finite value was added.
+    pub fn into_histogram(self) -> Histogram<'static> {
+        let bins = self.hist.len();
+        let (min, max) = if self.step > 0. {
+            let min = self.offset as f64 * self.step;
+            (min, min + bins as f64 * self.step)
+        } else {
+            (0., 1.)
+        };
+        Histogram {
+            cfg: Cow::Owned(Config::from_min_max_bins(min, max, bins)),
+            hist: self.hist,
+            min: self.min,
+            max: self.max,
+            count: self.count,
+        }
+    }
+
+    /// The lower edges of the first and the last non-empty
+    /// bins, if any.
+    fn occupied(&self) -> Option<(f64, f64)> {
+        let first = self.hist.iter().position(|&count| count > 0)?;
+        let last = self.hist.iter().rposition(|&count| count > 0)?;
+        let edge = |idx: usize| (self.offset + idx as i64) as f64 * self.step;
+        Some((edge(first), edge(last)))
+    }
+
+    /// The finest step (at least `step`), and the first cell
+    /// of the window of bins containing `[low, high]`,
+    /// centered on it.
+    fn window_for(&self, mut step: f64, low: f64, high: f64) -> (f64, i64) {
+        let bins = self.hist.len() as f64;
+        loop {
+            let (lo, hi) = ((low / step).floor(), (high / step).floor());
+            if hi - lo < bins {
+                let slack = bins - (hi - lo + 1.);
+                return (step, (lo - (slack / 2.).floor()) as i64);
+            }
+            step *= 2.;
+        }
+    }
+
+    /// Move the bins to the window at `offset` of the grid
+    /// of `step`, which must be at least the current step,
+    /// and contain the non-empty bins.
+    fn regrid(&mut self, step: f64, offset: i64) {
+        let mut hist = vec![0; self.hist.len()];
+        for (idx, &count) in self.hist.iter().enumerate() {
+            if count > 0 {
+                let edge = (self.offset + idx as i64) as f64 * self.step;
+                hist[((edge / step).floor() as i64 - offset) as usize] += count;
+            }
+        }
+        self.hist = hist;
+        self.step = step;
+        self.offset = offset;
+    }
+
+    /// Index of the bin containing `val`, if it is inside
+    /// the window.
+    fn bin_for(&self, val: f64) -> Option<usize> {
+        let idx = (val / self.step).floor() as i64 - self.offset;
+        if idx >= 0 && (idx as usize) < self.hist.len() {
+            Some(idx as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl AddAssign<f64> for AdaptiveHistogram {
+    fn add_assign(&mut self, val: f64) {
+        if val.is_nan() {
+            return;
+        }
+        self.count += 1;
+        if val == f64::NEG_INFINITY {
+            self.min += 1;
+            return;
+        } else if val == f64::INFINITY {
+            self.max += 1;
+            return;
+        }
+
+        if self.step == 0. {
+            let exp = if val == 0. {
+                0
+            } else {
+                (val.abs().log2().floor() as i32).max(-1000)
+            };
+            let (step, offset) = self.window_for(2f64.powi(exp + ADAPTIVE_STEP_EXP), val, val);
+            self.step = step;
+            self.offset = offset;
+        } else if self.bin_for(val).is_none() {
+            let (low, high) = self.occupied().expect("non-empty adaptive histogram");
+            let (step, offset) = self.window_for(self.step, low.min(val), high.max(val));
+            self.regrid(step, offset);
+        }
+        let bin = self.bin_for(val).expect("value within adaptive histogram");
+        self.hist[bin] += 1;
+    }
+}
+
+impl AddAssign for AdaptiveHistogram {
+    fn add_assign(&mut self, mut other: AdaptiveHistogram) {
+        assert_eq!(
+            self.hist.len(),
+            other.hist.len(),
+            "adding adaptive histogram with a different number of bins"
+        );
+        self.min += other.min;
+        self.max += other.max;
+        self.count += other.count;
+
+        // Move both to a window containing the non-empty
+        // bins of either
+        let (low, high) = match (self.occupied(), other.occupied()) {
+            (_, None) => return,
+            (None, Some(_)) => {
+                self.step = other.step;
+                self.offset = other.offset;
+                self.hist = other.hist;
+                return;
+            }
+            (Some((l1, h1)), Some((l2, h2))) => (l1.min(l2), h1.max(h2)),
+        };
+        let (step, offset) = self.window_for(self.step.max(other.step), low, high);
+        self.regrid(step, offset);
+        other.regrid(step, offset);
+        for (a, b) in self.hist.iter_mut().zip(other.hist) {
+            *a += b;
+        }
+    }
+}
+
+/// A 2D histogram of pairs of values `(x, y)`, eg. the
+/// difference of a pair of rasters against the value of
+/// the first. Each axis has a [`Config`], and the values
+/// outside its range are counted in its underflow (resp.
+/// overflow), like a [`Histogram`]. The counts are stored
+/// in a flattened array, with the slots of `y` varying
+/// fastest; the slots of an axis are its underflow, its
+/// bins, and its overflow (see [`Histogram2d::slot`]).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Histogram2d<'a> {
+    cfg: Cow<'a, Config2d>,
+    hist: Vec<usize>,
+    count: usize,
+}
+
+/// Configuration of a [`Histogram2d`]: a [`Config`] for
+/// either axis.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Config2d {
+    x: Config,
+    y: Config,
+}
+
+impl Config2d {
+    pub fn new(x: Config, y: Config) -> Self {
+        Config2d { x, y }
+    }
+
+    #[inline]
+    pub fn x(&self) -> &Config {
+        &self.x
+    }
+
+    #[inline]
+    pub fn y(&self) -> &Config {
+        &self.y
+    }
+}
+
+impl<'a> Histogram2d<'a> {
+    pub fn new(cfg: &'a Config2d) -> Self {
+        Histogram2d {
+            cfg: Cow::Borrowed(cfg),
+            hist: vec![0; (cfg.x.len() + 2) * (cfg.y.len() + 2)],
+            count: 0,
+        }
+    }
+
+    /// The configuration of the histogram.
+    #[inline]
+    pub fn config(&self) -> &Config2d {
+        &self.cfg
+    }
+
+    /// Number of pairs accumulated, including those outside
+    /// the range of either axis.
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Index of a bin of an axis of `cfg` among its slots:
+    /// `0` for the underflow, `bin + 1` for a bin, and
+    /// `cfg.len() + 1` for the overflow.
+    #[inline]
+    pub fn slot(cfg: &Config, bin: HistBin) -> usize {
+        match bin {
+            HistBin::Min => 0,
+            HistBin::Bin(bin) => bin + 1,
+            HistBin::Max => cfg.len() + 1,
+        }
+    }
+
+    /// The count of the pairs in the slot `x` of the `x`
+    /// axis, and `y` of the `y` axis (see
+    /// [`slot`](Histogram2d::slot)).
+    #[inline]
+    pub fn get(&self, x: usize, y: usize) -> usize {
+        self.hist[x * (self.cfg.y.len() + 2) + y]
+    }
+
+    /// The counts of each slot of the `x` axis, as rows of
+    /// the counts of each slot of the `y` axis (see
+    /// [`slot`](Histogram2d::slot)).
+    pub fn rows(&self) -> impl Iterator<Item = &[usize]> {
+        self.hist.chunks(self.cfg.y.len() + 2)
+    }
+
+    /// The histogram of the `x` values (summed over `y`).
+    pub fn marginal_x(&self) -> Histogram<'_> {
+        let counts = self.rows().map(|row| row.iter().sum()).collect();
+        marginal(&self.cfg.x, counts, self.count)
+    }
+
+    /// The histogram of the `y` values (summed over `x`).
+    pub fn marginal_y(&self) -> Histogram<'_> {
+        let mut counts = vec![0; self.cfg.y.len() + 2];
+        for row in self.rows() {
+            for (acc, &count) in counts.iter_mut().zip(row) {
+                *acc += count;
+            }
+        }
+        marginal(&self.cfg.y, counts, self.count)
+    }
+}
+
+/// The histogram of an axis from the `counts` of its slots.
+fn marginal(cfg: &Config, mut counts: Vec<usize>, count: usize) -> Histogram<'_> {
+    let max = counts.pop().expect("overflow slot");
+    let min = counts.remove(0);
+    Histogram {
+        cfg: Cow::Borrowed(cfg),
+        hist: counts,
+        min,
+        max,
+        count,
+    }
+}
+
+impl<'a> AddAssign<(f64, f64)> for Histogram2d<'a> {
+    fn add_assign(&mut self, (x, y): (f64, f64)) {
+        let slot_x = Histogram2d::slot(&self.cfg.x, self.cfg.x.bin_for(x));
+        let slot_y = Histogram2d::slot(&self.cfg.y, self.cfg.y.bin_for(y));
+        self.hist[slot_x * (self.cfg.y.len() + 2) + slot_y] += 1;
+        self.count += 1;
+    }
+}
+
+/// Panics if the configs differ.
+impl<'a, 'b> AddAssign<Histogram2d<'b>> for Histogram2d<'a> {
+    fn add_assign(&mut self, other: Histogram2d<'b>) {
+        assert!(
+            self.cfg == other.cfg,
+            "adding 2d histogram with a different config"
+        );
+        for (a, b) in self.hist.iter_mut().zip(other.hist.iter()) {
+            *a += *b;
+        }
+        self.count += other.count;
+    }
+}
+
+/// Write `val` to `path` (as CBOR).
+fn write_cbor<T: serde::Serialize>(val: &T, path: &Path) -> crate::Result<()> {
+    use anyhow::Context;
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("creating histogram {}", path.display()))?;
+    let buf = std::io::BufWriter::with_capacity(0x100000, file);
+    serde_cbor::to_writer(buf, val)?;
+    Ok(())
+}
+
+/// Read a value written by [`write_cbor`].
+fn read_cbor<T: serde::de::DeserializeOwned>(path: &Path) -> crate::Result<T> {
+    use anyhow::Context;
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("opening histogram {}", path.display()))?;
+    serde_cbor::from_reader(std::io::BufReader::new(file))
+        .with_context(|| format!("reading histogram {}", path.display()))
+}
+
+/// Check that a deserialized config has an edge more than
+/// the number of bins, if any.
+fn check_edges(cfg: &Config) -> crate::Result<()> {
+    if let Some(edges) = cfg.edges() {
+        if edges.len() != cfg.len() + 1 {
+            anyhow::bail!(
+                "histogram config has {} edges for {} bins",
+                edges.len(),
+                cfg.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Write a histogram to `path` (as CBOR).
+pub fn write(hist: &Histogram<'_>, path: &Path) -> crate::Result<()> {
+    write_cbor(hist, path)
+}
+
+/// Read a histogram written by [`write`] (eg. by
+/// `raster-diff --hist`). Returns an error if the number of
+/// bins (or edges) does not match the config.
+pub fn read(path: &Path) -> crate::Result<Histogram<'static>> {
+    let hist: Histogram<'static> = read_cbor(path)?;
+    if hist.hist.len() != hist.cfg.len() {
+        anyhow::bail!(
+            "histogram has {} bins, but its config has {}",
+            hist.hist.len(),
+            hist.cfg.len()
+        );
+    }
+    check_edges(&hist.cfg)?;
+    Ok(hist)
+}
+
+/// Write a 2D histogram to `path` (as CBOR).
+pub fn write_2d(hist: &Histogram2d<'_>, path: &Path) -> crate::Result<()> {
+    write_cbor(hist, path)
+}
+
+/// Read a 2D histogram written by [`write_2d`] (eg. by
+/// `raster-diff --hist2d`). Returns an error if the number
+/// of slots (or edges) does not match the config.
+pub fn read_2d(path: &Path) -> crate::Result<Histogram2d<'static>> {
+    let hist: Histogram2d<'static> = read_cbor(path)?;
+    let slots = (hist.cfg.x.len() + 2) * (hist.cfg.y.len() + 2);
+    if hist.hist.len() != slots {
+        anyhow::bail!(
+            "2d histogram has {} slots, but its config has {}",
+            hist.hist.len(),
+            slots
+        );
+    }
+    check_edges(&hist.cfg.x)?;
+    check_edges(&hist.cfg.y)?;
+    Ok(hist)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bins() {
+        let cfg = Config::from_min_max_step(0., 1., 0.3);
+        let mut hist = Histogram::new(&cfg);
+        for &val in &[-1., 0., 0.1, 0.5, 0.95, 1., 2.] {
+            hist += val;
+        }
+
+        assert_eq!(hist.underflow(), 1);
+        assert_eq!(hist.overflow(), 2);
+        assert_eq!(hist.count(), 7);
+
+        let bins = hist.to_bins();
+        assert_eq!(bins.len(), 4);
+        assert_eq!(bins[0].2, 2);
+        assert_eq!(bins[1], (0.3, 0.6, 1));
+        // Last bin is truncated to the max.
+        assert_eq!((bins[3].1, bins[3].2), (1., 1));
+
+        assert_eq!(hist.cumulative(), vec![3, 4, 4, 5]);
+    }
+
+    #[test]
+    fn test_rebin() -> crate::Result<()> {
+        // Values at the centers of the bins of `cfg`,
+        // including some outside its range
+        let cfg = Config::from_min_max_bins(0., 1., 10);
+        let mut hist = Histogram::new(&cfg);
+        for k in -2..12 {
+            for _ in 0..k + 3 {
+                hist += 0.05 + 0.1 * k as f64;
+            }
+        }
+
+        // The edges line up: same as accumulating directly
+        let aligned = Config::from_min_max_bins(0.2, 0.6, 2);
+        let rebinned = hist.rebin_into(&aligned);
+        let mut expected = Histogram::new(&aligned);
+        for k in -2..12 {
+            for _ in 0..k + 3 {
+                expected += 0.05 + 0.1 * k as f64;
+            }
+        }
+        assert_eq!(rebinned.to_bins(), expected.to_bins());
+        assert_eq!(
+            (rebinned.underflow(), rebinned.overflow()),
+            (expected.underflow(), expected.overflow())
+        );
+        assert_eq!(rebinned.count(), hist.count());
+
+        // Otherwise, the counts are split, and the total is
+        // preserved
+        let shifted = Config::from_min_max_step(0.13, 0.77, 0.07);
+        let rebinned = hist.rebin_into(&shifted);
+        let total: usize = rebinned.to_bins().iter().map(|b| b.2).sum();
+        assert_eq!(
+            rebinned.underflow() + total + rebinned.overflow(),
+            hist.count()
+        );
+        assert_eq!(rebinned.count(), hist.count());
+
+        let cfg = Config::from_min_max_bins(0., 1., 2);
+        let mut hist = Histogram::new(&cfg);
+        for &val in &[0.1, 0.2, 0.3, 0.7] {
+            hist += val;
+        }
+        let finer = Config::from_min_max_bins(0., 1., 4);
+        let bins: Vec<_> = hist
+            .rebin_into(&finer)
+            .to_bins()
+            .iter()
+            .map(|b| b.2)
+            .collect();
+        assert_eq!(bins, vec![2, 1, 1, 0]);
+
+        // Histograms with different configs are added by
+        // rebinning
+        let mut total = Histogram::new(&finer);
+        total += 0.6;
+        total.try_add_assign(hist.clone())?;
+        assert_eq!(total.count(), 5);
+        assert_eq!(total.cumulative(), vec![2, 3, 5, 5]);
+
+        let unbounded = Config::from_min_max_bins(0., f64::INFINITY, 2);
+        assert!(total.try_add_assign(Histogram::new(&unbounded)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_non_uniform() -> crate::Result<()> {
+        let cfg = Config::log_spaced(1., 1000., 3);
+        let edges = cfg.edges().unwrap();
+        assert_eq!((edges[0], edges[3]), (1., 1000.));
+        assert!((edges[1] - 10.).abs() < 1e-9 && (edges[2] - 100.).abs() < 1e-9);
+
+        let mut hist = Histogram::new(&cfg);
+        for &val in &[0.5, 1., 9., 11., 500., 999., 1000.] {
+            hist += val;
+        }
+        assert_eq!((hist.underflow(), hist.overflow()), (1, 1));
+        assert_eq!(hist.cumulative(), vec![3, 4, 6]);
+        assert_eq!(hist.to_bins()[1].0, edges[1]);
+
+        // Either sign, with a bin for the small magnitudes
+        let cfg = Config::signed_log_spaced(0.1, 10., 2);
+        assert_eq!(cfg.len(), 5);
+        assert_eq!((cfg.min(), cfg.max()), (-10., 10.));
+        let mut hist = Histogram::new(&cfg);
+        for &val in &[-5., -0.5, -0.1, 0., 0.05, 2., 9.] {
+            hist += val;
+        }
+        assert_eq!(hist.cumulative(), vec![1, 2, 5, 5, 7]);
+
+        // The edges are stored along with the histogram
+        let cfg = Config::from_edges(vec![-1., 0., 0.5, 4.]);
+        let mut hist = Histogram::new(&cfg);
+        for &val in &[-0.5, 0.25, 0.5, 3.] {
+            hist += val;
+        }
+        assert_eq!(hist.cumulative(), vec![1, 2, 4]);
+        let path = std::env::temp_dir().join(format!("rasters-edges-{}.bin", std::process::id()));
+        write(&hist, &path)?;
+        let read_hist = read(&path);
+        std::fs::remove_file(&path)?;
+        let read_hist = read_hist?;
+        assert_eq!(read_hist.config(), &cfg);
+        assert_eq!(read_hist.to_bins(), hist.to_bins());
+
+        // Rebinned into uniform bins, and back
+        let uniform = Config::from_min_max_bins(-1., 4., 5);
+        let rebinned = hist.rebin_into(&uniform);
+        assert_eq!(rebinned.cumulative(), vec![1, 2, 3, 3, 4]);
+        assert_eq!(rebinned.rebin_into(&cfg).count(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_adaptive() {
+        let vals: Vec<_> = (0..800).map(|i| -3. + i as f64 * 0.01).collect();
+        let mut hist = AdaptiveHistogram::new(16);
+        for &val in &vals {
+            hist += val;
+        }
+
+        // The finest step at which the values fit in 16
+        // bins
+        let hist = hist.into_histogram();
+        assert_eq!(hist.config().step(), 0.5);
+        assert_eq!((hist.config().min(), hist.config().max()), (-3., 5.));
+        assert_eq!(hist.count(), vals.len());
+        assert_eq!((hist.underflow(), hist.overflow()), (0, 0));
+        assert_eq!(hist.cumulative().last(), Some(&vals.len()));
+
+        // The halves start at different steps, and are merged
+        // exactly
+        let (mut first, mut second) = (AdaptiveHistogram::new(16), AdaptiveHistogram::new(16));
+        for &val in &vals[..400] {
+            first += val;
+        }
+        for &val in &vals[400..] {
+            second += val;
+        }
+        first += second;
+        first += AdaptiveHistogram::new(16);
+        let merged = first.into_histogram();
+        assert_eq!(merged.config(), hist.config());
+        assert_eq!(merged.to_bins(), hist.to_bins());
+
+        // Infinite values are out of range, and NaN ignored
+        let mut hist = AdaptiveHistogram::new(4);
+        for &val in &[f64::NEG_INFINITY, f64::NAN, 0., f64::INFINITY] {
+            hist += val;
+        }
+        let hist = hist.into_histogram();
+        assert_eq!(hist.count(), 3);
+        assert_eq!((hist.underflow(), hist.overflow()), (1, 1));
+        assert_eq!(AdaptiveHistogram::new(4).into_histogram().count(), 0);
+    }
+
+    #[test]
+    fn test_hist_2d() -> crate::Result<()> {
+        let cfg = Config2d::new(
+            Config::from_min_max_bins(0., 10., 2),
+            Config::from_min_max_bins(-1., 1., 4),
+        );
+        let pairs = [
+            (1., -0.75),
+            (2., 0.),
+            (7., 0.),
+            (7., 5.),
+            (-1., 0.6),
+            (11., -3.),
+        ];
+        let mut hist = Histogram2d::new(&cfg);
+        for &pair in &pairs {
+            hist += pair;
+        }
+        assert_eq!(hist.count(), 6);
+        let slot_x = |val| Histogram2d::slot(cfg.x(), cfg.x().bin_for(val));
+        let slot_y = |val| Histogram2d::slot(cfg.y(), cfg.y().bin_for(val));
+        assert_eq!(hist.get(slot_x(1.), slot_y(-0.75)), 1);
+        assert_eq!(hist.get(slot_x(7.), slot_y(0.)), 1);
+        assert_eq!(hist.get(slot_x(7.), slot_y(5.)), 1);
+        assert_eq!(hist.get(0, 4), 1);
+        assert_eq!(hist.get(3, 0), 1);
+
+        // The marginals match the 1D histograms
+        let (mut xs, mut ys) = (Histogram::new(cfg.x()), Histogram::new(cfg.y()));
+        for &(x, y) in &pairs {
+            xs += x;
+            ys += y;
+        }
+        for (marginal, expected) in [(hist.marginal_x(), xs), (hist.marginal_y(), ys)] {
+            assert_eq!(marginal.to_bins(), expected.to_bins());
+            assert_eq!(
+                (marginal.underflow(), marginal.overflow(), marginal.count()),
+                (expected.underflow(), expected.overflow(), expected.count())
+            );
+        }
+
+        let mut total = Histogram2d::new(&cfg);
+        total += hist.clone();
+        total += hist;
+        assert_eq!(total.count(), 12);
+        assert_eq!(total.get(3, 0), 2);
+
+        let path = std::env::temp_dir().join(format!("rasters-hist2d-{}.bin", std::process::id()));
+        write_2d(&total, &path)?;
+        let read_hist = read_2d(&path);
+        std::fs::remove_file(&path)?;
+        let read_hist = read_hist?;
+        assert_eq!(read_hist.config(), &cfg);
+        assert!(read_hist.rows().eq(total.rows()));
+        Ok(())
+    }
+
+    #[cfg(all(feature = "gdal", feature = "use-rayon"))]
+    #[test]
+    fn test_from_reader() -> crate::Result<()> {
+        use crate::chunking::ChunkConfig;
+        use crate::reader::MemoryReader;
+
+        // Value at `(x, y)` is `x + y`, with a no-data value
+        // on the diagonal
+        let (width, height) = (12, 10);
+        let data = ndarray::Array2::from_shape_fn((height, width), |(y, x)| {
+            if x == y {
+                -1.
+            } else {
+                (x + y) as f64
+            }
+        });
+        let cfg = ChunkConfig::with_dims(width, height)
+            .with_tile_dims(5, 3)
+            .with_padding(1);
+        let hist_cfg = Config::from_min_max_bins(0., 20., 4);
+        let (hist, stats) = Histogram::from_reader(
+            &MemoryReader(data.clone(), None),
+            &cfg,
+            &hist_cfg,
+            Some(-1.),
+        )?;
+
+        let mut expected = Histogram::new(&hist_cfg);
+        for &val in data.iter().filter(|&&val| val >= 0.) {
+            expected += val;
+        }
+        assert_eq!(hist.to_bins(), expected.to_bins());
+        assert_eq!(hist.count(), width * height - height);
+        assert_eq!(hist.overflow(), expected.overflow());
+        assert_eq!(stats.count(), hist.count() as f64);
+        assert_eq!((stats.min(), stats.max()), (1., 20.));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_write() -> crate::Result<()> {
+        let cfg = Config::from_min_max_bins(-1., 1., 4);
+        let mut hist = Histogram::new(&cfg);
+        for &val in &[-2., -0.75, 0., 0.1, 0.9, 1.] {
+            hist += val;
+        }
+
+        let path = std::env::temp_dir().join(format!("rasters-hist-{}.bin", std::process::id()));
+        write(&hist, &path)?;
+        let read_hist = read(&path);
+        std::fs::remove_file(&path)?;
+
+        let read_hist = read_hist?;
+        assert_eq!(read_hist.config(), &cfg);
+        assert_eq!(read_hist.to_bins(), hist.to_bins());
+        assert_eq!(
+            (
+                read_hist.count(),
+                read_hist.underflow(),
+                read_hist.overflow()
+            ),
+            (6, 1, 1)
+        );
+
+        // The loaded histogram accumulates as usual
+        let mut total = Histogram::new(&cfg);
+        total += read_hist;
+        total += hist;
+        assert_eq!(total.cumulative(), vec![4, 4, 8, 10]);
+        Ok(())
+    }
+}
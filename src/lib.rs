@@ -10,11 +10,20 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub mod geometry;
 pub mod histogram;
 pub mod stats;
+pub mod typed;
 
+#[cfg(all(feature = "gdal", feature = "async"))]
+pub mod async_reader;
 pub mod chunking;
 #[cfg(feature = "gdal")]
+pub mod focal;
+#[cfg(feature = "gdal")]
 pub mod reader;
+#[cfg(all(feature = "gdal", any(test, feature = "test-util")))]
+pub mod test_util;
 
 pub mod align;
+#[cfg(feature = "gdal")]
+pub mod diff;
 
 pub mod prelude;
@@ -4,8 +4,15 @@ pub use crate::chunking::*;
 pub use crate::geometry::*;
 #[cfg(feature = "gdal")]
 pub use crate::reader::*;
+#[cfg(feature = "gdal")]
+pub use crate::focal::focal_apply;
+#[cfg(all(feature = "gdal", feature = "async"))]
+pub use crate::async_reader::*;
 
 pub use crate::histogram::*;
 pub use crate::stats::*;
+pub use crate::typed::*;
 
 pub use crate::align::*;
+#[cfg(feature = "gdal")]
+pub use crate::diff::*;
@@ -1,15 +1,57 @@
 //! Abstractions to safely read GDAL datasets from multiple
 //! threads.
 
-use crate::chunking::ChunkConfig;
-use crate::geometry::{RasterDims, RasterOffset};
+use crate::chunking::ChunkWindow;
+use crate::geometry::{clamp_to_dims, offset_within, RasterDims, RasterOffset, RasterWindow};
+use crate::typed::TypedChunk;
 use crate::Result;
-use anyhow::{format_err, Context};
+use anyhow::{bail, format_err, Context};
 use gdal::{
-    raster::{GdalType, RasterBand},
+    raster::{GdalDataType, GdalType, RasterBand, ResampleAlg},
     Dataset,
 };
-use ndarray::Array2;
+use ndarray::{Array2, Array3, ArrayView2, ArrayViewMut2};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The no-data value of a band, if any. A value is no-data
+/// if it is NaN, or equals the no-data value (see
+/// [`is_nodata`]); use it instead of comparing with the
+/// value directly, which is always false for a NaN no-data
+/// value.
+///
+/// [`is_nodata`]: NoData::is_nodata
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoData(Option<f64>);
+
+impl NoData {
+    /// No-data marked by NaN values only (eg. the output of
+    /// [`NodataToNan`]).
+    pub const NAN: NoData = NoData(Some(f64::NAN));
+
+    /// No-data value `value`, if any.
+    pub fn new(value: Option<f64>) -> Self {
+        NoData(value)
+    }
+
+    /// The no-data value, if any.
+    pub fn value(&self) -> Option<f64> {
+        self.0
+    }
+
+    /// Whether `val` is NaN, or the no-data value.
+    pub fn is_nodata(&self, val: f64) -> bool {
+        val.is_nan() || Some(val) == self.0
+    }
+}
+
+impl From<Option<f64>> for NoData {
+    fn from(value: Option<f64>) -> Self {
+        NoData(value)
+    }
+}
 
 /// Abstracts reading chunks from raster.
 pub trait ChunkReader {
@@ -36,31 +78,327 @@ pub trait ChunkReader {
         Ok(Array2::from_shape_vec((size.1, size.0), buf)?)
     }
 
+    /// Read a window into `out`, which must be of shape
+    /// `(size.1, size.0)`. Unlike [`read_as_array`], no
+    /// buffer is allocated if `out` is in standard layout,
+    /// so a buffer may be re-used across reads (eg. one per
+    /// thread, via `rayon`'s `map_init`):
+    ///
+    /// ```no_run
+    /// # use rasters::prelude::*;
+    /// # use ndarray::ArrayViewMut2;
+    /// # fn main() -> Result<()> {
+    /// let reader = PooledReader::new("input.tif", 1, 1)?;
+    /// let cfg = ChunkConfig::for_reader(&reader)?;
+    ///
+    /// // Sized to the largest chunk
+    /// let max_len = cfg.iter().map(|win| win.width() * win.height()).max();
+    /// let mut buf = vec![0f64; max_len.unwrap_or(0)];
+    /// for win in cfg.iter() {
+    ///     let (off, size) = win.raster_window();
+    ///     let len = size.0 * size.1;
+    ///     let mut data = ArrayViewMut2::from_shape((size.1, size.0), &mut buf[..len])?;
+    ///     reader.read_into_array(data.view_mut(), off, size)?;
+    ///     println!("chunk {}: sum {}", win.index(), data.sum());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`read_as_array`]: ChunkReader::read_as_array
+    fn read_into_array<T>(
+        &self,
+        mut out: ArrayViewMut2<'_, T>,
+        off: RasterOffset,
+        size: RasterDims,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        if out.dim() != (size.1, size.0) {
+            bail!(
+                "output of shape {:?} does not match window of dimension ({}x{})",
+                out.dim(),
+                size.0,
+                size.1
+            );
+        }
+        match out.as_slice_mut() {
+            Some(out) => self.read_into_slice(out, off, size),
+            None => {
+                out.assign(&self.read_as_array::<T>(off, size)?);
+                Ok(())
+            }
+        }
+    }
+
     /// Helper to read into slice from output of
+    /// [`ChunkConfig`] iterator. The window includes the
+    /// padding rows and columns, so `out` must have a length
+    /// of `chunk.width() * chunk.height()`.
+    ///
+    /// [`ChunkConfig`]: crate::chunking::ChunkConfig
+    fn read_chunk_into_slice<T>(&self, out: &mut [T], chunk: ChunkWindow<'_>) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        let (off, size) = chunk.raster_window();
+        self.read_into_slice(out, off, size)
+    }
+
+    /// Helper to read ndarray from output of
     /// [`ChunkConfig`] iterator
-    fn read_chunk_into_slice<T>(
+    ///
+    /// [`ChunkConfig`]: crate::chunking::ChunkConfig
+    fn read_chunk<T>(&self, chunk: ChunkWindow<'_>) -> Result<Array2<T>>
+    where
+        T: GdalType + Copy,
+    {
+        let (off, size) = chunk.raster_window();
+        self.read_as_array(off, size)
+    }
+
+    /// Read the window at `off` of size `src_size` into
+    /// `out`, resampled to `out_size` with `alg` by GDAL
+    /// during the read (eg. a decimated read). `out` must
+    /// have a length of `out_size.0 * out_size.1`. Defaults
+    /// to an error unless the sizes are equal.
+    fn read_resampled_into_slice<T>(
         &self,
         out: &mut [T],
-        chunk: (&ChunkConfig, usize, usize),
+        off: RasterOffset,
+        src_size: RasterDims,
+        out_size: RasterDims,
+        _alg: ResampleAlg,
     ) -> Result<()>
     where
         T: GdalType + Copy,
     {
-        let (cfg, start, end) = chunk;
-        let width = cfg.width();
-        let height = end - start;
-        self.read_into_slice(out, (0 as isize, start as isize), (width, height))
+        if src_size != out_size {
+            bail!("reader does not support resampled reads");
+        }
+        self.read_into_slice(out, off, src_size)
     }
 
-    /// Helper to read ndarray from output of
-    /// [`ChunkConfig`] iterator
-    fn read_chunk<T>(&self, chunk: (&ChunkConfig, usize, usize)) -> Result<Array2<T>>
+    /// Helper to read a window resampled to `out_size` (see
+    /// [`read_resampled_into_slice`]) into an ndarray.
+    /// Returns an error if either dimension of `out_size` is
+    /// zero.
+    ///
+    /// [`read_resampled_into_slice`]: ChunkReader::read_resampled_into_slice
+    fn read_resampled<T>(
+        &self,
+        off: RasterOffset,
+        src_size: RasterDims,
+        out_size: RasterDims,
+        alg: ResampleAlg,
+    ) -> Result<Array2<T>>
+    where
+        T: GdalType + Copy,
+    {
+        if out_size.0 < 1 || out_size.1 < 1 {
+            bail!(
+                "output dimensions must both be at least 1 (got {}x{})",
+                out_size.0,
+                out_size.1
+            );
+        }
+        let bufsize = out_size.0 * out_size.1;
+        let mut buf = Vec::with_capacity(bufsize);
+
+        // Safety: paradigm suggested in std docs
+        // https://doc.rust-lang.org/std/vec/struct.Vec.html#examples-18
+        unsafe {
+            buf.set_len(bufsize);
+        }
+
+        self.read_resampled_into_slice(&mut buf[..], off, src_size, out_size, alg)?;
+        Ok(Array2::from_shape_vec((out_size.1, out_size.0), buf)?)
+    }
+
+    /// The dimensions of the band. Defaults to an error, as
+    /// the dimensions are unknown.
+    fn raster_size(&self) -> Result<RasterDims> {
+        bail!("raster dimensions unknown")
+    }
+
+    /// Read a window that may extend past the edges of the
+    /// band (see [`raster_size`]). The returned array has
+    /// the requested `size`, with the region outside the
+    /// band set to `fill`. Also returns the window (in the
+    /// pixels of the band) that was read; it is empty if the
+    /// requested window does not overlap the band.
+    ///
+    /// [`raster_size`]: ChunkReader::raster_size
+    fn read_as_array_clamped<T>(
+        &self,
+        off: RasterOffset,
+        size: RasterDims,
+        fill: T,
+    ) -> Result<(Array2<T>, RasterWindow)>
     where
         T: GdalType + Copy,
     {
-        let (cfg, start, height) = chunk;
-        let width = cfg.width();
-        self.read_as_array((0 as isize, start as isize), (width, height))
+        let valid = clamp_to_dims((off, size), self.raster_size()?);
+        let mut arr = Array2::from_elem((size.1, size.0), fill);
+        if valid.1 .0 > 0 && valid.1 .1 > 0 {
+            let data = self.read_as_array::<T>(valid.0, valid.1)?;
+            sub_window_mut(&mut arr, off, valid).assign(&data);
+        }
+        Ok((arr, valid))
+    }
+
+    /// Read a window that may extend past the edges of the
+    /// band (see [`read_as_array_clamped`]) along with the
+    /// validity of each pixel (see [`read_as_array_masked`]).
+    /// The pixels outside the band are set to `fill`, and
+    /// are invalid.
+    ///
+    /// [`read_as_array_clamped`]: ChunkReader::read_as_array_clamped
+    /// [`read_as_array_masked`]: ChunkReader::read_as_array_masked
+    fn read_as_array_masked_clamped<T>(
+        &self,
+        off: RasterOffset,
+        size: RasterDims,
+        fill: T,
+    ) -> Result<(Array2<T>, Array2<bool>)>
+    where
+        T: GdalType + Copy + Into<f64>,
+    {
+        let valid = clamp_to_dims((off, size), self.raster_size()?);
+        let mut arr = Array2::from_elem((size.1, size.0), fill);
+        let mut mask = Array2::from_elem((size.1, size.0), false);
+        if valid.1 .0 > 0 && valid.1 .1 > 0 {
+            let (data, data_mask) = self.read_as_array_masked::<T>(valid.0, valid.1)?;
+            sub_window_mut(&mut arr, off, valid).assign(&data);
+            sub_window_mut(&mut mask, off, valid).assign(&data_mask);
+        }
+        Ok((arr, mask))
+    }
+
+    /// The no-data value of the band, if any. Defaults to
+    /// `None`.
+    fn no_data_value(&self) -> Result<Option<f64>> {
+        Ok(None)
+    }
+
+    /// The no-data value of the band (see
+    /// [`no_data_value`]), to check the values read.
+    ///
+    /// [`no_data_value`]: ChunkReader::no_data_value
+    fn no_data(&self) -> Result<NoData> {
+        Ok(self.no_data_value()?.into())
+    }
+
+    /// The data type of the band. Defaults to `Float64`.
+    fn band_type(&self) -> Result<GdalDataType> {
+        Ok(GdalDataType::Float64)
+    }
+
+    /// Read a window in the type of the band (see
+    /// [`band_type`]), avoiding the memory overhead of
+    /// reading eg. byte data as `f64`.
+    ///
+    /// [`band_type`]: ChunkReader::band_type
+    fn read_as_array_dynamic(&self, off: RasterOffset, size: RasterDims) -> Result<TypedChunk> {
+        Ok(match self.band_type()? {
+            GdalDataType::UInt8 => TypedChunk::U8(self.read_as_array(off, size)?),
+            GdalDataType::UInt16 => TypedChunk::U16(self.read_as_array(off, size)?),
+            GdalDataType::Int16 => TypedChunk::I16(self.read_as_array(off, size)?),
+            GdalDataType::UInt32 => TypedChunk::U32(self.read_as_array(off, size)?),
+            GdalDataType::Int32 => TypedChunk::I32(self.read_as_array(off, size)?),
+            GdalDataType::Float32 => TypedChunk::F32(self.read_as_array(off, size)?),
+            _ => TypedChunk::F64(self.read_as_array(off, size)?),
+        })
+    }
+
+    /// Helper to read a chunk (see [`read_chunk`]) in the
+    /// type of the band (see [`read_as_array_dynamic`]).
+    ///
+    /// [`read_chunk`]: ChunkReader::read_chunk
+    /// [`read_as_array_dynamic`]: ChunkReader::read_as_array_dynamic
+    fn read_chunk_dynamic(&self, chunk: ChunkWindow<'_>) -> Result<TypedChunk> {
+        let (off, size) = chunk.raster_window();
+        self.read_as_array_dynamic(off, size)
+    }
+
+    /// Emulate reading a window of the mask band (see
+    /// [`RasterBand::open_mask_band`]): a non-zero value
+    /// marks a valid pixel. Returns `false` without reading
+    /// if the validity is determined by the no-data value
+    /// alone. Defaults to `false`.
+    fn read_mask_into_slice(
+        &self,
+        _out: &mut [u8],
+        _off: RasterOffset,
+        _size: RasterDims,
+    ) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Read a window along with the validity of each pixel.
+    /// A pixel is valid if its value is neither NaN nor the
+    /// no-data value (see [`no_data`]), and the mask
+    /// (eg. an alpha band; see [`read_mask_into_slice`])
+    /// marks it valid.
+    ///
+    /// [`no_data`]: ChunkReader::no_data
+    /// [`read_mask_into_slice`]: ChunkReader::read_mask_into_slice
+    fn read_as_array_masked<T>(
+        &self,
+        off: RasterOffset,
+        size: RasterDims,
+    ) -> Result<(Array2<T>, Array2<bool>)>
+    where
+        T: GdalType + Copy + Into<f64>,
+    {
+        let data = self.read_as_array::<T>(off, size)?;
+        let mut valid = Array2::from_elem(data.dim(), false);
+        fill_valid(self, data.view(), valid.view_mut(), off, size)?;
+        Ok((data, valid))
+    }
+
+    /// Read a window into `out`, along with the validity of
+    /// each pixel into `valid` (see [`read_into_array`] and
+    /// [`read_as_array_masked`]). Both must be of shape
+    /// `(size.1, size.0)`.
+    ///
+    /// [`read_into_array`]: ChunkReader::read_into_array
+    /// [`read_as_array_masked`]: ChunkReader::read_as_array_masked
+    fn read_into_array_masked<T>(
+        &self,
+        mut out: ArrayViewMut2<'_, T>,
+        valid: ArrayViewMut2<'_, bool>,
+        off: RasterOffset,
+        size: RasterDims,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy + Into<f64>,
+    {
+        if valid.dim() != (size.1, size.0) {
+            bail!(
+                "validity of shape {:?} does not match window of dimension ({}x{})",
+                valid.dim(),
+                size.0,
+                size.1
+            );
+        }
+        self.read_into_array(out.view_mut(), off, size)?;
+        fill_valid(self, out.view(), valid, off, size)
+    }
+
+    /// Helper to read a chunk (see [`read_chunk`]) along
+    /// with the validity of each pixel (see
+    /// [`read_as_array_masked`]).
+    ///
+    /// [`read_chunk`]: ChunkReader::read_chunk
+    /// [`read_as_array_masked`]: ChunkReader::read_as_array_masked
+    fn read_chunk_masked<T>(&self, chunk: ChunkWindow<'_>) -> Result<(Array2<T>, Array2<bool>)>
+    where
+        T: GdalType + Copy + Into<f64>,
+    {
+        let (off, size) = chunk.raster_window();
+        self.read_as_array_masked(off, size)
     }
 }
 
@@ -69,6 +407,8 @@ impl<'a> ChunkReader for RasterBand<'a> {
     where
         T: GdalType + Copy,
     {
+        #[cfg(debug_assertions)]
+        warn_unaligned(self, off, size);
         Ok(self
             .read_into_slice(off, size, size, out, None)
             .with_context(|| {
@@ -81,12 +421,137 @@ impl<'a> ChunkReader for RasterBand<'a> {
                 )
             })?)
     }
+
+    fn read_resampled_into_slice<T>(
+        &self,
+        out: &mut [T],
+        off: RasterOffset,
+        src_size: RasterDims,
+        out_size: RasterDims,
+        alg: ResampleAlg,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        Ok(self
+            .read_into_slice(off, src_size, out_size, out, Some(alg))
+            .with_context(|| {
+                format_err!(
+                    "reading window @ ({},{}) of dimension ({}x{}) resampled to ({}x{})",
+                    off.0,
+                    off.1,
+                    src_size.0,
+                    src_size.1,
+                    out_size.0,
+                    out_size.1
+                )
+            })?)
+    }
+
+    fn raster_size(&self) -> Result<RasterDims> {
+        Ok(self.size())
+    }
+
+    fn no_data_value(&self) -> Result<Option<f64>> {
+        Ok(RasterBand::no_data_value(self))
+    }
+
+    fn band_type(&self) -> Result<GdalDataType> {
+        Ok(RasterBand::band_type(self))
+    }
+
+    fn read_mask_into_slice(
+        &self,
+        out: &mut [u8],
+        off: RasterOffset,
+        size: RasterDims,
+    ) -> Result<bool> {
+        // The no-data value is checked by the caller
+        let flags = self.mask_flags()?;
+        if flags.is_all_valid() || flags.is_nodata() {
+            return Ok(false);
+        }
+        let mask = self.open_mask_band()?;
+        ChunkReader::read_into_slice(&mask, out, off, size).context("reading mask band")?;
+        Ok(true)
+    }
+}
+
+/// The read amplification of a window `(off, size)` of a
+/// band of dimensions `dims`, with blocks of `block_size`:
+/// the number of pixels in the blocks overlapping the
+/// window (clamped to the band), divided by the number of
+/// pixels of the window. It is `1` if each edge of the
+/// window is on a block boundary (or an edge of the band),
+/// and for an empty window.
+pub fn read_amplification(
+    (off, size): RasterWindow,
+    block_size: RasterDims,
+    dims: RasterDims,
+) -> f64 {
+    let (off, size) = clamp_to_dims((off, size), dims);
+    if size.0 == 0 || size.1 == 0 {
+        return 1.;
+    }
+    // Pixels of the blocks overlapping [start, start + len)
+    // along an axis
+    let covered = |start: usize, len: usize, block: usize, dim: usize| {
+        let block = block.max(1);
+        let first = start / block * block;
+        let last = ((start + len + block - 1) / block * block).min(dim);
+        last - first
+    };
+    let cols = covered(off.0 as usize, size.0, block_size.0, dims.0);
+    let rows = covered(off.1 as usize, size.1, block_size.1, dims.1);
+    (cols * rows) as f64 / (size.0 * size.1) as f64
+}
+
+/// Reads with a [`read_amplification`] above this are
+/// reported by debug builds (see [`warn_unaligned`]).
+#[cfg(debug_assertions)]
+const UNALIGNED_READ_THRESHOLD: f64 = 2.;
+
+/// Report a read of a band that is not aligned to its
+/// blocks (see [`read_amplification`]), eg. due to a chunk
+/// size smaller than the block height. Only in debug
+/// builds, and if the environment variable
+/// `RASTERS_WARN_UNALIGNED` is set; the first such read
+/// (of the process) is printed to `stderr`.
+#[cfg(debug_assertions)]
+fn warn_unaligned(band: &RasterBand<'_>, off: RasterOffset, size: RasterDims) {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if WARNED.load(Ordering::Relaxed) || std::env::var_os("RASTERS_WARN_UNALIGNED").is_none() {
+        return;
+    }
+    let block_size = band.block_size();
+    let amplification = read_amplification((off, size), block_size, band.size());
+    if amplification > UNALIGNED_READ_THRESHOLD && !WARNED.swap(true, Ordering::Relaxed) {
+        eprintln!(
+            "warning: reading window @ ({},{}) of dimension ({}x{}) reads {:.1}x the pixels, \
+             as it is not aligned to the blocks ({}x{}) of the band; use chunks of a multiple \
+             of the block height",
+            off.0, off.1, size.0, size.1, amplification, block_size.0, block_size.1
+        );
+    }
 }
 
 /// A `ChunkReader` that is `Send`, but not `Sync`. Obtains
-/// a `RasterBand` handle for each read.
-pub struct DatasetReader(pub Dataset, pub isize);
+/// a `RasterBand` handle for each read, as the handle
+/// borrows the dataset.
+///
+/// Prefer [`DatasetReader::new`], which checks that the
+/// band is in the dataset. If the tuple is constructed
+/// directly, an invalid band is only reported by each read.
+/// Rust can't deprecate the constructor of a tuple struct,
+/// so only the fields are deprecated: use the accessors
+/// instead; the fields will be private in a future release.
+pub struct DatasetReader(
+    #[deprecated(note = "use DatasetReader::dataset or DatasetReader::into_dataset")] pub Dataset,
+    #[deprecated(note = "use DatasetReader::band_index")] pub isize,
+);
 
+#[allow(deprecated)]
 impl ChunkReader for DatasetReader {
     fn read_into_slice<T>(&self, out: &mut [T], off: RasterOffset, size: RasterDims) -> Result<()>
     where
@@ -95,14 +560,324 @@ impl ChunkReader for DatasetReader {
         let band = self.0.rasterband(self.1)?;
         ChunkReader::read_into_slice(&band, out, off, size)
     }
+
+    fn read_resampled_into_slice<T>(
+        &self,
+        out: &mut [T],
+        off: RasterOffset,
+        src_size: RasterDims,
+        out_size: RasterDims,
+        alg: ResampleAlg,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        let band = self.0.rasterband(self.1)?;
+        band.read_resampled_into_slice(out, off, src_size, out_size, alg)
+    }
+
+    fn raster_size(&self) -> Result<RasterDims> {
+        Ok(self.0.rasterband(self.1)?.size())
+    }
+
+    fn no_data_value(&self) -> Result<Option<f64>> {
+        Ok(self.0.rasterband(self.1)?.no_data_value())
+    }
+
+    fn band_type(&self) -> Result<GdalDataType> {
+        Ok(self.0.rasterband(self.1)?.band_type())
+    }
+
+    fn read_mask_into_slice(
+        &self,
+        out: &mut [u8],
+        off: RasterOffset,
+        size: RasterDims,
+    ) -> Result<bool> {
+        let band = self.0.rasterband(self.1)?;
+        band.read_mask_into_slice(out, off, size)
+    }
+}
+
+#[allow(deprecated)]
+impl DatasetReader {
+    /// Construct a reader of `band` of `ds`. Returns an
+    /// error if the band is not in the dataset.
+    pub fn new(ds: Dataset, band: isize) -> Result<Self> {
+        ds.rasterband(band)
+            .with_context(|| format!("opening rasterband {}", band))?;
+        Ok(DatasetReader(ds, band))
+    }
+
+    /// The dataset read from.
+    pub fn dataset(&self) -> &Dataset {
+        &self.0
+    }
+
+    /// The (1-based) index of the band read from.
+    pub fn band_index(&self) -> isize {
+        self.1
+    }
+
+    /// The dataset read from, consuming the reader.
+    pub fn into_dataset(self) -> Dataset {
+        self.0
+    }
+
+    /// Read a window from multiple bands of the dataset into
+    /// an [`Array3`] of shape `(bands, rows, cols)`. Returns
+    /// an error if any of the band indices is not in the
+    /// dataset.
+    pub fn read_bands_as_array<T>(
+        &self,
+        bands: &[isize],
+        off: RasterOffset,
+        size: RasterDims,
+    ) -> Result<Array3<T>>
+    where
+        T: GdalType + Copy,
+    {
+        read_bands(&self.0, bands, off, size)
+    }
+}
+
+/// Read a window of `bands` of `ds` into an [`Array3`] of
+/// shape `(bands, rows, cols)`, with one read per band.
+fn read_bands<T>(
+    ds: &Dataset,
+    bands: &[isize],
+    off: RasterOffset,
+    size: RasterDims,
+) -> Result<Array3<T>>
+where
+    T: GdalType + Copy,
+{
+    let count = ds.raster_count();
+    for &band_idx in bands {
+        if band_idx < 1 || band_idx > count {
+            bail!(
+                "band index {} out of range: dataset has {} band(s)",
+                band_idx,
+                count
+            );
+        }
+    }
+
+    let band_size = size.0 * size.1;
+    let bufsize = bands.len() * band_size;
+    let mut buf = Vec::with_capacity(bufsize);
+
+    // Safety: paradigm suggested in std docs
+    // https://doc.rust-lang.org/std/vec/struct.Vec.html#examples-18
+    unsafe {
+        buf.set_len(bufsize);
+    }
+
+    for (band_buf, &band_idx) in buf.chunks_mut(band_size.max(1)).zip(bands) {
+        let band = ds.rasterband(band_idx)?;
+        ChunkReader::read_into_slice(&band, band_buf, off, size)
+            .with_context(|| format!("reading band {}", band_idx))?;
+    }
+    Ok(Array3::from_shape_vec((bands.len(), size.1, size.0), buf)?)
+}
+
+/// Reads chunks of multiple bands of a dataset, with a
+/// single dataset handle and one read per band. Like
+/// [`DatasetReader`], this is `Send`, but not `Sync`.
+pub struct MultiBandReader(pub Dataset, pub Vec<isize>);
+
+impl MultiBandReader {
+    /// Construct a reader of the given `bands` of `ds`.
+    /// Returns an error if any of the band indices is not in
+    /// the dataset.
+    pub fn new(ds: Dataset, bands: Vec<isize>) -> Result<Self> {
+        let count = ds.raster_count();
+        if let Some(band_idx) = bands.iter().find(|&&b| b < 1 || b > count) {
+            bail!(
+                "band index {} out of range: dataset has {} band(s)",
+                band_idx,
+                count
+            );
+        }
+        Ok(MultiBandReader(ds, bands))
+    }
+
+    /// Construct a reader of all the bands of `ds`.
+    pub fn all_bands(ds: Dataset) -> Self {
+        let bands = (1..=ds.raster_count()).collect();
+        MultiBandReader(ds, bands)
+    }
+
+    /// Read a window of the bands into an [`Array3`] of
+    /// shape `(bands, rows, cols)`.
+    pub fn read_as_array<T>(&self, off: RasterOffset, size: RasterDims) -> Result<Array3<T>>
+    where
+        T: GdalType + Copy,
+    {
+        read_bands(&self.0, &self.1, off, size)
+    }
+
+    /// Read a chunk (including padding) of the bands into
+    /// an [`Array3`] of shape `(bands, rows, cols)`.
+    pub fn read_chunk<T>(&self, chunk: ChunkWindow<'_>) -> Result<Array3<T>>
+    where
+        T: GdalType + Copy,
+    {
+        let (off, size) = chunk.raster_window();
+        self.read_as_array(off, size)
+    }
+
+    /// Read a chunk into a pixel-interleaved [`Array3`] of
+    /// shape `(rows, cols, bands)` (in standard layout), so
+    /// that the values of each pixel are contiguous.
+    pub fn read_chunk_interleaved<T>(&self, chunk: ChunkWindow<'_>) -> Result<Array3<T>>
+    where
+        T: GdalType + Copy,
+    {
+        let arr = self.read_chunk(chunk)?.permuted_axes([1, 2, 0]);
+        Ok(arr.as_standard_layout().into_owned())
+    }
+}
+
+/// A `ChunkReader` of an overview (a decimated copy, eg.
+/// built by `gdaladdo`) of a band of a dataset. Offsets and
+/// sizes of reads are in the pixels of the overview, whose
+/// dimensions are given by [`dims`]; use [`scale`] to
+/// translate from the pixels of the full resolution band.
+/// Like [`DatasetReader`], this is `Send`, but not `Sync`.
+///
+/// [`dims`]: OverviewReader::dims
+/// [`scale`]: OverviewReader::scale
+pub struct OverviewReader {
+    ds: Dataset,
+    band: isize,
+    level: isize,
+    dims: RasterDims,
+}
+
+impl OverviewReader {
+    /// Construct a reader of overview `level` (0-based,
+    /// finest first) of `band` of `ds`. Returns an error if
+    /// the band does not have such an overview.
+    pub fn new(ds: Dataset, band: isize, level: isize) -> Result<Self> {
+        let dims = {
+            let rb = ds
+                .rasterband(band)
+                .with_context(|| format!("opening rasterband {}", band))?;
+            let count = rb.overview_count()? as isize;
+            if level < 0 || level >= count {
+                bail!(
+                    "overview level {} out of range: band {} has {} overview(s)",
+                    level,
+                    band,
+                    count
+                );
+            }
+            rb.overview(level)?.size()
+        };
+        Ok(OverviewReader {
+            ds,
+            band,
+            level,
+            dims,
+        })
+    }
+
+    /// The dimensions of the overview.
+    pub fn dims(&self) -> RasterDims {
+        self.dims
+    }
+
+    /// The overview level read.
+    pub fn level(&self) -> isize {
+        self.level
+    }
+
+    /// The ratio of the full resolution dimensions to those
+    /// of the overview, along each axis. Divide a full
+    /// resolution pixel position by this to obtain the
+    /// position in the overview.
+    pub fn scale(&self) -> (f64, f64) {
+        let (width, height) = self.ds.raster_size();
+        (
+            width as f64 / self.dims.0 as f64,
+            height as f64 / self.dims.1 as f64,
+        )
+    }
+
+    /// The underlying dataset.
+    pub fn dataset(&self) -> &Dataset {
+        &self.ds
+    }
+
+    /// Consume the reader, and return the dataset.
+    pub fn into_inner(self) -> Dataset {
+        self.ds
+    }
+
+    pub(crate) fn overview_band(&self) -> Result<RasterBand<'_>> {
+        Ok(self.ds.rasterband(self.band)?.overview(self.level)?)
+    }
+}
+
+impl ChunkReader for OverviewReader {
+    fn read_into_slice<T>(&self, out: &mut [T], off: RasterOffset, size: RasterDims) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        let band = self.overview_band()?;
+        ChunkReader::read_into_slice(&band, out, off, size)
+            .with_context(|| format!("reading overview level {}", self.level))
+    }
+
+    fn read_resampled_into_slice<T>(
+        &self,
+        out: &mut [T],
+        off: RasterOffset,
+        src_size: RasterDims,
+        out_size: RasterDims,
+        alg: ResampleAlg,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        let band = self.overview_band()?;
+        band.read_resampled_into_slice(out, off, src_size, out_size, alg)
+            .with_context(|| format!("reading overview level {}", self.level))
+    }
+
+    /// The dimensions of the overview.
+    fn raster_size(&self) -> Result<RasterDims> {
+        Ok(self.dims)
+    }
+
+    /// The no-data value of the full resolution band.
+    fn no_data_value(&self) -> Result<Option<f64>> {
+        Ok(self.ds.rasterband(self.band)?.no_data_value())
+    }
+
+    fn band_type(&self) -> Result<GdalDataType> {
+        Ok(self.overview_band()?.band_type())
+    }
+
+    fn read_mask_into_slice(
+        &self,
+        out: &mut [u8],
+        off: RasterOffset,
+        size: RasterDims,
+    ) -> Result<bool> {
+        let band = self.overview_band()?;
+        band.read_mask_into_slice(out, off, size)
+    }
 }
 
 /// A `ChunkReader` that is both `Send` and `Sync`. Opens
 /// the dataset for each read. `P` may be set to [ `Path` ]
-/// or a `PathBuf` for a `Send + Sync` reader.
+/// or a `PathBuf` for a `Send + Sync` reader. See
+/// [`PooledReader`] and [`ThreadLocalReader`] to re-use the
+/// opened datasets across reads.
 pub struct RasterPathReader<'a, P: ?Sized>(pub &'a P, pub isize);
 
-use std::path::Path;
 impl<'a, P> ChunkReader for RasterPathReader<'a, P>
 where
     P: AsRef<Path> + ?Sized,
@@ -113,4 +888,1759 @@ where
     {
         DatasetReader(Dataset::open(self.0.as_ref())?, self.1).read_into_slice(out, off, size)
     }
+
+    fn read_resampled_into_slice<T>(
+        &self,
+        out: &mut [T],
+        off: RasterOffset,
+        src_size: RasterDims,
+        out_size: RasterDims,
+        alg: ResampleAlg,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        DatasetReader(Dataset::open(self.0.as_ref())?, self.1)
+            .read_resampled_into_slice(out, off, src_size, out_size, alg)
+    }
+
+    fn raster_size(&self) -> Result<RasterDims> {
+        DatasetReader(Dataset::open(self.0.as_ref())?, self.1).raster_size()
+    }
+
+    fn no_data_value(&self) -> Result<Option<f64>> {
+        DatasetReader(Dataset::open(self.0.as_ref())?, self.1).no_data_value()
+    }
+
+    fn band_type(&self) -> Result<GdalDataType> {
+        DatasetReader(Dataset::open(self.0.as_ref())?, self.1).band_type()
+    }
+
+    fn read_mask_into_slice(
+        &self,
+        out: &mut [u8],
+        off: RasterOffset,
+        size: RasterDims,
+    ) -> Result<bool> {
+        DatasetReader(Dataset::open(self.0.as_ref())?, self.1).read_mask_into_slice(out, off, size)
+    }
+}
+
+/// A `ChunkReader` that is both `Send` and `Sync`, and
+/// re-uses open [`Dataset`] handles across reads. Each read
+/// checks out a handle from a pool, opening a new one if
+/// none is available, and returns it to the pool after the
+/// read. The pool retains at most `pool_size` handles
+/// (typically the number of threads reading).
+///
+/// A failure to open the dataset, or to read from a handle,
+/// is reported as an error for that read only: the failed
+/// handle is discarded, and subsequent reads re-open the
+/// dataset as necessary.
+pub struct PooledReader {
+    path: PathBuf,
+    band: isize,
+    pool_size: usize,
+    pool: Mutex<Vec<Dataset>>,
+}
+
+impl PooledReader {
+    /// Construct a reader of `band` of the raster at `path`,
+    /// retaining at most `pool_size` open handles. Opens the
+    /// dataset once to validate the path and band.
+    pub fn new<P: AsRef<Path>>(path: P, band: isize, pool_size: usize) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let ds =
+            Dataset::open(&path).with_context(|| format!("opening dataset {}", path.display()))?;
+        ds.rasterband(band)
+            .with_context(|| format!("opening rasterband {}", band))?;
+
+        let mut pool = Vec::with_capacity(pool_size);
+        if pool_size > 0 {
+            pool.push(ds);
+        }
+        Ok(PooledReader {
+            path,
+            band,
+            pool_size,
+            pool: Mutex::new(pool),
+        })
+    }
+
+    /// The number of open handles currently in the pool.
+    pub fn pooled(&self) -> usize {
+        self.pool.lock().map(|pool| pool.len()).unwrap_or(0)
+    }
+
+    fn checkout(&self) -> Result<Dataset> {
+        let pooled = self
+            .pool
+            .lock()
+            .map_err(|_| format_err!("reader pool poisoned"))?
+            .pop();
+        match pooled {
+            Some(ds) => Ok(ds),
+            None => Ok(Dataset::open(&self.path)
+                .with_context(|| format!("opening dataset {}", self.path.display()))?),
+        }
+    }
+
+    fn checkin(&self, ds: Dataset) {
+        if let Ok(mut pool) = self.pool.lock() {
+            if pool.len() < self.pool_size {
+                pool.push(ds);
+            }
+        }
+    }
+}
+
+impl ChunkReader for PooledReader {
+    fn read_into_slice<T>(&self, out: &mut [T], off: RasterOffset, size: RasterDims) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        let reader = DatasetReader(self.checkout()?, self.band);
+        reader.read_into_slice(out, off, size)?;
+        self.checkin(reader.into_dataset());
+        Ok(())
+    }
+
+    fn read_resampled_into_slice<T>(
+        &self,
+        out: &mut [T],
+        off: RasterOffset,
+        src_size: RasterDims,
+        out_size: RasterDims,
+        alg: ResampleAlg,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        let reader = DatasetReader(self.checkout()?, self.band);
+        reader.read_resampled_into_slice(out, off, src_size, out_size, alg)?;
+        self.checkin(reader.into_dataset());
+        Ok(())
+    }
+
+    fn raster_size(&self) -> Result<RasterDims> {
+        let reader = DatasetReader(self.checkout()?, self.band);
+        let size = reader.raster_size()?;
+        self.checkin(reader.into_dataset());
+        Ok(size)
+    }
+
+    fn no_data_value(&self) -> Result<Option<f64>> {
+        let reader = DatasetReader(self.checkout()?, self.band);
+        let no_val = reader.no_data_value()?;
+        self.checkin(reader.into_dataset());
+        Ok(no_val)
+    }
+
+    fn band_type(&self) -> Result<GdalDataType> {
+        let reader = DatasetReader(self.checkout()?, self.band);
+        let band_type = reader.band_type()?;
+        self.checkin(reader.into_dataset());
+        Ok(band_type)
+    }
+
+    fn read_mask_into_slice(
+        &self,
+        out: &mut [u8],
+        off: RasterOffset,
+        size: RasterDims,
+    ) -> Result<bool> {
+        let reader = DatasetReader(self.checkout()?, self.band);
+        let has_mask = reader.read_mask_into_slice(out, off, size)?;
+        self.checkin(reader.into_dataset());
+        Ok(has_mask)
+    }
+}
+
+/// A `ChunkReader` that is both `Send` and `Sync`, and
+/// keeps an open [`Dataset`] handle per thread. The first
+/// read (of a path and band) on a thread opens the
+/// dataset, and caches the handle in a thread-local; the
+/// subsequent reads on the thread re-use it. Thus, no
+/// handle is shared across threads, while the cost of
+/// opening the dataset is paid once per thread, instead of
+/// once per read as with [`RasterPathReader`].
+///
+/// Unlike a [`DatasetReader`] per thread (eg. via `rayon`'s
+/// `map_init`), the reader may be shared by reference with
+/// code that is unaware of the threads. The tradeoff is
+/// that the cached handles are only closed when their
+/// thread exits (for a thread pool, at the end of the
+/// program), or by [`ThreadLocalReader::close`] on that
+/// thread; so a file re-written at the same path is not
+/// re-opened. A failed read discards the handle of the
+/// thread, so the next read re-opens the dataset.
+pub struct ThreadLocalReader {
+    path: PathBuf,
+    band: isize,
+}
+
+/// The readers cached by a thread, keyed by the path and
+/// band.
+type ThreadReaders = std::cell::RefCell<std::collections::HashMap<(PathBuf, isize), DatasetReader>>;
+
+thread_local! {
+    static THREAD_READERS: ThreadReaders = Default::default();
+}
+
+impl ThreadLocalReader {
+    /// Construct a reader of `band` of the raster at `path`.
+    /// Opens the dataset (on the current thread) to
+    /// validate the path and band.
+    pub fn new<P: AsRef<Path>>(path: P, band: isize) -> Result<Self> {
+        let reader = ThreadLocalReader {
+            path: path.as_ref().to_path_buf(),
+            band,
+        };
+        reader.with_reader(|reader| {
+            reader
+                .dataset()
+                .rasterband(reader.band_index())
+                .with_context(|| format!("opening rasterband {}", reader.band_index()))?;
+            Ok(())
+        })?;
+        Ok(reader)
+    }
+
+    /// Close the handle cached by the current thread, if
+    /// any.
+    pub fn close(&self) {
+        let key = (self.path.clone(), self.band);
+        THREAD_READERS.with(|readers| readers.borrow_mut().remove(&key));
+    }
+
+    /// Call `f` with the reader of the current thread,
+    /// opening the dataset if necessary. The reader is taken
+    /// out of the cache during the call, and only returned
+    /// if `f` succeeds.
+    fn with_reader<O, F>(&self, f: F) -> Result<O>
+    where
+        F: FnOnce(&DatasetReader) -> Result<O>,
+    {
+        let key = (self.path.clone(), self.band);
+        let cached = THREAD_READERS.with(|readers| readers.borrow_mut().remove(&key));
+        let reader = match cached {
+            Some(reader) => reader,
+            None => DatasetReader(
+                Dataset::open(&self.path)
+                    .with_context(|| format!("opening dataset {}", self.path.display()))?,
+                self.band,
+            ),
+        };
+        let output = f(&reader)?;
+        THREAD_READERS.with(|readers| readers.borrow_mut().insert(key, reader));
+        Ok(output)
+    }
+}
+
+impl ChunkReader for ThreadLocalReader {
+    fn read_into_slice<T>(&self, out: &mut [T], off: RasterOffset, size: RasterDims) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        self.with_reader(|reader| reader.read_into_slice(out, off, size))
+    }
+
+    fn read_resampled_into_slice<T>(
+        &self,
+        out: &mut [T],
+        off: RasterOffset,
+        src_size: RasterDims,
+        out_size: RasterDims,
+        alg: ResampleAlg,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        self.with_reader(|reader| {
+            reader.read_resampled_into_slice(out, off, src_size, out_size, alg)
+        })
+    }
+
+    fn raster_size(&self) -> Result<RasterDims> {
+        self.with_reader(|reader| reader.raster_size())
+    }
+
+    fn no_data_value(&self) -> Result<Option<f64>> {
+        self.with_reader(|reader| reader.no_data_value())
+    }
+
+    fn band_type(&self) -> Result<GdalDataType> {
+        self.with_reader(|reader| reader.band_type())
+    }
+
+    fn read_mask_into_slice(
+        &self,
+        out: &mut [u8],
+        off: RasterOffset,
+        size: RasterDims,
+    ) -> Result<bool> {
+        self.with_reader(|reader| reader.read_mask_into_slice(out, off, size))
+    }
+}
+
+/// A `ChunkReader` that caches the data read from an
+/// underlying reader, evicting the least recently used
+/// first. The reader is `Send` (resp. `Sync`) if the
+/// underlying reader is.
+///
+/// Constructed with [`CachingReader::new`], it caches the
+/// last few windows read, and only exact matches of the
+/// element type, offset and size of a window are served
+/// from the cache. Useful when the same windows are read
+/// repeatedly (eg. by multiple passes over the chunks).
+///
+/// Constructed with [`CachingReader::with_blocks`], it
+/// caches blocks of full-width rows instead, and serves any
+/// window within the raster from the blocks it spans. Thus,
+/// overlapping windows (eg. padded chunks, or windows
+/// aligned to another raster) share the blocks they read.
+/// Windows extending past the raster, and blocks larger
+/// than the budget, are read directly.
+pub struct CachingReader<R> {
+    reader: R,
+    mode: CacheMode,
+    cache: Mutex<Cache>,
+}
+
+/// What a [`CachingReader`] caches.
+enum CacheMode {
+    /// The last `capacity` windows read.
+    Windows { capacity: usize },
+    /// Blocks of `block_height` rows of the raster (of
+    /// dimensions `dims`), within `budget` bytes.
+    Blocks {
+        dims: RasterDims,
+        block_height: usize,
+        budget: usize,
+    },
+}
+
+/// The element type, offset and size of a cached window.
+/// A block is cached as the window of its rows.
+type CacheKey = (&'static str, RasterOffset, RasterDims);
+
+/// Windows of a [`CachingReader`], least recently used
+/// first.
+#[derive(Default)]
+struct Cache {
+    entries: VecDeque<(CacheKey, Vec<u8>)>,
+    bytes: usize,
+}
+
+impl Cache {
+    fn get(&mut self, key: &CacheKey) -> Option<&[u8]> {
+        let pos = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(pos).unwrap();
+        self.entries.push_back(entry);
+        self.entries.back().map(|(_, buf)| &buf[..])
+    }
+
+    /// Insert a window, and evict the least recently used
+    /// ones until at most `max_entries` windows of
+    /// `max_bytes` in total remain.
+    fn insert(&mut self, key: CacheKey, buf: Vec<u8>, max_entries: usize, max_bytes: usize) {
+        if self.entries.iter().any(|(k, _)| *k == key) {
+            return;
+        }
+        self.bytes += buf.len();
+        self.entries.push_back((key, buf));
+        while self.entries.len() > max_entries || self.bytes > max_bytes {
+            match self.entries.pop_front() {
+                Some((_, buf)) => self.bytes -= buf.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+impl<R: ChunkReader> CachingReader<R> {
+    /// Wrap `reader` with a cache holding the last
+    /// `capacity` windows read.
+    pub fn new(reader: R, capacity: usize) -> Self {
+        CachingReader {
+            reader,
+            mode: CacheMode::Windows { capacity },
+            cache: Mutex::new(Cache::default()),
+        }
+    }
+
+    /// Wrap `reader` with a cache of blocks of
+    /// `block_height` rows, holding at most `budget` bytes.
+    /// Returns an error if the reader does not know the
+    /// raster dimensions, or if `block_height` is zero.
+    pub fn with_blocks(reader: R, block_height: usize, budget: usize) -> Result<Self> {
+        if block_height == 0 {
+            bail!("block height must be positive");
+        }
+        let dims = reader.raster_size()?;
+        Ok(CachingReader {
+            reader,
+            mode: CacheMode::Blocks {
+                dims,
+                block_height,
+                budget,
+            },
+            cache: Mutex::new(Cache::default()),
+        })
+    }
+
+    /// The number of rows of each cached block, if the
+    /// cache is block-aligned.
+    pub fn block_height(&self) -> Option<usize> {
+        match self.mode {
+            CacheMode::Windows { .. } => None,
+            CacheMode::Blocks { block_height, .. } => Some(block_height),
+        }
+    }
+
+    /// The maximum number of bytes held by the cache, if it
+    /// is block-aligned.
+    pub fn budget(&self) -> Option<usize> {
+        match self.mode {
+            CacheMode::Windows { .. } => None,
+            CacheMode::Blocks { budget, .. } => Some(budget),
+        }
+    }
+
+    /// The number of bytes currently cached.
+    pub fn cached_bytes(&self) -> usize {
+        self.cache.lock().map(|cache| cache.bytes).unwrap_or(0)
+    }
+
+    /// Consume the cache, and return the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Cache>> {
+        self.cache
+            .lock()
+            .map_err(|_| format_err!("reader cache poisoned"))
+    }
+
+    fn read_window<T>(
+        &self,
+        out: &mut [T],
+        off: RasterOffset,
+        size: RasterDims,
+        capacity: usize,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        let key = (std::any::type_name::<T>(), off, size);
+        if let Some(buf) = self.lock()?.get(&key) {
+            as_bytes_mut(out).copy_from_slice(buf);
+            return Ok(());
+        }
+
+        self.reader.read_into_slice(out, off, size)?;
+        if capacity > 0 {
+            let buf = as_bytes_mut(out).to_vec();
+            self.lock()?.insert(key, buf, capacity, usize::MAX);
+        }
+        Ok(())
+    }
+
+    fn read_blocks<T>(
+        &self,
+        out: &mut [T],
+        off: RasterOffset,
+        size: RasterDims,
+        (width, height): RasterDims,
+        block_height: usize,
+        budget: usize,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        let elem = std::mem::size_of::<T>();
+        let within = off.0 >= 0
+            && off.1 >= 0
+            && off.0 as usize + size.0 <= width
+            && off.1 as usize + size.1 <= height;
+        if !within || size.0 * size.1 == 0 || width * block_height * elem > budget {
+            return self.reader.read_into_slice(out, off, size);
+        }
+
+        let (x, y) = (off.0 as usize, off.1 as usize);
+        let row_bytes = size.0 * elem;
+        let out = as_bytes_mut(out);
+        for idx in y / block_height..=(y + size.1 - 1) / block_height {
+            let y_0 = idx * block_height;
+            let rows = block_height.min(height - y_0);
+
+            // Copy the rows of the window within the block
+            let copy = |block: &[u8], out: &mut [u8]| {
+                for row in y.max(y_0)..(y + size.1).min(y_0 + rows) {
+                    let src = ((row - y_0) * width + x) * elem;
+                    let dst = (row - y) * row_bytes;
+                    out[dst..dst + row_bytes].copy_from_slice(&block[src..src + row_bytes]);
+                }
+            };
+
+            let key = (std::any::type_name::<T>(), (0, y_0 as isize), (width, rows));
+            if let Some(block) = self.lock()?.get(&key) {
+                copy(block, out);
+                continue;
+            }
+
+            // Read without holding the lock
+            let mut data = self.reader.read_as_array::<T>(key.1, key.2)?.into_raw_vec();
+            let block = as_bytes_mut(&mut data).to_vec();
+            copy(&block, out);
+            self.lock()?.insert(key, block, usize::MAX, budget);
+        }
+        Ok(())
+    }
+}
+
+impl<R: ChunkReader> ChunkReader for CachingReader<R> {
+    fn read_into_slice<T>(&self, out: &mut [T], off: RasterOffset, size: RasterDims) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        match self.mode {
+            CacheMode::Windows { capacity } => self.read_window(out, off, size, capacity),
+            CacheMode::Blocks {
+                dims,
+                block_height,
+                budget,
+            } => self.read_blocks(out, off, size, dims, block_height, budget),
+        }
+    }
+
+    /// Resampled reads are not cached.
+    fn read_resampled_into_slice<T>(
+        &self,
+        out: &mut [T],
+        off: RasterOffset,
+        src_size: RasterDims,
+        out_size: RasterDims,
+        alg: ResampleAlg,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        self.reader
+            .read_resampled_into_slice(out, off, src_size, out_size, alg)
+    }
+
+    fn raster_size(&self) -> Result<RasterDims> {
+        match self.mode {
+            CacheMode::Windows { .. } => self.reader.raster_size(),
+            CacheMode::Blocks { dims, .. } => Ok(dims),
+        }
+    }
+
+    fn no_data_value(&self) -> Result<Option<f64>> {
+        self.reader.no_data_value()
+    }
+
+    fn band_type(&self) -> Result<GdalDataType> {
+        self.reader.band_type()
+    }
+
+    /// The mask is not cached.
+    fn read_mask_into_slice(
+        &self,
+        out: &mut [u8],
+        off: RasterOffset,
+        size: RasterDims,
+    ) -> Result<bool> {
+        self.reader.read_mask_into_slice(out, off, size)
+    }
+}
+
+/// Wraps a `ChunkReader`, replacing its no-data value by
+/// NaN, so that processing only needs to check for NaN.
+/// Only `f64` reads are supported, so the adaptor provides
+/// the reading helpers for `f64` arrays (instead of
+/// implementing `ChunkReader`).
+pub struct NodataToNan<R> {
+    reader: R,
+    no_val: Option<f64>,
+}
+
+impl<R: ChunkReader> NodataToNan<R> {
+    /// Wrap `reader`, using the no-data value it reports
+    /// (see [`ChunkReader::no_data_value`]).
+    pub fn new(reader: R) -> Result<Self> {
+        let no_val = reader.no_data_value()?;
+        Ok(NodataToNan { reader, no_val })
+    }
+
+    /// Wrap `reader`, replacing `no_val` by NaN, regardless
+    /// of the no-data value reported by the reader.
+    pub fn with_value(reader: R, no_val: f64) -> Self {
+        NodataToNan {
+            reader,
+            no_val: Some(no_val),
+        }
+    }
+
+    /// The no-data value replaced by NaN, if any.
+    pub fn replaced_value(&self) -> Option<f64> {
+        self.no_val
+    }
+
+    /// Consume the adaptor, and return the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Read a window (see [`ChunkReader::read_as_array`]),
+    /// with the no-data values replaced by NaN.
+    pub fn read_as_array(&self, off: RasterOffset, size: RasterDims) -> Result<Array2<f64>> {
+        let mut data = self.reader.read_as_array::<f64>(off, size)?;
+        self.replace_no_data(&mut data);
+        Ok(data)
+    }
+
+    /// Read a chunk (see [`ChunkReader::read_chunk`]), with
+    /// the no-data values replaced by NaN.
+    pub fn read_chunk(&self, chunk: ChunkWindow<'_>) -> Result<Array2<f64>> {
+        let (off, size) = chunk.raster_window();
+        self.read_as_array(off, size)
+    }
+
+    /// Read a resampled window (see
+    /// [`ChunkReader::read_resampled`]), with the no-data
+    /// values replaced by NaN.
+    pub fn read_resampled(
+        &self,
+        off: RasterOffset,
+        src_size: RasterDims,
+        out_size: RasterDims,
+        alg: ResampleAlg,
+    ) -> Result<Array2<f64>> {
+        let mut data = self
+            .reader
+            .read_resampled::<f64>(off, src_size, out_size, alg)?;
+        self.replace_no_data(&mut data);
+        Ok(data)
+    }
+
+    /// Replace the no-data value in `data` by NaN.
+    fn replace_no_data(&self, data: &mut Array2<f64>) {
+        let no_val = match self.no_val {
+            Some(no_val) if !no_val.is_nan() => no_val,
+            _ => return,
+        };
+        for val in data.iter_mut().filter(|val| **val == no_val) {
+            *val = f64::NAN;
+        }
+    }
+}
+
+/// A `ChunkReader` that overrides the no-data value of an
+/// underlying reader, eg. when a raster uses a sentinel
+/// value (like `-9999`) without declaring it. The values
+/// read are unchanged; only the validity of the pixels (see
+/// [`ChunkReader::read_as_array_masked`]) is affected.
+pub struct NodataOverride<R> {
+    reader: R,
+    no_val: Option<f64>,
+}
+
+impl<R: ChunkReader> NodataOverride<R> {
+    /// Wrap `reader`, reporting `no_val` (which may be NaN)
+    /// as its no-data value if given, and the value of the
+    /// reader otherwise.
+    pub fn new(reader: R, no_val: Option<f64>) -> Self {
+        NodataOverride { reader, no_val }
+    }
+
+    /// Consume the adaptor, and return the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: ChunkReader> ChunkReader for NodataOverride<R> {
+    fn read_into_slice<T>(&self, out: &mut [T], off: RasterOffset, size: RasterDims) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        self.reader.read_into_slice(out, off, size)
+    }
+
+    fn read_resampled_into_slice<T>(
+        &self,
+        out: &mut [T],
+        off: RasterOffset,
+        src_size: RasterDims,
+        out_size: RasterDims,
+        alg: ResampleAlg,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        self.reader
+            .read_resampled_into_slice(out, off, src_size, out_size, alg)
+    }
+
+    fn raster_size(&self) -> Result<RasterDims> {
+        self.reader.raster_size()
+    }
+
+    fn no_data_value(&self) -> Result<Option<f64>> {
+        match self.no_val {
+            Some(no_val) => Ok(Some(no_val)),
+            None => self.reader.no_data_value(),
+        }
+    }
+
+    fn band_type(&self) -> Result<GdalDataType> {
+        self.reader.band_type()
+    }
+
+    fn read_mask_into_slice(
+        &self,
+        out: &mut [u8],
+        off: RasterOffset,
+        size: RasterDims,
+    ) -> Result<bool> {
+        self.reader.read_mask_into_slice(out, off, size)
+    }
+}
+
+/// A `ChunkReader` of an in-memory array, with an optional
+/// no-data value. Useful to test chunk processing without
+/// creating datasets. Reads must be of the element type
+/// `T` of the array, and within its bounds. The array is
+/// indexed as `(row, col)`, like the arrays read.
+pub struct MemoryReader<T>(pub Array2<T>, pub Option<f64>);
+
+impl<T: GdalType + Copy> ChunkReader for MemoryReader<T> {
+    fn read_into_slice<U>(&self, out: &mut [U], off: RasterOffset, size: RasterDims) -> Result<()>
+    where
+        U: GdalType + Copy,
+    {
+        if T::datatype() != U::datatype() || std::mem::size_of::<T>() != std::mem::size_of::<U>() {
+            bail!(
+                "cannot read {} data as {}",
+                std::any::type_name::<T>(),
+                std::any::type_name::<U>()
+            );
+        }
+        let (rows, cols) = self.0.dim();
+        if off.0 < 0
+            || off.1 < 0
+            || off.0 as usize + size.0 > cols
+            || off.1 as usize + size.1 > rows
+        {
+            bail!(
+                "window @ ({},{}) of dimension ({}x{}) out of bounds of ({}x{})",
+                off.0,
+                off.1,
+                size.0,
+                size.1,
+                cols,
+                rows
+            );
+        }
+        if out.len() != size.0 * size.1 {
+            bail!(
+                "buffer of length {} does not match dimension ({}x{})",
+                out.len(),
+                size.0,
+                size.1
+            );
+        }
+
+        // Safety: `U` has the GDAL data type, and the size of
+        // `T` as checked above, so is the same primitive.
+        let out = unsafe { std::slice::from_raw_parts_mut(out.as_mut_ptr() as *mut T, out.len()) };
+        let (x, y) = (off.0 as usize, off.1 as usize);
+        let window = self.0.slice(ndarray::s![y..y + size.1, x..x + size.0]);
+        for (out, &val) in out.iter_mut().zip(window.iter()) {
+            *out = val;
+        }
+        Ok(())
+    }
+
+    fn raster_size(&self) -> Result<RasterDims> {
+        let (rows, cols) = self.0.dim();
+        Ok((cols, rows))
+    }
+
+    fn no_data_value(&self) -> Result<Option<f64>> {
+        Ok(self.1)
+    }
+
+    fn band_type(&self) -> Result<GdalDataType> {
+        Ok(T::datatype())
+    }
+}
+
+/// Policy to retry failing operations, eg. reads of remote
+/// rasters (`/vsicurl/`, `/vsis3/`, etc.) that fail due to
+/// transient network errors. The delay between attempts
+/// starts at the backoff, and doubles after each attempt.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    max_attempts: usize,
+    backoff: Duration,
+    on_retry: Option<RetryHook>,
+}
+
+/// A hook called by [`RetryPolicy::run`] before each retry,
+/// with the description of the operation, the (1-based)
+/// number of the failed attempt, its error, and the delay
+/// before the retry. Eg. to log the transient failures.
+pub type RetryHook = fn(&str, usize, &crate::Error, Duration);
+
+impl RetryPolicy {
+    /// Construct a policy making at most `max_attempts`
+    /// (at least 1) attempts, with a backoff of 1 second.
+    pub fn new(max_attempts: usize) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            backoff: Duration::from_secs(1),
+            on_retry: None,
+        }
+    }
+
+    /// Set the delay before the first retry.
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Set a hook to call before each retry (see
+    /// [`RetryHook`]). By default, the failures are only
+    /// reported by the final error, if all attempts fail.
+    pub fn with_on_retry(mut self, hook: RetryHook) -> Self {
+        self.on_retry = Some(hook);
+        self
+    }
+
+    /// The maximum number of attempts (incl. the first).
+    pub fn max_attempts(&self) -> usize {
+        self.max_attempts
+    }
+
+    /// The delay before the first retry.
+    pub fn backoff(&self) -> Duration {
+        self.backoff
+    }
+
+    /// Run `f` until it succeeds, or the attempts are
+    /// exhausted. Each failure but the last is passed to the
+    /// hook (if any), and the final error includes those of
+    /// the earlier attempts. `what` describes the operation
+    /// in the messages.
+    pub fn run<T, F>(&self, what: &str, mut f: F) -> Result<T>
+    where
+        F: FnMut() -> Result<T>,
+    {
+        let mut errors = vec![];
+        let mut delay = self.backoff;
+        for attempt in 1..=self.max_attempts {
+            match f() {
+                Ok(val) => return Ok(val),
+                Err(e) => {
+                    if attempt < self.max_attempts {
+                        if let Some(hook) = self.on_retry {
+                            hook(what, attempt, &e, delay);
+                        }
+                        std::thread::sleep(delay);
+                        delay *= 2;
+                    }
+                    errors.push(e);
+                }
+            }
+        }
+
+        let last = errors.pop().expect("at least one attempt");
+        let err = if errors.is_empty() {
+            last
+        } else {
+            let earlier: Vec<_> = errors.iter().map(|e| format!("{:#}", e)).collect();
+            last.context(format!("earlier attempts: {}", earlier.join("; ")))
+        };
+        Err(err.context(format!(
+            "{} failed after {} attempt(s)",
+            what, self.max_attempts
+        )))
+    }
+}
+
+/// Four attempts, with a backoff of 1 second.
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(4)
+    }
+}
+
+/// A `ChunkReader` that retries the failing reads of an
+/// underlying reader (see [`RetryPolicy`]). Useful for
+/// remote rasters, where a transient failure would
+/// otherwise abort a long-running job.
+pub struct RetryingReader<R> {
+    reader: R,
+    policy: RetryPolicy,
+}
+
+impl<R: ChunkReader> RetryingReader<R> {
+    /// Wrap `reader`, retrying reads as per `policy`.
+    pub fn new(reader: R, policy: RetryPolicy) -> Self {
+        RetryingReader { reader, policy }
+    }
+
+    /// The policy of the retries.
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Consume the adaptor, and return the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: ChunkReader> ChunkReader for RetryingReader<R> {
+    fn read_into_slice<T>(&self, out: &mut [T], off: RasterOffset, size: RasterDims) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        let what = format!(
+            "reading window @ ({},{}) of dimension ({}x{})",
+            off.0, off.1, size.0, size.1
+        );
+        self.policy
+            .run(&what, || self.reader.read_into_slice(out, off, size))
+    }
+
+    fn read_resampled_into_slice<T>(
+        &self,
+        out: &mut [T],
+        off: RasterOffset,
+        src_size: RasterDims,
+        out_size: RasterDims,
+        alg: ResampleAlg,
+    ) -> Result<()>
+    where
+        T: GdalType + Copy,
+    {
+        let what = format!(
+            "reading window @ ({},{}) of dimension ({}x{}) resampled to ({}x{})",
+            off.0, off.1, src_size.0, src_size.1, out_size.0, out_size.1
+        );
+        self.policy.run(&what, || {
+            self.reader
+                .read_resampled_into_slice(out, off, src_size, out_size, alg)
+        })
+    }
+
+    fn raster_size(&self) -> Result<RasterDims> {
+        self.policy
+            .run("reading raster size", || self.reader.raster_size())
+    }
+
+    fn no_data_value(&self) -> Result<Option<f64>> {
+        self.policy
+            .run("reading no-data value", || self.reader.no_data_value())
+    }
+
+    fn band_type(&self) -> Result<GdalDataType> {
+        self.policy
+            .run("reading band type", || self.reader.band_type())
+    }
+
+    fn read_mask_into_slice(
+        &self,
+        out: &mut [u8],
+        off: RasterOffset,
+        size: RasterDims,
+    ) -> Result<bool> {
+        self.policy.run("reading mask", || {
+            self.reader.read_mask_into_slice(out, off, size)
+        })
+    }
+}
+
+/// The view of `arr` (the window at `off`) covering the
+/// sub-window `sub`.
+fn sub_window_mut<T>(
+    arr: &mut Array2<T>,
+    off: RasterOffset,
+    sub: RasterWindow,
+) -> ndarray::ArrayViewMut2<'_, T> {
+    let (x, y) = offset_within((off, (arr.ncols(), arr.nrows())), sub)
+        .expect("sub-window outside the array");
+    let (x, y) = (x as usize, y as usize);
+    arr.slice_mut(ndarray::s![y..y + sub.1 .1, x..x + sub.1 .0])
+}
+
+/// Set the validity of each pixel of `data`, read from the
+/// window at `off` of `reader` (see
+/// [`ChunkReader::read_as_array_masked`]).
+fn fill_valid<R, T>(
+    reader: &R,
+    data: ArrayView2<'_, T>,
+    mut valid: ArrayViewMut2<'_, bool>,
+    off: RasterOffset,
+    size: RasterDims,
+) -> Result<()>
+where
+    R: ChunkReader + ?Sized,
+    T: Copy + Into<f64>,
+{
+    let no_data = reader.no_data()?;
+
+    let mut mask = vec![0u8; size.0 * size.1];
+    let has_mask = reader.read_mask_into_slice(&mut mask, off, size)?;
+
+    for ((i, j), valid) in valid.indexed_iter_mut() {
+        let val: f64 = data[(i, j)].into();
+        *valid = !no_data.is_nodata(val) && (!has_mask || mask[i * size.0 + j] != 0);
+    }
+    Ok(())
+}
+
+/// View a slice of raster data as bytes.
+fn as_bytes_mut<T: GdalType + Copy>(out: &mut [T]) -> &mut [u8] {
+    // Safety: the `GdalType`s are primitive numeric types,
+    // which have no padding, and are valid for any bytes;
+    // the view spans exactly the memory of `out`.
+    unsafe {
+        std::slice::from_raw_parts_mut(out.as_mut_ptr() as *mut u8, std::mem::size_of_val(out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Mock reader that fills each read with the number of
+    /// reads made so far.
+    #[derive(Default)]
+    struct CountingReader(AtomicUsize);
+
+    impl ChunkReader for CountingReader {
+        fn read_into_slice<T>(
+            &self,
+            out: &mut [T],
+            _off: RasterOffset,
+            _size: RasterDims,
+        ) -> Result<()>
+        where
+            T: GdalType + Copy,
+        {
+            let count = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+            for b in as_bytes_mut(out) {
+                *b = count as u8;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_caching_reader() -> Result<()> {
+        let reader = CachingReader::new(CountingReader::default(), 2);
+
+        let first = reader.read_as_array::<u8>((0, 4), (3, 2))?;
+        let second = reader.read_as_array::<u8>((0, 4), (3, 2))?;
+        assert_eq!(first, second);
+        assert_eq!(reader.reader.0.load(Ordering::SeqCst), 1);
+
+        // Different windows and types are read afresh
+        reader.read_as_array::<u8>((1, 4), (3, 2))?;
+        reader.read_as_array::<u16>((0, 4), (3, 2))?;
+        assert_eq!(reader.reader.0.load(Ordering::SeqCst), 3);
+
+        // Least recently used window is evicted
+        reader.read_as_array::<u8>((0, 4), (3, 2))?;
+        assert_eq!(reader.into_inner().0.load(Ordering::SeqCst), 4);
+
+        Ok(())
+    }
+
+    /// Mock reader that counts the reads of an in-memory
+    /// raster.
+    struct TallyReader(MemoryReader<u16>, AtomicUsize);
+
+    impl ChunkReader for TallyReader {
+        fn read_into_slice<T>(
+            &self,
+            out: &mut [T],
+            off: RasterOffset,
+            size: RasterDims,
+        ) -> Result<()>
+        where
+            T: GdalType + Copy,
+        {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            self.0.read_into_slice(out, off, size)
+        }
+
+        fn raster_size(&self) -> Result<RasterDims> {
+            self.0.raster_size()
+        }
+    }
+
+    #[test]
+    fn test_caching_reader_blocks() -> Result<()> {
+        // Value at `(row, col)` is `10 * row + col`
+        let arr = Array2::from_shape_fn((8, 6), |(i, j)| (10 * i + j) as u16);
+        let inner = TallyReader(MemoryReader(arr.clone(), None), AtomicUsize::new(0));
+        // Room for two blocks of 2 rows
+        let reader = CachingReader::with_blocks(inner, 2, 2 * 2 * 6 * 2)?;
+        assert_eq!(reader.block_height(), Some(2));
+        let reads = |reader: &CachingReader<TallyReader>| reader.reader.1.load(Ordering::SeqCst);
+
+        // Spans blocks 0 and 1
+        let data = reader.read_as_array::<u16>((1, 1), (3, 3))?;
+        assert_eq!(data, arr.slice(ndarray::s![1..4, 1..4]));
+        assert_eq!(reads(&reader), 2);
+
+        // Overlapping read is served from the cache
+        let data = reader.read_as_array::<u16>((0, 2), (6, 2))?;
+        assert_eq!(data, arr.slice(ndarray::s![2..4, ..]));
+        assert_eq!(reads(&reader), 2);
+        assert_eq!(reader.cached_bytes(), 48);
+
+        // Block 2 evicts block 0
+        reader.read_as_array::<u16>((0, 3), (2, 2))?;
+        assert_eq!(reads(&reader), 3);
+        reader.read_as_array::<u16>((0, 0), (1, 1))?;
+        assert_eq!(reads(&reader), 4);
+
+        // Windows past the raster are read directly
+        assert!(reader.read_as_array::<u16>((5, 0), (2, 1)).is_err());
+        assert_eq!(reads(&reader), 5);
+        Ok(())
+    }
+
+    /// Mock reader with a no-data value, and a mask that
+    /// marks the first column invalid.
+    struct MaskedReader(Option<f64>);
+
+    impl ChunkReader for MaskedReader {
+        fn read_into_slice<T>(
+            &self,
+            out: &mut [T],
+            _off: RasterOffset,
+            _size: RasterDims,
+        ) -> Result<()>
+        where
+            T: GdalType + Copy,
+        {
+            // Values 0, 1, 2, ... as bytes of `f64` data
+            let len = out.len();
+            let vals: Vec<f64> = (0..len).map(|i| i as f64).collect();
+            as_bytes_mut(out).copy_from_slice(unsafe {
+                std::slice::from_raw_parts(vals.as_ptr() as *const u8, len * 8)
+            });
+            Ok(())
+        }
+
+        fn no_data_value(&self) -> Result<Option<f64>> {
+            Ok(self.0)
+        }
+
+        fn read_mask_into_slice(
+            &self,
+            out: &mut [u8],
+            _off: RasterOffset,
+            size: RasterDims,
+        ) -> Result<bool> {
+            for (i, val) in out.iter_mut().enumerate() {
+                *val = if i % size.0 == 0 { 0 } else { 255 };
+            }
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn test_read_masked() -> Result<()> {
+        let (data, valid) = MaskedReader(Some(4.)).read_as_array_masked::<f64>((0, 0), (3, 2))?;
+        assert_eq!(data[(1, 1)], 4.);
+        assert_eq!(
+            valid.iter().cloned().collect::<Vec<_>>(),
+            vec![false, true, true, false, false, true]
+        );
+
+        // A NaN no-data value
+        let (_, valid) =
+            MaskedReader(Some(f64::NAN)).read_as_array_masked::<f64>((0, 0), (3, 2))?;
+        assert_eq!(valid.iter().filter(|&&v| v).count(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_data() -> Result<()> {
+        let no_data = MaskedReader(Some(4.)).no_data()?;
+        assert_eq!(no_data.value(), Some(4.));
+        assert!(no_data.is_nodata(4.) && no_data.is_nodata(f64::NAN));
+        assert!(!no_data.is_nodata(0.));
+
+        // NaN no-data value, or none at all
+        assert!(NoData::new(Some(f64::NAN)).is_nodata(f64::NAN));
+        assert!(NoData::NAN.is_nodata(f64::NAN) && !NoData::NAN.is_nodata(4.));
+        assert!(!MaskedReader(None).no_data()?.is_nodata(4.));
+        Ok(())
+    }
+
+    #[test]
+    fn test_nodata_to_nan() -> Result<()> {
+        let reader = NodataToNan::new(MaskedReader(Some(4.)))?;
+        let data = reader.read_as_array((0, 0), (3, 2))?;
+        assert!(data[(1, 1)].is_nan());
+        assert_eq!(data.iter().filter(|v| v.is_nan()).count(), 1);
+        assert_eq!(reader.replaced_value(), Some(4.));
+
+        // Explicit value overrides that of the reader
+        let reader = NodataToNan::with_value(MaskedReader(None), 2.);
+        let data = reader.read_as_array((0, 0), (3, 2))?;
+        assert!(data[(0, 2)].is_nan());
+        Ok(())
+    }
+
+    #[test]
+    fn test_nodata_override() -> Result<()> {
+        // The value overrides that of the reader, or
+        // defaults to it
+        let reader = NodataOverride::new(MaskedReader(Some(4.)), Some(2.));
+        assert_eq!(reader.no_data_value()?, Some(2.));
+        let (_, valid) = reader.read_as_array_masked::<f64>((0, 0), (3, 2))?;
+        assert_eq!(
+            valid.iter().cloned().collect::<Vec<_>>(),
+            vec![false, true, false, false, true, true]
+        );
+        let reader = NodataOverride::new(MaskedReader(Some(4.)), None);
+        assert_eq!(reader.no_data_value()?, Some(4.));
+
+        // A NaN value only marks the NaN values
+        let reader = NodataOverride::new(MaskedReader(Some(4.)), Some(f64::NAN));
+        let (_, valid) = reader.read_as_array_masked::<f64>((0, 0), (3, 2))?;
+        assert_eq!(
+            valid.iter().cloned().collect::<Vec<_>>(),
+            vec![false, true, true, false, true, true]
+        );
+
+        // The values read are unchanged
+        let reader = NodataToNan::new(NodataOverride::new(MaskedReader(None), Some(2.)))?;
+        let data = reader.read_as_array((0, 0), (3, 2))?;
+        assert!(data[(0, 2)].is_nan());
+        assert_eq!(data[(1, 1)], 4.);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_masked_alpha() -> Result<()> {
+        use gdal::raster::{Buffer, ColorInterpretation};
+        use gdal::DriverManager;
+
+        let driver = DriverManager::get_driver_by_name("MEM")?;
+        let ds = driver.create_with_band_type::<u8, _>("", 4, 2, 2)?;
+        ds.rasterband(1)?
+            .write((0, 0), (4, 2), &Buffer::new((4, 2), vec![1; 8]))?;
+        let mut alpha = ds.rasterband(2)?;
+        alpha.set_color_interpretation(ColorInterpretation::AlphaBand)?;
+        alpha.write(
+            (0, 0),
+            (4, 2),
+            &Buffer::new((4, 2), vec![0, 0, 255, 255, 0, 0, 255, 255]),
+        )?;
+
+        let (_, valid) = DatasetReader::new(ds, 1)?.read_as_array_masked::<u8>((0, 0), (4, 2))?;
+        assert_eq!(
+            valid.iter().cloned().collect::<Vec<_>>(),
+            vec![false, false, true, true, false, false, true, true]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_band_reader() -> Result<()> {
+        use crate::chunking::ChunkConfig;
+        use gdal::{raster::Buffer, DriverManager};
+
+        // Value of band `b` at `(x, y)` is `100 * b + 10 * y + x`
+        let driver = DriverManager::get_driver_by_name("MEM")?;
+        let ds = driver.create_with_band_type::<u16, _>("", 6, 4, 3)?;
+        for b in 1..=3 {
+            let data = (0..24).map(|i| 100 * b + 10 * (i / 6) + i % 6).collect();
+            ds.rasterband(b as isize)?
+                .write((0, 0), (6, 4), &Buffer::new((6, 4), data))?;
+        }
+        let reader = MultiBandReader::new(ds, vec![3, 1])?;
+
+        let cfg = ChunkConfig::with_dims(6, 4).with_tile_dims(3, 2);
+        let chunk = cfg.window_at(3).unwrap();
+        let arr = reader.read_chunk::<u16>(chunk)?;
+        assert_eq!(arr.dim(), (2, 2, 3));
+        assert_eq!(arr[(0, 1, 2)], 335);
+        assert_eq!(arr[(1, 0, 0)], 123);
+
+        let arr = reader.read_chunk_interleaved::<u16>(chunk)?;
+        assert_eq!(arr.dim(), (2, 3, 2));
+        assert_eq!(arr.as_slice().unwrap()[..4], [323, 123, 324, 124]);
+
+        assert!(MultiBandReader::new(reader.0, vec![1, 4]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_overview_reader() -> Result<()> {
+        use crate::chunking::ChunkConfig;
+        use gdal::{raster::Buffer, DriverManager};
+
+        // Value at `(x, y)` is `x + 100 * y`
+        let (width, height) = (8, 6);
+        let driver = DriverManager::get_driver_by_name("GTIFF")?;
+        let mut ds = driver.create_with_band_type::<f64, _>(
+            "/vsimem/test_overview_reader.tif",
+            width as isize,
+            height as isize,
+            1,
+        )?;
+        let data = (0..width * height)
+            .map(|i| (i % width + 100 * (i / width)) as f64)
+            .collect();
+        ds.rasterband(1)?
+            .write((0, 0), (width, height), &Buffer::new((width, height), data))?;
+        ds.build_overviews("AVERAGE", &[2], &[])?;
+
+        let reader = OverviewReader::new(ds, 1, 0)?;
+        assert_eq!(reader.dims(), (4, 3));
+        assert_eq!(reader.scale(), (2., 2.));
+
+        // Average of the 2x2 block at `(2x, 2y)`
+        let cfg = ChunkConfig::for_overview(&reader)?.with_tile_dims(2, 2);
+        for win in cfg.iter() {
+            let arr = reader.read_chunk::<f64>(win)?;
+            for ((i, j), &val) in arr.indexed_iter() {
+                let (x, y) = (win.x() + j, win.y() + i);
+                assert_eq!(val, (2 * x) as f64 + 200. * y as f64 + 50.5);
+            }
+        }
+
+        assert!(OverviewReader::new(reader.into_inner(), 1, 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dataset_reader_new() -> Result<()> {
+        use gdal::DriverManager;
+
+        let driver = DriverManager::get_driver_by_name("MEM")?;
+        let ds = driver.create_with_band_type::<u8, _>("", 4, 3, 2)?;
+        let reader = DatasetReader::new(ds, 2)?;
+        assert_eq!(reader.band_index(), 2);
+        assert_eq!(reader.raster_size()?, (4, 3));
+
+        let ds = driver.create_with_band_type::<u8, _>("", 4, 3, 2)?;
+        assert!(DatasetReader::new(ds, 3).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_resampled() -> Result<()> {
+        use gdal::{raster::Buffer, DriverManager};
+
+        // Value at `(x, y)` is `x + 100 * y`
+        let (width, height) = (8, 6);
+        let driver = DriverManager::get_driver_by_name("MEM")?;
+        let ds = driver.create_with_band_type::<f64, _>("", width as isize, height as isize, 1)?;
+        let data = (0..width * height)
+            .map(|i| (i % width + 100 * (i / width)) as f64)
+            .collect();
+        ds.rasterband(1)?
+            .write((0, 0), (width, height), &Buffer::new((width, height), data))?;
+        let reader = DatasetReader::new(ds, 1)?;
+
+        // Average of the 2x2 block at `(2 + 2x, 2y)`
+        let arr = reader.read_resampled::<f64>((2, 0), (6, 6), (3, 3), ResampleAlg::Average)?;
+        assert_eq!(arr.dim(), (3, 3));
+        for ((y, x), &val) in arr.indexed_iter() {
+            assert_eq!(val, (2 + 2 * x) as f64 + 200. * y as f64 + 50.5);
+        }
+
+        assert!(reader
+            .read_resampled::<f64>((0, 0), (4, 4), (0, 2), ResampleAlg::Average)
+            .is_err());
+
+        // Readers without resampling only read at 1:1
+        let reader = MaskedReader(None);
+        assert!(reader
+            .read_resampled::<f64>((0, 0), (4, 4), (2, 2), ResampleAlg::Average)
+            .is_err());
+        assert!(reader
+            .read_resampled::<f64>((0, 0), (4, 4), (4, 4), ResampleAlg::Average)
+            .is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_dynamic() -> Result<()> {
+        use gdal::{raster::Buffer, DriverManager};
+        let driver = DriverManager::get_driver_by_name("MEM")?;
+        let data: Vec<u8> = (0..12).collect();
+
+        let ds = driver.create_with_band_type::<u8, _>("", 4, 3, 1)?;
+        ds.rasterband(1)?
+            .write((0, 0), (4, 3), &Buffer::new((4, 3), data.clone()))?;
+        let chunk = DatasetReader::new(ds, 1)?.read_as_array_dynamic((1, 1), (2, 2))?;
+        assert!(matches!(chunk, TypedChunk::U8(_)));
+        assert_eq!(chunk.to_f64()[(1, 1)], 10.);
+
+        let ds = driver.create_with_band_type::<u16, _>("", 4, 3, 1)?;
+        let vals = data.iter().map(|&v| 1000 * v as u16).collect();
+        ds.rasterband(1)?
+            .write((0, 0), (4, 3), &Buffer::new((4, 3), vals))?;
+        let chunk = DatasetReader::new(ds, 1)?.read_as_array_dynamic((1, 1), (2, 2))?;
+        assert!(matches!(chunk, TypedChunk::U16(_)));
+        assert_eq!(chunk.to_f64()[(1, 1)], 10000.);
+
+        let ds = driver.create_with_band_type::<f32, _>("", 4, 3, 1)?;
+        let vals = data.iter().map(|&v| v as f32 / 4.).collect();
+        ds.rasterband(1)?
+            .write((0, 0), (4, 3), &Buffer::new((4, 3), vals))?;
+        let chunk = DatasetReader::new(ds, 1)?.read_as_array_dynamic((1, 1), (2, 2))?;
+        assert!(matches!(chunk, TypedChunk::F32(_)));
+        let mut sum = 0.;
+        chunk.map_f64(|_, val| sum += val);
+        assert_eq!(sum, (5 + 6 + 9 + 10) as f64 / 4.);
+        Ok(())
+    }
+
+    /// Mock reader that fails a number of times before
+    /// succeeding.
+    struct FlakyReader(AtomicUsize);
+
+    impl ChunkReader for FlakyReader {
+        fn read_into_slice<T>(
+            &self,
+            out: &mut [T],
+            _off: RasterOffset,
+            _size: RasterDims,
+        ) -> Result<()>
+        where
+            T: GdalType + Copy,
+        {
+            let failures = self.0.load(Ordering::SeqCst);
+            if failures > 0 {
+                self.0.store(failures - 1, Ordering::SeqCst);
+                bail!("transient failure {}", failures);
+            }
+            for b in as_bytes_mut(out) {
+                *b = 1;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_retrying_reader() -> Result<()> {
+        static RETRIES: AtomicUsize = AtomicUsize::new(0);
+        let policy = RetryPolicy::new(3)
+            .with_backoff(Duration::from_millis(0))
+            .with_on_retry(|_, attempt, err, _| {
+                assert!(err.to_string().contains("transient failure"));
+                RETRIES.fetch_add(attempt, Ordering::SeqCst);
+            });
+
+        let reader = RetryingReader::new(FlakyReader(AtomicUsize::new(2)), policy);
+        let data = reader.read_as_array::<u8>((0, 0), (3, 2))?;
+        assert!(data.iter().all(|&v| v == 1));
+        // Called after attempts 1 and 2
+        assert_eq!(RETRIES.load(Ordering::SeqCst), 3);
+
+        // Gives up, with the errors of all the attempts
+        let reader = RetryingReader::new(FlakyReader(AtomicUsize::new(3)), policy);
+        let err = reader.read_as_array::<u8>((0, 0), (3, 2)).unwrap_err();
+        let msg = format!("{:#}", err);
+        assert!(msg.contains("after 3 attempt(s)"), "{}", msg);
+        for failure in 1..=3 {
+            assert!(msg.contains(&format!("transient failure {}", failure)));
+        }
+        assert_eq!(reader.into_inner().0.load(Ordering::SeqCst), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_reader() -> Result<()> {
+        use crate::chunking::ChunkConfig;
+
+        // Value at `(row, col)` is `10 * row + col`
+        let arr = Array2::from_shape_fn((4, 6), |(i, j)| (10 * i + j) as u16);
+        let reader = MemoryReader(arr.clone(), Some(23.));
+
+        let cfg = ChunkConfig::for_reader(&reader)?.with_tile_dims(4, 2);
+        assert_eq!(cfg.iter().len(), 4);
+        for win in cfg.iter() {
+            let (data, valid) = reader.read_chunk_masked::<u16>(win)?;
+            for ((i, j), &val) in data.indexed_iter() {
+                let (y, x) = (win.y() + i, win.x() + j);
+                assert_eq!(val, arr[(y, x)]);
+                assert_eq!(valid[(i, j)], (y, x) != (2, 3));
+            }
+        }
+        assert!(matches!(
+            reader.read_as_array_dynamic((0, 0), (2, 2))?,
+            TypedChunk::U16(_)
+        ));
+
+        assert!(reader.read_as_array::<u16>((4, 0), (3, 1)).is_err());
+        assert!(reader.read_as_array::<u16>((0, -1), (1, 1)).is_err());
+        assert!(reader.read_as_array::<f64>((0, 0), (1, 1)).is_err());
+        assert!(reader.read_as_array::<i16>((0, 0), (1, 1)).is_err());
+        assert_eq!(reader.band_type()?, GdalDataType::UInt16);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_into_array() -> Result<()> {
+        let arr = Array2::from_shape_fn((4, 6), |(i, j)| (10 * i + j) as f64);
+        let reader = MemoryReader(arr.clone(), Some(23.));
+
+        // Contiguous, and strided outputs
+        let mut buf = Array2::<f64>::zeros((3, 4));
+        reader.read_into_array(buf.view_mut(), (1, 1), (4, 3))?;
+        assert_eq!(buf, arr.slice(ndarray::s![1..4, 1..5]));
+        let mut buf = Array2::<f64>::zeros((2, 6));
+        reader.read_into_array(buf.slice_mut(ndarray::s![.., ..;2]), (2, 2), (3, 2))?;
+        assert_eq!(
+            buf.slice(ndarray::s![.., ..;2]),
+            arr.slice(ndarray::s![2..4, 2..5])
+        );
+
+        let mut valid = Array2::from_elem((3, 4), true);
+        let mut buf = Array2::<f64>::zeros((3, 4));
+        reader.read_into_array_masked(buf.view_mut(), valid.view_mut(), (1, 1), (4, 3))?;
+        assert_eq!(valid.iter().filter(|&&v| !v).count(), 1);
+        assert!(!valid[(1, 2)]);
+
+        assert!(reader
+            .read_into_array(buf.view_mut(), (0, 0), (3, 4))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_clamped() -> Result<()> {
+        let arr = Array2::from_shape_fn((4, 6), |(i, j)| (10 * i + j) as f64);
+        let reader = MemoryReader(arr, Some(12.));
+
+        // Window overlapping the top-right corner
+        let (data, valid) = reader.read_as_array_clamped((4, -1), (3, 2), -1.)?;
+        assert_eq!(valid, ((4, 0), (2, 1)));
+        assert_eq!(
+            data.iter().cloned().collect::<Vec<_>>(),
+            vec![-1., -1., -1., 4., 5., -1.]
+        );
+
+        let (data, valid) = reader.read_as_array_masked_clamped((-1, 1), (3, 2), f64::NAN)?;
+        assert!(data[(0, 0)].is_nan());
+        assert_eq!(data[(1, 2)], 21.);
+        assert_eq!(
+            valid.iter().cloned().collect::<Vec<_>>(),
+            vec![false, true, true, false, true, true]
+        );
+
+        // Disjoint windows are not read
+        let (data, valid) = reader.read_as_array_clamped((8, 8), (2, 2), 0.)?;
+        assert_eq!(valid.1, (0, 0));
+        assert!(data.iter().all(|&v| v == 0.));
+
+        assert!(CountingReader::default()
+            .read_as_array_clamped((0, 0), (1, 1), 0u8)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_caching_reader_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<CachingReader<DatasetReader>>();
+    }
+
+    #[test]
+    fn test_pooled_reader() -> Result<()> {
+        use gdal::{raster::Buffer, DriverManager};
+        let (width, height) = (16, 8);
+        let path = "/vsimem/test_pooled_reader.tif";
+        {
+            let driver = DriverManager::get_driver_by_name("GTIFF")?;
+            let ds = driver.create_with_band_type::<u16, _>(path, width, height, 1)?;
+            let data = (0..width * height).map(|i| i as u16).collect();
+            ds.rasterband(1)?.write(
+                (0, 0),
+                (width as usize, height as usize),
+                &Buffer::new((width as usize, height as usize), data),
+            )?;
+        }
+
+        assert!(PooledReader::new("/vsimem/missing.tif", 1, 2).is_err());
+        assert!(PooledReader::new(path, 2, 2).is_err());
+
+        fn assert_sync<T: Send + Sync>() {}
+        assert_sync::<PooledReader>();
+
+        let reader = PooledReader::new(path, 1, 2)?;
+        std::thread::scope(|s| {
+            for y in 0..height {
+                let reader = &reader;
+                s.spawn(move || {
+                    let row = reader
+                        .read_as_array::<u16>((0, y), (width as usize, 1))
+                        .unwrap();
+                    for (x, &val) in row.iter().enumerate() {
+                        assert_eq!(val as isize, y * width + x as isize);
+                    }
+                });
+            }
+        });
+        assert!(reader.pooled() >= 1 && reader.pooled() <= 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_amplification() {
+        let dims = (100, 50);
+        // Aligned to the blocks, or the edges of the band
+        assert_eq!(
+            read_amplification(((0, 16), (100, 16)), (100, 16), dims),
+            1.
+        );
+        assert_eq!(
+            read_amplification(((0, 48), (100, 10)), (100, 16), dims),
+            1.
+        );
+        assert_eq!(read_amplification(((32, 32), (64, 18)), (32, 32), dims), 1.);
+        assert_eq!(read_amplification(((0, 0), (0, 0)), (32, 32), dims), 1.);
+
+        // A row of a band with 16 row strips
+        assert_eq!(read_amplification(((0, 3), (100, 1)), (100, 16), dims), 16.);
+        // Crossing two block boundaries: three strips for 18
+        // rows
+        assert_eq!(
+            read_amplification(((0, 15), (100, 18)), (100, 16), dims),
+            48. / 18.
+        );
+        // A tile of 32x32, clamped to the band
+        assert_eq!(
+            read_amplification(((40, 40), (8, 8)), (32, 32), dims),
+            32. * 18. / 64.
+        );
+    }
+
+    #[test]
+    fn test_thread_local_reader() -> Result<()> {
+        use gdal::{raster::Buffer, DriverManager};
+        let (width, height) = (16, 8);
+        let path = "/vsimem/test_thread_local_reader.tif";
+        {
+            let driver = DriverManager::get_driver_by_name("GTIFF")?;
+            let ds = driver.create_with_band_type::<u16, _>(path, width, height, 1)?;
+            let data = (0..width * height).map(|i| i as u16).collect();
+            ds.rasterband(1)?.write(
+                (0, 0),
+                (width as usize, height as usize),
+                &Buffer::new((width as usize, height as usize), data),
+            )?;
+        }
+
+        assert!(ThreadLocalReader::new("/vsimem/missing.tif", 1).is_err());
+        assert!(ThreadLocalReader::new(path, 2).is_err());
+
+        fn assert_sync<T: Send + Sync>() {}
+        assert_sync::<ThreadLocalReader>();
+
+        let reader = ThreadLocalReader::new(path, 1)?;
+        std::thread::scope(|s| {
+            for y in 0..height {
+                let reader = &reader;
+                s.spawn(move || {
+                    // Both reads use the handle of the thread
+                    for _ in 0..2 {
+                        let row = reader
+                            .read_as_array::<u16>((0, y), (width as usize, 1))
+                            .unwrap();
+                        for (x, &val) in row.iter().enumerate() {
+                            assert_eq!(val as isize, y * width + x as isize);
+                        }
+                    }
+                });
+            }
+        });
+
+        // A failed read discards the handle, and the next
+        // read re-opens the dataset
+        assert!(reader.read_as_array::<u16>((0, 0), (100, 1)).is_err());
+        assert_eq!(reader.raster_size()?, (width as usize, height as usize));
+        reader.close();
+        assert_eq!(reader.read_as_array::<u16>((1, 0), (1, 1))?[(0, 0)], 1);
+        Ok(())
+    }
+
+    /// Compare the reads per second of the `Send + Sync`
+    /// readers of a tiled GeoTIFF, with a thread per core.
+    /// Run with `cargo test --release -- --ignored
+    /// bench_path_readers --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_path_readers() -> Result<()> {
+        use gdal::{raster::RasterCreationOption, DriverManager};
+        use std::time::Instant;
+
+        let (width, height) = (4096, 4096);
+        let path = "/vsimem/bench_path_readers.tif";
+        {
+            let driver = DriverManager::get_driver_by_name("GTIFF")?;
+            let options = [RasterCreationOption {
+                key: "TILED",
+                value: "YES",
+            }];
+            driver
+                .create_with_band_type_with_options::<f32, _>(path, width, height, 1, &options)?;
+        }
+
+        // Read 256x256 windows (a tile each) from each thread
+        const READS: usize = 256;
+        let threads = std::thread::available_parallelism().map_or(4, |n| n.get());
+        fn bench<R: ChunkReader + Sync>(name: &str, reader: &R, threads: usize) {
+            let start = Instant::now();
+            std::thread::scope(|s| {
+                for t in 0..threads {
+                    s.spawn(move || {
+                        for i in 0..READS {
+                            let tile = (t * READS + i) % 256;
+                            let off = ((tile % 16) as isize * 256, (tile / 16) as isize * 256);
+                            reader.read_as_array::<f32>(off, (256, 256)).unwrap();
+                        }
+                    });
+                }
+            });
+            let rate = (threads * READS) as f64 / start.elapsed().as_secs_f64();
+            eprintln!("{}: {:.0} reads/sec", name, rate);
+        }
+
+        bench(
+            "RasterPathReader",
+            &RasterPathReader(Path::new(path), 1),
+            threads,
+        );
+        bench(
+            "PooledReader",
+            &PooledReader::new(path, 1, threads)?,
+            threads,
+        );
+        bench(
+            "ThreadLocalReader",
+            &ThreadLocalReader::new(path, 1)?,
+            threads,
+        );
+        Ok(())
+    }
 }
@@ -1,6 +1,9 @@
 //! Utilities to accumulate first and second moments; min;
-//! and max of a `f64` statistic incrementally.
+//! max; and approximate quantiles of a `f64` statistic
+//! incrementally. Also supports class counts of a
+//! categorical statistic.
 use serde_derive::Serialize;
+use std::collections::HashMap;
 use std::ops::AddAssign;
 
 /// Stores the statistics collected from a `f64` random
@@ -10,6 +13,12 @@ use std::ops::AddAssign;
 /// - a `f64` value.  Adds a new sample
 /// - a `(f64, f64)` tuple.  Adds the first component with weight specified by the second component.
 /// - another `PixelStats` value.  Accumulates the statistic from the other into `self`.
+///
+/// The mean and variance are accumulated using Welford's
+/// algorithm (and its parallel variant to merge), which
+/// avoids the catastrophic cancellation of computing them
+/// from `sum` and `sum_2` when the values are large
+/// compared to their spread (eg. elevations).
 #[derive(Debug, Serialize, Clone)]
 pub struct PixelStats {
     max: f64,
@@ -17,6 +26,8 @@ pub struct PixelStats {
     sum: f64,
     sum_2: f64,
     count: f64,
+    mean: f64,
+    m2: f64,
 }
 
 impl Default for PixelStats {
@@ -28,16 +39,25 @@ impl Default for PixelStats {
             sum: 0.,
             sum_2: 0.,
             count: 0.,
+            mean: 0.,
+            m2: 0.,
         }
     }
 }
 impl AddAssign<(f64, f64)> for PixelStats {
     fn add_assign(&mut self, other: (f64, f64)) {
-        self.max = self.max.max(other.0);
-        self.min = self.min.min(other.0);
-        self.sum += other.0;
-        self.sum_2 += other.0 * other.0;
-        self.count += other.1;
+        let (val, weight) = other;
+        self.max = self.max.max(val);
+        self.min = self.min.min(val);
+        self.sum += val * weight;
+        self.sum_2 += val * val * weight;
+        self.count += weight;
+
+        if self.count > 0. {
+            let delta = val - self.mean;
+            self.mean += delta * weight / self.count;
+            self.m2 += weight * delta * (val - self.mean);
+        }
     }
 }
 
@@ -52,7 +72,14 @@ impl AddAssign<&PixelStats> for PixelStats {
         self.min = self.min.min(other.min);
         self.sum += other.sum;
         self.sum_2 += other.sum_2;
-        self.count += other.count;
+
+        let count = self.count + other.count;
+        if count > 0. {
+            let delta = other.mean - self.mean;
+            self.m2 += other.m2 + delta * delta * self.count * other.count / count;
+            self.mean += delta * other.count / count;
+        }
+        self.count = count;
     }
 }
 
@@ -84,12 +111,18 @@ impl PixelStats {
 
     #[inline]
     pub fn mean(&self) -> f64 {
-        self.sum / self.count
+        if self.count > 0. {
+            self.mean
+        } else {
+            f64::NAN
+        }
     }
 
+    /// The (population) variance of the samples, i.e.
+    /// `E[X^2] - E[X]^2`.
     #[inline]
     pub fn variance(&self) -> f64 {
-        self.sum_2 / self.count
+        self.m2 / self.count
     }
 
     #[inline]
@@ -97,3 +130,385 @@ impl PixelStats {
         self.variance().sqrt()
     }
 }
+
+/// Default compression parameter of [`QuantileStats`].
+pub const DEFAULT_COMPRESSION: f64 = 100.;
+
+/// Stores a mergeable sketch (a [t-digest]) of a `f64`
+/// random variable to compute approximate quantiles.
+/// Accumulation is done by add-assigning (using `+=`) the
+/// same values as [`PixelStats`]:
+///
+/// - a `f64` value.  Adds a new sample
+/// - a `(f64, f64)` tuple.  Adds the first component with weight specified by the second component.
+/// - another `QuantileStats` value.  Accumulates the sketch from the other into `self`.
+///
+/// The memory used is bounded by the `compression`
+/// parameter: larger values are more accurate, but use
+/// proportionally more memory.
+///
+/// [t-digest]: https://arxiv.org/abs/1902.04023
+#[derive(Debug, Serialize, Clone)]
+pub struct QuantileStats {
+    compression: f64,
+    centroids: Vec<(f64, f64)>,
+    buffer: Vec<(f64, f64)>,
+    max: f64,
+    min: f64,
+    count: f64,
+}
+
+impl Default for QuantileStats {
+    fn default() -> Self {
+        QuantileStats::with_compression(DEFAULT_COMPRESSION)
+    }
+}
+
+impl AddAssign<(f64, f64)> for QuantileStats {
+    fn add_assign(&mut self, other: (f64, f64)) {
+        if other.0.is_nan() || other.1.is_nan() || other.1 <= 0. {
+            return;
+        }
+        self.max = self.max.max(other.0);
+        self.min = self.min.min(other.0);
+        self.count += other.1;
+        self.buffer.push(other);
+        if self.buffer.len() >= self.buffer_capacity() {
+            self.compress();
+        }
+    }
+}
+
+impl AddAssign<f64> for QuantileStats {
+    fn add_assign(&mut self, other: f64) {
+        *self += (other, 1.);
+    }
+}
+impl AddAssign<&QuantileStats> for QuantileStats {
+    fn add_assign(&mut self, other: &QuantileStats) {
+        self.max = self.max.max(other.max);
+        self.min = self.min.min(other.min);
+        self.count += other.count;
+        self.buffer.extend_from_slice(&other.centroids);
+        self.buffer.extend_from_slice(&other.buffer);
+        self.compress();
+    }
+}
+
+impl QuantileStats {
+    /// Construct an empty sketch with the given compression
+    /// parameter. The number of centroids stored is
+    /// (roughly) bounded by the compression.
+    pub fn with_compression(compression: f64) -> Self {
+        QuantileStats {
+            compression: compression.max(1.),
+            centroids: vec![],
+            buffer: vec![],
+            max: f64::NEG_INFINITY,
+            min: f64::INFINITY,
+            count: 0.,
+        }
+    }
+
+    #[inline]
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    #[inline]
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    #[inline]
+    pub fn count(&self) -> f64 {
+        self.count
+    }
+
+    #[inline]
+    pub fn median(&self) -> f64 {
+        self.quantile(0.5)
+    }
+
+    /// Estimate the `q`-th quantile (`0 <= q <= 1`) of the
+    /// samples. Returns `NAN` if no samples were added.
+    pub fn quantile(&self, q: f64) -> f64 {
+        if self.count <= 0. {
+            return f64::NAN;
+        }
+        let q = q.clamp(0., 1.);
+
+        let mut centroids = self.centroids.clone();
+        if !self.buffer.is_empty() {
+            centroids.extend_from_slice(&self.buffer);
+            centroids = merge_centroids(centroids, self.compression);
+        }
+
+        // Interpolate linearly between the centers of the
+        // centroids, and the min. / max. at either end.
+        let target = q * self.count;
+        let mut prev = (self.min, 0.);
+        let mut cumulative = 0.;
+        for &(mean, weight) in &centroids {
+            let center = cumulative + weight / 2.;
+            if target < center {
+                return interpolate(prev, (mean, center), target);
+            }
+            prev = (mean, center);
+            cumulative += weight;
+        }
+        interpolate(prev, (self.max, self.count), target)
+    }
+
+    #[inline]
+    fn buffer_capacity(&self) -> usize {
+        5 * self.compression.ceil() as usize
+    }
+
+    /// Merge the buffered samples into the centroids.
+    fn compress(&mut self) {
+        let mut centroids = std::mem::take(&mut self.centroids);
+        centroids.append(&mut self.buffer);
+        self.centroids = merge_centroids(centroids, self.compression);
+    }
+}
+
+/// Merge a list of (possibly unsorted) `(mean, weight)`
+/// centroids using the `k_1` scale function of the t-digest.
+fn merge_centroids(mut centroids: Vec<(f64, f64)>, compression: f64) -> Vec<(f64, f64)> {
+    use std::f64::consts::PI;
+    if centroids.is_empty() {
+        return centroids;
+    }
+    centroids.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+    let total: f64 = centroids.iter().map(|c| c.1).sum();
+    let scale = |q: f64| compression / (2. * PI) * (2. * q - 1.).clamp(-1., 1.).asin();
+
+    let mut merged = Vec::with_capacity(centroids.len());
+    let mut iter = centroids.into_iter();
+    let mut current = iter.next().unwrap();
+    let mut weight_before = 0.;
+    let mut k_lower = scale(0.);
+    for (mean, weight) in iter {
+        let q = (weight_before + current.1 + weight) / total;
+        if scale(q) - k_lower <= 1. {
+            let combined = current.1 + weight;
+            current.0 += (mean - current.0) * weight / combined;
+            current.1 = combined;
+        } else {
+            weight_before += current.1;
+            k_lower = scale(weight_before / total);
+            merged.push(current);
+            current = (mean, weight);
+        }
+    }
+    merged.push(current);
+    merged
+}
+
+#[inline]
+fn interpolate(left: (f64, f64), right: (f64, f64), at: f64) -> f64 {
+    let (val_l, pos_l) = left;
+    let (val_r, pos_r) = right;
+    if pos_r <= pos_l {
+        return val_r;
+    }
+    val_l + (val_r - val_l) * (at - pos_l) / (pos_r - pos_l)
+}
+
+/// Stores the class counts of a categorical (integer
+/// valued) random variable, eg. from land-cover rasters.
+/// Accumulation is done by add-assigning (using `+=`) one
+/// of the following.
+///
+/// - a `i64` value.  Adds a new sample of the class
+/// - another `CategoryStats` value.  Accumulates the counts from the other into `self`.
+///
+/// Values of a `f64` raster may be added via
+/// [`add_value`](CategoryStats::add_value), which checks
+/// that the value is integral.
+#[derive(Debug, Default, Serialize, Clone)]
+pub struct CategoryStats {
+    counts: HashMap<i64, usize>,
+    count: usize,
+}
+
+impl AddAssign<i64> for CategoryStats {
+    fn add_assign(&mut self, class: i64) {
+        *self.counts.entry(class).or_insert(0) += 1;
+        self.count += 1;
+    }
+}
+
+impl AddAssign<&CategoryStats> for CategoryStats {
+    fn add_assign(&mut self, other: &CategoryStats) {
+        for (&class, &count) in &other.counts {
+            *self.counts.entry(class).or_insert(0) += count;
+        }
+        self.count += other.count;
+    }
+}
+
+impl CategoryStats {
+    /// Add a sample from a `f64` value. Returns an error if
+    /// the value is not an integer (incl. `NaN`), or does
+    /// not fit an `i64`.
+    pub fn add_value(&mut self, val: f64) -> crate::Result<()> {
+        let integral = val.fract() == 0. && val >= i64::MIN as f64 && val < i64::MAX as f64;
+        if !integral {
+            anyhow::bail!("categorical value {} is not an integer", val);
+        }
+        *self += val as i64;
+        Ok(())
+    }
+
+    /// Number of samples accumulated.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Number of samples of the given class.
+    pub fn count_of(&self, class: i64) -> usize {
+        self.counts.get(&class).copied().unwrap_or(0)
+    }
+
+    /// Number of distinct classes seen.
+    pub fn distinct(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// The most frequent class; ties are broken in favour
+    /// of the smaller class. Returns `None` if no samples
+    /// were accumulated.
+    pub fn mode(&self) -> Option<i64> {
+        self.counts
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(a.0)))
+            .map(|(&class, _)| class)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_pass_variance(vals: &[f64]) -> f64 {
+        let mean = vals.iter().sum::<f64>() / vals.len() as f64;
+        vals.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / vals.len() as f64
+    }
+
+    #[test]
+    fn test_variance() {
+        // Large values with a small spread
+        let vals: Vec<f64> = (0..10000)
+            .map(|i| 1e6 + (i * 37 % 101) as f64 * 0.01)
+            .collect();
+        let expected = two_pass_variance(&vals);
+
+        let mut stats = PixelStats::default();
+        for &val in &vals {
+            stats += val;
+        }
+        assert!((stats.variance() - expected).abs() < 1e-9 * expected);
+        let mean = vals.iter().sum::<f64>() / vals.len() as f64;
+        assert!((stats.mean() - mean).abs() < 1e-12 * mean);
+
+        // Merge partial stats
+        let mut merged = PixelStats::default();
+        for part in vals.chunks(999) {
+            let mut stats = PixelStats::default();
+            for &val in part {
+                stats += val;
+            }
+            merged += &stats;
+        }
+        assert!((merged.variance() - expected).abs() < 1e-9 * expected);
+        assert_eq!(merged.count(), vals.len() as f64);
+
+        // Weighted samples
+        let mut weighted = PixelStats::default();
+        weighted += (1., 3.);
+        weighted += (5., 1.);
+        assert_eq!(weighted.mean(), 2.);
+        assert_eq!(weighted.variance(), 3.);
+
+        assert!(PixelStats::default().variance().is_nan());
+    }
+
+    /// A permutation of `N` uniformly spaced values in
+    /// `[0, 1)`.
+    fn uniform_samples(n: usize) -> impl Iterator<Item = f64> {
+        (0..n).map(move |i| (i * 7919 % n) as f64 / n as f64)
+    }
+
+    fn check_quantiles(stats: &QuantileStats) {
+        for &q in &[0., 0.01, 0.1, 0.25, 0.5, 0.75, 0.9, 0.99, 1.] {
+            let est = stats.quantile(q);
+            assert!((est - q).abs() < 0.01, "quantile({}) = {}", q, est);
+        }
+        assert!((stats.median() - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_quantiles_uniform() {
+        let mut stats = QuantileStats::default();
+        for val in uniform_samples(1_000_000) {
+            stats += val;
+        }
+        assert_eq!(stats.count(), 1e6);
+        assert!(stats.centroids.len() < 10 * DEFAULT_COMPRESSION as usize);
+        check_quantiles(&stats);
+    }
+
+    #[test]
+    fn test_quantiles_merged() {
+        let mut first = QuantileStats::default();
+        let mut second = QuantileStats::default();
+        for (i, val) in uniform_samples(1_000_000).enumerate() {
+            if i % 3 == 0 {
+                first += val;
+            } else {
+                second += (val, 1.);
+            }
+        }
+        first += &second;
+        assert_eq!(first.count(), 1e6);
+        check_quantiles(&first);
+    }
+
+    #[test]
+    fn test_quantiles_empty() {
+        assert!(QuantileStats::default().median().is_nan());
+    }
+
+    #[test]
+    fn test_categories() {
+        let mut first = CategoryStats::default();
+        let mut second = CategoryStats::default();
+        for &val in &[3., 1., 3., 2., -1.] {
+            first.add_value(val).unwrap();
+        }
+        second += 1;
+        second += 2;
+        second += 2;
+
+        first += &second;
+        assert_eq!(first.count(), 8);
+        assert_eq!(first.distinct(), 4);
+        assert_eq!(first.count_of(2), 3);
+        assert_eq!(first.count_of(5), 0);
+
+        assert_eq!(first.mode(), Some(2));
+
+        // Ties favour the smaller class
+        first += 3;
+        assert_eq!(first.mode(), Some(2));
+        assert_eq!(CategoryStats::default().mode(), None);
+
+        assert!(first.add_value(1.5).is_err());
+        assert!(first.add_value(f64::NAN).is_err());
+        assert!(first.add_value(1e20).is_err());
+        assert_eq!(first.count(), 9);
+    }
+}
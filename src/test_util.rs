@@ -0,0 +1,86 @@
+//! Helpers to construct in-memory (GDAL `MEM`) datasets,
+//! to test readers and chunking without filesystem IO.
+//! Available to the tests of this crate, and to others
+//! with the `test-util` feature.
+
+use crate::Result;
+use anyhow::ensure;
+use gdal::{
+    raster::{Buffer, GdalType},
+    Dataset, DriverManager,
+};
+use ndarray::{ArrayView3, Axis};
+
+/// The geo-transform of a dataset created by
+/// [`mem_dataset`]: unit pixels, with the top-left corner
+/// at the origin.
+pub const MEM_GEO_TRANSFORM: [f64; 6] = [0., 1., 0., 0., 0., -1.];
+
+/// Create an in-memory dataset of `bands` bands of `width`
+/// x `height` pixels, with the values of `data`, which must
+/// be of shape `(bands, height, width)`. The geo-transform
+/// is [`MEM_GEO_TRANSFORM`]; change it with
+/// `Dataset::set_geo_transform`. The bands have no no-data
+/// value; see [`set_no_data_value`]. Read it with eg.
+/// `DatasetReader::new(ds, 1)`.
+pub fn mem_dataset<T: GdalType + Copy>(
+    width: usize,
+    height: usize,
+    bands: usize,
+    data: ArrayView3<'_, T>,
+) -> Result<Dataset> {
+    ensure!(
+        data.dim() == (bands, height, width),
+        "data of shape {:?} for {} bands of {}x{} pixels",
+        data.dim(),
+        bands,
+        width,
+        height
+    );
+    let driver = DriverManager::get_driver_by_name("MEM")?;
+    let mut ds = driver.create_with_band_type::<T, _>(
+        "",
+        width as isize,
+        height as isize,
+        bands as isize,
+    )?;
+    ds.set_geo_transform(&MEM_GEO_TRANSFORM)?;
+    for (idx, band_data) in data.axis_iter(Axis(0)).enumerate() {
+        let buf = Buffer::new((width, height), band_data.iter().copied().collect());
+        ds.rasterband(idx as isize + 1)?
+            .write((0, 0), (width, height), &buf)?;
+    }
+    Ok(ds)
+}
+
+/// Set (or clear) the no-data value of every band of `ds`.
+pub fn set_no_data_value(ds: &Dataset, no_val: Option<f64>) -> Result<()> {
+    for idx in 1..=ds.raster_count() {
+        ds.rasterband(idx)?.set_no_data_value(no_val)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{ChunkReader, DatasetReader};
+    use ndarray::Array3;
+
+    #[test]
+    fn test_mem_dataset() -> Result<()> {
+        // Value of band `b` at `(x, y)` is `100 * b + 10 * y + x`
+        let data = Array3::from_shape_fn((2, 3, 4), |(b, y, x)| (100 * b + 10 * y + x) as f64);
+        let ds = mem_dataset(4, 3, 2, data.view())?;
+        assert_eq!(ds.geo_transform()?, MEM_GEO_TRANSFORM);
+
+        set_no_data_value(&ds, Some(112.))?;
+        let reader = DatasetReader::new(ds, 2)?;
+        assert_eq!(reader.no_data_value()?, Some(112.));
+        let arr = reader.read_as_array::<f64>((1, 1), (3, 2))?;
+        assert_eq!(arr, data.slice(ndarray::s![1, 1.., 1..]));
+
+        assert!(mem_dataset(3, 4, 2, data.view()).is_err());
+        Ok(())
+    }
+}
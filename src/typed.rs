@@ -0,0 +1,100 @@
+//! Chunks of data in the native type of a raster band.
+
+use ndarray::Array2;
+
+/// A chunk of data in the type of the band it was read from
+/// (see [`ChunkReader::read_chunk_dynamic`]). Bands of a
+/// type without a variant are read as `f64`.
+///
+/// [`ChunkReader::read_chunk_dynamic`]: crate::reader::ChunkReader::read_chunk_dynamic
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedChunk {
+    U8(Array2<u8>),
+    U16(Array2<u16>),
+    I16(Array2<i16>),
+    U32(Array2<u32>),
+    I32(Array2<i32>),
+    F32(Array2<f32>),
+    F64(Array2<f64>),
+}
+
+/// Apply `$body` to the array of any variant, bound to
+/// `$arr`. The array of the `F64` variant may instead be
+/// given to `$body_f64`, eg. to skip converting it.
+macro_rules! with_array {
+    ($chunk:expr, $arr:ident => $body:expr) => {
+        with_array!($chunk, $arr => $body, F64($arr) => $body)
+    };
+    ($chunk:expr, $arr:ident => $body:expr, F64($arr_f64:ident) => $body_f64:expr) => {
+        match $chunk {
+            TypedChunk::U8($arr) => $body,
+            TypedChunk::U16($arr) => $body,
+            TypedChunk::I16($arr) => $body,
+            TypedChunk::U32($arr) => $body,
+            TypedChunk::I32($arr) => $body,
+            TypedChunk::F32($arr) => $body,
+            TypedChunk::F64($arr_f64) => $body_f64,
+        }
+    };
+}
+
+impl TypedChunk {
+    /// The dimensions `(rows, cols)` of the chunk.
+    pub fn dim(&self) -> (usize, usize) {
+        with_array!(self, arr => arr.dim())
+    }
+
+    /// Convert the data to `f64`. Clones the data if it is
+    /// already `f64`; see [`into_f64`] to avoid it.
+    ///
+    /// [`into_f64`]: TypedChunk::into_f64
+    pub fn to_f64(&self) -> Array2<f64> {
+        with_array!(
+            self,
+            arr => arr.mapv(|val| val as f64),
+            F64(arr) => arr.clone()
+        )
+    }
+
+    /// Convert the data to `f64`, re-using the array if it
+    /// is already `f64`.
+    pub fn into_f64(self) -> Array2<f64> {
+        match self {
+            TypedChunk::F64(arr) => arr,
+            chunk => chunk.to_f64(),
+        }
+    }
+
+    /// Visit each value (with its `(row, col)` index) as an
+    /// `f64`, without converting the whole array. Useful to
+    /// accumulate statistics.
+    pub fn map_f64<F>(&self, mut f: F)
+    where
+        F: FnMut((usize, usize), f64),
+    {
+        with_array!(
+            self,
+            arr => arr.indexed_iter().for_each(|(idx, &val)| f(idx, val as f64)),
+            F64(arr) => arr.indexed_iter().for_each(|(idx, &val)| f(idx, val))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_chunk() {
+        let chunk = TypedChunk::U16(Array2::from_shape_fn((2, 3), |(i, j)| (10 * i + j) as u16));
+        assert_eq!(chunk.dim(), (2, 3));
+        assert_eq!(chunk.to_f64()[(1, 2)], 12.);
+
+        let mut sum = 0.;
+        chunk.map_f64(|_, val| sum += val);
+        assert_eq!(sum, 36.);
+
+        let arr = Array2::from_elem((2, 2), 0.5);
+        assert_eq!(TypedChunk::F64(arr.clone()).into_f64(), arr);
+    }
+}